@@ -1,26 +1,314 @@
-use clap::Parser;
-use std::path::PathBuf;
-
-#[derive(Parser, Debug, Default)]
-#[clap(
-    author = "Lucas Waddell",
-    version,
-    about = "A tool to sort images based on metadata or Google Takeout JSON files."
-)]
+use crate::config::DEFAULT_CONFIG;
+use crate::filename::parse_pattern;
+use crate::filter::{parse_coordinates, parse_distance, parse_size, Filter, DEFAULT_RADIUS_KM};
+use crate::infer::Fallback;
+use crate::layout::{Hemisphere, Key, Layout, MonthStyle};
+use crate::metadata::{Coordinates, DateTag, Strip, DEFAULT_DATE_TAGS};
+use crate::pairs::RawPairs;
+use crate::save::{Conflict, SaveOptions};
+use crate::scan::{ScanOptions, PATTERNS};
+use crate::screenshot::Screenshots;
+use crate::template::Template;
+use crate::transfer::{Reflink, Transfer};
+use crate::xmp::XmpPriority;
+use crate::zone::{parse_zone, Zone};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Datelike, Local, Locale, NaiveDate, TimeDelta};
+use clap::{ArgAction, Args, ColorChoice, ValueEnum};
+use log::LevelFilter;
+use regex::Regex;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Options every subcommand takes, for output and the config file
+#[derive(Args, Debug, Default)]
+pub struct Common {
+    /// Show more detail, once for per-file decisions and twice for metadata reads
+    #[clap(
+        short,
+        long,
+        action = ArgAction::Count,
+        help = "Show more detail, repeat for even more (-vv)"
+    )]
+    pub verbose: u8,
+
+    /// Only print warnings and errors
+    #[clap(
+        short,
+        long,
+        conflicts_with = "verbose",
+        help = "Only print warnings and errors"
+    )]
+    pub quiet: bool,
+
+    /// When to color the output
+    #[clap(
+        long,
+        value_name = "WHEN",
+        default_value_t = ColorChoice::Auto,
+        help = "Color the output: auto, always or never (auto respects NO_COLOR)"
+    )]
+    pub color: ColorChoice,
+
+    /// How progress is written
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Output::Text,
+        help = "How to report progress: text, or ndjson for one JSON event per line on stdout"
+    )]
+    pub output: Output,
+
+    /// Config file with default options and profiles
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Read default options from a TOML file [default: ./img-sort.toml if present]"
+    )]
+    pub config: Option<PathBuf>,
+
+    /// Named profile in the config file to use
+    #[clap(
+        long,
+        help = "Use the options of a [profile.<name>] table in the config file"
+    )]
+    pub profile: Option<String>,
+}
+
+/// How a run reports what it's doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Output {
+    /// Messages and a progress bar, for people
+    #[default]
+    Text,
+    /// One JSON object per event on stdout, for scripts and front ends, with messages
+    /// moved to stderr
+    Ndjson,
+}
+
+impl Common {
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Warn,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+
+    /// When output is colored, where auto still leaves it plain for `NO_COLOR` and output
+    /// that isn't a terminal
+    pub fn color_choice(&self) -> anstream::ColorChoice {
+        match self.color {
+            ColorChoice::Auto => anstream::ColorChoice::Auto,
+            ColorChoice::Always => anstream::ColorChoice::Always,
+            ColorChoice::Never => anstream::ColorChoice::Never,
+        }
+    }
+
+    pub fn validate(&self) -> Result<&Self, String> {
+        if self.profile.is_some() && self.config.is_none() && !Path::new(DEFAULT_CONFIG).is_file() {
+            return Err(String::from(
+                "A profile needs a config file to read it from.",
+            ));
+        }
+
+        Ok(self)
+    }
+}
+
+/// The year of the oldest photograph there is, before which no date can be right
+const OLDEST_YEAR: i32 = 1826;
+
+/// Options for finding, grouping and saving media
+#[derive(Args, Debug, Default)]
 pub struct Arguments {
-    /// Path to the directory containing images
+    /// Paths to the directories containing images
     #[clap(
         short,
         long,
-        help = "Path to the directory containing images"
+        required_unless_present = "files_from",
+        help = "Path to a directory containing images (repeat to merge several sources, which IMG_SORT_PATH can't)"
     )]
-    pub path: PathBuf,
+    pub path: Vec<PathBuf>,
+
+    /// File listing the media to sort, or - for stdin
+    #[clap(
+        long,
+        help = "Read newline or NUL separated media paths from a file, or - for stdin, instead of scanning"
+    )]
+    pub files_from: Option<PathBuf>,
+
+    /// How deep to search the source directories
+    #[clap(
+        long,
+        default_value_t = 4,
+        help = "How many directories deep to search the sources (0 for unlimited)"
+    )]
+    pub max_depth: usize,
+
+    /// Search the source directories without a depth limit
+    #[clap(long, help = "Search the sources without a depth limit")]
+    pub no_max_depth: bool,
+
+    /// Don't follow symlinks while searching the source directories
+    #[clap(long, help = "Don't follow symlinks while searching the sources")]
+    pub no_follow_links: bool,
+
+    /// Globs for paths to skip while searching
+    #[clap(
+        long,
+        help = "Skip paths matching a glob, e.g. \"**/@eaDir/**\" (repeatable)"
+    )]
+    pub exclude: Vec<String>,
+
+    /// Extra file extensions to search for
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Extra file extensions to search for, e.g. \"dng,cr2,mp4\""
+    )]
+    pub ext: Vec<String>,
+
+    /// Only search for the extensions given with --ext
+    #[clap(long, help = "Only search for the extensions given with --ext")]
+    pub no_default_ext: bool,
+
+    /// Only sort media taken on or after this date
+    #[clap(
+        long,
+        help = "Only sort media taken on or after this date, e.g. 2019-01-01"
+    )]
+    pub after: Option<NaiveDate>,
+
+    /// Only sort media taken on or before this date
+    #[clap(
+        long,
+        help = "Only sort media taken on or before this date, e.g. 2021-12-31"
+    )]
+    pub before: Option<NaiveDate>,
+
+    /// Fail instead of saving media without a date
+    #[clap(
+        long,
+        help = "List media without a date and exit with an error instead of sorting"
+    )]
+    pub strict: bool,
+
+    /// Leave media without a date in the source
+    #[clap(
+        long,
+        conflicts_with = "strict",
+        help = "Leave media without a date where it is instead of saving it"
+    )]
+    pub skip_unknown: bool,
+
+    /// Only sort media of at least this size
+    #[clap(
+        long,
+        value_parser = parse_size,
+        help = "Only sort media of at least this size, e.g. 50K"
+    )]
+    pub min_size: Option<u64>,
+
+    /// Only sort media of at most this size
+    #[clap(
+        long,
+        value_parser = parse_size,
+        help = "Only sort media of at most this size, e.g. 2G"
+    )]
+    pub max_size: Option<u64>,
+
+    /// Only sort media tagged with a keyword
+    #[clap(
+        long,
+        help = "Only sort media tagged with this keyword in its XMP or IPTC, e.g. Birthday (may be repeated)"
+    )]
+    pub filter_keyword: Vec<String>,
+
+    /// Save bursts in folders of their own
+    #[clap(
+        long,
+        help = "Save each burst, several shots taken in quick succession, in a Burst_<timestamp> folder inside its bucket"
+    )]
+    pub group_bursts: bool,
+
+    /// How far apart the shots of a burst may be
+    #[clap(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_duration,
+        requires = "group_bursts",
+        help = "How long after the last shot the next may be taken to be part of a burst, e.g. 2s [default: 1s]"
+    )]
+    pub burst_gap: Option<Duration>,
+
+    /// Save the videos inside Motion Photos apart
+    #[clap(
+        long,
+        help = "Save the video inside each Samsung or Pixel Motion Photo beside it as an MP4, keeping the photo whole"
+    )]
+    pub split_motion_photos: bool,
+
+    /// What to do with screenshots
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Screenshots::Normal,
+        help = "Sort screenshots like other media, route them into a Screenshots folder, or skip them"
+    )]
+    pub screenshots: Screenshots,
+
+    /// Only sort media rated with at least this many stars
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(i32).range(0..=5),
+        help = "Only sort media rated with at least this many stars, from 0 to 5, in its XMP or EXIF"
+    )]
+    pub min_rating: Option<i32>,
+
+    /// The earliest year a date may be in
+    #[clap(
+        long,
+        value_name = "YEAR",
+        help = "Treat dates before this year as missing, e.g. a scanner's 0012 [default: 1826]"
+    )]
+    pub min_year: Option<i32>,
+
+    /// The latest year a date may be in
+    #[clap(
+        long,
+        value_name = "YEAR",
+        help = "Treat dates after this year as missing, e.g. a camera's 2071 [default: next year]"
+    )]
+    pub max_year: Option<i32>,
+
+    /// Only sort media taken near a place
+    #[clap(
+        long,
+        value_name = "LATITUDE,LONGITUDE",
+        value_parser = parse_coordinates,
+        allow_hyphen_values = true,
+        help = "Only sort media whose GPS puts it near this place, e.g. \"48.8584,2.2945\""
+    )]
+    pub near: Option<Coordinates>,
+
+    /// How far from the place media may have been taken
+    #[clap(
+        long,
+        value_name = "DISTANCE",
+        value_parser = parse_distance,
+        requires = "near",
+        help = "How far from --near media may have been taken, e.g. 50km, 500m or 3mi [default: 10km]"
+    )]
+    pub radius: Option<f64>,
 
     /// Path to the directory to copy the sorted media to
     #[clap(
-        short,
+        short = 'o',
         long,
-        help = "Path to the directory containing the sorted images should be copied to"
+        help = "Path to the directory the sorted images should be copied to (created if missing)"
     )]
     pub dest: PathBuf,
 
@@ -31,21 +319,701 @@ pub struct Arguments {
     /// Sort images by years
     #[clap(short, help = "Sort images by years")]
     pub years: bool,
+
+    /// Directory layout as a list of keywords
+    #[clap(
+        long,
+        help = "Directory layout as keywords separated by '/', e.g. \"year/month/camera\" (year, month, day, week, quarter, decade, season, camera, type, keyword, country, city, place)"
+    )]
+    pub layout: Option<Layout>,
+
+    /// Sort images by days
+    #[clap(short, long, help = "Sort images by days (requires -y and -m)")]
+    pub days: bool,
+
+    /// Sort images by ISO weeks
+    #[clap(long, help = "Sort images by ISO year and week, e.g. 2024/W07")]
+    pub weeks: bool,
+
+    /// Sort images by quarters
+    #[clap(long, help = "Sort images by quarters, e.g. Q1, or 2023/Q1 with -y")]
+    pub quarters: bool,
+
+    /// Sort images by decades
+    #[clap(long, help = "Sort images by decades, e.g. 1970s")]
+    pub decades: bool,
+
+    /// Sort images by seasons
+    #[clap(long, help = "Sort images by year and season, e.g. 2024/Winter")]
+    pub seasons: bool,
+
+    /// Hemisphere used to name seasons
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Hemisphere::North,
+        help = "Hemisphere used to name seasons"
+    )]
+    pub hemisphere: Hemisphere,
+
+    /// strftime-style format for the directory each image is saved in
+    #[clap(
+        long,
+        help = "Directory path for each image as a strftime-style format, e.g. \"%Y/%Y-%m\""
+    )]
+    pub format: Option<String>,
+
+    /// How month directories are named
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = MonthStyle::Name,
+        help = "How month directories are named, e.g. 03, March or 03-March"
+    )]
+    pub month_style: MonthStyle,
+
+    /// Locale used to name month directories
+    #[clap(
+        long,
+        value_parser = parse_locale,
+        help = "Locale used to name month directories, e.g. es_ES or fr_FR"
+    )]
+    pub locale: Option<Locale>,
+
+    /// Split photos, videos and RAW files into separate top-level folders
+    #[clap(
+        long,
+        help = "Split photos, videos and RAW files into Photos/, Videos/ and RAW/ folders"
+    )]
+    pub split_media: bool,
+
+    /// Move media into the destination instead of copying it
+    #[clap(
+        long = "move",
+        help = "Move media into the destination instead of copying it"
+    )]
+    pub move_media: bool,
+
+    /// Hardlink media into the destination instead of copying it
+    #[clap(
+        long,
+        help = "Hardlink media into the destination instead of copying it"
+    )]
+    pub link: bool,
+
+    /// Symlink media into the destination instead of copying it
+    #[clap(
+        long,
+        help = "Symlink media into the destination instead of copying it"
+    )]
+    pub symlink: bool,
+
+    /// Clone media with copy-on-write when copying
+    #[clap(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = Reflink::Never,
+        default_missing_value = "auto",
+        help = "Clone media with copy-on-write (APFS, Btrfs, XFS) instead of copying its data"
+    )]
+    pub reflink: Reflink,
+
+    /// What to do when a file already exists in the destination
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Conflict::Rename,
+        help = "What to do when a file with the same name already exists in the destination"
+    )]
+    pub on_conflict: Conflict,
+
+    /// Recreate each source's subdirectories inside the buckets
+    #[clap(
+        long,
+        help = "Keep the source's subdirectories inside each bucket, e.g. 2024/March/Trips/Japan"
+    )]
+    pub keep_structure: bool,
+
+    /// Copy extended attributes, e.g. Finder tags, along with the media
+    #[clap(
+        long,
+        help = "Copy extended attributes, such as Finder tags and user.* attributes, with the media"
+    )]
+    pub preserve_xattrs: bool,
+
+    /// Recreate Google Takeout albums as folders of links
+    #[clap(
+        long,
+        help = "Sort photos in Takeout album folders once, and recreate the albums as folders of symlinks in Albums/"
+    )]
+    pub takeout_albums: bool,
+
+    /// Recreate iCloud Photos albums and favorites as folders of links
+    #[clap(
+        long,
+        help = "Recreate the albums and favorites of iCloud Photos exports as folders of symlinks in Albums/"
+    )]
+    pub icloud_albums: bool,
+
+    /// Write the date and location from Takeout sidecars into the copies
+    ///
+    /// Media without a date of its own is sorted by its sidecar's either way.
+    #[clap(
+        long,
+        conflicts_with = "strip_metadata",
+        help = "Write the date and location in Takeout JSON sidecars into the copies' EXIF (media without a date is sorted by its sidecar's date either way)"
+    )]
+    pub embed_takeout: bool,
+
+    /// EXIF tags to date photos from, in the order they're tried
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = DEFAULT_DATE_TAGS,
+        help = "EXIF tags to date photos from, tried in order until one has a date"
+    )]
+    pub date_tags: Vec<DateTag>,
+
+    /// Patterns for dates in names, tried before the built-in ones
+    #[clap(
+        long,
+        value_name = "REGEX",
+        value_parser = parse_pattern,
+        help = "Date media without EXIF from names matching a regex with year, month and day groups, e.g. \"(?P<y>\\d{4})-(?P<m>\\d{2})-(?P<d>\\d{2})\" (repeatable)"
+    )]
+    pub filename_pattern: Vec<Regex>,
+
+    /// Which file time dates media without a date of its own
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Fallback::None,
+        help = "Date media without a date of its own from when its file was modified (mtime) or created (btime), rather than leaving it unknown"
+    )]
+    pub fallback: Fallback,
+
+    /// Read media img-sort can't with exiftool
+    #[clap(
+        long,
+        help = "Read the metadata of media img-sort can't read itself with exiftool, which must be installed"
+    )]
+    pub use_exiftool: bool,
+
+    /// Date videos img-sort can't read with ffprobe
+    #[clap(
+        long,
+        help = "Date videos in containers img-sort can't read itself, like MTS and WMV, with ffprobe, which must be installed"
+    )]
+    pub use_ffprobe: bool,
+
+    /// Time zone of dates that don't say which they're in
+    #[clap(
+        long,
+        value_name = "ZONE",
+        value_parser = parse_zone,
+        requires = "convert_to_tz",
+        help = "Time zone of dates without an offset when converting them, e.g. UTC, +09:00 or Asia/Tokyo [default: local]"
+    )]
+    pub assume_tz: Option<Zone>,
+
+    /// Time zone to sort dates in
+    #[clap(
+        long,
+        value_name = "ZONE",
+        value_parser = parse_zone,
+        help = "Sort and name media by the time in this zone rather than where it was taken, e.g. local, UTC or Europe/Paris"
+    )]
+    pub convert_to_tz: Option<Zone>,
+
+    /// How dates in XMP sidecars are weighed against the media's own
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = XmpPriority::Fallback,
+        help = "Date media from an XMP sidecar beside it when it has no date of its own, in preference to its own, or not at all"
+    )]
+    pub xmp: XmpPriority,
+
+    /// What to do with the RAW and JPEG a camera writes for the same shot
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = RawPairs::Keep,
+        help = "Keep RAW and JPEG shots of the same name and time together: side by side, only one of them, or with the RAW in a RAW folder beside the JPEG"
+    )]
+    pub raw_pairs: RawPairs,
+
+    /// Write an HTML page summarizing the run
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Write an HTML page of the run, with thumbnails by month, undated media and errors"
+    )]
+    pub report: Option<PathBuf>,
+
+    /// Remove EXIF, XMP and IPTC metadata from the copies
+    ///
+    /// Only JPEGs can be stripped, so other media fails rather than being copied as it is.
+    #[clap(
+        long,
+        conflicts_with = "strip_gps",
+        help = "Remove EXIF, XMP and IPTC metadata from copied JPEGs, leaving the originals untouched (other media fails to save)"
+    )]
+    pub strip_metadata: bool,
+
+    /// Remove only the GPS location from the copies
+    #[clap(
+        long,
+        help = "Remove the GPS location from the EXIF and XMP of copied JPEGs, leaving the originals untouched (other media fails to save)"
+    )]
+    pub strip_gps: bool,
+
+    /// Save misnamed media under the extension of its real format
+    #[clap(
+        long,
+        help = "Save media whose extension doesn't match its contents, like a HEIC named .jpg, with the right one"
+    )]
+    pub fix_extensions: bool,
+
+    /// Ask about each conflict
+    #[clap(
+        long,
+        help = "Ask whether to skip, overwrite or rename each conflicting file"
+    )]
+    pub interactive: bool,
+
+    /// Save even when a destination seems to lack the space
+    #[clap(
+        long,
+        help = "Warn instead of stopping when the destination looks too full"
+    )]
+    pub force: bool,
+
+    /// How many files to save at once
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Save this many files at once, or 0 for one per CPU"
+    )]
+    pub threads: usize,
+
+    /// File to append a line to for each operation
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Append each operation to this JSON lines journal [default: a new one in DEST/.img-sort]"
+    )]
+    pub journal: Option<PathBuf>,
+
+    /// Pick up an interrupted run where its journal left off
+    #[clap(
+        long,
+        help = "Skip media the journal (--journal, or the latest in DEST/.img-sort) shows was already saved"
+    )]
+    pub resume: bool,
+
+    /// Wait for another run using the destination to finish
+    #[clap(
+        long,
+        help = "Wait for another run saving to the destination to finish instead of exiting"
+    )]
+    pub wait: bool,
+
+    /// Keep running, sorting new media on a schedule
+    #[clap(
+        long,
+        help = "Keep running and sort new media from the sources every --every, skipping media already sorted"
+    )]
+    pub daemon: bool,
+
+    /// How long to wait between scans in daemon mode
+    #[clap(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_duration,
+        requires = "daemon",
+        help = "How often the daemon scans the sources, e.g. 30m or 1h [default: 1h]"
+    )]
+    pub every: Option<Duration>,
+
+    /// Move files replaced by `--on-conflict overwrite` to the trash
+    #[clap(
+        long,
+        help = "Send files replaced by --on-conflict overwrite to the trash instead of deleting them"
+    )]
+    pub trash: bool,
+
+    /// Template used to rename media in the destination
+    #[clap(
+        long,
+        help = "Rename media using a template, e.g. \"{year}{month:02}{day:02}_{hhmmss}_{original}\""
+    )]
+    pub rename: Option<Template>,
+
+    /// Directory that media without a date is saved in
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Save media without a date in this directory [default: Unknown]"
+    )]
+    pub unknown_dir: Option<String>,
+
+    #[clap(flatten)]
+    pub common: Common,
 }
 
 impl Arguments {
-    pub fn validate(&self) -> Result<&Self, String> {
-        if !self.path.exists() {
-            return Err(format!("The path {:?} does not exist.", self.path));
+    pub fn patterns(&self) -> Vec<String> {
+        let defaults = match self.no_default_ext {
+            true => &[][..],
+            false => &PATTERNS[..],
+        };
+
+        defaults
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .chain(self.ext.iter().map(|ext| {
+                let ext = ext.trim().trim_start_matches('*').trim_start_matches('.');
+                format!("*.{ext}")
+            }))
+            .collect()
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter {
+            after: self.after,
+            before: self.before,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            keywords: self.filter_keyword.clone(),
+            min_rating: self.min_rating,
+            near: self.near,
+            radius_km: self.radius.unwrap_or(DEFAULT_RADIUS_KM),
+        }
+    }
+
+    /// The years dates may be in, outside which they're taken to be wrong
+    pub fn years(&self) -> RangeInclusive<i32> {
+        // Clocks running a little ahead, or in a zone ahead, can date media next year
+        let next_year = Local::now().year() + 1;
+        self.min_year.unwrap_or(OLDEST_YEAR)..=self.max_year.unwrap_or(next_year)
+    }
+
+    /// The date tags to try, where none at all means the defaults
+    pub fn date_tags(&self) -> &[DateTag] {
+        match self.date_tags.is_empty() {
+            true => &DEFAULT_DATE_TAGS,
+            false => &self.date_tags,
+        }
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            max_depth: match (self.no_max_depth, self.max_depth) {
+                (true, _) | (false, 0) => None,
+                (false, depth) => Some(depth),
+            },
+            follow_links: !self.no_follow_links,
+            excludes: self.exclude.clone(),
+        }
+    }
+
+    pub fn save_options(&self) -> SaveOptions {
+        SaveOptions {
+            transfer: self.transfer(),
+            on_conflict: self.on_conflict,
+            rename: self.rename.clone(),
+            month_style: self.month_style,
+            locale: self.locale,
+            hemisphere: self.hemisphere,
+            keep_structure: self.keep_structure,
+            preserve_xattrs: self.preserve_xattrs,
+            trash: self.trash,
+            interactive: self.interactive,
+            force: self.force,
+            threads: self.threads,
+            unknown_dir: self.unknown_dir.clone(),
+            strip: self.strip(),
+            embed_takeout: self.embed_takeout,
+            fix_extensions: self.fix_extensions,
+        }
+    }
+
+    pub fn strip(&self) -> Option<Strip> {
+        match (self.strip_metadata, self.strip_gps) {
+            (true, _) => Some(Strip::All),
+            (false, true) => Some(Strip::Gps),
+            (false, false) => None,
         }
-        if !self.path.is_dir() {
-            return Err(format!("{:?} is not a directory.", self.path));
+    }
+
+    pub fn layout(&self) -> Layout {
+        let mut keys = if let Some(layout) = &self.layout {
+            layout.keys.clone()
+        } else if let Some(format) = &self.format {
+            vec![Key::Format(format.clone())]
+        } else if self.seasons {
+            vec![Key::Year, Key::Season]
+        } else if self.decades {
+            vec![Key::Decade]
+        } else if self.weeks {
+            vec![Key::Year, Key::Week]
+        } else {
+            [
+                (self.years, Key::Year),
+                (self.months, Key::Month),
+                (self.days, Key::Day),
+                (self.quarters, Key::Quarter),
+            ]
+            .into_iter()
+            .filter_map(|(set, key)| set.then_some(key))
+            .collect()
+        };
+
+        if self.split_media {
+            keys.insert(0, Key::Type);
         }
-        if !self.years && !self.months {
-            return Err(String::from("Either the months or years flag must be set"));
+
+        Layout::new(keys)
+    }
+
+    pub fn transfer(&self) -> Transfer {
+        if self.move_media {
+            Transfer::Move
+        } else if self.link {
+            Transfer::Link
+        } else if self.symlink {
+            Transfer::Symlink
+        } else {
+            match self.reflink {
+                Reflink::Auto => Transfer::ReflinkOrCopy,
+                Reflink::Always => Transfer::Reflink,
+                Reflink::Never => Transfer::Copy,
+            }
         }
+    }
 
+    pub fn validate(&self) -> Result<&Self, String> {
+        self.common.validate()?;
+        if let Some(list) = &self.files_from {
+            if list != Path::new("-") && !list.is_file() {
+                return Err(format!("The file list {:?} does not exist.", list));
+            }
+        }
+        if let (Some(after), Some(before)) = (self.after, self.before) {
+            if after > before {
+                return Err(format!("The date {after} is after {before}."));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            if min > max {
+                return Err(String::from(
+                    "The minimum size is larger than the maximum size.",
+                ));
+            }
+        }
+        if self.years().is_empty() {
+            return Err(format!(
+                "No year is between --min-year and --max-year, {:?}.",
+                self.years()
+            ));
+        }
+        if self.no_default_ext && self.ext.is_empty() {
+            return Err(String::from(
+                "--no-default-ext needs at least one extension given with --ext",
+            ));
+        }
+        for path in &self.path {
+            if !path.exists() {
+                return Err(format!("The path {:?} does not exist.", path));
+            }
+            if !path.is_dir() {
+                return Err(format!("{:?} is not a directory.", path));
+            }
+        }
+        if self.layout.is_some()
+            && (self.years
+                || self.months
+                || self.days
+                || self.weeks
+                || self.quarters
+                || self.decades
+                || self.seasons
+                || self.format.is_some())
+        {
+            return Err(String::from(
+                "The layout option can't be combined with other sort flags",
+            ));
+        }
+        if let Some(format) = &self.format {
+            if self.years || self.months || self.days {
+                return Err(String::from(
+                    "The format option can't be combined with other sort flags",
+                ));
+            }
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(format!("{:?} is not a valid date format.", format));
+            }
+            if Path::new(format).has_root() || format.split('/').any(|part| part == "..") {
+                return Err(format!(
+                    "The format {:?} must stay inside the destination.",
+                    format
+                ));
+            }
+        }
+        if let Some(dir) = &self.unknown_dir {
+            if dir.is_empty() || dir == "." || dir == ".." || dir.contains(['/', '\\']) {
+                return Err(format!(
+                    "The unknown directory {:?} must be a single directory name.",
+                    dir
+                ));
+            }
+        }
+        if self.interactive && self.files_from.as_deref() == Some(Path::new("-")) {
+            return Err(String::from(
+                "The interactive option needs stdin, so files can't be listed on it.",
+            ));
+        }
+        if self.trash && self.on_conflict != Conflict::Overwrite && !self.interactive {
+            return Err(String::from(
+                "The trash option only applies with --on-conflict overwrite or --interactive.",
+            ));
+        }
+        let groupings = [
+            self.weeks,
+            self.quarters,
+            self.decades,
+            self.seasons,
+            self.format.is_some(),
+        ];
+        if !self.years && !self.months && !groupings.contains(&true) && self.layout.is_none() {
+            return Err(String::from(
+                "One of the months, years, weeks, quarters, decades or seasons flags, or a format or layout, must be set",
+            ));
+        }
+        if groupings.iter().filter(|set| **set).count() > 1 {
+            return Err(String::from(
+                "Only one of --weeks, --quarters, --decades, --seasons and --format may be set",
+            ));
+        }
+        if groupings.contains(&true) && (self.months || self.days) {
+            return Err(String::from(
+                "The months and days flags can't be combined with --weeks, --quarters, --decades or --seasons",
+            ));
+        }
+        if self.decades && self.years {
+            return Err(String::from(
+                "The decades flag can't be combined with the years flag",
+            ));
+        }
+        if self.days && !(self.years && self.months) {
+            return Err(String::from(
+                "The days flag can only be used with both the years and months flags",
+            ));
+        }
+        let transfers = [self.move_media, self.link, self.symlink];
+        if transfers.iter().filter(|set| **set).count() > 1 {
+            return Err(String::from(
+                "Only one of --move, --link and --symlink may be set",
+            ));
+        }
+        if self.reflink != Reflink::Never && transfers.contains(&true) {
+            return Err(String::from("--reflink can only be used when copying"));
+        }
+        if self.strip().is_some() && transfers.contains(&true) {
+            return Err(String::from(
+                "--strip-metadata and --strip-gps can only be used when copying",
+            ));
+        }
+        if self.embed_takeout && transfers.contains(&true) {
+            return Err(String::from(
+                "--embed-takeout can only be used when copying",
+            ));
+        }
+        if self.dest.exists() && !self.dest.is_dir() {
+            return Err(format!(
+                "The destination {:?} is not a directory.",
+                self.dest
+            ));
+        }
 
         Ok(self)
     }
 }
+
+/// Parses an interval such as `90`, `30s`, `15m`, `1h` or `1d`, in seconds when no unit is given
+pub fn parse_duration(interval: &str) -> Result<Duration, String> {
+    let interval = interval.trim();
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (number, unit) = interval.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid interval", interval))?;
+    let seconds = match unit.trim().to_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("{:?} has an unknown unit", interval)),
+    };
+
+    match number.checked_mul(seconds) {
+        Some(0) => Err(String::from("The interval must be longer than nothing")),
+        Some(seconds) => Ok(Duration::from_secs(seconds)),
+        None => Err(format!("{:?} is too long an interval", interval)),
+    }
+}
+
+/// Parses an offset such as `2h`, `-2d3h` or `+1h30m` into a signed span of time
+pub fn parse_offset(offset: &str) -> Result<TimeDelta, String> {
+    let invalid = || format!("{:?} is not a valid offset, e.g. -2d3h", offset);
+    let trimmed = offset.trim();
+    let (negative, mut rest) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+
+    let mut seconds: i64 = 0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let number: i64 = rest[..split].parse().map_err(|_| invalid())?;
+        let unit = match rest[split..].chars().next() {
+            Some('d') => 24 * 60 * 60,
+            Some('h') => 60 * 60,
+            Some('m') => 60,
+            Some('s') => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = number
+            .checked_mul(unit)
+            .and_then(|span| seconds.checked_add(span))
+            .ok_or_else(invalid)?;
+        rest = &rest[split + 1..];
+    }
+
+    match seconds {
+        0 => Err(String::from("The offset must shift dates by something")),
+        seconds if negative => Ok(TimeDelta::seconds(-seconds)),
+        seconds => Ok(TimeDelta::seconds(seconds)),
+    }
+}
+
+fn parse_locale(locale: &str) -> Result<Locale, String> {
+    // Accept the forms found in LANG, e.g. de-DE or de_DE.UTF-8
+    let name = locale
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .replace('-', "_");
+
+    Locale::try_from(name.as_str())
+        .map_err(|_| format!("Unknown locale {:?}, expected e.g. es_ES", locale))
+}
@@ -0,0 +1,175 @@
+use crate::journal::JOURNAL_DIR;
+use crate::metadata::read_metadata;
+use crate::scan::{build_glob_walker, ScanOptions, PATTERNS};
+use chrono::{Datelike, Month, NaiveDateTime};
+use log::debug;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// A piece of media in a folder for a different date than it was taken
+#[derive(Debug, Clone, PartialEq)]
+pub struct Misfiled {
+    pub path: PathBuf,
+    pub taken: NaiveDateTime,
+    /// Where it belongs, named the way the library names its folders
+    pub dest: PathBuf,
+}
+
+/// How a library names its month folders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MonthFolder {
+    /// 3 or 03
+    Number { width: usize },
+    /// 2024-03
+    YearNumber,
+    /// March, mar, ...
+    Name(NameCase),
+    /// 03-March
+    NumberName(NameCase),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NameCase {
+    short: bool,
+    lower: bool,
+}
+
+/// Finds the media in a library sorted into year and month folders that was taken in a
+/// different year or month than its folders say
+///
+/// Media without a date, or outside a year folder, can't be misfiled and is passed over.
+pub fn audit(library: &Path) -> Result<Vec<Misfiled>, Box<dyn Error>> {
+    let options = ScanOptions {
+        max_depth: None,
+        follow_links: false,
+        excludes: vec![format!("**/{JOURNAL_DIR}/**")],
+    };
+    let mut misfiled = Vec::new();
+
+    for entry in build_glob_walker(library, &PATTERNS, &options)?.filter_map(Result::ok) {
+        let path = entry.path();
+        let Some(taken) = read_metadata(path).datetime else {
+            debug!("Passing over {:?}, it has no date", path);
+            continue;
+        };
+        let Some(relative) = path
+            .strip_prefix(library)
+            .ok()
+            .and_then(|relative| refile(relative, taken))
+        else {
+            debug!("Passing over {:?}, it isn't in a year folder", path);
+            continue;
+        };
+
+        let dest = library.join(relative);
+        if dest != path {
+            misfiled.push(Misfiled {
+                path: path.to_path_buf(),
+                taken,
+                dest,
+            });
+        }
+    }
+
+    Ok(misfiled)
+}
+
+/// Rewrites the year folder of a path within a library, and the month folder inside it
+/// if there is one, for media taken at `taken`, keeping the library's folder names
+pub fn refile(relative: &Path, taken: NaiveDateTime) -> Option<PathBuf> {
+    let mut folders: Vec<String> = relative
+        .parent()?
+        .iter()
+        .map(|folder| folder.to_string_lossy().into_owned())
+        .collect();
+
+    let year = folders
+        .iter()
+        .position(|folder| parse_year(folder).is_some())?;
+    folders[year] = taken.year().to_string();
+
+    if let Some(folder) = folders.get_mut(year + 1) {
+        if let Some((_, style)) = parse_month(folder) {
+            *folder = style.render(taken.year(), taken.month());
+        }
+    }
+
+    let mut path: PathBuf = folders.into_iter().collect();
+    path.push(relative.file_name()?);
+    Some(path)
+}
+
+pub fn parse_year(folder: &str) -> Option<i32> {
+    let year = (folder.len() == 4).then(|| folder.parse().ok()).flatten()?;
+    (1800..=2200).contains(&year).then_some(year)
+}
+
+pub fn parse_month(folder: &str) -> Option<(u32, MonthFolder)> {
+    let number = |digits: &str| {
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())
+            .flatten()
+            .filter(|month| (1..=12).contains(month))
+    };
+
+    let (month, style) = match folder.split_once('-') {
+        Some((year, month)) if parse_year(year).is_some() => {
+            (number(month)?, MonthFolder::YearNumber)
+        }
+        Some((digits, name)) => {
+            let (month, case) = parse_name(name)?;
+            (
+                number(digits).filter(|n| *n == month)?,
+                MonthFolder::NumberName(case),
+            )
+        }
+        None if folder.len() <= 2 => (
+            number(folder)?,
+            MonthFolder::Number {
+                width: folder.len(),
+            },
+        ),
+        None => {
+            let (month, case) = parse_name(folder)?;
+            (month, MonthFolder::Name(case))
+        }
+    };
+
+    Some((month, style))
+}
+
+fn parse_name(name: &str) -> Option<(u32, NameCase)> {
+    // Months parse from their full English names or the first three letters
+    let month: Month = name.parse().ok()?;
+    let case = NameCase {
+        short: name.len() == 3,
+        lower: name.chars().all(|c| c.is_lowercase()),
+    };
+
+    Some((month.number_from_month(), case))
+}
+
+impl MonthFolder {
+    fn render(self, year: i32, month: u32) -> String {
+        let name = |case: NameCase| {
+            let name = Month::try_from(month as u8).map_or("", |month| month.name());
+            let name = match case.short {
+                true => &name[..3],
+                false => name,
+            };
+            match case.lower {
+                true => name.to_lowercase(),
+                false => name.to_string(),
+            }
+        };
+
+        match self {
+            MonthFolder::Number { width } => format!("{month:0width$}"),
+            MonthFolder::YearNumber => format!("{year}-{month:02}"),
+            MonthFolder::Name(case) => name(case),
+            MonthFolder::NumberName(case) => format!("{month:02}-{}", name(case)),
+        }
+    }
+}
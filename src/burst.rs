@@ -0,0 +1,69 @@
+use crate::image::MediaClass;
+use crate::tree::Tree;
+use chrono::{NaiveDateTime, TimeDelta};
+use log::debug;
+use std::path::PathBuf;
+
+/// The fewest shots that make a burst, rather than a few taken in quick succession
+pub const MIN_BURST: usize = 3;
+
+/// A run of shots by one camera, each taken within the gap of the last
+struct Burst {
+    camera: Option<String>,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    shots: usize,
+}
+
+/// Saves each burst, a run of shots by one camera each taken no more than `gap` after the
+/// last, in a `Burst_<timestamp>` folder of its own, returning how many were found
+///
+/// Only photos are counted, but the RAW shot with each frame and the video of a Live
+/// Photo go into the folder with it.
+pub fn group_bursts(tree: &mut Tree, gap: TimeDelta) -> usize {
+    let mut shots: Vec<(Option<&String>, NaiveDateTime)> = tree
+        .images()
+        .filter(|image| image.media != MediaClass::Video && image.nest.is_none())
+        .filter_map(|image| Some((image.camera.as_ref(), image.datetime?)))
+        .collect();
+    shots.sort();
+
+    let mut runs: Vec<Burst> = Vec::new();
+    for (camera, taken) in shots {
+        match runs.last_mut() {
+            Some(run) if run.camera.as_ref() == camera && taken - run.end <= gap => {
+                run.end = taken;
+                run.shots += 1;
+            }
+            _ => runs.push(Burst {
+                camera: camera.cloned(),
+                start: taken,
+                end: taken,
+                shots: 1,
+            }),
+        }
+    }
+    runs.retain(|run| run.shots >= MIN_BURST);
+    if runs.is_empty() {
+        return 0;
+    }
+
+    tree.update(|image| {
+        let Some(taken) = image.datetime else {
+            return;
+        };
+        let Some(burst) = runs
+            .iter()
+            .find(|run| run.camera == image.camera && (run.start..=run.end).contains(&taken))
+        else {
+            return;
+        };
+        let folder = PathBuf::from(burst.start.format("Burst_%Y%m%d_%H%M%S").to_string());
+        debug!("Saving {:?} in {:?}", image.path, folder);
+        image.nest = Some(match image.nest.take() {
+            Some(nest) => folder.join(nest),
+            None => folder,
+        });
+    });
+    runs.len()
+}
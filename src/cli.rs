@@ -0,0 +1,573 @@
+use crate::arguments::{parse_duration, parse_offset, Arguments, Common};
+use crate::dedupe::Action;
+use crate::filter::Filter;
+use crate::save::{Conflict, SaveOptions};
+use crate::transfer::Transfer;
+use chrono::{NaiveDate, TimeDelta};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[clap(
+    author = "Lucas Waddell",
+    version,
+    about = "A tool to sort images based on metadata or Google Takeout JSON files."
+)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Sort media from the sources into the destination
+    Sort(Arguments),
+    /// List the media in the sources, grouped as it would be sorted, without saving anything
+    // Nothing is saved, so there's no need for a destination
+    #[clap(mut_arg("dest", |arg| arg.required(false).default_value(".").hide(true)))]
+    Scan(Arguments),
+    /// Work out where every piece of media would be saved, and write it to a JSON plan
+    Plan(PlanArguments),
+    /// Save media as a plan says, after checking it hasn't changed since the plan was made
+    Apply(ApplyArguments),
+    /// Reverse a run using its journal, moving moved media back and removing copies
+    Undo(UndoArguments),
+    /// Check the media in a destination still matches what its journals recorded
+    Verify(VerifyArguments),
+    /// Find media in a year and month library that's in the wrong folder for its date
+    Audit(AuditArguments),
+    /// Count the media in a collection by year, month, camera and type
+    Stats(StatsArguments),
+    /// Find byte-identical media, and delete, hardlink or move the extra copies
+    Dedupe(DedupeArguments),
+    /// Compare two trees by content, listing the media only one of them has
+    Diff(DiffArguments),
+    /// Merge a sorted library into another, keeping its folders
+    Merge(MergeArguments),
+    /// Sort media into the destination as it arrives in a directory
+    // The directories to watch are given on their own rather than with --path
+    #[clap(mut_arg("path", |arg| {
+        arg.short(None)
+            .long(None)
+            .value_name("INCOMING")
+            .help("Directories to watch for new media, e.g. one a phone syncs into")
+    }))]
+    Watch(WatchArguments),
+    /// Write dates inferred from names, folders or sidecars into media's EXIF
+    FixDates(FixDatesArguments),
+    /// Move the EXIF dates of media by a fixed offset, e.g. for a camera's wrong clock
+    ShiftDates(ShiftDatesArguments),
+    /// Write GPS locations into media's EXIF from a GPX track or Google Location History
+    Geotag(GeotagArguments),
+    /// Check every piece of media can be read, listing any that's damaged or misnamed
+    Doctor(DoctorArguments),
+}
+
+#[derive(Args, Debug)]
+pub struct PlanArguments {
+    #[clap(flatten)]
+    pub args: Arguments,
+
+    /// File the plan is written to
+    #[clap(
+        value_name = "PLAN",
+        help = "File to write the plan to, or - for stdout (use with -q)"
+    )]
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ApplyArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Plan written by `img-sort plan`
+    #[clap(value_name = "PLAN", help = "Plan file written by img-sort plan")]
+    pub file: PathBuf,
+
+    /// Copy extended attributes, e.g. Finder tags, along with the media
+    #[clap(
+        long,
+        help = "Copy extended attributes, such as Finder tags and user.* attributes, with the media"
+    )]
+    pub preserve_xattrs: bool,
+
+    /// Save even when a destination seems to lack the space
+    #[clap(
+        long,
+        help = "Warn instead of stopping when the destination looks too full"
+    )]
+    pub force: bool,
+
+    /// How many files to save at once
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "Save this many files at once, or 0 for one per CPU"
+    )]
+    pub threads: usize,
+
+    /// File to append a line to for each operation
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Append each operation to this JSON lines journal [default: a new one in DEST/.img-sort]"
+    )]
+    pub journal: Option<PathBuf>,
+
+    /// Move files the plan overwrites to the trash
+    #[clap(
+        long,
+        help = "Send files the plan overwrites to the trash instead of deleting them"
+    )]
+    pub trash: bool,
+
+    /// Wait for another run using the destination to finish
+    #[clap(
+        long,
+        help = "Wait for another run saving to the destination to finish instead of exiting"
+    )]
+    pub wait: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct UndoArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Journal written by the run to undo
+    #[clap(
+        value_name = "JOURNAL",
+        help = "Journal of the run to undo, from the destination's .img-sort directory"
+    )]
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct VerifyArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Destination to check
+    #[clap(short = 'o', long, help = "Destination directory to check")]
+    pub dest: PathBuf,
+
+    /// Journal to check the destination against
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Journal to check against [default: every journal in DEST/.img-sort]"
+    )]
+    pub journal: Option<PathBuf>,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct AuditArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Library of year and month folders to check
+    #[clap(
+        value_name = "LIBRARY",
+        help = "Library sorted into year and month folders, e.g. 2024/03 or 2024/March"
+    )]
+    pub library: PathBuf,
+
+    /// Move misfiled media to the folders it belongs in
+    #[clap(
+        long,
+        help = "Move misfiled media to the right folders, keeping a journal to undo it with"
+    )]
+    pub fix: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct StatsArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories to count the media in
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories to count the media in, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Print the counts as JSON
+    #[clap(long, help = "Print the counts as JSON instead of tables")]
+    pub json: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct FixDatesArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories with media to fix the dates of
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories with media to fix, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Keep a copy of each file before changing it
+    #[clap(
+        long,
+        help = "Keep each original beside it, named like photo.jpg_original"
+    )]
+    pub backup: bool,
+
+    /// Only report the dates that would be written
+    #[clap(short = 'n', long, help = "Report the dates without writing them")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ShiftDatesArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories with media to shift the dates of
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories with media to shift, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// How far to move the dates
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        value_parser = parse_offset,
+        help = "How far to move the dates, in days, hours, minutes and seconds, e.g. -2d3h"
+    )]
+    pub offset: TimeDelta,
+
+    /// Only shift media taken on or after this date
+    #[clap(
+        long,
+        value_name = "DATE",
+        help = "Only shift media taken on or after this date, e.g. 2019-07-01"
+    )]
+    pub after: Option<NaiveDate>,
+
+    /// Only shift media taken on or before this date
+    #[clap(
+        long,
+        value_name = "DATE",
+        help = "Only shift media taken on or before this date, e.g. 2019-07-14"
+    )]
+    pub before: Option<NaiveDate>,
+
+    /// Only shift media from this camera
+    #[clap(
+        long,
+        help = "Only shift media from this camera, as its make and model, e.g. \"Canon EOS 5D\""
+    )]
+    pub camera: Option<String>,
+
+    /// Move the files' modification times too
+    #[clap(long, help = "Move the files' modification times by the offset too")]
+    pub mtime: bool,
+
+    /// Keep a copy of each file before changing it
+    #[clap(
+        long,
+        help = "Keep each original beside it, named like photo.jpg_original"
+    )]
+    pub backup: bool,
+
+    /// Only report the dates that would be written
+    #[clap(short = 'n', long, help = "Report the new dates without writing them")]
+    pub dry_run: bool,
+}
+
+impl ShiftDatesArguments {
+    pub fn filter(&self) -> Filter {
+        Filter {
+            after: self.after,
+            before: self.before,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct GeotagArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories with media to geotag
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories with media to geotag, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Where the media was taken, over time
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "GPX track, or Takeout Location History.json or Records.json, covering when the media was taken"
+    )]
+    pub track: PathBuf,
+
+    /// Correction for the camera's clock
+    #[clap(
+        long,
+        allow_hyphen_values = true,
+        value_parser = parse_offset,
+        help = "Added to the media's dates, taken as this machine's local time, to match the track, e.g. -9h"
+    )]
+    pub offset: Option<TimeDelta>,
+
+    /// How far from a track point media may be
+    #[clap(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_duration,
+        default_value = "5m",
+        help = "Leave media taken further than this from every track point, e.g. 90s or 1h"
+    )]
+    pub max_gap: Duration,
+
+    /// Replace locations media already has
+    #[clap(long, help = "Replace the locations of media that already has one")]
+    pub overwrite: bool,
+
+    /// Keep a copy of each file before changing it
+    #[clap(
+        long,
+        help = "Keep each original beside it, named like photo.jpg_original"
+    )]
+    pub backup: bool,
+
+    /// Only report the locations that would be written
+    #[clap(short = 'n', long, help = "Report the locations without writing them")]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct DoctorArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories with media to check
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories with media to check, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(Args, Debug, Default)]
+#[clap(group(ArgGroup::new("action").args(["delete", "hardlink", "move_to"])))]
+pub struct DedupeArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Directories to look for duplicates in
+    #[clap(
+        value_name = "PATH",
+        required = true,
+        help = "Directories to look for duplicate media in, searched without a depth limit"
+    )]
+    pub paths: Vec<PathBuf>,
+
+    /// Delete the duplicates
+    #[clap(
+        long,
+        help = "Delete all but the first copy, by path, of each duplicate"
+    )]
+    pub delete: bool,
+
+    /// Replace the duplicates with hard links
+    #[clap(
+        long,
+        help = "Replace all but the first copy of each duplicate with a hard link to it"
+    )]
+    pub hardlink: bool,
+
+    /// Move the duplicates into a directory
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Move all but the first copy of each duplicate into this directory"
+    )]
+    pub move_to: Option<PathBuf>,
+
+    /// Carry out the action instead of showing it
+    #[clap(
+        long,
+        requires = "action",
+        help = "Carry out --delete, --hardlink or --move-to, which otherwise only show what they'd do"
+    )]
+    pub execute: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct DiffArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// First tree to compare
+    #[clap(value_name = "A", help = "First tree to compare, e.g. the old library")]
+    pub a: PathBuf,
+
+    /// Second tree to compare
+    #[clap(
+        value_name = "B",
+        help = "Second tree to compare, e.g. the new sorted library"
+    )]
+    pub b: PathBuf,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct MergeArguments {
+    #[clap(flatten)]
+    pub common: Common,
+
+    /// Sorted library to merge in
+    #[clap(
+        value_name = "LIBRARY",
+        help = "Sorted library to merge into the destination"
+    )]
+    pub source: PathBuf,
+
+    /// Library to merge into
+    #[clap(
+        short = 'o',
+        long,
+        help = "Library to merge into, keeping each file's folders (created if missing)"
+    )]
+    pub dest: PathBuf,
+
+    /// What to do when a different file already exists in the destination
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = Conflict::Rename,
+        help = "What to do when a different file with the same name is already in the destination"
+    )]
+    pub on_conflict: Conflict,
+
+    /// Skip media whose contents are anywhere in the destination
+    #[clap(
+        long,
+        help = "Skip media already anywhere in the destination, not just in the same folder"
+    )]
+    pub dedupe: bool,
+
+    /// Move media instead of copying it
+    #[clap(
+        long = "move",
+        help = "Move media into the destination instead of copying it"
+    )]
+    pub move_media: bool,
+
+    /// Save even when a destination seems to lack the space
+    #[clap(
+        long,
+        help = "Warn instead of stopping when the destination looks too full"
+    )]
+    pub force: bool,
+
+    /// File to append a line to for each operation
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Append each operation to this JSON lines journal [default: a new one in DEST/.img-sort]"
+    )]
+    pub journal: Option<PathBuf>,
+
+    /// Wait for another run using the destination to finish
+    #[clap(
+        long,
+        help = "Wait for another run saving to the destination to finish instead of exiting"
+    )]
+    pub wait: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct WatchArguments {
+    #[clap(flatten)]
+    pub args: Arguments,
+
+    /// How long a file must go unchanged before it's sorted
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 2.0,
+        help = "Sort files once they've gone this many seconds without changing"
+    )]
+    pub settle: f64,
+}
+
+impl MergeArguments {
+    pub fn save_options(&self) -> SaveOptions {
+        SaveOptions {
+            transfer: match self.move_media {
+                true => Transfer::Move,
+                false => Transfer::Copy,
+            },
+            on_conflict: self.on_conflict,
+            force: self.force,
+            ..Default::default()
+        }
+    }
+}
+
+impl DedupeArguments {
+    pub fn action(&self) -> Option<Action> {
+        if self.delete {
+            Some(Action::Delete)
+        } else if self.hardlink {
+            Some(Action::Hardlink)
+        } else {
+            self.move_to.clone().map(Action::MoveTo)
+        }
+    }
+}
+
+impl ApplyArguments {
+    /// How the plan's media is saved, which the plan decides the transfer of
+    pub fn save_options(&self, transfer: Transfer) -> SaveOptions {
+        SaveOptions {
+            transfer,
+            preserve_xattrs: self.preserve_xattrs,
+            trash: self.trash,
+            force: self.force,
+            threads: self.threads,
+            ..Default::default()
+        }
+    }
+}
+
+impl Command {
+    /// The options shared by every subcommand, such as verbosity and colors
+    pub fn common(&self) -> &Common {
+        match self {
+            Command::Sort(args) | Command::Scan(args) => &args.common,
+            Command::Plan(plan) => &plan.args.common,
+            Command::Apply(apply) => &apply.common,
+            Command::Undo(undo) => &undo.common,
+            Command::Verify(verify) => &verify.common,
+            Command::Audit(audit) => &audit.common,
+            Command::Stats(stats) => &stats.common,
+            Command::Dedupe(dedupe) => &dedupe.common,
+            Command::Diff(diff) => &diff.common,
+            Command::Merge(merge) => &merge.common,
+            Command::Watch(watch) => &watch.args.common,
+            Command::FixDates(fix) => &fix.common,
+            Command::ShiftDates(shift) => &shift.common,
+            Command::Geotag(geotag) => &geotag.common,
+            Command::Doctor(doctor) => &doctor.common,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::cli::Cli;
+use crate::Status;
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgAction, Command, CommandFactory, FromArgMatches};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use toml::{Table, Value};
+
+/// Config file read from the working directory when `--config` isn't given
+pub const DEFAULT_CONFIG: &str = "img-sort.toml";
+
+/// Prefix of the environment variables that set options, e.g. `IMG_SORT_ON_CONFLICT`
+pub const ENV_PREFIX: &str = "IMG_SORT_";
+
+/// The command line interface, with every option also read from its environment variable
+///
+/// A variable holds one value, so `IMG_SORT_PATH` names a single source, and more are
+/// given with `--path` or the config.
+pub fn command() -> Command {
+    command_with_prefix(ENV_PREFIX)
+}
+
+/// The command line interface, reading options from variables starting with `prefix`
+pub fn command_with_prefix(prefix: &str) -> Command {
+    Cli::command().mut_subcommands(|command| {
+        command.mut_args(|arg| {
+            let env = format!("{prefix}{}", arg.get_id().as_str().to_uppercase());
+            let arg = arg.env(env);
+
+            // Let flags be turned on with 1, yes or on as well as true
+            match arg.get_action() {
+                ArgAction::SetTrue => arg.value_parser(BoolishValueParser::new()),
+                _ => arg,
+            }
+        })
+    })
+}
+
+/// Parses the command line, filling in options it leaves out from environment variables
+/// and then a config file
+///
+/// Keys are option names, e.g. `on-conflict = "skip"` or `years = true`, and
+/// `[profile.<name>]` tables override the top level when picked with `--profile`.
+/// Options only some subcommands have are left out of the others.
+pub fn parse_args(args: Vec<OsString>) -> Result<Cli, String> {
+    // Options that only come from the config would fail the first parse, so let them
+    let command = command();
+    let matches = command.clone().ignore_errors(true).get_matches_from(&args);
+    let Some((name, matches)) = matches.subcommand() else {
+        return Ok(parse(args));
+    };
+    let subcommand = command
+        .find_subcommand(name)
+        .expect("Matched subcommands exist");
+
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None if Path::new(DEFAULT_CONFIG).is_file() => PathBuf::from(DEFAULT_CONFIG),
+        None => return Ok(parse(args)),
+    };
+    let profile = matches.get_one::<String>("profile").map(String::as_str);
+    let options = read_config(&path, profile)?;
+
+    let mut args = args;
+    for (key, value) in options {
+        let id = key.replace('-', "_");
+        let find = |command: &Command| {
+            command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id.as_str())
+                .cloned()
+        };
+
+        let Some(arg) = find(subcommand) else {
+            if command.get_subcommands().any(|other| find(other).is_some()) {
+                continue;
+            }
+            return Err(format!("Unknown option {:?} in {:?}", key, path));
+        };
+
+        // The command line and environment win over the config
+        if matches!(
+            matches.value_source(&id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+
+        let flag = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{long}"),
+            (None, Some(short)) => format!("-{short}"),
+            (None, None) => return Err(format!("{:?} can't be set in {:?}", key, path)),
+        };
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+
+        for value in values {
+            match value {
+                Value::Boolean(true) => args.push(flag.clone().into()),
+                Value::Boolean(false) => {}
+                Value::String(value) => args.extend([flag.clone(), value].map(OsString::from)),
+                Value::Integer(value) => {
+                    args.extend([flag.clone(), value.to_string()].map(OsString::from))
+                }
+                value => {
+                    return Err(format!(
+                        "{:?} in {:?} has an unsupported value {}",
+                        key, path, value
+                    ))
+                }
+            }
+        }
+    }
+
+    Ok(parse(args))
+}
+
+fn parse(args: Vec<OsString>) -> Cli {
+    command()
+        .try_get_matches_from(args)
+        .and_then(|matches| Cli::from_arg_matches(&matches))
+        .unwrap_or_else(|e| exit(e))
+}
+
+/// Prints a parse error, or the help or version, and exits with a matching status
+fn exit(error: clap::Error) -> ! {
+    let _ = error.print();
+
+    // Help and version are printed to stdout, and aren't failures
+    let status = if error.use_stderr() {
+        Status::Usage
+    } else {
+        Status::Success
+    };
+    process::exit(status.code())
+}
+
+/// Reads the top level options of a config file, with a profile's options on top
+pub fn read_config(path: &Path, profile: Option<&str>) -> Result<Table, String> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| format!("Could not read {:?}: {}", path, e))?;
+    let mut options: Table = contents
+        .parse()
+        .map_err(|e| format!("Could not parse {:?}: {}", path, e))?;
+
+    let profiles = match options.remove("profile") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err(format!("\"profile\" in {:?} must be a table", path)),
+        None => Table::new(),
+    };
+
+    if let Some(name) = profile {
+        match profiles.get(name) {
+            Some(Value::Table(profile)) => options.extend(profile.clone()),
+            _ => return Err(format!("No profile {:?} in {:?}", name, path)),
+        }
+    }
+
+    Ok(options)
+}
@@ -0,0 +1,115 @@
+use crate::hash::hash_file;
+use crate::save::numbered;
+use crate::transfer::{partial_path, Transfer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What to do with the duplicates of the file kept from each group
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Delete,
+    /// Replace each duplicate with a hard link to the kept file
+    Hardlink,
+    /// Move each duplicate into a directory
+    MoveTo(PathBuf),
+}
+
+/// Files with identical contents, the first of which is the one to keep
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub files: Vec<PathBuf>,
+    /// Size of each of the files
+    pub bytes: u64,
+}
+
+impl Group {
+    pub fn keep(&self) -> &Path {
+        &self.files[0]
+    }
+
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.files[1..]
+    }
+}
+
+/// Groups the files with identical contents, hashing only files that share a size with
+/// another, in the order of their paths
+///
+/// Empty files are left out, as are extra links to a file already seen.
+pub fn find_duplicates(files: impl IntoIterator<Item = PathBuf>) -> io::Result<Vec<Group>> {
+    let mut by_size: BTreeMap<u64, Vec<PathBuf>> = BTreeMap::new();
+    let mut seen = HashSet::new();
+
+    for file in files {
+        let metadata = fs::metadata(&file)?;
+        if metadata.len() > 0 && seen.insert(file_id(&file, &metadata)) {
+            by_size.entry(metadata.len()).or_default().push(file);
+        }
+    }
+
+    let mut groups = Vec::new();
+    for (bytes, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        let mut by_hash: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for file in files {
+            by_hash.entry(hash_file(&file)?).or_default().push(file);
+        }
+
+        groups.extend(
+            by_hash
+                .into_values()
+                .filter(|files| files.len() > 1)
+                .map(|mut files| {
+                    files.sort();
+                    Group { files, bytes }
+                }),
+        );
+    }
+
+    groups.sort_by(|a, b| a.files.cmp(&b.files));
+    Ok(groups)
+}
+
+/// Identifies the file a path leads to, so hard links to it aren't counted twice
+#[cfg(unix)]
+fn file_id(_path: &Path, metadata: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(path: &Path, _metadata: &fs::Metadata) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Action {
+    /// Applies the action to one duplicate of `keep`, returning where it went, if anywhere
+    pub fn apply(&self, keep: &Path, duplicate: &Path) -> io::Result<Option<PathBuf>> {
+        match self {
+            Action::Delete => fs::remove_file(duplicate).map(|()| None),
+            Action::Hardlink => {
+                // Link beside the duplicate first, so it's only replaced once the link exists
+                let partial = partial_path(duplicate);
+                let _ = fs::remove_file(&partial);
+                fs::hard_link(keep, &partial)?;
+                fs::rename(&partial, duplicate).map(|()| None)
+            }
+            Action::MoveTo(dir) => {
+                fs::create_dir_all(dir)?;
+                let name = duplicate.file_name().unwrap_or_default();
+                let dest = numbered(&dir.join(name), |path| path.symlink_metadata().is_ok());
+                Transfer::Move.apply(duplicate, &dest).map(|()| Some(dest))
+            }
+        }
+    }
+
+    /// Describes the action, e.g. "Deleted"
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Delete => "Deleted",
+            Action::Hardlink => "Hardlinked",
+            Action::MoveTo(_) => "Moved",
+        }
+    }
+}
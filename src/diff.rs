@@ -0,0 +1,75 @@
+use crate::hash::hash_file;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Files grouped by the hash of their contents
+type ByHash = HashMap<String, Vec<PathBuf>>;
+
+/// The media two trees don't have in common, matched by contents rather than names
+#[derive(Debug, Default, PartialEq)]
+pub struct Difference {
+    /// Files in the first tree with contents found in the second
+    pub shared: usize,
+    pub only_a: Vec<PathBuf>,
+    pub only_b: Vec<PathBuf>,
+}
+
+/// Compares two lists of files by contents, hashing only the files of a size found in
+/// both lists
+pub fn diff(a: &[PathBuf], b: &[PathBuf]) -> io::Result<Difference> {
+    let (a, b) = (sizes(a)?, sizes(b)?);
+    let sizes_a: HashSet<u64> = a.iter().map(|(_, size)| *size).collect();
+    let sizes_b: HashSet<u64> = b.iter().map(|(_, size)| *size).collect();
+
+    let (hashes_a, mut only_a) = hashes(&a, &sizes_b)?;
+    let (hashes_b, mut only_b) = hashes(&b, &sizes_a)?;
+    let mut shared = 0;
+
+    for (hash, files) in &hashes_a {
+        match hashes_b.contains_key(hash) {
+            true => shared += files.len(),
+            false => only_a.extend(files.iter().cloned()),
+        }
+    }
+    for (hash, files) in hashes_b {
+        if !hashes_a.contains_key(&hash) {
+            only_b.extend(files);
+        }
+    }
+
+    only_a.sort();
+    only_b.sort();
+    Ok(Difference {
+        shared,
+        only_a,
+        only_b,
+    })
+}
+
+fn sizes(files: &[PathBuf]) -> io::Result<Vec<(&PathBuf, u64)>> {
+    files
+        .iter()
+        .map(|file| Ok((file, fs::metadata(file)?.len())))
+        .collect()
+}
+
+/// Hashes the files with a size in `other`, the rest of which can't have a match
+fn hashes(files: &[(&PathBuf, u64)], other: &HashSet<u64>) -> io::Result<(ByHash, Vec<PathBuf>)> {
+    let mut hashes = ByHash::new();
+    let mut unmatched = Vec::new();
+
+    for (file, size) in files {
+        if other.contains(size) {
+            hashes
+                .entry(hash_file(file)?)
+                .or_default()
+                .push(file.to_path_buf());
+        } else {
+            unmatched.push(file.to_path_buf());
+        }
+    }
+
+    Ok((hashes, unmatched))
+}
@@ -0,0 +1,72 @@
+use crate::metadata::read_metadata;
+use crate::sniff::{is_truncated, sniff_file, Format};
+use std::fmt;
+use std::path::Path;
+
+/// What's wrong with a piece of media, if anything
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnosis {
+    Ok,
+    /// It can be read, but has no date to sort it by
+    NoDate,
+    /// It couldn't be opened, or isn't any format img-sort knows
+    Unreadable(String),
+    /// It stops before its end, as an interrupted copy leaves it
+    Truncated,
+    /// Its contents are a different format than its extension says
+    WrongExtension(Format),
+}
+
+impl Diagnosis {
+    /// Whether the media can't be sorted as it is
+    pub fn is_problem(&self) -> bool {
+        !matches!(self, Diagnosis::Ok | Diagnosis::NoDate)
+    }
+
+    /// The class the diagnosis falls in, for counting
+    pub fn label(&self) -> &'static str {
+        match self {
+            Diagnosis::Ok => "OK",
+            Diagnosis::NoDate => "No date",
+            Diagnosis::Unreadable(_) => "Unreadable",
+            Diagnosis::Truncated => "Truncated",
+            Diagnosis::WrongExtension(_) => "Wrong extension",
+        }
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnosis::Ok => write!(f, "is fine"),
+            Diagnosis::NoDate => write!(f, "has no date in its metadata"),
+            Diagnosis::Unreadable(reason) => write!(f, "can't be read: {reason}"),
+            Diagnosis::Truncated => write!(f, "is cut short, e.g. by an interrupted copy"),
+            Diagnosis::WrongExtension(format) => write!(
+                f,
+                "is really a {:?} file, so should end in .{}",
+                format,
+                format.extensions()[0]
+            ),
+        }
+    }
+}
+
+/// Opens a piece of media and checks its contents, stopping at the first problem
+pub fn diagnose(path: &Path) -> Diagnosis {
+    let format = match sniff_file(path) {
+        Ok(Some(format)) => format,
+        Ok(None) => return Diagnosis::Unreadable(String::from("it isn't any known media format")),
+        Err(e) => return Diagnosis::Unreadable(e.to_string()),
+    };
+    if !format.matches_extension(path) {
+        return Diagnosis::WrongExtension(format);
+    }
+
+    match is_truncated(path, format) {
+        Ok(true) => Diagnosis::Truncated,
+        Err(e) => Diagnosis::Unreadable(e.to_string()),
+        Ok(false) if read_metadata(path).datetime.is_none() => Diagnosis::NoDate,
+        Ok(false) => Diagnosis::Ok,
+    }
+}
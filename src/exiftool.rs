@@ -0,0 +1,152 @@
+use crate::infer::DateSource;
+use crate::metadata::{name_camera, Coordinates, DateTag, Metadata, EXIF_DATETIME};
+use crate::zone::parse_utc_offset;
+use chrono::{FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Timelike};
+use log::{debug, trace};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The tags exiftool is asked for, beyond the dates
+const TAGS: [&str; 8] = [
+    "-Make",
+    "-Model",
+    "-GPSLatitude",
+    "-GPSLongitude",
+    "-OffsetTimeOriginal",
+    "-OffsetTimeDigitized",
+    "-OffsetTime",
+    "-Rating",
+];
+
+/// The names exiftool gives a date tag, and the tag of its UTC offset
+fn tag_names(tag: DateTag) -> (&'static str, Option<&'static str>) {
+    match tag {
+        DateTag::DateTimeOriginal => ("DateTimeOriginal", Some("OffsetTimeOriginal")),
+        // exiftool calls DateTimeDigitized by the name QuickTime gives it too
+        DateTag::DateTimeDigitized => ("CreateDate", Some("OffsetTimeDigitized")),
+        DateTag::DateTime => ("ModifyDate", Some("OffsetTime")),
+        DateTag::Gps => ("GPSDateTime", None),
+    }
+}
+
+/// Reads the metadata of files img-sort can't read itself with exiftool, dating them from
+/// the first of `date_tags` each has
+///
+/// All the files are read by one exiftool, which is given their paths on its input.
+pub fn read_metadata(
+    paths: &[&Path],
+    date_tags: &[DateTag],
+) -> io::Result<HashMap<PathBuf, Metadata>> {
+    let mut command = Command::new("exiftool");
+    command
+        // Numbers rather than text for the coordinates, and QuickTime dates in local time
+        .args([
+            "-json",
+            "-n",
+            "-api",
+            "QuickTimeUTC",
+            "-charset",
+            "filename=utf8",
+        ])
+        .args(
+            date_tags
+                .iter()
+                .map(|&tag| format!("-{}", tag_names(tag).0)),
+        )
+        .args(TAGS)
+        .args(["-@", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    debug!("Asking exiftool about {} files", paths.len());
+    let mut child = command.spawn()?;
+
+    let mut stdin = child.stdin.take().expect("exiftool's input is piped");
+    for path in paths {
+        writeln!(stdin, "{}", path.display())?;
+    }
+    drop(stdin);
+    let output = child.wait_with_output()?;
+
+    // exiftool fails when any file can't be read, but still reports the rest
+    if output.stdout.is_empty() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(error.trim().to_string()));
+    }
+    parse_output(&output.stdout, date_tags)
+}
+
+/// Reads the JSON exiftool writes, an object of tags for each file
+pub fn parse_output(json: &[u8], date_tags: &[DateTag]) -> io::Result<HashMap<PathBuf, Metadata>> {
+    let files: Vec<Map<String, Value>> = serde_json::from_slice(json)?;
+    Ok(files
+        .iter()
+        .filter_map(|tags| {
+            let path = PathBuf::from(tags.get("SourceFile")?.as_str()?);
+            let metadata = read_tags(tags, date_tags);
+            trace!("exiftool read {:?} as {:?}", path, metadata);
+            Some((path, metadata))
+        })
+        .collect())
+}
+
+fn read_tags(tags: &Map<String, Value>, date_tags: &[DateTag]) -> Metadata {
+    // Models like `5` are written as numbers
+    let text = |name: &str| match tags.get(name)? {
+        Value::String(text) if !text.trim().is_empty() => Some(text.trim().to_string()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    };
+    let dated = date_tags.iter().find_map(|&tag| {
+        let (name, offset_name) = tag_names(tag);
+        let (datetime, offset) = parse_datetime(&text(name)?)?;
+        let offset = offset.or_else(|| parse_utc_offset(&text(offset_name?)?));
+        Some((datetime, tag, offset))
+    });
+
+    let degrees = |name: &str| tags.get(name).and_then(Value::as_f64);
+
+    Metadata {
+        datetime: dated.map(|(datetime, ..)| datetime),
+        date_source: dated.map(|(_, tag, _)| DateSource::from(tag)),
+        offset: dated.and_then(|(.., offset)| offset),
+        format: None,
+        camera: name_camera(text("Make"), text("Model")),
+        coordinates: degrees("GPSLatitude").zip(degrees("GPSLongitude")).map(
+            |(latitude, longitude)| Coordinates {
+                latitude,
+                longitude,
+            },
+        ),
+        rating: tags
+            .get("Rating")
+            .and_then(Value::as_i64)
+            .and_then(|rating| i32::try_from(rating).ok())
+            .filter(|rating| (-1..=5).contains(rating)),
+        ..Default::default()
+    }
+}
+
+/// Parses a date as exiftool writes it, maybe with a fraction of a second and a UTC offset
+/// after it, moving times in UTC, like the GPS one, into this computer's zone
+fn parse_datetime(text: &str) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let mut datetime = NaiveDateTime::parse_from_str(text.get(..19)?, EXIF_DATETIME).ok()?;
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        let nanos = format!("{:0<9}", &fraction[..digits.min(9)]).parse().ok()?;
+        datetime = datetime.with_nanosecond(nanos)?;
+        rest = &fraction[digits..];
+    }
+    match rest {
+        "" => Some((datetime, None)),
+        "Z" => {
+            let local = Local.from_utc_datetime(&datetime);
+            Some((local.naive_local(), Some(local.offset().fix())))
+        }
+        offset => Some((datetime, Some(parse_utc_offset(offset)?))),
+    }
+}
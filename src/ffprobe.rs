@@ -0,0 +1,59 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset};
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// What ffprobe writes of a file's container and streams, with only their tags asked for
+#[derive(Debug, Default, Deserialize)]
+struct Probe {
+    #[serde(default)]
+    format: Section,
+    #[serde(default)]
+    streams: Vec<Section>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Section {
+    #[serde(default)]
+    tags: Tags,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Tags {
+    creation_time: Option<String>,
+}
+
+/// Reads when a video was recorded with ffprobe, for containers img-sort can't read itself,
+/// as local time and the offset it was in
+pub fn read_creation_time(path: &Path) -> io::Result<Option<(NaiveDateTime, FixedOffset)>> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-print_format", "json"])
+        .args([
+            "-show_entries",
+            "format_tags=creation_time:stream_tags=creation_time",
+        ])
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(error.trim().to_string()));
+    }
+    parse_output(&output.stdout)
+}
+
+/// Reads the creation time from ffprobe's JSON, the container's before any stream's, since
+/// streams can be dated when they were last edited
+pub fn parse_output(json: &[u8]) -> io::Result<Option<(NaiveDateTime, FixedOffset)>> {
+    let probe: Probe = serde_json::from_slice(json)?;
+    Ok(std::iter::once(&probe.format)
+        .chain(&probe.streams)
+        .filter_map(|section| section.tags.creation_time.as_deref())
+        // Cameras without a clock write the start of the epoch
+        .filter_map(|time| DateTime::parse_from_rfc3339(time).ok())
+        .find(|time| time.timestamp() > 0)
+        .map(|time| {
+            let local = time.with_timezone(&Local);
+            (local.naive_local(), local.offset().fix())
+        }))
+}
@@ -0,0 +1,91 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Names phones and apps give media, with when it was taken in them, as regexes with
+/// `year`, `month` and `day` groups and maybe `hour`, `minute`, `second` and `meridiem`
+///
+/// The names of particular apps come first, since the date alone can be found in most
+/// names, but not always the time after it.
+static LIBRARY: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        // Android cameras, e.g. `IMG_20230415_093000.jpg` or `PXL_20230415_093000123.jpg`
+        r"^(?:IMG|VID|PXL|PANO|MVIMG|BURST)_(?<year>\d{4})(?<month>\d{2})(?<day>\d{2})_(?<hour>\d{2})(?<minute>\d{2})(?<second>\d{2})",
+        // WhatsApp, e.g. `WhatsApp Image 2022-01-03 at 10.11.12 PM.jpeg`
+        r"^WhatsApp (?:Image|Video) (?<year>\d{4})-(?<month>\d{2})-(?<day>\d{2}) at (?<hour>\d{1,2})\.(?<minute>\d{2})\.(?<second>\d{2})(?:\s?(?<meridiem>[AP]M))?",
+        // macOS, e.g. `Screenshot 2024-05-01 at 9.30.00 AM.png`
+        r"^Screen ?[Ss]hot (?<year>\d{4})-(?<month>\d{2})-(?<day>\d{2}) at (?<hour>\d{1,2})\.(?<minute>\d{2})\.(?<second>\d{2})(?:\s?(?<meridiem>[AP]M))?",
+        // Android, e.g. `Screenshot_20240501-093000.png` or `Screenshot_2024-05-01-09-30-00`
+        r"^Screenshot_(?<year>\d{4})-?(?<month>\d{2})-?(?<day>\d{2})[-_](?<hour>\d{2})-?(?<minute>\d{2})-?(?<second>\d{2})",
+        // Signal, e.g. `signal-2021-03-04-093000.jpg` or `signal-2021-03-04-09-30-00-123.jpg`
+        r"^signal-(?<year>\d{4})-(?<month>\d{2})-(?<day>\d{2})-(?<hour>\d{2})-?(?<minute>\d{2})-?(?<second>\d{2})",
+        // Anything else with a date in it, e.g. `2019-07-04 10.11.12.png`
+        GENERIC,
+    ]
+    .into_iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect()
+});
+
+/// A date, maybe followed by a time, anywhere in some text
+const GENERIC: &str = r"(?:^|\D)(?<year>(?:19|20)\d{2})[-_.]?(?<month>0[1-9]|1[0-2])[-_.]?(?<day>0[1-9]|[12]\d|3[01])(?:[-_ T.]?(?<hour>[01]\d|2[0-3])[-_.:]?(?<minute>[0-5]\d)[-_.:]?(?<second>[0-5]\d))?(?:\D|$)";
+
+/// Finds when media was taken from its name, less its extension
+pub fn parse_name(name: &str) -> Option<NaiveDateTime> {
+    parse_name_with(name, &[])
+}
+
+/// Finds when media was taken from its name like `parse_name`, trying `custom` patterns
+/// before the library, since they're written for the names at hand
+pub fn parse_name_with(name: &str, custom: &[Regex]) -> Option<NaiveDateTime> {
+    custom
+        .iter()
+        .chain(LIBRARY.iter())
+        .find_map(|pattern| parse_captures(&pattern.captures(name)?))
+}
+
+/// Parses a pattern of the user's own, which needs groups for the year, month and day
+pub fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    for (long, short) in [("year", "y"), ("month", "m"), ("day", "d")] {
+        if !names.contains(&long) && !names.contains(&short) {
+            return Err(format!(
+                "{:?} has no {:?} or {:?} group to read the {} from",
+                pattern, long, short, long
+            ));
+        }
+    }
+    Ok(regex)
+}
+
+/// Finds a date, and maybe a time, anywhere in some text, like a folder's name
+pub fn parse_text(text: &str) -> Option<NaiveDateTime> {
+    LIBRARY
+        .last()
+        .and_then(|generic| parse_captures(&generic.captures(text)?))
+}
+
+/// Builds a date from the named groups of a match, where a missing time is midnight
+///
+/// Groups may also have the short names of `strftime`, `y`, `m`, `d`, `H`, `M`, `S` and `p`.
+fn parse_captures(captures: &Captures) -> Option<NaiveDateTime> {
+    let group = |long: &str, short: &str| captures.name(long).or_else(|| captures.name(short));
+    let number = |long: &str, short: &str| match group(long, short) {
+        Some(found) => found.as_str().parse::<u32>().ok(),
+        None => Some(0),
+    };
+
+    let date = NaiveDate::from_ymd_opt(
+        group("year", "y")?.as_str().parse().ok()?,
+        group("month", "m")?.as_str().parse().ok()?,
+        group("day", "d")?.as_str().parse().ok()?,
+    )?;
+    let meridiem = group("meridiem", "p").map(|meridiem| meridiem.as_str().to_uppercase());
+    let hour = match (number("hour", "H")?, meridiem.as_deref()) {
+        (12, Some("AM")) => 0,
+        (hour, Some("PM")) if hour < 12 => hour + 12,
+        (hour, _) => hour,
+    };
+    date.and_hms_opt(hour, number("minute", "M")?, number("second", "S")?)
+}
@@ -0,0 +1,151 @@
+use crate::image::Image;
+use crate::metadata::Coordinates;
+use chrono::NaiveDate;
+use std::fs;
+
+/// Criteria media must meet to be sorted
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// Keep media taken on or after this date
+    pub after: Option<NaiveDate>,
+    /// Keep media taken on or before this date
+    pub before: Option<NaiveDate>,
+    /// Keep media of at least this many bytes
+    pub min_size: Option<u64>,
+    /// Keep media of at most this many bytes
+    pub max_size: Option<u64>,
+    /// Keep media tagged with any of these keywords, ignoring case
+    pub keywords: Vec<String>,
+    /// Keep media rated with at least this many stars
+    pub min_rating: Option<i32>,
+    /// Keep media taken near this place
+    pub near: Option<Coordinates>,
+    /// How many kilometers from `near` media may have been taken
+    pub radius_km: f64,
+}
+
+/// How far from `--near` media may have been taken when no radius is given
+pub const DEFAULT_RADIUS_KM: f64 = 10.0;
+
+impl Filter {
+    pub fn matches(&self, image: &Image) -> bool {
+        self.matches_date(image)
+            && self.matches_size(image)
+            && self.matches_keywords(image)
+            && self.matches_rating(image)
+            && self.matches_place(image)
+    }
+
+    fn matches_place(&self, image: &Image) -> bool {
+        // Media without GPS can't be placed near anywhere
+        self.near.is_none_or(|near| {
+            image
+                .coordinates
+                .is_some_and(|coordinates| coordinates.distance_km(near) <= self.radius_km)
+        })
+    }
+
+    fn matches_rating(&self, image: &Image) -> bool {
+        // Media that was never rated has no stars
+        self.min_rating
+            .is_none_or(|min| image.rating.is_some_and(|rating| rating >= min))
+    }
+
+    fn matches_keywords(&self, image: &Image) -> bool {
+        self.keywords.is_empty()
+            || image.keywords.iter().any(|keyword| {
+                self.keywords
+                    .iter()
+                    .any(|wanted| wanted.to_lowercase() == keyword.to_lowercase())
+            })
+    }
+
+    fn matches_size(&self, image: &Image) -> bool {
+        if self.min_size.is_none() && self.max_size.is_none() {
+            return true;
+        }
+
+        let Ok(size) = fs::metadata(&image.path).map(|metadata| metadata.len()) else {
+            return false;
+        };
+
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+
+    fn matches_date(&self, image: &Image) -> bool {
+        if self.after.is_none() && self.before.is_none() {
+            return true;
+        }
+
+        // Media without a date can't be placed inside a range
+        let Some(date) = image.datetime.map(|dt| dt.date()) else {
+            return false;
+        };
+
+        self.after.is_none_or(|after| date >= after)
+            && self.before.is_none_or(|before| date <= before)
+    }
+}
+
+/// Parses a size such as `500`, `10K`, `2MB` or `1.5GiB` into bytes, using 1024-byte units
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid size", size))?;
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("{:?} has an unknown unit", size)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses a place such as `48.8584,2.2945`, as latitude and longitude in decimal degrees
+pub fn parse_coordinates(place: &str) -> Result<Coordinates, String> {
+    let invalid = || format!("{:?} is not a valid place, e.g. 48.8584,2.2945", place);
+    let (latitude, longitude) = place.split_once(',').ok_or_else(invalid)?;
+    let latitude: f64 = latitude.trim().parse().map_err(|_| invalid())?;
+    let longitude: f64 = longitude.trim().parse().map_err(|_| invalid())?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("{:?} is not a place on Earth", place));
+    }
+    Ok(Coordinates {
+        latitude,
+        longitude,
+    })
+}
+
+/// Parses a distance such as `500m`, `50km` or `3mi` into kilometers, which is the unit
+/// when none is given
+pub fn parse_distance(distance: &str) -> Result<f64, String> {
+    let distance = distance.trim();
+    let split = distance
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(distance.len());
+    let (number, unit) = distance.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid distance", distance))?;
+    let kilometers = match unit.trim().to_lowercase().as_str() {
+        "" | "km" => number,
+        "m" => number / 1000.0,
+        "mi" => number * 1.609344,
+        _ => return Err(format!("{:?} has an unknown unit", distance)),
+    };
+
+    match kilometers {
+        0.0 => Err(String::from("The radius must be longer than nothing")),
+        kilometers => Ok(kilometers),
+    }
+}
@@ -0,0 +1,144 @@
+use crate::metadata::Coordinates;
+use chrono::{DateTime, TimeDelta, Utc};
+use roxmltree::Node;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Where a track says someone was at a moment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub time: DateTime<Utc>,
+    pub coordinates: Coordinates,
+    /// Meters above sea level
+    pub altitude: Option<f64>,
+}
+
+/// Points from a GPX file or Google Location History, in time order
+#[derive(Debug, Default, PartialEq)]
+pub struct Track {
+    points: Vec<Point>,
+}
+
+impl Track {
+    pub fn new(mut points: Vec<Point>) -> Self {
+        points.sort_by_key(|point| point.time);
+        Track { points }
+    }
+
+    /// Reads a track from a GPX file, or a Takeout `Location History.json` or
+    /// `Records.json`, going by its extension
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let extension = path.extension().unwrap_or_default().to_ascii_lowercase();
+        let points = match extension.to_str() {
+            Some("gpx") => parse_gpx(&text)?,
+            Some("json") => parse_location_history(&text)?,
+            _ => return Err(format!("{:?} isn't a GPX or Location History file", path).into()),
+        };
+        Ok(Track::new(points))
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Works out where the track was at `time`, between the points either side of it
+    ///
+    /// Times further than `max_gap` from every point are too far from the track to place.
+    pub fn locate(&self, time: DateTime<Utc>, max_gap: TimeDelta) -> Option<Point> {
+        let after = self.points.partition_point(|point| point.time < time);
+        let before = after.checked_sub(1).and_then(|i| self.points.get(i));
+        let after = self.points.get(after);
+        let near = |point: &&Point| (point.time - time).abs() <= max_gap;
+
+        match (before.filter(near), after.filter(near)) {
+            (Some(before), Some(after)) if after.time > before.time => {
+                let span = (after.time - before.time).num_milliseconds() as f64;
+                let fraction = (time - before.time).num_milliseconds() as f64 / span;
+                let between = |a: f64, b: f64| a + (b - a) * fraction;
+
+                Some(Point {
+                    time,
+                    coordinates: Coordinates {
+                        latitude: between(before.coordinates.latitude, after.coordinates.latitude),
+                        longitude: between(
+                            before.coordinates.longitude,
+                            after.coordinates.longitude,
+                        ),
+                    },
+                    altitude: before
+                        .altitude
+                        .zip(after.altitude)
+                        .map(|(a, b)| between(a, b)),
+                })
+            }
+            (Some(point), _) | (None, Some(point)) => Some(*point),
+            (None, None) => None,
+        }
+    }
+}
+
+fn parse_gpx(text: &str) -> Result<Vec<Point>, Box<dyn Error>> {
+    let document = roxmltree::Document::parse(text)?;
+
+    // Waypoints and route points rarely have times, so only track points are read
+    let points = document
+        .descendants()
+        .filter(|node| node.has_tag_name("trkpt"))
+        .filter_map(|node| {
+            Some(Point {
+                time: child(node, "time")?.parse().ok()?,
+                coordinates: Coordinates {
+                    latitude: node.attribute("lat")?.parse().ok()?,
+                    longitude: node.attribute("lon")?.parse().ok()?,
+                },
+                altitude: child(node, "ele").and_then(|ele| ele.trim().parse().ok()),
+            })
+        })
+        .collect();
+
+    Ok(points)
+}
+
+fn child<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+}
+
+fn parse_location_history(text: &str) -> Result<Vec<Point>, Box<dyn Error>> {
+    let json: serde_json::Value = serde_json::from_str(text)?;
+    let locations = json["locations"]
+        .as_array()
+        .ok_or("The Location History has no locations")?;
+
+    let points = locations
+        .iter()
+        .filter_map(|location| {
+            // Older exports give milliseconds since the epoch, newer ones a timestamp
+            let time = match location["timestamp"].as_str() {
+                Some(timestamp) => timestamp.parse().ok()?,
+                None => {
+                    let millis = location["timestampMs"].as_str()?.parse().ok()?;
+                    DateTime::from_timestamp_millis(millis)?
+                }
+            };
+
+            Some(Point {
+                time,
+                coordinates: Coordinates {
+                    latitude: location["latitudeE7"].as_f64()? / 1e7,
+                    longitude: location["longitudeE7"].as_f64()? / 1e7,
+                },
+                altitude: location["altitude"].as_f64(),
+            })
+        })
+        .collect();
+
+    Ok(points)
+}
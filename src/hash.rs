@@ -0,0 +1,10 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Hashes a file's contents with BLAKE3, as lowercase hex
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(File::open(path)?)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
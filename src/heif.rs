@@ -0,0 +1,182 @@
+use crate::quicktime::{boxes, find_box, read_top_level_box};
+use exif::{Exif, Reader};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The largest `Exif` item worth reading, well past what any camera writes
+const MAX_EXIF_LEN: u64 = 4 << 20;
+
+/// Reads the EXIF of a HEIC, HEIF or AVIF from its `Exif` item
+///
+/// With the `heif` feature, files this can't parse are given to libheif as well.
+pub fn read_exif(path: &Path) -> Result<Exif, exif::Error> {
+    let exif = read_exif_item(path);
+
+    #[cfg(feature = "heif")]
+    if let Err(e) = &exif {
+        log::trace!("Trying libheif on {:?}, after {}", path, e);
+        if let Some(tiff) = libheif::read_exif(path) {
+            return Reader::new().read_raw(tiff);
+        }
+    }
+    exif
+}
+
+/// Finds the `Exif` item, which is listed in `meta/iinf` and located through
+/// `meta/iloc`, either elsewhere in the file or in `meta/idat`
+///
+/// Unlike the EXIF reader's own, this doesn't need `mif1` among the compatible brands,
+/// which some AVIF encoders leave out.
+fn read_exif_item(path: &Path) -> Result<Exif, exif::Error> {
+    let not_found = || exif::Error::NotFound("HEIF");
+
+    // Both `meta` and the boxes in it start with a version and flags
+    let meta = read_top_level_box(path, b"meta")?.ok_or_else(not_found)?;
+    let meta = meta.get(4..).ok_or_else(not_found)?;
+    let item = find_box(meta, b"iinf")
+        .and_then(exif_item)
+        .ok_or_else(not_found)?;
+    let location = find_box(meta, b"iloc")
+        .and_then(|iloc| locate(iloc, item))
+        .ok_or_else(not_found)?;
+
+    let data = match location {
+        Location::File { offset, len } => {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::new();
+            file.take(len.min(MAX_EXIF_LEN)).read_to_end(&mut data)?;
+            data
+        }
+        Location::Idat { offset, len } => find_box(meta, b"idat")
+            .and_then(|idat| idat.get(offset as usize..offset.checked_add(len)? as usize))
+            .ok_or_else(not_found)?
+            .to_vec(),
+    };
+
+    // The item starts with where the TIFF header is, after the offset itself
+    let skip = u32::from_be_bytes(data.get(..4).ok_or_else(not_found)?.try_into().unwrap());
+    let tiff = data
+        .get(4 + skip as usize..)
+        .ok_or(exif::Error::InvalidFormat("Broken HEIF Exif item"))?;
+    Reader::new().read_raw(tiff.to_vec())
+}
+
+/// Where an item's data is
+enum Location {
+    /// From the start of the file
+    File { offset: u64, len: u64 },
+    /// From the start of `meta/idat`
+    Idat { offset: u64, len: u64 },
+}
+
+/// Reads big-endian numbers of the sizes HEIF boxes use from the start of some bytes
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn uint(&mut self, size: usize) -> Option<u64> {
+        let (bytes, rest) = (self.0.get(..size)?, self.0.get(size..)?);
+        self.0 = rest;
+        Some(bytes.iter().fold(0, |n, &byte| n << 8 | byte as u64))
+    }
+}
+
+/// Finds the ID of the `Exif` item among the `infe` entries of `iinf`
+fn exif_item(iinf: &[u8]) -> Option<u64> {
+    let entries = match iinf.first()? {
+        0 => iinf.get(6..)?,
+        _ => iinf.get(8..)?,
+    };
+
+    boxes(entries)
+        .filter(|(kind, _)| kind == b"infe")
+        .find_map(|(_, infe)| {
+            let mut fields = Fields(infe);
+            let version = fields.uint(1)?;
+            fields.uint(3)?;
+            // Older entries have no item type, so can't be the EXIF
+            let id = match version {
+                2 => fields.uint(2)?,
+                3 => fields.uint(4)?,
+                _ => return None,
+            };
+            fields.uint(2)?;
+            (fields.0.get(..4)? == b"Exif").then_some(id)
+        })
+}
+
+/// Finds where an item's data is from its entry in `iloc`, if it's in one piece
+fn locate(iloc: &[u8], item: u64) -> Option<Location> {
+    let mut fields = Fields(iloc);
+    let version = fields.uint(1)?;
+    fields.uint(3)?;
+    let sizes = fields.uint(1)?;
+    let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0xF) as usize);
+    let sizes = fields.uint(1)?;
+    let base_offset_size = (sizes >> 4) as usize;
+    let index_size = match version {
+        1 | 2 => (sizes & 0xF) as usize,
+        _ => 0,
+    };
+    let id_size = if version < 2 { 2 } else { 4 };
+    let count = fields.uint(id_size)?;
+
+    for _ in 0..count {
+        let id = fields.uint(id_size)?;
+        let construction = match version {
+            1 | 2 => fields.uint(2)? & 0xF,
+            _ => 0,
+        };
+        fields.uint(2)?;
+        let base = fields.uint(base_offset_size)?;
+        let extents = fields.uint(2)?;
+
+        let mut first = None;
+        for _ in 0..extents {
+            fields.uint(index_size)?;
+            let offset = fields.uint(offset_size)?;
+            let len = fields.uint(length_size)?;
+            first.get_or_insert((offset, len));
+        }
+
+        if id == item && extents == 1 {
+            let (offset, len) = first?;
+            let offset = base.checked_add(offset)?;
+            return match construction {
+                0 => Some(Location::File { offset, len }),
+                1 => Some(Location::Idat { offset, len }),
+                _ => None,
+            };
+        }
+    }
+
+    None
+}
+
+#[cfg(feature = "heif")]
+mod libheif {
+    use libheif_rs::{HeifContext, LibHeif};
+    use std::path::Path;
+    use std::sync::LazyLock;
+
+    /// libheif is set up once, and stays set up for the rest of the run
+    static LIBHEIF: LazyLock<LibHeif> = LazyLock::new(LibHeif::new);
+
+    /// Reads the TIFF of the primary image's `Exif` item with libheif
+    pub fn read_exif(path: &Path) -> Option<Vec<u8>> {
+        LazyLock::force(&LIBHEIF);
+        let context = HeifContext::read_from_file(path.to_str()?).ok()?;
+        let data = context
+            .primary_image_handle()
+            .ok()?
+            .all_metadata()
+            .into_iter()
+            .find(|item| item.item_type.0 == *b"Exif")?
+            .raw_data;
+
+        // Like the item itself, the data starts with where the TIFF header is
+        let skip = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        data.get(4 + skip..).map(<[u8]>::to_vec)
+    }
+}
@@ -0,0 +1,212 @@
+use crate::infer::DateSource;
+use crate::takeout::Album;
+use crate::tree::Tree;
+use chrono::{Local, NaiveDateTime};
+use log::debug;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The CSVs an iCloud Photos export describes the photos of a folder with, which are
+/// numbered when there are several, e.g. `Photo Details-1.csv`
+const DETAILS: &str = "Photo Details";
+
+/// The folder beside the photos holding a CSV of each album's photos
+const ALBUMS: &str = "Albums";
+
+/// What favorites are linked into, alongside the albums
+pub const FAVORITES: &str = "Favorites";
+
+/// What an iCloud Photos export says about a photo
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Details {
+    /// In local time, like EXIF dates
+    pub taken: Option<NaiveDateTime>,
+    pub favorite: bool,
+}
+
+/// Reads the details of the photos in a folder of an iCloud export, by lowercase name
+pub fn read_details(dir: &Path) -> HashMap<String, Details> {
+    let mut details = HashMap::new();
+    for csv in list_csvs(dir) {
+        let is_details = csv
+            .file_stem()
+            .is_some_and(|stem| stem.to_string_lossy().starts_with(DETAILS));
+        let Some(text) = is_details.then(|| fs::read_to_string(&csv).ok()).flatten() else {
+            continue;
+        };
+        let rows = parse_csv(&text);
+        let Some((header, rows)) = rows.split_first() else {
+            continue;
+        };
+        let column = |name: &str| header.iter().position(|column| column == name);
+        let (Some(name), taken, favorite) = (
+            column("imgName"),
+            column("originalCreationDate"),
+            column("favorite"),
+        ) else {
+            debug!("{:?} has no imgName column", csv);
+            continue;
+        };
+
+        for row in rows {
+            let Some(img_name) = row.get(name) else {
+                continue;
+            };
+            let cell = |column: Option<usize>| column.and_then(|column| row.get(column));
+            details.insert(
+                img_name.to_lowercase(),
+                Details {
+                    taken: cell(taken).and_then(|taken| parse_date(taken)),
+                    favorite: cell(favorite).is_some_and(|favorite| favorite == "yes"),
+                },
+            );
+        }
+    }
+    details
+}
+
+/// Dates media without a date from the details of the export it's in
+pub fn date_from_details(tree: &mut Tree) {
+    let mut exports: HashMap<PathBuf, HashMap<String, Details>> = HashMap::new();
+    tree.update(|image| {
+        if image.datetime.is_some() {
+            return;
+        }
+        let dir = image.path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let details = exports
+            .entry(dir)
+            .or_insert_with_key(|dir| read_details(dir));
+        let taken = details
+            .get(&image.name.to_lowercase())
+            .and_then(|details| details.taken);
+        if let Some(taken) = taken {
+            debug!("Dated {:?} from its iCloud details", image.path);
+            image.datetime = Some(taken);
+            image.date_source = Some(DateSource::Sidecar);
+            image.offset = None;
+        }
+    });
+}
+
+/// Finds the albums and favorites of the iCloud exports media is sorted from
+///
+/// Each album is a CSV of names in the `Albums` folder beside the photos, and may name
+/// photos in any part of an export that's split in several.
+pub fn icloud_albums(tree: &Tree) -> Vec<Album> {
+    let mut by_name: HashMap<String, PathBuf> = HashMap::new();
+    let mut dirs: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for image in tree.images() {
+        by_name
+            .entry(image.name.to_lowercase())
+            .or_insert_with(|| image.path.clone());
+        let dir = image.path.parent().unwrap_or(Path::new("."));
+        dirs.entry(dir).or_default().push(&image.path);
+    }
+
+    let mut albums: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for (dir, paths) in dirs {
+        let details = read_details(dir);
+        if details.is_empty() {
+            continue;
+        }
+        for path in paths {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if details
+                .get(&name.to_lowercase())
+                .is_some_and(|d| d.favorite)
+            {
+                albums
+                    .entry(FAVORITES.to_string())
+                    .or_default()
+                    .push(path.to_path_buf());
+            }
+        }
+
+        let Some(export) = dir.parent() else {
+            continue;
+        };
+        for csv in list_csvs(&export.join(ALBUMS)) {
+            let (Some(title), Ok(text)) = (csv.file_stem(), fs::read_to_string(&csv)) else {
+                continue;
+            };
+            // The title becomes a directory name
+            let title = title.to_string_lossy().replace(['/', '\\'], "-");
+            let media = albums.entry(title).or_default();
+            for row in parse_csv(&text).iter().skip(1) {
+                let Some(path) = row
+                    .first()
+                    .and_then(|name| by_name.get(&name.to_lowercase()))
+                else {
+                    continue;
+                };
+                if !media.contains(path) {
+                    media.push(path.clone());
+                }
+            }
+        }
+    }
+
+    albums
+        .into_iter()
+        .filter(|(_, media)| !media.is_empty())
+        .map(|(title, media)| Album { title, media })
+        .collect()
+}
+
+fn list_csvs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut csvs: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+        })
+        .collect();
+    csvs.sort();
+    csvs
+}
+
+/// Parses the dates iCloud writes, like `Thursday July 4,2019 8:11 AM GMT`, which are in
+/// UTC, as local time
+pub fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    // The weekday is left out, since chrono checks it against the date
+    let (_, text) = text.trim().split_once(' ')?;
+    let text = text.trim_end_matches(" GMT").trim_end_matches(" UTC");
+    let utc = NaiveDateTime::parse_from_str(text, "%B %d,%Y %I:%M %p").ok()?;
+    Some(utc.and_utc().with_timezone(&Local).naive_local())
+}
+
+/// Splits CSV into rows of cells, unquoting those in double quotes
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut cell)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|cell| !cell.trim().is_empty()));
+    rows
+}
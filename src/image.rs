@@ -1,13 +1,165 @@
-use std::path::PathBuf;
+use crate::infer::DateSource;
+use crate::metadata::Coordinates;
+use crate::sniff::Format;
+use chrono::{FixedOffset, NaiveDateTime};
+use std::path::{Path, PathBuf};
+
+const VIDEO_EXTENSIONS: [&str; 9] = [
+    "mov", "mp4", "m4v", "avi", "mkv", "3gp", "mts", "m2ts", "wmv",
+];
+const RAW_EXTENSIONS: [&str; 10] = [
+    "cr2", "cr3", "nef", "arw", "orf", "rw2", "dng", "raf", "pef", "srw",
+];
+
+/// The broad kind of media a file holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaClass {
+    Photo,
+    Video,
+    Raw,
+}
+
+impl MediaClass {
+    /// The kind of media a file holds, going by its format when its extension is wrong
+    pub fn from_format(path: &Path, format: Option<Format>) -> Self {
+        match format {
+            Some(format) if !format.matches_extension(path) => match format {
+                Format::Mov | Format::Mp4 | Format::Avi | Format::Matroska => MediaClass::Video,
+                Format::Orf | Format::Rw2 | Format::Raf | Format::Cr3 => MediaClass::Raw,
+                _ => MediaClass::Photo,
+            },
+            _ => MediaClass::from_path(path),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if VIDEO_EXTENSIONS.contains(&extension.as_str()) {
+            MediaClass::Video
+        } else if RAW_EXTENSIONS.contains(&extension.as_str()) {
+            MediaClass::Raw
+        } else {
+            MediaClass::Photo
+        }
+    }
+
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            MediaClass::Photo => "Photos",
+            MediaClass::Video => "Videos",
+            MediaClass::Raw => "RAW",
+        }
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub struct Image {
     pub name: String,
     pub path: PathBuf,
+    pub datetime: Option<NaiveDateTime>,
+    /// Where the date was read or inferred from
+    pub date_source: Option<DateSource>,
+    /// The UTC offset the date is in, when it's known
+    pub offset: Option<FixedOffset>,
+    pub media: MediaClass,
+    pub camera: Option<String>,
+    /// Where it was taken, from its GPS
+    pub coordinates: Option<Coordinates>,
+    /// Directory the image was found in, relative to its source
+    pub subdir: PathBuf,
+    /// The format its contents are in, if it's one img-sort knows
+    pub format: Option<Format>,
+    /// Files apps keep beside the media, which are saved along with it
+    pub sidecars: Vec<PathBuf>,
+    /// Folder inside its bucket the media is saved in, like `RAW` for the RAW of a pair
+    pub nest: Option<PathBuf>,
+    /// For the video of a Live Photo, the photo it's saved beside and named after
+    pub live_photo: Option<PathBuf>,
+    /// Keywords it's tagged with, only read when they're needed
+    pub keywords: Vec<String>,
+    /// Stars from 0 to 5, or -1 for a rejected photo
+    pub rating: Option<i32>,
+    pub screenshot: bool,
+    /// Whether it's a Motion Photo, with a video inside it, only looked for when the
+    /// videos are saved apart
+    pub motion_photo: bool,
+    /// Folder in the destination the media is sorted under, like `Screenshots`
+    pub branch: Option<PathBuf>,
 }
 
 impl Image {
     pub fn new(path: PathBuf, name: String) -> Self {
-        Image { path, name }
+        Image {
+            media: MediaClass::from_path(&path),
+            path,
+            name,
+            datetime: None,
+            date_source: None,
+            offset: None,
+            camera: None,
+            coordinates: None,
+            subdir: PathBuf::new(),
+            format: None,
+            sidecars: Vec::new(),
+            nest: None,
+            live_photo: None,
+            keywords: Vec::new(),
+            rating: None,
+            screenshot: false,
+            motion_photo: false,
+            branch: None,
+        }
+    }
+
+    pub fn with_datetime(mut self, datetime: Option<NaiveDateTime>) -> Self {
+        self.datetime = datetime;
+        self
+    }
+
+    pub fn with_date_source(mut self, date_source: Option<DateSource>) -> Self {
+        self.date_source = date_source;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Option<FixedOffset>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_rating(mut self, rating: Option<i32>) -> Self {
+        self.rating = rating;
+        self
+    }
+
+    pub fn with_screenshot(mut self, screenshot: bool) -> Self {
+        self.screenshot = screenshot;
+        self
+    }
+
+    pub fn with_camera(mut self, camera: Option<String>) -> Self {
+        self.camera = camera;
+        self
+    }
+
+    /// Sets where it was taken, as its GPS says
+    pub fn with_coordinates(mut self, coordinates: Option<Coordinates>) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    /// Sets the format found in the file, which decides its media class over the extension
+    pub fn with_format(mut self, format: Option<Format>) -> Self {
+        self.media = MediaClass::from_format(&self.path, format);
+        self.format = format;
+        self
+    }
+
+    pub fn with_subdir(mut self, subdir: PathBuf) -> Self {
+        self.subdir = subdir;
+        self
     }
 }
@@ -0,0 +1,205 @@
+use crate::audit::{parse_month, parse_year};
+use crate::filename::{parse_name, parse_name_with, parse_text};
+use crate::metadata::{Coordinates, DateTag};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where a date came from, most trusted first
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateSource {
+    /// A `.img-sort-dates.toml` in its folder, or one above it
+    Override,
+    DateTimeOriginal,
+    DateTimeDigitized,
+    DateTime,
+    /// The GPS date and time
+    GpsDate,
+    /// A date kept outside of EXIF, such as in a video's container or a PNG's text
+    Metadata,
+    /// A sidecar, such as the JSON Google Takeout writes or an XMP file
+    Sidecar,
+    Filename,
+    Folder,
+    /// When the file was last modified
+    Modified,
+    /// When the file was created
+    Created,
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            DateSource::Override => "its folder's dates file",
+            DateSource::DateTimeOriginal => "its DateTimeOriginal",
+            DateSource::DateTimeDigitized => "its DateTimeDigitized",
+            DateSource::DateTime => "its DateTime",
+            DateSource::GpsDate => "its GPS date",
+            DateSource::Metadata => "its metadata",
+            DateSource::Sidecar => "its sidecar",
+            DateSource::Filename => "its name",
+            DateSource::Folder => "its folder",
+            DateSource::Modified => "when it was modified",
+            DateSource::Created => "when it was created",
+        })
+    }
+}
+
+impl From<DateTag> for DateSource {
+    fn from(tag: DateTag) -> Self {
+        match tag {
+            DateTag::DateTimeOriginal => DateSource::DateTimeOriginal,
+            DateTag::DateTimeDigitized => DateSource::DateTimeDigitized,
+            DateTag::DateTime => DateSource::DateTime,
+            DateTag::Gps => DateSource::GpsDate,
+        }
+    }
+}
+
+/// Works out when media without an embedded date was taken from what's around it
+pub fn infer_datetime(path: &Path) -> Option<(NaiveDateTime, DateSource)> {
+    from_sidecar(path)
+        .map(|datetime| (datetime, DateSource::Sidecar))
+        .or_else(|| from_filename(path).map(|datetime| (datetime, DateSource::Filename)))
+        .or_else(|| from_folders(path).map(|datetime| (datetime, DateSource::Folder)))
+}
+
+/// Dates media that can't carry a date of its own, such as GIFs, from its name and then
+/// from when it was last modified
+pub fn fallback_datetime(path: &Path) -> Option<(NaiveDateTime, DateSource)> {
+    from_filename(path)
+        .map(|datetime| (datetime, DateSource::Filename))
+        .or_else(|| from_modified(path).map(|datetime| (datetime, DateSource::Modified)))
+}
+
+/// Which of a file's times dates media that has no date of its own
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Fallback {
+    /// Leave it undated
+    #[default]
+    None,
+    /// When it was last modified
+    Mtime,
+    /// When it was created, where the filesystem keeps that
+    Btime,
+}
+
+/// Dates media from a date in its name, like the ones phones and messaging apps give it,
+/// or one of the `custom` patterns matches
+pub fn filename_datetime(path: &Path, custom: &[Regex]) -> Option<(NaiveDateTime, DateSource)> {
+    let datetime = parse_name_with(&path.file_stem()?.to_string_lossy(), custom)?;
+    Some((datetime, DateSource::Filename))
+}
+
+/// Dates media from the file time `fallback` picks
+pub fn file_datetime(path: &Path, fallback: Fallback) -> Option<(NaiveDateTime, DateSource)> {
+    match fallback {
+        Fallback::None => None,
+        Fallback::Mtime => from_modified(path).map(|datetime| (datetime, DateSource::Modified)),
+        Fallback::Btime => from_created(path).map(|datetime| (datetime, DateSource::Created)),
+    }
+}
+
+/// The JSON files Takeout may have written alongside `path`
+pub fn takeout_sidecars(path: &Path) -> Vec<PathBuf> {
+    let (Some(name), Some(stem)) = (path.file_name(), path.file_stem()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy();
+
+    vec![
+        path.with_file_name(format!("{name}.json")),
+        path.with_file_name(format!("{name}.supplemental-metadata.json")),
+        path.with_file_name(format!("{}.json", stem.to_string_lossy())),
+    ]
+}
+
+/// What a Google Takeout JSON sidecar says about a photo
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sidecar {
+    /// In local time, like EXIF dates
+    pub taken: Option<NaiveDateTime>,
+    pub coordinates: Option<Coordinates>,
+    /// Meters above sea level
+    pub altitude: Option<f64>,
+}
+
+/// Reads the Takeout sidecar written alongside a photo, if there is one
+pub fn read_sidecar(path: &Path) -> Option<Sidecar> {
+    takeout_sidecars(path).iter().find_map(|sidecar| {
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(sidecar).ok()?).ok()?;
+        let timestamp = &json["photoTakenTime"]["timestamp"];
+        let seconds = match timestamp {
+            serde_json::Value::String(seconds) => seconds.parse().ok(),
+            _ => timestamp.as_i64(),
+        };
+
+        // Takeout stores UTC, but EXIF dates are the local time they were taken
+        let taken = seconds
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .map(|taken| taken.with_timezone(&Local).naive_local());
+
+        // Photos without a location have one of 0, 0
+        let geo = &json["geoData"];
+        let coordinates = geo["latitude"]
+            .as_f64()
+            .zip(geo["longitude"].as_f64())
+            .filter(|&(latitude, longitude)| latitude != 0.0 || longitude != 0.0)
+            .map(|(latitude, longitude)| Coordinates {
+                latitude,
+                longitude,
+            });
+
+        Some(Sidecar {
+            taken,
+            coordinates,
+            altitude: coordinates.and(geo["altitude"].as_f64()),
+        })
+    })
+}
+
+fn from_sidecar(path: &Path) -> Option<NaiveDateTime> {
+    read_sidecar(path)?.taken
+}
+
+fn from_filename(path: &Path) -> Option<NaiveDateTime> {
+    parse_name(&path.file_stem()?.to_string_lossy())
+}
+
+fn from_modified(path: &Path) -> Option<NaiveDateTime> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(DateTime::<Local>::from(modified).naive_local())
+}
+
+fn from_created(path: &Path) -> Option<NaiveDateTime> {
+    let created = fs::metadata(path).ok()?.created().ok()?;
+    Some(DateTime::<Local>::from(created).naive_local())
+}
+
+/// Reads a date from the folders `path` is in, either a folder named for the day or a
+/// year folder with a month folder inside it
+fn from_folders(path: &Path) -> Option<NaiveDateTime> {
+    let folders: Vec<String> = path
+        .parent()?
+        .iter()
+        .map(|folder| folder.to_string_lossy().into_owned())
+        .collect();
+
+    // The innermost folder is the most specific
+    if let Some(datetime) = folders.iter().rev().find_map(|folder| parse_text(folder)) {
+        return Some(datetime);
+    }
+
+    // A year alone is too vague to write into a file
+    let year = folders
+        .iter()
+        .rposition(|folder| parse_year(folder).is_some())?;
+    let (month, _) = parse_month(folders.get(year + 1)?)?;
+
+    NaiveDate::from_ymd_opt(parse_year(&folders[year])?, month, 1)?.and_hms_opt(0, 0, 0)
+}
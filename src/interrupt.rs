@@ -0,0 +1,42 @@
+use crate::Status;
+use log::warn;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C and SIGTERM stop a run once the files being saved are done, rather than
+/// partway through one, and a second signal stop it straight away
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        if interrupt() {
+            process::exit(Status::Interrupted.code());
+        }
+        warn!("Stopping once the files being saved are done, interrupt again to stop now");
+    })
+}
+
+/// Asks the run to stop as a signal would, returning whether it already had been
+pub fn interrupt() -> bool {
+    INTERRUPTED.swap(true, Ordering::SeqCst)
+}
+
+/// Whether the run has been asked to stop
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Sleeps like `thread::sleep`, waking early to return false if the run is interrupted
+pub fn sleep(duration: Duration) -> bool {
+    let until = Instant::now() + duration;
+    while !interrupted() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(200)));
+    }
+    false
+}
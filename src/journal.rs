@@ -0,0 +1,243 @@
+use crate::hash::hash_file;
+use crate::transfer::Transfer;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Directory inside the destination that runs keep their journals in
+pub const JOURNAL_DIR: &str = ".img-sort";
+
+/// One operation a run carried out, kept as a line of JSON in its journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// When the operation finished, in RFC 3339
+    pub timestamp: String,
+    pub op: Transfer,
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub bytes: u64,
+    /// BLAKE3 hash of the saved media
+    pub hash: String,
+    /// Whether a file already at `dest` was deleted or trashed to make way
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replaced: bool,
+    /// Whether this is a record undo added, repeating the entry it reversed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+/// A record of a run's operations, written as they happen so it survives the run
+/// being killed
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /// Opens a journal to append to, creating it and its directory if needed
+    ///
+    /// Several runs can share a journal, each adding its operations to the end.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Where a run saving to `dest` keeps its journal by default, named after the time
+    pub fn default_path(dest: &Path) -> PathBuf {
+        let name = format!("journal-{}.jsonl", Local::now().format("%Y%m%d-%H%M%S"));
+        dest.join(JOURNAL_DIR).join(name)
+    }
+
+    /// Lists the journals kept in the destination, oldest first
+    pub fn list(dest: &Path) -> io::Result<Vec<PathBuf>> {
+        let dir = dest.join(JOURNAL_DIR);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut journals = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with("journal-") && name.ends_with(".jsonl") {
+                journals.push(path);
+            }
+        }
+
+        // Names start with the time, so they sort in the order they were made
+        journals.sort();
+        Ok(journals)
+    }
+
+    /// Finds the most recent journal kept in the destination, if there is one
+    pub fn latest(dest: &Path) -> io::Result<Option<PathBuf>> {
+        Ok(Journal::list(dest)?.pop())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, entry: &Entry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        // One write per line keeps lines from several workers apart
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+
+    /// Makes sure what's been recorded survives the machine going down
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.lock().unwrap().sync_data()
+    }
+
+    /// Reads every entry of a journal, in the order they were recorded
+    pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+        let lines = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<io::Result<Vec<_>>>()?;
+        let last = lines.len();
+        let mut entries = Vec::with_capacity(last);
+
+        for (number, line) in (1..).zip(&lines) {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                // A run killed mid-write leaves its last line cut short
+                Err(_) if number == last => {}
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Line {} of {:?} is not a journal entry: {}",
+                            number, path, e
+                        ),
+                    ))
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+impl Entry {
+    pub fn new(op: Transfer, source: &Path, dest: &Path, bytes: u64, hash: String) -> Self {
+        Entry {
+            timestamp: Local::now().to_rfc3339(),
+            op,
+            source: source.to_path_buf(),
+            dest: dest.to_path_buf(),
+            bytes,
+            hash,
+            replaced: false,
+            undone: false,
+        }
+    }
+
+    /// The record that says this entry was undone
+    pub fn as_undone(&self) -> Entry {
+        Entry {
+            undone: true,
+            ..self.clone()
+        }
+    }
+
+    /// Reverses the operation, moving moved media back and removing anything else
+    /// that was saved
+    ///
+    /// Media that was changed after it was saved is left alone.
+    pub fn undo(&self) -> io::Result<()> {
+        let Entry { source, dest, .. } = self;
+
+        // A symlink's target may have moved on, so only check it's still a link
+        let unchanged = match self.op {
+            Transfer::Symlink => dest.symlink_metadata()?.file_type().is_symlink(),
+            _ => hash_file(dest)? == self.hash,
+        };
+        if !unchanged {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} has changed since it was saved", dest),
+            ));
+        }
+
+        match self.op {
+            Transfer::Move => {
+                if source.symlink_metadata().is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{:?} can't be moved back, {:?} exists", dest, source),
+                    ));
+                }
+                if let Some(dir) = source.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                Transfer::Move.apply(dest, source)
+            }
+            _ => fs::remove_file(dest),
+        }
+    }
+}
+
+/// The entries that haven't been undone, leaving out the records of those that have
+pub fn not_undone(entries: &[Entry]) -> Vec<&Entry> {
+    let undone: HashSet<(&str, &Path)> = entries
+        .iter()
+        .filter(|entry| entry.undone)
+        .map(|entry| (entry.timestamp.as_str(), entry.dest.as_path()))
+        .collect();
+    entries
+        .iter()
+        .filter(|entry| {
+            !entry.undone && !undone.contains(&(entry.timestamp.as_str(), entry.dest.as_path()))
+        })
+        .collect()
+}
+
+/// Removes the directories left empty by undoing the entries of the journal at `path`
+///
+/// Nothing above the destination is removed, which is where the journal is kept by
+/// default, or otherwise the directory all of the entries were saved under.
+pub fn remove_empty_dirs(path: &Path, entries: &[&Entry]) {
+    let root = match path.parent() {
+        Some(dir) if dir.ends_with(JOURNAL_DIR) => dir.parent().unwrap_or(dir),
+        _ => {
+            let mut dirs = entries.iter().filter_map(|entry| entry.dest.parent());
+            let Some(first) = dirs.next() else {
+                return;
+            };
+            dirs.fold(first, |root, dir| {
+                root.ancestors()
+                    .find(|ancestor| dir.starts_with(ancestor))
+                    .unwrap_or(Path::new(""))
+            })
+        }
+    };
+
+    for entry in entries {
+        let dirs = entry.dest.ancestors().skip(1);
+
+        for dir in dirs.take_while(|dir| *dir != root && dir.starts_with(root)) {
+            // Removing a directory fails once it isn't empty, which ends the climb
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::io;
+
+const SOI: u8 = 0xD8;
+const SOS: u8 = 0xDA;
+const APP0: u8 = 0xE0;
+/// EXIF and XMP
+const APP1: u8 = 0xE1;
+/// IPTC, in Photoshop's resource block
+const APP13: u8 = 0xED;
+const COM: u8 = 0xFE;
+
+/// What starts the APP1 segment that holds a JPEG's EXIF
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// What starts the APP1 segment that holds a JPEG's XMP
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Whether some data starts like a JPEG
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, SOI])
+}
+
+/// Rebuilds a JPEG with its EXIF replaced by `exif`, a TIFF structure, or removed for
+/// `None`, leaving every other segment and the image data as they were
+pub fn replace_exif(jpeg: &[u8], exif: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let exif = exif.map(|exif| [EXIF_HEADER, exif].concat());
+    rebuild(
+        jpeg,
+        |marker, data| !(marker == APP1 && data.starts_with(EXIF_HEADER)),
+        exif.as_deref(),
+    )
+}
+
+/// Rebuilds a JPEG with the packet in its XMP segment passed through `edit`, leaving
+/// every other segment and the image data as they were
+pub fn edit_xmp(jpeg: &[u8], edit: impl FnOnce(&str) -> io::Result<String>) -> io::Result<Vec<u8>> {
+    let found = segments(jpeg)
+        .into_iter()
+        .find(|(marker, data)| *marker == APP1 && data.starts_with(XMP_HEADER));
+    let Some((_, data)) = found else {
+        return Ok(jpeg.to_vec());
+    };
+
+    let packet = std::str::from_utf8(&data[XMP_HEADER.len()..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let packet = edit(packet)?;
+
+    // The segment's marker and length come just before its data
+    let start = data.as_ptr() as usize - jpeg.as_ptr() as usize - 4;
+    let end = start + 4 + data.len();
+    let mut output = jpeg[..start].to_vec();
+    write_segment(&mut output, APP1, &[XMP_HEADER, packet.as_bytes()].concat())?;
+    output.extend_from_slice(&jpeg[end..]);
+    Ok(output)
+}
+
+/// Rebuilds a JPEG without its EXIF, XMP, IPTC or comments, keeping what's needed to
+/// show it as before, such as its color profile
+pub fn strip_metadata(jpeg: &[u8]) -> io::Result<Vec<u8>> {
+    rebuild(
+        jpeg,
+        |marker, _| !matches!(marker, APP1 | APP13 | COM),
+        None,
+    )
+}
+
+/// Copies the segments of a JPEG that `keep` accepts, given each one's marker and data,
+/// adding an EXIF segment as early as it may go
+fn rebuild(
+    jpeg: &[u8],
+    keep: impl Fn(u8, &[u8]) -> bool,
+    exif: Option<&[u8]>,
+) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if !is_jpeg(jpeg) {
+        return Err(invalid("Not a JPEG"));
+    }
+
+    let mut output = Vec::with_capacity(jpeg.len() + exif.map_or(0, <[u8]>::len));
+    output.extend_from_slice(&jpeg[..2]);
+    let mut exif = exif;
+    let mut position = 2;
+
+    loop {
+        let marker = match jpeg.get(position..position + 2) {
+            Some([0xFF, marker]) => *marker,
+            _ => return Err(invalid("A JPEG segment is malformed")),
+        };
+
+        // EXIF goes first, or just after the JFIF header that must come first
+        if marker != APP0 {
+            if let Some(exif) = exif.take() {
+                write_segment(&mut output, APP1, exif)?;
+            }
+        }
+
+        // The compressed image follows the start of scan to the end of the file
+        if marker == SOS {
+            output.extend_from_slice(&jpeg[position..]);
+            return Ok(output);
+        }
+
+        let length = match jpeg.get(position + 2..position + 4) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]) as usize,
+            _ => return Err(invalid("A JPEG segment is cut short")),
+        };
+        let end = position + 2 + length;
+        let segment = jpeg
+            .get(position..end)
+            .filter(|_| length >= 2)
+            .ok_or_else(|| invalid("A JPEG segment is cut short"))?;
+
+        if keep(marker, &segment[4..]) {
+            output.extend_from_slice(segment);
+        }
+        position = end;
+    }
+}
+
+/// The marker and data of each segment before a JPEG's image data, stopping early at
+/// any that's malformed
+pub fn segments(jpeg: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut segments = Vec::new();
+    if !is_jpeg(jpeg) {
+        return segments;
+    }
+    let mut position = 2;
+    while let Some(&[0xFF, marker, high, low]) = jpeg.get(position..position + 4) {
+        let length = u16::from_be_bytes([high, low]) as usize;
+        if marker == SOS || length < 2 {
+            break;
+        }
+        let Some(data) = jpeg.get(position + 4..position + 2 + length) else {
+            break;
+        };
+        segments.push((marker, data));
+        position += 2 + length;
+    }
+    segments
+}
+
+fn write_segment(output: &mut Vec<u8>, marker: u8, data: &[u8]) -> io::Result<()> {
+    let length = u16::try_from(data.len() + 2).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The metadata is too large for a JPEG segment",
+        )
+    })?;
+
+    output.extend_from_slice(&[0xFF, marker]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(data);
+    Ok(())
+}
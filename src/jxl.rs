@@ -0,0 +1,52 @@
+use crate::quicktime::{read_body, top_level_boxes};
+use brotli_decompressor::Decompressor;
+use exif::{Exif, Reader};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// The largest EXIF worth decompressing, well past what any camera writes
+const MAX_EXIF_LEN: u64 = 4 << 20;
+
+/// Reads the EXIF of a JPEG XL from its `Exif` box, or a `brob` box compressing one
+///
+/// Only the container keeps metadata; a bare codestream has none to read.
+pub fn read_exif(path: &Path) -> Result<Exif, exif::Error> {
+    let mut file = File::open(path)?;
+    let mut exif = None;
+
+    for found in top_level_boxes(&mut file)? {
+        match &found.kind {
+            b"Exif" => {
+                exif = read_body(&mut file, &found)?;
+                break;
+            }
+            // A Brotli-compressed box starts with the type of the box it compresses
+            b"brob" if found.len > 4 => {
+                let mut kind = [0; 4];
+                file.seek(SeekFrom::Start(found.offset))?;
+                file.read_exact(&mut kind)?;
+                if &kind == b"Exif" {
+                    let mut body = Vec::new();
+                    Decompressor::new((&mut file).take(found.len - 4), 4096)
+                        .take(MAX_EXIF_LEN)
+                        .read_to_end(&mut body)?;
+                    exif = Some(body);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The box starts with where the TIFF header is, after the offset itself
+    let data = exif.ok_or(exif::Error::NotFound("JPEG XL"))?;
+    let skip = match data.get(..4) {
+        Some(skip) => u32::from_be_bytes(skip.try_into().unwrap()) as usize,
+        None => return Err(exif::Error::InvalidFormat("Broken JPEG XL Exif box")),
+    };
+    let tiff = data
+        .get(4 + skip..)
+        .ok_or(exif::Error::InvalidFormat("Broken JPEG XL Exif box"))?;
+    Reader::new().read_raw(tiff.to_vec())
+}
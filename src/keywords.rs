@@ -0,0 +1,132 @@
+use crate::jpeg::{is_jpeg, segments};
+use crate::xmp::{
+    find_sidecar, read_keywords as read_xmp_keywords, read_rating as read_xmp_rating,
+};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+/// How much of a JPEG is read for its metadata, which comes before the image data
+const HEAD_LEN: u64 = 1 << 20;
+
+/// What starts the APP1 segment holding a JPEG's XMP
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// What starts the APP13 segment holding Photoshop's resources, IPTC among them
+const PHOTOSHOP_HEADER: &[u8] = b"Photoshop 3.0\0";
+
+/// The Photoshop resource holding IPTC
+const IPTC_RESOURCE: u16 = 0x0404;
+
+/// The IPTC record and dataset of a keyword
+const IPTC_KEYWORD: (u8, u8) = (2, 25);
+
+/// The XMP media is tagged with, its sidecar's before a JPEG's own, and a JPEG's IPTC
+#[derive(Default)]
+struct Tags {
+    xmp: Vec<String>,
+    iptc: Vec<String>,
+}
+
+fn read_tags(path: &Path) -> Tags {
+    let mut tags = Tags::default();
+    if let Some(xmp) = find_sidecar(path).and_then(|sidecar| fs::read_to_string(sidecar).ok()) {
+        tags.xmp.push(xmp);
+    }
+
+    let mut head = Vec::new();
+    let read = File::open(path).and_then(|file| file.take(HEAD_LEN).read_to_end(&mut head));
+    if read.is_ok() && is_jpeg(&head) {
+        for (marker, data) in segments(&head) {
+            match marker {
+                0xE1 => {
+                    if let Some(xmp) = data.strip_prefix(XMP_HEADER) {
+                        tags.xmp.push(String::from_utf8_lossy(xmp).into_owned());
+                    }
+                }
+                0xED => tags.iptc.extend(read_iptc_keywords(data)),
+                _ => {}
+            }
+        }
+    }
+    tags
+}
+
+/// Reads the keywords media is tagged with in its XMP sidecar, then in a JPEG's own XMP
+/// and IPTC, each once whatever its case
+pub fn read_keywords(path: &Path) -> Vec<String> {
+    let tags = read_tags(path);
+    let mut keywords: Vec<String> = tags
+        .xmp
+        .iter()
+        .flat_map(|xmp| read_xmp_keywords(xmp))
+        .chain(tags.iptc)
+        .collect();
+
+    let mut seen = Vec::new();
+    keywords.retain(|keyword| {
+        let lowercase = keyword.to_lowercase();
+        let new = !seen.contains(&lowercase);
+        seen.push(lowercase);
+        new
+    });
+    keywords
+}
+
+/// Reads the stars media is rated with in its XMP sidecar, or a JPEG's own XMP, where
+/// editors write them rather than in EXIF
+pub fn read_rating(path: &Path) -> Option<i32> {
+    read_tags(path)
+        .xmp
+        .iter()
+        .find_map(|xmp| read_xmp_rating(xmp))
+}
+
+/// Reads the keywords from the IPTC in Photoshop's resources, which are 8BIM blocks of an
+/// ID, a padded name and padded data
+fn read_iptc_keywords(app13: &[u8]) -> Vec<String> {
+    let Some(mut resources) = app13.strip_prefix(PHOTOSHOP_HEADER) else {
+        return Vec::new();
+    };
+    let mut keywords = Vec::new();
+    while let Some(rest) = resources.strip_prefix(b"8BIM") {
+        let Some(&[high, low, name_len]) = rest.get(..3) else {
+            break;
+        };
+        // The name's length byte and the name together take an even number of bytes
+        let start = 2 + (1 + name_len as usize).next_multiple_of(2);
+        let Some(&[a, b, c, d]) = rest.get(start..start + 4) else {
+            break;
+        };
+        let len = u32::from_be_bytes([a, b, c, d]) as usize;
+        let Some(data) = rest.get(start + 4..start + 4 + len) else {
+            break;
+        };
+        if u16::from_be_bytes([high, low]) == IPTC_RESOURCE {
+            keywords.extend(read_iptc_datasets(data));
+        }
+        resources = rest
+            .get(start + 4 + len.next_multiple_of(2)..)
+            .unwrap_or_default();
+    }
+    keywords
+}
+
+/// Reads the keyword datasets of IPTC, each a tag marker, record, dataset and length
+fn read_iptc_datasets(mut iptc: &[u8]) -> Vec<String> {
+    let mut keywords = Vec::new();
+    while let Some(&[0x1C, record, dataset, high, low]) = iptc.get(..5) {
+        let len = u16::from_be_bytes([high, low]) as usize;
+        let Some(data) = iptc.get(5..5 + len) else {
+            break;
+        };
+        if (record, dataset) == IPTC_KEYWORD {
+            let keyword = String::from_utf8_lossy(data).trim().to_string();
+            if !keyword.is_empty() {
+                keywords.push(keyword);
+            }
+        }
+        iptc = &iptc[5 + len..];
+    }
+    keywords
+}
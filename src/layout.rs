@@ -0,0 +1,302 @@
+use crate::image::Image;
+use crate::place::locate;
+use crate::save::SaveOptions;
+use chrono::{Datelike, Locale, NaiveDate};
+use clap::ValueEnum;
+use std::str::FromStr;
+
+/// Which half of the globe season names are taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Hemisphere {
+    #[default]
+    North,
+    South,
+}
+
+/// How month directories are named
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum MonthStyle {
+    /// 03
+    Numeric,
+    /// March
+    #[default]
+    Name,
+    /// 03-March
+    Both,
+}
+
+/// One directory level of the sorted tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    Year,
+    /// The ISO week-numbering year, used alongside weeks
+    IsoYear,
+    /// The year a season ends in, used alongside seasons
+    SeasonYear,
+    Month,
+    Day,
+    Week,
+    Quarter,
+    Decade,
+    Season,
+    Camera,
+    Type,
+    /// The first keyword media is tagged with, e.g. in Lightroom
+    Keyword,
+    /// The country media was taken in, going by its GPS
+    Country,
+    /// The city media was taken in or near, going by its GPS
+    City,
+    Format(String),
+}
+
+/// The value an image has for a key, ordered so buckets sort chronologically
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Value {
+    Number(i32),
+    Text(String),
+    /// A date key of media without a date, which sorts after everything else
+    Unknown,
+}
+
+impl Key {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Key::Year | Key::IsoYear | Key::SeasonYear => "Year",
+            Key::Month => "Month",
+            Key::Day => "Day",
+            Key::Week => "Week",
+            Key::Quarter => "Quarter",
+            Key::Decade => "Decade",
+            Key::Season => "Season",
+            Key::Camera => "Camera",
+            Key::Type => "Type",
+            Key::Keyword => "Keyword",
+            Key::Country => "Country",
+            Key::City => "City",
+            Key::Format(_) => "Directory",
+        }
+    }
+
+    /// Whether the key is taken from the date the media was captured
+    pub fn is_dated(&self) -> bool {
+        !matches!(
+            self,
+            Key::Camera | Key::Type | Key::Keyword | Key::Country | Key::City
+        )
+    }
+
+    pub fn value(&self, image: &Image) -> Value {
+        match self {
+            Key::Camera => {
+                return Value::Text(
+                    image
+                        .camera
+                        .clone()
+                        .unwrap_or_else(|| String::from("Unknown")),
+                )
+            }
+            Key::Type => return Value::Text(String::from(image.media.dir_name())),
+            Key::Keyword => {
+                // The keyword becomes a directory name
+                let keyword = image
+                    .keywords
+                    .first()
+                    .map(|keyword| keyword.replace(['/', '\\'], "-"));
+                return Value::Text(keyword.unwrap_or_else(|| String::from("Unknown")));
+            }
+            Key::Country | Key::City => {
+                let place = image.coordinates.and_then(locate);
+                let name = place.map(|place| match self {
+                    Key::Country => place.country,
+                    _ => place.city,
+                });
+                return Value::Text(String::from(name.unwrap_or("Unknown")));
+            }
+            _ => {}
+        }
+
+        let Some(datetime) = image.datetime else {
+            return Value::Unknown;
+        };
+        let (year, month) = (datetime.year(), datetime.month());
+
+        match self {
+            Key::Year => Value::Number(year),
+            Key::IsoYear => Value::Number(datetime.iso_week().year()),
+            Key::SeasonYear => Value::Number(get_season(year, month).0),
+            Key::Month => Value::Number(month as i32),
+            Key::Day => Value::Number(datetime.day() as i32),
+            Key::Week => Value::Number(datetime.iso_week().week() as i32),
+            Key::Quarter => Value::Number(get_quarter(month) as i32),
+            Key::Decade => Value::Number(year - year.rem_euclid(10)),
+            Key::Season => Value::Number(get_season(year, month).1 as i32),
+            Key::Format(format) => Value::Text(datetime.format(format).to_string()),
+            Key::Camera | Key::Type | Key::Keyword | Key::Country | Key::City => {
+                unreachable!("handled above")
+            }
+        }
+    }
+
+    /// Names the directory for a value of this key
+    pub fn render(&self, value: &Value, options: &SaveOptions) -> String {
+        let number = match value {
+            Value::Number(number) => *number,
+            Value::Text(text) => return text.clone(),
+            Value::Unknown => return String::from(options.unknown_dir()),
+        };
+
+        match self {
+            Key::Month => get_month(number as u32, &options.month_style, &options.locale),
+            Key::Day => format!("{number:02}"),
+            Key::Week => format!("W{number:02}"),
+            Key::Quarter => get_quarter_name(number),
+            Key::Decade => get_decade_name(number),
+            Key::Season => get_season_name(number, &options.hemisphere),
+            _ => number.to_string(),
+        }
+    }
+}
+
+/// An ordered list of keys, one per directory level, e.g. `year/month/camera`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub keys: Vec<Key>,
+}
+
+impl Layout {
+    pub fn new(keys: Vec<Key>) -> Self {
+        // Years next to weeks or seasons must agree with them at year boundaries
+        let year = if keys.contains(&Key::Week) {
+            Key::IsoYear
+        } else if keys.contains(&Key::Season) {
+            Key::SeasonYear
+        } else {
+            Key::Year
+        };
+
+        let keys = keys
+            .into_iter()
+            .map(|key| match key {
+                Key::Year | Key::IsoYear | Key::SeasonYear => year.clone(),
+                key => key,
+            })
+            .collect();
+
+        Layout { keys }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(layout: &str) -> Result<Self, Self::Err> {
+        let mut keys = Vec::new();
+        for keyword in layout.split('/') {
+            match keyword.trim() {
+                "year" => keys.push(Key::Year),
+                "month" => keys.push(Key::Month),
+                "day" => keys.push(Key::Day),
+                "week" => keys.push(Key::Week),
+                "quarter" => keys.push(Key::Quarter),
+                "decade" => keys.push(Key::Decade),
+                "season" => keys.push(Key::Season),
+                "camera" => keys.push(Key::Camera),
+                "type" => keys.push(Key::Type),
+                "keyword" => keys.push(Key::Keyword),
+                "country" => keys.push(Key::Country),
+                "city" => keys.push(Key::City),
+                // A place is a folder for the country with one for the city inside it
+                "place" => keys.extend([Key::Country, Key::City]),
+                keyword => return Err(format!(
+                    "Unknown layout keyword {:?}, expected one of year, month, day, week, quarter, decade, season, camera, type, keyword, country, city or place",
+                    keyword
+                )),
+            }
+        }
+
+        Ok(Layout::new(keys))
+    }
+}
+
+fn get_month(month: u32, style: &MonthStyle, locale: &Option<Locale>) -> String {
+    let name = match locale {
+        Some(locale) => get_localized_month_name(month, *locale),
+        None => get_month_name(month),
+    };
+
+    match (month, style) {
+        (1..=12, MonthStyle::Numeric) => format!("{month:02}"),
+        (1..=12, MonthStyle::Both) => format!("{month:02}-{name}"),
+        _ => name,
+    }
+}
+
+fn get_month_name(month: u32) -> String {
+    match month {
+        1 => String::from("January"),
+        2 => String::from("February"),
+        3 => String::from("March"),
+        4 => String::from("April"),
+        5 => String::from("May"),
+        6 => String::from("June"),
+        7 => String::from("July"),
+        8 => String::from("August"),
+        9 => String::from("September"),
+        10 => String::from("October"),
+        11 => String::from("November"),
+        12 => String::from("December"),
+        _ => String::from("Unknown"),
+    }
+}
+
+fn get_localized_month_name(month: u32, locale: Locale) -> String {
+    let Some(date) = NaiveDate::from_ymd_opt(2000, month, 1) else {
+        return get_month_name(month);
+    };
+
+    // Many locales write month names in lowercase, but folders read better capitalized
+    let name = date.format_localized("%B", locale).to_string();
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
+fn get_quarter(month: u32) -> u32 {
+    month.div_ceil(3)
+}
+
+fn get_quarter_name(quarter: i32) -> String {
+    match quarter {
+        1..=4 => format!("Q{quarter}"),
+        _ => String::from("Unknown"),
+    }
+}
+
+fn get_decade_name(decade: i32) -> String {
+    format!("{decade}s")
+}
+
+/// Maps a month to its season's year and its index (1 for Dec-Feb up to 4 for Sep-Nov)
+///
+/// December belongs to the season that continues into the following year.
+fn get_season(year: i32, month: u32) -> (i32, u32) {
+    match month {
+        12 => (year + 1, 1),
+        1..=11 => (year, month / 3 + 1),
+        _ => (year, 0),
+    }
+}
+
+fn get_season_name(season: i32, hemisphere: &Hemisphere) -> String {
+    let name = match (season, hemisphere) {
+        (1, Hemisphere::North) | (3, Hemisphere::South) => "Winter",
+        (2, Hemisphere::North) | (4, Hemisphere::South) => "Spring",
+        (3, Hemisphere::North) | (1, Hemisphere::South) => "Summer",
+        (4, Hemisphere::North) | (2, Hemisphere::South) => "Autumn",
+        _ => "Unknown",
+    };
+    String::from(name)
+}
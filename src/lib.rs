@@ -1,279 +1,6047 @@
-use chrono::{Datelike, NaiveDateTime};
-use exif::{In, Tag};
-use globwalk::{GlobError, GlobWalker};
+use chrono::{Datelike, Local, NaiveDateTime, TimeDelta, TimeZone};
+use indicatif::{HumanBytes, HumanDuration};
+use log::{debug, error, info, trace, warn};
+use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
+use std::fs;
 use std::io;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 pub mod arguments;
 use crate::arguments::Arguments;
 
+pub mod audit;
+
+pub mod burst;
+use crate::burst::group_bursts;
+
+pub mod cli;
+use crate::cli::{
+    ApplyArguments, AuditArguments, DedupeArguments, DiffArguments, DoctorArguments,
+    FixDatesArguments, GeotagArguments, MergeArguments, ShiftDatesArguments, StatsArguments,
+    UndoArguments, VerifyArguments, WatchArguments,
+};
+
+pub mod config;
+
 pub mod tree;
 use crate::tree::{build_tree, Tree};
 
-pub mod image;
-use crate::image::Image;
+pub mod dedupe;
+use crate::dedupe::find_duplicates;
+
+pub mod diff;
+
+pub mod doctor;
+use crate::doctor::Diagnosis;
+
+pub mod exiftool;
+
+pub mod ffprobe;
+
+pub mod filename;
+
+pub mod filter;
+
+pub mod geotag;
+use crate::geotag::Track;
+
+pub mod hash;
+use crate::hash::hash_file;
+
+pub mod heif;
+
+pub mod icloud;
+use crate::icloud::{date_from_details, icloud_albums};
+
+pub mod image;
+use crate::image::{Image, MediaClass};
+
+pub mod infer;
+
+pub mod interrupt;
+use crate::infer::{
+    file_datetime, filename_datetime, infer_datetime, read_sidecar, DateSource, Fallback,
+};
+use crate::interrupt::interrupted;
+
+pub mod jpeg;
+
+pub mod journal;
+use crate::journal::{not_undone, remove_empty_dirs, Journal, JOURNAL_DIR};
+
+pub mod jxl;
+
+pub mod keywords;
+use crate::keywords::{read_keywords, read_rating};
+
+pub mod layout;
+use crate::layout::{Key, Layout};
+
+pub mod live;
+use crate::live::pair_live_photos;
+
+pub mod lock;
+use crate::lock::Lock;
+
+pub mod merge;
+
+pub mod metadata;
+use crate::metadata::{
+    read_metadata, shift_datetimes, write_datetime_original, write_gps, Coordinates, DateTag,
+    DEFAULT_DATE_TAGS,
+};
+
+pub mod motion;
+use crate::motion::find_motion_photos;
+
+pub mod overrides;
+use crate::overrides::date_from_overrides;
+
+pub mod pairs;
+use crate::pairs::pair_raw_jpeg;
+
+pub mod place;
+
+pub mod plan;
+use crate::plan::Plan;
+
+pub mod png;
+
+pub mod progress;
+use crate::progress::{Event, Hook};
+
+pub mod prompt;
+
+pub mod quicktime;
+
+pub mod raw;
+
+pub mod report;
+use crate::report::write_report;
+
+pub mod save;
+use crate::save::{execute, numbered, Outcome, SaveOptions, SaveReport, Step};
+
+pub mod scan;
+use crate::scan::{
+    build_glob_walker, find_listed, find_with, insert_image, list_media, matches_patterns,
+    ScanOptions, PATTERNS,
+};
+
+pub mod screenshot;
+use crate::screenshot::{Screenshots, SCREENSHOTS_DIR};
+
+pub mod sidecar;
+use crate::sidecar::associate_sidecars;
+
+pub mod sniff;
+
+pub mod stats;
+use crate::stats::Stats;
+
+pub mod summary;
+use crate::summary::Summary;
+
+pub mod takeout;
+use crate::takeout::{collapse_albums, link_albums, ALBUMS_DIR};
+
+pub mod template;
+
+pub mod transfer;
+use crate::transfer::Transfer;
+
+pub mod watch;
+
+pub mod verify;
+
+pub mod video;
+
+pub mod webp;
+
+pub mod xmp;
+use crate::xmp::{read_sidecar_date, XmpPriority};
+
+pub mod zone;
+use crate::zone::{convert, Zone};
+
+/// How a run ended, which `code` turns into the process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Every piece of media was saved or deliberately skipped
+    Success,
+    /// Something went wrong that stopped the run
+    Fatal,
+    /// The run finished, but some media couldn't be saved
+    PartialFailure,
+    /// No media was found, or all of it was filtered out
+    NothingToDo,
+    /// The arguments or config were invalid
+    Usage,
+    /// Another run was saving to the destination
+    Busy,
+    /// A signal stopped the run before everything was saved
+    Interrupted,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        match self {
+            Status::Success => 0,
+            Status::Fatal => 1,
+            Status::PartialFailure => 2,
+            Status::NothingToDo => 3,
+            // EX_USAGE from sysexits.h
+            Status::Usage => 64,
+            // EX_TEMPFAIL, since trying again later should work
+            Status::Busy => 75,
+            // What shells report for a process stopped by SIGINT
+            Status::Interrupted => 130,
+        }
+    }
+}
+
+// Means that function will return a type that implements the Error trait
+pub fn run(args: &Arguments) -> Result<Status, Box<dyn Error>> {
+    run_with(args, &progress::ignore)
+}
+
+/// Runs like `run`, reporting progress to `on_event`
+pub fn run_with(args: &Arguments, on_event: Hook) -> Result<Status, Box<dyn Error>> {
+    let (mut tree, mut summary) = gather(args, on_event)?;
+    if interrupted() {
+        return Ok(Status::Interrupted);
+    }
+    if tree.size() == 0 {
+        return Ok(Status::NothingToDo);
+    }
+
+    // Another run could still be adding to the journal a resume reads
+    fs::create_dir_all(&args.dest)
+        .map_err(|e| format!("The destination {:?} could not be created: {e}", args.dest))?;
+    let Some(_lock) = lock(&args.dest, args.wait)? else {
+        return Ok(Status::Busy);
+    };
+    let journal_path = match (&args.journal, args.resume) {
+        (Some(path), _) => path.clone(),
+        (None, true) => {
+            Journal::latest(&args.dest)?.unwrap_or_else(|| Journal::default_path(&args.dest))
+        }
+        (None, false) => Journal::default_path(&args.dest),
+    };
+    if args.resume {
+        resume(&mut tree, &journal_path)?;
+    }
+    let mut albums = if args.takeout_albums {
+        let found = tree.size();
+        let albums = collapse_albums(&mut tree)?;
+        info!(
+            "Found {} albums, {} of whose photos are copies sorted from elsewhere",
+            albums.len(),
+            found - tree.size()
+        );
+        albums
+    } else {
+        Vec::new()
+    };
+    if args.icloud_albums {
+        let found = icloud_albums(&tree);
+        info!("Found {} iCloud albums", found.len());
+        albums.extend(found);
+    }
+    if tree.size() == 0 {
+        return Ok(Status::NothingToDo);
+    }
+
+    info!("Saving sorted media...");
+    let save_start = Instant::now();
+    let options = args.save_options();
+    let journal = Journal::create(&journal_path)?;
+    summary.transfer = options.transfer;
+    let steps = tree.plan(&args.dest, &options)?;
+    summary.report = execute(&steps, &options, Some(&journal), on_event)?;
+    let save_duration = save_start.elapsed();
+
+    if !albums.is_empty() {
+        let failed: HashSet<&PathBuf> = summary
+            .report
+            .failures
+            .iter()
+            .map(|(path, _)| path)
+            .collect();
+        let saved = steps
+            .iter()
+            .filter(|step| step.outcome != Outcome::Skip && !failed.contains(&step.source))
+            .map(|step| (step.source.as_path(), step.dest.as_path()))
+            .collect();
+        let linked = link_albums(&albums, &saved, &args.dest, Some(&journal))?;
+        info!(
+            "Linked {} photos into {} albums in {:?}",
+            linked,
+            albums.len(),
+            args.dest.join(ALBUMS_DIR)
+        );
+    }
+
+    info!(
+        "Media successfully saved to: {:?} in {:?}",
+        &args.dest, save_duration
+    );
+    // Styles are stripped again when stdout isn't a terminal or colors are off
+    info!("{}", summary);
+    info!("Undo this run with: img-sort undo {:?}", journal.path());
+    if interrupted() {
+        info!("Save the rest by running again with --resume");
+    }
+    on_event(Event::Summary(&summary));
+
+    // The media is saved either way, so a report that can't be written isn't fatal
+    if let Some(report) = &args.report {
+        match write_report(report, &summary, &tree, &steps) {
+            Ok(()) => info!("Wrote a report of this run to {:?}", report),
+            Err(e) => warn!("Could not write the report to {:?}: {}", report, e),
+        }
+    }
+
+    Ok(save_status(&summary.report))
+}
+
+/// Checks the media in a destination against the hashes its journals recorded
+pub fn verify(args: &VerifyArguments) -> Result<Status, Box<dyn Error>> {
+    let journals = match &args.journal {
+        Some(path) => vec![path.clone()],
+        None => Journal::list(&args.dest)?,
+    };
+    if journals.is_empty() {
+        warn!(
+            "There are no journals in {:?} to verify it against",
+            args.dest
+        );
+        return Ok(Status::NothingToDo);
+    }
+
+    let mut entries = Vec::new();
+    for journal in &journals {
+        entries.extend(Journal::read(journal)?);
+    }
+
+    info!("Verifying {:?}...", args.dest);
+    let verification = verify::verify(&args.dest, &entries)?;
+    for path in &verification.corrupted {
+        error!("Corrupted: {:?}", path);
+    }
+    for path in &verification.missing {
+        error!("Missing: {:?}", path);
+    }
+    for path in &verification.unreadable {
+        error!("Unreadable: {:?}", path);
+    }
+    for path in &verification.extra {
+        warn!("Not in a journal: {:?}", path);
+    }
+
+    info!(
+        "{} intact, {} corrupted, {} missing, {} unreadable and {} extra files",
+        verification.intact,
+        verification.corrupted.len(),
+        verification.missing.len(),
+        verification.unreadable.len(),
+        verification.extra.len()
+    );
+
+    if verification.passed() {
+        Ok(Status::Success)
+    } else {
+        Ok(Status::PartialFailure)
+    }
+}
+
+/// Reports media filed under the wrong year or month in a library, moving it to the right
+/// folder with `--fix`
+pub fn audit(args: &AuditArguments) -> Result<Status, Box<dyn Error>> {
+    info!("Auditing {:?}...", args.library);
+    let misfiled = audit::audit(&args.library)?;
+    if misfiled.is_empty() {
+        info!("Every piece of media is in the right folder");
+        return Ok(Status::Success);
+    }
+
+    for media in &misfiled {
+        warn!(
+            "{:?} was taken {} and belongs in {:?}",
+            media.path,
+            media.taken,
+            media.dest.parent().unwrap_or(&media.dest)
+        );
+    }
+    if !args.fix {
+        info!(
+            "{} pieces of media are misfiled, move them with --fix",
+            misfiled.len()
+        );
+        return Ok(Status::PartialFailure);
+    }
+
+    // Media moved into the same folder mustn't replace what's there, or each other
+    let mut taken = HashSet::new();
+    let mut steps = Vec::with_capacity(misfiled.len());
+    for media in misfiled {
+        let dest = numbered(&media.dest, |path| {
+            taken.contains(path) || path.symlink_metadata().is_ok()
+        });
+        taken.insert(dest.clone());
+
+        steps.push(Step {
+            bytes: fs::metadata(&media.path)?.len(),
+            hash: Some(hash_file(&media.path)?),
+            source: media.path,
+            dest,
+            outcome: Outcome::Save,
+            sidecars: Vec::new(),
+            motion_video: None,
+            date_source: None,
+        });
+    }
+
+    let options = SaveOptions {
+        transfer: Transfer::Move,
+        ..Default::default()
+    };
+    let journal = Journal::create(&Journal::default_path(&args.library))?;
+    let report = execute(&steps, &options, Some(&journal), &progress::ignore)?;
+
+    info!(
+        "Moved {} of {} misfiled pieces of media",
+        report.saved,
+        steps.len()
+    );
+    info!("Undo this with: img-sort undo {:?}", journal.path());
+
+    Ok(save_status(&report))
+}
+
+/// Prints counts of the media in a collection, without sorting it
+pub fn stats(args: &StatsArguments) -> Result<Status, Box<dyn Error>> {
+    let mut tree = Tree::new(Layout::new(Vec::new()));
+    let options = ScanOptions {
+        max_depth: None,
+        ..Default::default()
+    };
+
+    for path in &args.paths {
+        let walker = build_glob_walker(path, &PATTERNS, &options)?;
+        if find_with(walker, &DEFAULT_DATE_TAGS, &mut tree, &progress::ignore).is_err() {
+            warn!("No media found in {:?}", path);
+        }
+    }
+    if tree.size() == 0 {
+        return Ok(Status::NothingToDo);
+    }
+
+    let stats = Stats::new(tree.images());
+    if args.json {
+        anstream::println!("{}", stats.to_json()?);
+    } else {
+        anstream::println!("{}", stats);
+    }
+
+    Ok(Status::Success)
+}
+
+/// Lists groups of identical media, and with `--execute` deletes, hardlinks or moves all
+/// but the first of each
+pub fn dedupe(args: &DedupeArguments) -> Result<Status, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for path in &args.paths {
+        files.extend(list_media(path)?);
+    }
+
+    info!("Comparing {} files...", files.len());
+    let groups = find_duplicates(files)?;
+    if groups.is_empty() {
+        info!("No duplicates found");
+        return Ok(Status::Success);
+    }
+
+    let action = args.action();
+    let mut failed = 0;
+    for group in &groups {
+        info!("{:?}", group.keep());
+
+        for duplicate in group.duplicates() {
+            let result = match &action {
+                Some(action) if args.execute => action.apply(group.keep(), duplicate),
+                _ => Ok(None),
+            };
+
+            match (&action, result) {
+                (Some(action), Ok(Some(dest))) if args.execute => {
+                    info!("  {} {:?} to {:?}", action.past_tense(), duplicate, dest)
+                }
+                (Some(action), Ok(None)) if args.execute => {
+                    info!("  {} {:?}", action.past_tense(), duplicate)
+                }
+                (_, Ok(_)) => info!("  Duplicate: {:?}", duplicate),
+                (_, Err(e)) => {
+                    warn!("Could not deal with {:?}: {}", duplicate, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    let duplicates: usize = groups.iter().map(|group| group.duplicates().len()).sum();
+    let bytes: u64 = groups
+        .iter()
+        .map(|group| group.bytes * group.duplicates().len() as u64)
+        .sum();
+    info!(
+        "{} duplicates of {} files, taking up {}",
+        duplicates,
+        groups.len(),
+        HumanBytes(bytes)
+    );
+    if action.is_some() && !args.execute {
+        info!("Nothing was changed, add --execute to carry out the action");
+    }
+
+    if failed > 0 {
+        Ok(Status::PartialFailure)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+/// Compares the media in two trees by content, listing what each has that the other
+/// doesn't
+pub fn diff(args: &DiffArguments) -> Result<Status, Box<dyn Error>> {
+    let (a, b) = (list_media(&args.a)?, list_media(&args.b)?);
+    info!("Comparing {} files with {}...", a.len(), b.len());
+    let difference = diff::diff(&a, &b)?;
+
+    for (dir, only) in [(&args.a, &difference.only_a), (&args.b, &difference.only_b)] {
+        if !only.is_empty() {
+            info!("Only in {:?}:", dir);
+        }
+        for path in only {
+            info!("  {:?}", path);
+        }
+    }
+
+    info!(
+        "{} files are in both, {} only in {:?} and {} only in {:?}",
+        difference.shared,
+        difference.only_a.len(),
+        args.a,
+        difference.only_b.len(),
+        args.b
+    );
+
+    if difference.only_a.is_empty() && difference.only_b.is_empty() {
+        Ok(Status::Success)
+    } else {
+        Ok(Status::PartialFailure)
+    }
+}
+
+/// Merges a sorted library into another, following the conflict policy for media that
+/// isn't already there
+pub fn merge(args: &MergeArguments, on_event: Hook) -> Result<Status, Box<dyn Error>> {
+    fs::create_dir_all(&args.dest)
+        .map_err(|e| format!("The destination {:?} could not be created: {e}", args.dest))?;
+    let Some(_lock) = lock(&args.dest, args.wait)? else {
+        return Ok(Status::Busy);
+    };
+
+    info!("Comparing {:?} with {:?}...", args.source, args.dest);
+    let options = args.save_options();
+    let steps = merge::plan_merge(&args.source, &args.dest, &options, args.dedupe)?;
+    if steps.is_empty() {
+        warn!("Did not find any media in {:?}", args.source);
+        return Ok(Status::NothingToDo);
+    }
+
+    info!("Merging {} pieces of media...", steps.len());
+    let journal = match &args.journal {
+        Some(path) => Journal::create(path)?,
+        None => Journal::create(&Journal::default_path(&args.dest))?,
+    };
+    let report = execute(&steps, &options, Some(&journal), on_event)?;
+
+    info!(
+        "{}: {}, Renamed: {}, Overwritten: {}, Already there: {}, Failed: {}",
+        options.transfer.past_tense(),
+        report.saved,
+        report.renamed,
+        report.overwritten,
+        report.skipped,
+        report.failed
+    );
+    info!("Undo this merge with: img-sort undo {:?}", journal.path());
+
+    Ok(save_status(&report))
+}
+
+/// Sorts the media in a directory, then keeps sorting media as it arrives until stopped
+pub fn watch(args: &WatchArguments) -> Result<Status, Box<dyn Error>> {
+    let sort = &args.args;
+    let settle = Duration::try_from_secs_f64(args.settle)
+        .map_err(|_| format!("Can't wait {} seconds for files to settle.", args.settle))?;
+
+    fs::create_dir_all(&sort.dest)
+        .map_err(|e| format!("The destination {:?} could not be created: {e}", sort.dest))?;
+    let journal = match &sort.journal {
+        Some(path) => Journal::create(path)?,
+        None => Journal::create(&Journal::default_path(&sort.dest))?,
+    };
+    let transfer = sort.save_options().transfer;
+    let patterns = sort.patterns();
+
+    let save = |paths: Vec<PathBuf>| match sort_arrived(sort, paths, &journal) {
+        Ok(Some(report)) => info!(
+            "{} {} pieces of media to {:?}",
+            transfer.past_tense(),
+            report.saved,
+            sort.dest
+        ),
+        Ok(None) => {}
+        Err(e) => error!("Failed to save media: {}", e),
+    };
+
+    // Media that arrived while nothing was watching goes first
+    let mut waiting = Vec::new();
+    for dir in &sort.path {
+        let walker = build_glob_walker(dir, &patterns, &sort.scan_options())?;
+        waiting.extend(walker.filter_map(Result::ok).map(|entry| entry.into_path()));
+    }
+    save(waiting);
+
+    info!("Watching {:?} for new media...", sort.path);
+    watch::watch(
+        &sort.path,
+        settle,
+        |path| matches_patterns(path, &patterns),
+        save,
+    )?;
+
+    Ok(Status::Interrupted)
+}
+
+/// Sorts media that arrived in a watched directory, dated and filtered just as a sort
+/// would, or returns `None` if none of it is to be saved
+fn sort_arrived(
+    args: &Arguments,
+    paths: Vec<PathBuf>,
+    journal: &Journal,
+) -> Result<Option<SaveReport>, Box<dyn Error>> {
+    let mut tree = build_tree(args);
+    for path in paths {
+        let subdir = args
+            .path
+            .iter()
+            .find_map(|dir| path.parent()?.strip_prefix(dir).ok())
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        insert_image(path, subdir, args.date_tags(), &mut tree);
+    }
+    let (tree, _) = refine(args, tree)?;
+    if tree.size() == 0 {
+        return Ok(None);
+    }
+
+    let _lock = Lock::acquire(&args.dest, true)?;
+    let options = args.save_options();
+    let report = tree.save_with(&args.dest, &options, Some(journal), &progress::ignore)?;
+    Ok(Some(report))
+}
+
+/// Checks every piece of media can be read and sorted, reporting each one that can't
+pub fn doctor(args: &DoctorArguments) -> Result<Status, Box<dyn Error>> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut problems = 0;
+
+    for path in &args.paths {
+        info!("Checking the media in {:?}...", path);
+        for media in list_media(path)? {
+            let diagnosis = doctor::diagnose(&media);
+            match &diagnosis {
+                Diagnosis::Ok => debug!("{:?} {}", media, diagnosis),
+                Diagnosis::NoDate => info!("{:?} {}", media, diagnosis),
+                _ => {
+                    warn!("{:?} {}", media, diagnosis);
+                    problems += 1;
+                }
+            }
+            *counts.entry(diagnosis.label()).or_default() += 1;
+        }
+    }
+
+    if counts.is_empty() {
+        info!("No media found");
+        return Ok(Status::NothingToDo);
+    }
+    for (label, count) in &counts {
+        info!("{label}: {count}");
+    }
+
+    if problems > 0 {
+        Ok(Status::PartialFailure)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+/// Writes the dates of media without one in its EXIF, as inferred from its sidecar, name
+/// or folders, so other tools see them too
+pub fn fix_dates(args: &FixDatesArguments) -> Result<Status, Box<dyn Error>> {
+    let (mut fixed, mut failed) = (0, 0);
+
+    for path in &args.paths {
+        for media in list_media(path)? {
+            if read_metadata(&media).datetime.is_some() {
+                continue;
+            }
+            let Some((taken, source)) = infer_datetime(&media) else {
+                debug!("Passing over {:?}, its date can't be inferred", media);
+                continue;
+            };
+
+            info!("{:?} was taken {} going by {}", media, taken, source);
+            if args.dry_run {
+                fixed += 1;
+                continue;
+            }
+
+            let result =
+                backup(&media, args.backup).and_then(|()| write_datetime_original(&media, taken));
+            match result {
+                Ok(()) => fixed += 1,
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    warn!("Can't write a date into {:?}: {}", media, e)
+                }
+                Err(e) => {
+                    error!("Couldn't fix the date of {:?}: {}", media, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    match (args.dry_run, fixed) {
+        (_, 0) => info!("No dates need fixing"),
+        (true, _) => info!("Would fix the dates of {} files", fixed),
+        (false, _) => info!("Fixed the dates of {} files", fixed),
+    }
+
+    if failed > 0 {
+        Ok(Status::PartialFailure)
+    } else if fixed == 0 {
+        Ok(Status::NothingToDo)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+/// Moves the EXIF dates of the media that passes the filters by a fixed offset
+pub fn shift_dates(args: &ShiftDatesArguments) -> Result<Status, Box<dyn Error>> {
+    let filter = args.filter();
+    let (mut shifted, mut failed) = (0, 0);
+
+    for path in &args.paths {
+        for media in list_media(path)? {
+            let metadata = read_metadata(&media);
+            let Some(taken) = metadata.datetime else {
+                debug!("Passing over {:?}, it has no date to shift", media);
+                continue;
+            };
+            if args.camera.is_some() && metadata.camera != args.camera {
+                continue;
+            }
+            let name = media.file_name().unwrap_or_default().to_string_lossy();
+            let image = Image::new(media.clone(), name.into_owned()).with_datetime(Some(taken));
+            if !filter.matches(&image) {
+                continue;
+            }
+
+            if args.dry_run {
+                info!(
+                    "{:?} would move from {} to {}",
+                    media,
+                    taken,
+                    taken + args.offset
+                );
+                shifted += 1;
+                continue;
+            }
+
+            match shift_file(&media, args) {
+                Ok(Some((from, to))) => {
+                    info!("{:?} moved from {} to {}", media, from, to);
+                    shifted += 1;
+                }
+                Ok(None) => {}
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    warn!("Can't write a date into {:?}: {}", media, e)
+                }
+                Err(e) => {
+                    error!("Couldn't shift the date of {:?}: {}", media, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    match (args.dry_run, shifted) {
+        (_, 0) => info!("No dates matched"),
+        (true, _) => info!("Would shift the dates of {} files", shifted),
+        (false, _) => info!("Shifted the dates of {} files", shifted),
+    }
+
+    if failed > 0 {
+        Ok(Status::PartialFailure)
+    } else if shifted == 0 {
+        Ok(Status::NothingToDo)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+/// Writes where media was taken into its EXIF, going by where a track was at the time
+pub fn geotag(args: &GeotagArguments) -> Result<Status, Box<dyn Error>> {
+    let track = Track::read(&args.track)?;
+    if track.is_empty() {
+        error!("{:?} has no points with times", args.track);
+        return Ok(Status::Fatal);
+    }
+    info!("Read {} points from {:?}", track.len(), args.track);
+
+    let offset = args.offset.unwrap_or_default();
+    let max_gap = TimeDelta::from_std(args.max_gap)?;
+    let (mut tagged, mut unplaced, mut failed) = (0, 0, 0);
+
+    for path in &args.paths {
+        for media in list_media(path)? {
+            let metadata = read_metadata(&media);
+            let Some(taken) = metadata.datetime else {
+                debug!("Passing over {:?}, it has no date", media);
+                continue;
+            };
+            if metadata.coordinates.is_some() && !args.overwrite {
+                debug!("Passing over {:?}, it already has a location", media);
+                continue;
+            }
+
+            let Some(point) = Local
+                .from_local_datetime(&(taken + offset))
+                .earliest()
+                .and_then(|taken| track.locate(taken.to_utc(), max_gap))
+            else {
+                debug!("{:?} was taken too far from the track to place", media);
+                unplaced += 1;
+                continue;
+            };
+
+            let Coordinates {
+                latitude,
+                longitude,
+            } = point.coordinates;
+            info!("{:?} was taken at {:.6}, {:.6}", media, latitude, longitude);
+            if args.dry_run {
+                tagged += 1;
+                continue;
+            }
+
+            let result = backup(&media, args.backup)
+                .and_then(|()| write_gps(&media, point.coordinates, point.altitude));
+            match result {
+                Ok(()) => tagged += 1,
+                Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                    warn!("Can't write a location into {:?}: {}", media, e)
+                }
+                Err(e) => {
+                    error!("Couldn't geotag {:?}: {}", media, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if unplaced > 0 {
+        info!(
+            "{} pieces of media were taken too far from the track, widen --max-gap or check --offset",
+            unplaced
+        );
+    }
+    match (args.dry_run, tagged) {
+        (_, 0) => info!("No media could be geotagged"),
+        (true, _) => info!("Would geotag {} files", tagged),
+        (false, _) => info!("Geotagged {} files", tagged),
+    }
+
+    if failed > 0 {
+        Ok(Status::PartialFailure)
+    } else if tagged == 0 {
+        Ok(Status::NothingToDo)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+fn shift_file(
+    path: &Path,
+    args: &ShiftDatesArguments,
+) -> io::Result<Option<(NaiveDateTime, NaiveDateTime)>> {
+    let modified = fs::metadata(path)?.modified()?;
+    backup(path, args.backup)?;
+    let shifted = shift_datetimes(path, args.offset)?;
+
+    // Rewriting the file touched its modification time, so set it from the old one
+    let modified = match (args.mtime, args.offset.to_std()) {
+        (false, _) => modified,
+        (true, Ok(forward)) => modified + forward,
+        (true, Err(_)) => modified - (-args.offset).to_std().unwrap_or_default(),
+    };
+    fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_modified(modified)?;
+
+    Ok(shifted)
+}
+
+/// Copies a file to `<name>_original`, as exiftool does, unless an earlier backup is there
+fn backup(path: &Path, enabled: bool) -> io::Result<()> {
+    let mut original = path.as_os_str().to_owned();
+    original.push("_original");
+    let original = PathBuf::from(original);
+
+    if enabled && !original.exists() {
+        fs::copy(path, original)?;
+    }
+    Ok(())
+}
+
+/// How a save ended, going by its report and whether it was interrupted
+fn save_status(report: &SaveReport) -> Status {
+    if interrupted() {
+        Status::Interrupted
+    } else if report.failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Success
+    }
+}
+
+/// Locks a destination for a run, or explains who has it and returns `None` if it's taken
+fn lock(dest: &Path, wait: bool) -> Result<Option<Lock>, Box<dyn Error>> {
+    match Lock::acquire(dest, wait) {
+        Ok(lock) => Ok(Some(lock)),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            error!("{}", e);
+            Ok(None)
+        }
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// Leaves out the media a journal shows was saved by an earlier, interrupted run
+///
+/// Only complete files are renamed into place and recorded, so anything still missing
+/// from the destination is saved again.
+fn resume(tree: &mut Tree, journal: &Path) -> io::Result<()> {
+    if !journal.exists() {
+        warn!("There is no journal at {:?} to resume from", journal);
+        return Ok(());
+    }
+
+    let saved: HashSet<PathBuf> = Journal::read(journal)?
+        .into_iter()
+        .filter(|entry| entry.dest.symlink_metadata().is_ok())
+        .map(|entry| entry.source)
+        .collect();
+    let resumed = tree.retain(|image| !saved.contains(&image.path));
+    info!(
+        "Resuming from {:?}, {} pieces of media were already saved",
+        journal, resumed
+    );
+
+    Ok(())
+}
+
+/// Sorts new media from the sources every `--every`, until stopped
+///
+/// The journal kept across scans (`--journal`, or `DEST/.img-sort/daemon.jsonl`) is the
+/// state that tells new media from media that was already sorted.
+pub fn daemon(args: &Arguments) -> Result<Status, Box<dyn Error>> {
+    let every = args.every.unwrap_or(Duration::from_secs(60 * 60));
+    let state = args
+        .journal
+        .clone()
+        .unwrap_or_else(|| args.dest.join(JOURNAL_DIR).join("daemon.jsonl"));
+
+    loop {
+        // A failed scan, e.g. an unmounted source, may work next time
+        if let Err(e) = sort_new(args, &state) {
+            error!("{}", e);
+        }
+
+        info!("Scanning again in {}", HumanDuration(every));
+        if !interrupt::sleep(every) {
+            return Ok(Status::Interrupted);
+        }
+    }
+}
+
+/// Sorts the media in the sources that the journal at `state` has no record of, returning
+/// how many pieces were saved
+pub fn sort_new(args: &Arguments, state: &Path) -> Result<usize, Box<dyn Error>> {
+    let (mut tree, _) = gather(args, &progress::ignore)?;
+
+    if state.exists() {
+        let sorted: HashSet<PathBuf> = Journal::read(state)?
+            .into_iter()
+            .map(|entry| entry.source)
+            .collect();
+        tree.retain(|image| !sorted.contains(&image.path));
+    }
+    if tree.size() == 0 {
+        info!("No new media to sort");
+        return Ok(0);
+    }
+
+    info!("Sorting {} new pieces of media...", tree.size());
+    fs::create_dir_all(&args.dest)
+        .map_err(|e| format!("The destination {:?} could not be created: {e}", args.dest))?;
+    // Waiting is fine, as there's nothing else to do until the next scan
+    let _lock = Lock::acquire(&args.dest, true)?;
+    let options = args.save_options();
+    let journal = Journal::create(state)?;
+    let report = tree.save_with(&args.dest, &options, Some(&journal), &progress::ignore)?;
+
+    info!(
+        "{} {} pieces of media to {:?}, {} failed",
+        options.transfer.past_tense(),
+        report.saved,
+        args.dest,
+        report.failed
+    );
+    Ok(report.saved)
+}
+
+/// Prints the media that would be sorted, grouped as it would be, without saving it
+pub fn scan(args: &Arguments) -> Result<Status, Box<dyn Error>> {
+    let (tree, _) = gather(args, &progress::ignore)?;
+    if tree.size() == 0 {
+        return Ok(Status::NothingToDo);
+    }
+
+    tree.print()?;
+
+    Ok(Status::Success)
+}
+
+/// Works out how the media would be sorted and writes it to `file` as a plan, without
+/// saving anything
+pub fn plan(args: &Arguments, file: &Path) -> Result<Status, Box<dyn Error>> {
+    let (tree, _) = gather(args, &progress::ignore)?;
+    if tree.size() == 0 {
+        return Ok(Status::NothingToDo);
+    }
+
+    let options = args.save_options();
+    let mut steps = tree.plan(&args.dest, &options)?;
+
+    // Hashes let the plan be checked against the sources before it's applied
+    for step in &mut steps {
+        step.hash = Some(hash_file(&step.source)?);
+    }
+
+    let count = steps.len();
+    Plan::new(&args.dest, options.transfer, steps).write(file)?;
+    info!("Planned {} pieces of media in {:?}", count, file);
+
+    Ok(Status::Success)
+}
+
+/// Carries out a plan written by `plan`, after checking none of its media has changed
+pub fn apply(args: &ApplyArguments, on_event: Hook) -> Result<Status, Box<dyn Error>> {
+    let plan = Plan::read(&args.file)?;
+
+    let drift = plan.drift();
+    for change in &drift {
+        error!("{}", change);
+    }
+    if !drift.is_empty() {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} pieces of media changed since the plan was made, make a new plan.",
+                drift.len()
+            ),
+        )));
+    }
+
+    fs::create_dir_all(&plan.dest)
+        .map_err(|e| format!("The destination {:?} could not be created: {e}", plan.dest))?;
+    let Some(_lock) = lock(&plan.dest, args.wait)? else {
+        return Ok(Status::Busy);
+    };
+
+    info!("Saving planned media...");
+    let save_start = Instant::now();
+    let options = args.save_options(plan.transfer);
+    let journal = match &args.journal {
+        Some(path) => Journal::create(path)?,
+        None => Journal::create(&Journal::default_path(&plan.dest))?,
+    };
+    let report = execute(&plan.steps, &options, Some(&journal), on_event)?;
+
+    info!(
+        "Applied {} steps to {:?} in {:?}: {} saved, {} skipped, {} failed",
+        plan.steps.len(),
+        plan.dest,
+        save_start.elapsed(),
+        report.saved,
+        report.skipped,
+        report.failed
+    );
+
+    info!("Undo this run with: img-sort undo {:?}", journal.path());
+
+    Ok(save_status(&report))
+}
+
+/// Reverses the operations recorded in a journal, newest first
+pub fn undo(args: &UndoArguments) -> Result<Status, Box<dyn Error>> {
+    let recorded = Journal::read(&args.file)?;
+    let entries = not_undone(&recorded);
+    if entries.is_empty() {
+        warn!("There is nothing to undo in {:?}", args.file);
+        return Ok(Status::NothingToDo);
+    }
+
+    // What's undone is recorded, so verify and later undos know it's gone
+    let journal = Journal::create(&args.file)?;
+    let mut failed = 0;
+    for entry in entries.iter().rev() {
+        match entry.undo() {
+            Ok(()) => {
+                debug!("Undid saving {:?} to {:?}", entry.source, entry.dest);
+                if let Err(e) = journal.record(&entry.as_undone()) {
+                    warn!("Could not record undoing {:?}: {}", entry.dest, e);
+                }
+            }
+            Err(e) => {
+                warn!("Could not undo saving {:?}: {}", entry.source, e);
+                failed += 1;
+            }
+        }
+
+        if entry.replaced {
+            warn!(
+                "{:?} replaced an existing file, which undo can't bring back",
+                entry.dest
+            );
+        }
+    }
+    remove_empty_dirs(&args.file, &entries);
+
+    info!(
+        "Undid {} of {} operations from {:?}",
+        entries.len() - failed,
+        entries.len(),
+        args.file
+    );
+
+    if failed > 0 {
+        Ok(Status::PartialFailure)
+    } else {
+        Ok(Status::Success)
+    }
+}
+
+/// Dates videos img-sort couldn't from when ffprobe says they were recorded
+fn read_with_ffprobe(tree: &mut Tree) {
+    let mut missing = false;
+    tree.update(|image| {
+        if missing || image.datetime.is_some() || image.media != MediaClass::Video {
+            return;
+        }
+        match ffprobe::read_creation_time(&image.path) {
+            Ok(Some((datetime, offset))) => {
+                debug!("ffprobe dated {:?} to {}", image.path, datetime);
+                image.datetime = Some(datetime);
+                image.date_source = Some(DateSource::Metadata);
+                image.offset = Some(offset);
+            }
+            Ok(None) => debug!("ffprobe found no date in {:?}", image.path),
+            // Without ffprobe, there's no use trying the rest
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("Couldn't run ffprobe: {}", e);
+                missing = true;
+            }
+            Err(e) => debug!("ffprobe couldn't read {:?}: {}", image.path, e),
+        }
+    });
+}
+
+/// Dates media img-sort couldn't from what exiftool reads, keeping what was already known
+fn read_with_exiftool(tree: &mut Tree, date_tags: &[DateTag]) {
+    let undated: Vec<&Path> = tree
+        .images()
+        .filter(|image| image.datetime.is_none())
+        .map(|image| image.path.as_path())
+        .collect();
+    if undated.is_empty() {
+        return;
+    }
+    let mut read = match exiftool::read_metadata(&undated, date_tags) {
+        Ok(read) => read,
+        Err(e) => {
+            warn!("Couldn't read metadata with exiftool: {}", e);
+            return;
+        }
+    };
+
+    tree.update(|image| {
+        let Some(metadata) = read.remove(&image.path) else {
+            return;
+        };
+        if let Some(datetime) = metadata.datetime {
+            debug!("exiftool dated {:?} to {}", image.path, datetime);
+            image.datetime = Some(datetime);
+            image.date_source = metadata.date_source;
+            image.offset = metadata.offset;
+        }
+        image.camera = image.camera.take().or(metadata.camera);
+        image.coordinates = image.coordinates.or(metadata.coordinates);
+        image.rating = image.rating.or(metadata.rating);
+    });
+}
+
+/// Forgets dates outside `years`, like those of scanners and cameras whose clocks were
+/// never set, so the media is dated some other way or sorted as undated
+fn forget_implausible_dates(tree: &mut Tree, years: &RangeInclusive<i32>) {
+    tree.update(|image| {
+        let Some(datetime) = image.datetime else {
+            return;
+        };
+        if !years.contains(&datetime.year()) {
+            warn!(
+                "Ignoring the date {} of {:?}, which isn't between {} and {}",
+                datetime,
+                image.path,
+                years.start(),
+                years.end()
+            );
+            image.datetime = None;
+            image.date_source = None;
+            image.offset = None;
+        }
+    });
+}
+
+/// Finds, filters and checks the media to sort, counting what's left out along the way
+fn gather(args: &Arguments, on_event: Hook) -> Result<(Tree, Summary), Box<dyn Error>> {
+    let mut tree = build_tree(args);
+
+    info!("Searching for media...");
+    let find_start = Instant::now();
+    if let Some(list) = &args.files_from {
+        find_listed(list, args.date_tags(), &mut tree, on_event)?;
+    }
+    for path in &args.path {
+        on_event(Event::Scanning(path));
+        let walker = build_glob_walker(path, &args.patterns(), &args.scan_options())?;
+
+        // A source without media is fine as long as another one has some
+        if find_with(walker, args.date_tags(), &mut tree, on_event).is_err() {
+            warn!("No media found in {:?}", path);
+        }
+    }
+    let find_duration = find_start.elapsed();
+
+    let (tree, summary) = refine(args, tree)?;
+    if tree.size() == 0 {
+        warn!("Did not find any media to sort.");
+    } else {
+        info!(
+            "Found {} pieces of media in {:?}",
+            tree.size(),
+            find_duration
+        );
+    }
+    Ok((tree, summary))
+}
+
+/// Dates, pairs and filters the media found for a run, the same way whether it was
+/// found by a scan or arrived while watching
+fn refine(args: &Arguments, mut tree: Tree) -> Result<(Tree, Summary), Box<dyn Error>> {
+    // A wrong date is worse than none, which other sources can still fill in
+    let years = args.years();
+    forget_implausible_dates(&mut tree, &years);
+
+    // ffprobe reads the containers of cameras and camcorders img-sort doesn't
+    if args.use_ffprobe {
+        read_with_ffprobe(&mut tree);
+    }
+
+    // exiftool knows formats and maker notes img-sort doesn't
+    if args.use_exiftool {
+        read_with_exiftool(&mut tree, args.date_tags());
+    }
+
+    // Cameras shooting RAW and JPEG name both files after the shot
+    let paired = pair_raw_jpeg(&mut tree, args.raw_pairs);
+    if paired > 0 {
+        info!("Left out {} pieces of media shot in another format", paired);
+    }
+
+    // A Live Photo's video is saved beside its photo, under the same name
+    let live = pair_live_photos(&mut tree);
+    if live > 0 {
+        debug!("Found {} Live Photos", live);
+    }
+
+    // Sidecars beside the media are saved along with it
+    associate_sidecars(&mut tree);
+
+    // Samsung and Pixel phones hide a short video inside their photos
+    if args.split_motion_photos {
+        let motion = find_motion_photos(&mut tree);
+        if motion > 0 {
+            debug!("Found {} Motion Photos", motion);
+        }
+    }
+
+    // Lightroom and digiKam keep curated tags in XMP and IPTC
+    if !args.filter_keyword.is_empty() || args.layout().keys.contains(&Key::Keyword) {
+        tree.update(|image| image.keywords = read_keywords(&image.path));
+    }
+    // Editors write stars into XMP, which outranks the EXIF of cameras
+    if args.min_rating.is_some() {
+        tree.update(|image| image.rating = read_rating(&image.path).or(image.rating));
+    }
+
+    // Takeout keeps the real date of many photos only in their sidecars, which sort them
+    // whether or not the date is also written into the copies
+    tree.update(|image| {
+        if image.datetime.is_none() {
+            image.datetime = read_sidecar(&image.path).and_then(|sidecar| sidecar.taken);
+            image.date_source = image.datetime.map(|_| DateSource::Sidecar);
+            image.offset = None;
+        }
+    });
+
+    // iCloud exports keep the date of each photo in a CSV beside it
+    date_from_details(&mut tree);
+
+    // RAW workflows often keep a corrected date in an XMP sidecar
+    if args.xmp != XmpPriority::Ignore {
+        tree.update(|image| {
+            if image.datetime.is_none() || args.xmp == XmpPriority::Prefer {
+                if let Some(taken) = read_sidecar_date(&image.path) {
+                    image.datetime = Some(taken);
+                    image.date_source = Some(DateSource::Sidecar);
+                    image.offset = None;
+                }
+            }
+        });
+    }
+
+    // Phones and messaging apps name media after when it was taken, or received
+    tree.update(|image| {
+        if image.datetime.is_some() {
+            return;
+        }
+        if let Some((datetime, source)) = filename_datetime(&image.path, &args.filename_pattern) {
+            debug!("Dated {:?} from {}", image.path, source);
+            image.datetime = Some(datetime);
+            image.date_source = Some(source);
+            image.offset = None;
+        }
+    });
+
+    // Scans rarely carry a date, so whole boxes of them are dated by hand
+    let overridden = date_from_overrides(&mut tree);
+    if overridden > 0 {
+        debug!(
+            "Dated {} pieces of media from {}",
+            overridden,
+            overrides::OVERRIDES_FILE
+        );
+    }
+
+    // Readers and sidecars since the scan can be just as wrong
+    forget_implausible_dates(&mut tree, &years);
+
+    // Screenshots and downloads often have nothing but their file times to go by
+    if args.fallback != Fallback::None {
+        tree.update(|image| {
+            if image.datetime.is_some() {
+                return;
+            }
+            if let Some((datetime, source)) = file_datetime(&image.path, args.fallback) {
+                debug!("Dated {:?} from {}", image.path, source);
+                image.datetime = Some(datetime);
+                image.date_source = Some(source);
+                image.offset = None;
+            }
+        });
+    }
+
+    // Photos taken abroad are sorted by the day and month it was here, or wherever asked
+    if let Some(target) = args.convert_to_tz {
+        let assumed = args.assume_tz.unwrap_or(Zone::Local);
+        tree.update(|image| {
+            let Some(datetime) = image.datetime else {
+                return;
+            };
+            if let Some((converted, offset)) = convert(datetime, image.offset, assumed, target) {
+                trace!("Moved {:?} from {} to {}", image.path, datetime, converted);
+                image.datetime = Some(converted);
+                image.offset = Some(offset);
+            }
+        });
+    }
+
+    // A burst's frames are kept together, apart from the rest of the day
+    if args.group_bursts {
+        let gap = args.burst_gap.unwrap_or(Duration::from_secs(1));
+        let bursts = group_bursts(&mut tree, TimeDelta::from_std(gap)?);
+        if bursts > 0 {
+            debug!("Found {} bursts", bursts);
+        }
+    }
+
+    // Screenshots are rarely wanted among photos
+    match args.screenshots {
+        Screenshots::Normal => {}
+        Screenshots::Route => tree.update(|image| {
+            if image.screenshot {
+                image.branch = Some(PathBuf::from(SCREENSHOTS_DIR));
+            }
+        }),
+        Screenshots::Skip => {
+            let skipped = tree.retain(|image| {
+                if image.screenshot {
+                    debug!("Left out {:?}, which is a screenshot", image.path);
+                }
+                !image.screenshot
+            });
+            if skipped > 0 {
+                info!("Left out {} screenshots", skipped);
+            }
+        }
+    }
+
+    let found = tree.size();
+    let filter = args.filter();
+    let filtered = tree.retain(|image| {
+        let keep = filter.matches(image);
+        if !keep {
+            debug!("Filtered out {:?}", image.path);
+        }
+        keep
+    });
+    if filtered > 0 {
+        info!("Filtered out {} pieces of media", filtered);
+    }
+
+    let unknown = if args.skip_unknown {
+        tree.retain(|image| {
+            if image.datetime.is_none() {
+                debug!("Leaving {:?} in place, it has no date", image.path);
+            }
+            image.datetime.is_some()
+        })
+    } else {
+        0
+    };
+
+    let summary = Summary::new(&tree, found, filtered, unknown);
+    if args.strict {
+        let undated: Vec<_> = tree
+            .images()
+            .filter(|image| image.datetime.is_none())
+            .collect();
+        for image in &undated {
+            error!("No date found in {:?}", image.path);
+        }
+
+        if !undated.is_empty() {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} pieces of media have no date.", undated.len()),
+            )));
+        }
+    }
+
+    Ok((tree, summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arguments::{parse_offset, Common};
+    use crate::cli::{Cli, Command};
+    use crate::config::{parse_args, read_config};
+    use crate::filter::{parse_coordinates, parse_distance, parse_size, Filter};
+    use crate::layout::{Hemisphere, Key, Layout, MonthStyle};
+    use crate::metadata::{
+        parse_exif_datetime, read_metadata, read_metadata_with, DateTag, Metadata, Strip,
+    };
+    use crate::pairs::RawPairs;
+    use crate::prompt::Prompt;
+    use crate::save::{check_free_space, Conflict, Outcome, SaveOptions, SaveReport, Step};
+    use crate::scan::{find, read_file_list, ScanOptions, PATTERNS};
+    use crate::sniff::Format;
+    use crate::template::Template;
+    use crate::transfer::Transfer;
+    use ::image::RgbImage;
+    use chrono::{DateTime, Datelike, FixedOffset, Locale, NaiveDate, TimeDelta};
+    use clap::{FromArgMatches, Parser};
+    use exif::experimental;
+    use exif::{Context, Field, In, Tag, Value};
+    use std::collections::{BTreeMap, HashSet};
+    use std::ffi::OsString;
+    use std::fs::File;
+    use std::io::{BufWriter, Cursor};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::time::Instant;
+    use tempfile::TempDir;
+
+    fn create_image_with_metadata(path: &PathBuf, datetime: &str) -> Result<(), Box<dyn Error>> {
+        // Create and save image
+        let img = RgbImage::new(32, 32);
+        img.save(path)?;
+
+        // Open image to add EXIF data
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+
+        // Add the DateTimeOriginal tag
+        let mut exif_writer = experimental::Writer::new();
+        let datetime_original = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![datetime.as_bytes().to_vec()]),
+        };
+
+        exif_writer.push_field(&datetime_original);
+        exif_writer.write(&mut writer, false)?;
+
+        Ok(())
+    }
+
+    fn year_month() -> Arguments {
+        Arguments {
+            years: true,
+            months: true,
+            ..Default::default()
+        }
+    }
+
+    fn touch(
+        dir: &TempDir,
+        names: impl IntoIterator<Item = impl AsRef<str>>,
+        datetime: Option<&str>,
+    ) {
+        for name in names {
+            let file_path = dir.path().join(name.as_ref());
+
+            if let Some(datetime) = datetime {
+                create_image_with_metadata(&file_path, datetime)
+                    .expect("Failed to create a test file with metadata");
+            } else {
+                File::create(&file_path).expect("Failed to create a test file");
+            }
+        }
+    }
+
+    #[test]
+    fn not_dir() {
+        // Ensure args has error on invalid directory
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path();
+
+        touch(&dir, ["f.txt"], None);
+
+        let path = dir_path.join("f.txt");
+
+        let args = Arguments {
+            path: vec![path],
+            dest: PathBuf::from("dest"),
+            months: true,
+            years: true,
+            ..Default::default()
+        };
+
+        let args = Arguments::validate(&args);
+
+        assert!(
+            args.is_err(),
+            "Expected an error for a file path used as a directory"
+        );
+    }
+
+    #[test]
+    fn invalid_path() {
+        // Ensure args has error on invalid path
+        let args = Arguments {
+            path: vec![PathBuf::from("bleh")],
+            dest: PathBuf::from("dest"),
+            months: true,
+            years: true,
+            ..Default::default()
+        };
+
+        let args = Arguments::validate(&args);
+
+        assert!(
+            args.is_err(),
+            "Expected an error for a non-existent file path used as a directory"
+        );
+    }
+    #[test]
+    fn invalid_sort_flags() {
+        // Ensure args has error on invalid path
+        let args = Arguments {
+            path: vec![PathBuf::from("bleh")],
+            dest: PathBuf::from("dest"),
+            months: false,
+            years: false,
+            ..Default::default()
+        };
+
+        let args = Arguments::validate(&args);
+
+        assert!(
+            args.is_err(),
+            "Expected an error for neither sort option selected"
+        );
+    }
+
+    #[test]
+    fn dest_not_dir() {
+        // Ensure args has error when the destination is a file
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["f.txt"], None);
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dir.path().join("f.txt"),
+            months: true,
+            years: true,
+            ..Default::default()
+        };
+
+        assert!(
+            args.validate().is_err(),
+            "Expected an error for a file used as the destination"
+        );
+    }
+
+    #[test]
+    fn dest_created() {
+        // Ensure a missing destination passes validation and is created when sorting
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = dir.path().join("library").join("sorted");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.clone(),
+            months: true,
+            years: true,
+            ..Default::default()
+        };
+
+        assert!(
+            args.validate().is_ok(),
+            "Expected OK for a creatable destination"
+        );
+        assert!(
+            !dest.exists(),
+            "Expected validation to leave the disk alone"
+        );
+
+        run(&args).expect("Failed to sort media");
+        assert!(dest.is_dir(), "Expected the destination to be created");
+    }
+
+    #[test]
+    fn plan_written() {
+        // Ensure a plan records every step with its hash and leaves the destination alone
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = dir.path().join("sorted");
+        let file = dir.path().join("plan.json");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.clone(),
+            years: true,
+            ..Default::default()
+        };
+        plan(&args, &file).expect("Failed to plan");
+        assert!(!dest.exists(), "Expected planning to save nothing");
+
+        let plan = Plan::read(&file).expect("Failed to read the plan");
+        assert_eq!(plan.dest, dest);
+        assert_eq!(plan.transfer, Transfer::Copy);
+        assert_eq!(plan.steps.len(), 2);
+        for step in &plan.steps {
+            assert_eq!(step.outcome, Outcome::Save);
+            assert_eq!(step.dest.parent(), Some(dest.join("2024").as_path()));
+            assert_eq!(step.hash, Some(hash_file(&step.source).unwrap()));
+        }
+    }
+
+    #[test]
+    fn apply_checks_drift() {
+        // Ensure a plan is refused once a source changes, and carried out as planned otherwise
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = dir.path().join("sorted");
+        let file = dir.path().join("plan.json");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.clone(),
+            years: true,
+            ..Default::default()
+        };
+        let apply_args = ApplyArguments {
+            file: file.clone(),
+            ..Default::default()
+        };
+
+        plan(&args, &file).expect("Failed to plan");
+        std::fs::write(dir.path().join("b.jpg"), "edited").unwrap();
+        assert!(
+            apply(&apply_args, &progress::ignore).is_err(),
+            "Expected an error for a changed source"
+        );
+        assert!(!dest.exists(), "Expected nothing to be saved");
+
+        plan(&args, &file).expect("Failed to plan");
+        let status = apply(&apply_args, &progress::ignore).expect("Failed to apply");
+        assert_eq!(status, Status::Success);
+        for step in Plan::read(&file).unwrap().steps {
+            assert!(step.dest.exists(), "Expected {:?} to be saved", step.dest);
+        }
+    }
+
+    #[test]
+    fn undo_from_journal() {
+        // Ensure undoing a run moves media back and removes the directories it made
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            months: true,
+            move_media: true,
+            ..Default::default()
+        };
+        run(&args).expect("Failed to sort media");
+        assert!(!dir.path().join("a.jpg").exists());
+
+        let journals = Journal::list(dest.path()).expect("Expected a journal directory");
+        assert_eq!(journals.len(), 1, "Expected one journal for the run");
+        let entries = Journal::read(&journals[0]).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.op == Transfer::Move));
+
+        let undo_args = UndoArguments {
+            file: journals[0].clone(),
+            ..Default::default()
+        };
+        assert_eq!(undo(&undo_args).unwrap(), Status::Success);
+        assert!(dir.path().join("a.jpg").exists());
+        assert!(dir.path().join("b.jpg").exists());
+        assert!(
+            !dest.path().join("2024").exists(),
+            "Expected the emptied bucket to be removed"
+        );
+    }
+
+    #[test]
+    fn journal_appended() {
+        // Ensure a chosen journal gets a line per operation, kept across runs
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let journal = dir.path().join("runs.jsonl");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            on_conflict: Conflict::Skip,
+            journal: Some(journal.clone()),
+            ..Default::default()
+        };
+        run(&args).expect("Failed to sort media");
+        touch(&dir, ["c.jpg"], Some("2024:03:01 00:00:00"));
+        run(&args).expect("Failed to sort media");
+
+        let entries = Journal::read(&journal).expect("Failed to read the journal");
+        let sources: Vec<_> = entries.iter().map(|entry| entry.source.clone()).collect();
+        assert_eq!(entries.len(), 3, "Expected skipped media to be left out");
+        assert_eq!(sources[2], dir.path().join("c.jpg"));
+        for entry in &entries {
+            assert_eq!(entry.op, Transfer::Copy);
+            assert_eq!(entry.hash, hash_file(&entry.source).unwrap());
+            assert_eq!(entry.bytes, std::fs::metadata(&entry.dest).unwrap().len());
+        }
+        assert_eq!(Journal::latest(dest.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn resume_from_journal() {
+        // Ensure a resumed run skips media the journal saved and doesn't number it again
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let mut args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            ..Default::default()
+        };
+        run(&args).expect("Failed to sort media");
+
+        // The run was cut short while saving c.jpg
+        touch(&dir, ["c.jpg"], Some("2024:03:01 00:00:00"));
+        let year = dest.path().join("2024");
+        std::fs::write(transfer::partial_path(&year.join("c.jpg")), "trunc").unwrap();
+
+        args.resume = true;
+        run(&args).expect("Failed to resume");
+
+        let mut names: Vec<_> = std::fs::read_dir(&year)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.jpg", "b.jpg", "c.jpg"]);
+
+        let journal = Journal::latest(dest.path()).unwrap().unwrap();
+        assert_eq!(Journal::read(&journal).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn interrupted_run_finishes_saving_file() {
+        // Ensure an interrupted run saves the file it's on, and no more, before stopping.
+        // Interrupting stops every run in the process, so this runs in a process of its own
+        const CHILD: &str = "IMG_SORT_INTERRUPTED_TEST";
+        if std::env::var_os(CHILD).is_none() {
+            let status = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "tests::interrupted_run_finishes_saving_file"])
+                .env(CHILD, "1")
+                .status()
+                .expect("Failed to run the test in a process of its own");
+            assert!(status.success());
+            return;
+        }
+
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(
+            &dir,
+            ["a.jpg", "b.jpg", "c.jpg"],
+            Some("2024:03:01 00:00:00"),
+        );
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            threads: 1,
+            ..Default::default()
+        };
+        let status = run_with(&args, &|event| {
+            if let Event::Saved { .. } = event {
+                interrupt::interrupt();
+            }
+        })
+        .expect("Failed to sort media");
+        assert_eq!(status, Status::Interrupted);
+
+        // Only whole files are left, and the journal knows which to resume from
+        let names: Vec<_> = std::fs::read_dir(dest.path().join("2024"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 1);
+        assert!(["a.jpg", "b.jpg", "c.jpg"].contains(&names[0].to_str().unwrap()));
+
+        let journal = Journal::latest(dest.path()).unwrap().unwrap();
+        assert_eq!(Journal::read(&journal).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn verify_destination() {
+        // Ensure corrupted, missing and extra files are each found
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(
+            &dir,
+            ["a.jpg", "b.jpg", "c.jpg"],
+            Some("2024:03:01 00:00:00"),
+        );
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            ..Default::default()
+        };
+        run(&args).expect("Failed to sort media");
+
+        let year = dest.path().canonicalize().unwrap().join("2024");
+        std::fs::write(year.join("a.jpg"), "flipped bits").unwrap();
+        std::fs::remove_file(year.join("b.jpg")).unwrap();
+        std::fs::write(year.join("d.jpg"), "").unwrap();
+
+        let journal = Journal::latest(dest.path()).unwrap().unwrap();
+        let verification = verify::verify(dest.path(), &Journal::read(&journal).unwrap())
+            .expect("Failed to verify");
+        assert_eq!(
+            verification,
+            verify::Verification {
+                intact: 1,
+                corrupted: vec![year.join("a.jpg")],
+                missing: vec![year.join("b.jpg")],
+                extra: vec![year.join("d.jpg")],
+                unreadable: Vec::new(),
+            }
+        );
+        assert!(!verification.passed());
+    }
+
+    #[test]
+    fn verify_replays_journals() {
+        // Ensure undone media isn't expected, moved media is only expected where it went,
+        // and a file that can't be read fails alone
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            ..Default::default()
+        };
+        run(&args).expect("Failed to sort media");
+        let journal = Journal::latest(dest.path()).unwrap().unwrap();
+        let undo_args = UndoArguments {
+            file: journal.clone(),
+            ..Default::default()
+        };
+        assert_eq!(undo(&undo_args).unwrap(), Status::Success);
+        assert_eq!(undo(&undo_args).unwrap(), Status::NothingToDo);
+
+        let verification = verify::verify(dest.path(), &Journal::read(&journal).unwrap())
+            .expect("Failed to verify");
+        assert_eq!(verification, verify::Verification::default());
+
+        // Moved within the destination, as audit --fix does
+        let year = dest.path().canonicalize().unwrap().join("2024");
+        std::fs::create_dir_all(year.join("unreadable.jpg")).unwrap();
+        std::fs::write(year.join("c.jpg"), "c").unwrap();
+        let hash = hash_file(&year.join("c.jpg")).unwrap();
+        let misfiled = year.join("01").join("c.jpg");
+        let entries = [
+            journal::Entry::new(
+                Transfer::Copy,
+                Path::new("c.jpg"),
+                &misfiled,
+                1,
+                hash.clone(),
+            ),
+            journal::Entry::new(
+                Transfer::Move,
+                &misfiled,
+                &year.join("c.jpg"),
+                1,
+                hash.clone(),
+            ),
+            journal::Entry::new(
+                Transfer::Copy,
+                Path::new("u.jpg"),
+                &year.join("unreadable.jpg"),
+                1,
+                hash,
+            ),
+        ];
+        let verification = verify::verify(dest.path(), &entries).expect("Failed to verify");
+        assert_eq!(
+            verification,
+            verify::Verification {
+                intact: 1,
+                unreadable: vec![year.join("unreadable.jpg")],
+                ..Default::default()
+            }
+        );
+        assert!(!verification.passed());
+    }
+
+    #[test]
+    fn refile_keeps_folder_names() {
+        // Ensure misfiled media is given folders named like the library's own
+        let taken = NaiveDate::from_ymd_opt(2024, 3, 9)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let refile = |path: &str| audit::refile(Path::new(path), taken);
+
+        let cases = [
+            ("2023/01/a.jpg", "2024/03/a.jpg"),
+            ("2023/1/a.jpg", "2024/3/a.jpg"),
+            ("Photos/2023/jan/a.jpg", "Photos/2024/mar/a.jpg"),
+            ("2023/2023-01/a.jpg", "2024/2024-03/a.jpg"),
+            ("2023/01-January/Trip/a.jpg", "2024/03-March/Trip/a.jpg"),
+            ("2023/a.jpg", "2024/a.jpg"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(refile(path), Some(PathBuf::from(expected)), "{path}");
+        }
+        assert_eq!(refile("Misc/a.jpg"), None);
+    }
+
+    #[test]
+    fn audit_fixes_misfiled_media() {
+        // Ensure media in the wrong month is reported and moved by --fix
+        let library = TempDir::new().expect("Failed to create temporary folder");
+        let march = library.path().join("2024").join("March");
+        let april = library.path().join("2024").join("April");
+        std::fs::create_dir_all(&march).unwrap();
+        std::fs::create_dir_all(&april).unwrap();
+
+        create_image_with_metadata(&march.join("a.jpg"), "2024:03:01 00:00:00").unwrap();
+        create_image_with_metadata(&march.join("b.jpg"), "2024:04:01 00:00:00").unwrap();
+
+        let misfiled = audit::audit(library.path()).expect("Failed to audit");
+        assert_eq!(misfiled.len(), 1);
+        assert_eq!(misfiled[0].dest, april.join("b.jpg"));
+
+        let args = AuditArguments {
+            library: library.path().to_path_buf(),
+            fix: true,
+            ..Default::default()
+        };
+        assert_eq!(audit(&args).unwrap(), Status::Success);
+        assert!(april.join("b.jpg").exists());
+        assert!(!march.join("b.jpg").exists());
+        assert!(audit::audit(library.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stats_as_json() {
+        // Ensure the JSON output carries each breakdown with its counts
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&dir, ["c.png"], None);
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&Stats::new(tree.images()).to_json().unwrap()).unwrap();
+
+        assert_eq!(json["total"]["files"], 3);
+        assert_eq!(json["years"]["2024"]["files"], 2);
+        assert_eq!(json["years"]["Unknown"]["files"], 1);
+        assert_eq!(json["months"]["2024-03"]["files"], 2);
+        assert_eq!(json["cameras"]["Unknown"]["files"], 3);
+        assert_eq!(json["types"]["jpg"]["files"], 2);
+        assert_eq!(json["types"]["png"]["files"], 1);
+        let bytes = std::fs::metadata(dir.path().join("a.jpg")).unwrap().len() * 2;
+        assert_eq!(json["types"]["jpg"]["bytes"], bytes);
+    }
+
+    #[test]
+    fn stats_by_year_and_type() {
+        // Ensure media is counted by year, month, camera and extension
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&dir, ["c.png"], None);
+
+        let images = [
+            Image::new(dir.path().join("a.jpg"), "a.jpg".to_string())
+                .with_datetime(
+                    NaiveDate::from_ymd_opt(2024, 3, 1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+                )
+                .with_camera(Some("Canon EOS 5D".to_string())),
+            Image::new(dir.path().join("b.jpg"), "b.jpg".to_string()).with_datetime(
+                NaiveDate::from_ymd_opt(2024, 3, 1).and_then(|d| d.and_hms_opt(0, 0, 0)),
+            ),
+            Image::new(dir.path().join("c.png"), "c.png".to_string()),
+        ];
+        let stats = Stats::new(&images);
+
+        assert_eq!(stats.total.files, 3);
+        assert_eq!(stats.years["2024"].files, 2);
+        assert_eq!(stats.years["Unknown"].files, 1);
+        assert_eq!(stats.months["2024-03"].files, 2);
+        assert_eq!(stats.cameras["Canon EOS 5D"].files, 1);
+        assert_eq!(stats.cameras["Unknown"].files, 2);
+        assert_eq!(stats.types["jpg"].files, 2);
+        assert_eq!(
+            stats.types["jpg"].bytes,
+            std::fs::metadata(dir.path().join("a.jpg")).unwrap().len() * 2
+        );
+        assert_eq!(
+            stats.total.bytes,
+            stats.years.values().map(|c| c.bytes).sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn dedupe_hardlinks_duplicates() {
+        // Ensure identical files are grouped, and hardlinked ones aren't found again
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        std::fs::write(dir.path().join("a.jpg"), "same").unwrap();
+        std::fs::write(dir.path().join("b.jpg"), "same").unwrap();
+        std::fs::write(dir.path().join("c.jpg"), "diff").unwrap();
+        std::fs::write(dir.path().join("d.jpg"), "").unwrap();
+        std::fs::write(dir.path().join("e.jpg"), "").unwrap();
+
+        let files =
+            || ["a.jpg", "b.jpg", "c.jpg", "d.jpg", "e.jpg"].map(|name| dir.path().join(name));
+        let groups = dedupe::find_duplicates(files()).expect("Failed to compare files");
+        assert_eq!(
+            groups,
+            [dedupe::Group {
+                files: vec![dir.path().join("a.jpg"), dir.path().join("b.jpg")],
+                bytes: 4,
+            }]
+        );
+
+        let args = DedupeArguments {
+            paths: vec![dir.path().to_path_buf()],
+            hardlink: true,
+            ..Default::default()
+        };
+        assert_eq!(dedupe(&args).unwrap(), Status::Success);
+        assert_eq!(
+            dedupe::find_duplicates(files()).unwrap().len(),
+            1,
+            "Expected a dry run"
+        );
+
+        let args = DedupeArguments {
+            execute: true,
+            ..args
+        };
+        assert_eq!(dedupe(&args).unwrap(), Status::Success);
+        assert!(dedupe::find_duplicates(files()).unwrap().is_empty());
+        assert_eq!(std::fs::read(dir.path().join("b.jpg")).unwrap(), b"same");
+    }
+
+    #[test]
+    fn diff_by_contents() {
+        // Ensure renamed media matches and media only one tree has is listed
+        let a = TempDir::new().expect("Failed to create temporary folder");
+        let b = TempDir::new().expect("Failed to create temporary folder");
+        std::fs::create_dir(b.path().join("2024")).unwrap();
+
+        std::fs::write(a.path().join("IMG_1.jpg"), "one").unwrap();
+        std::fs::write(a.path().join("IMG_2.jpg"), "two").unwrap();
+        std::fs::write(b.path().join("2024").join("one.jpg"), "one").unwrap();
+        std::fs::write(b.path().join("2024").join("three.jpg"), "three").unwrap();
+
+        let difference = diff::diff(
+            &list_media(a.path()).unwrap(),
+            &list_media(b.path()).unwrap(),
+        )
+        .expect("Failed to compare trees");
+        assert_eq!(
+            difference,
+            diff::Difference {
+                shared: 1,
+                only_a: vec![a.path().join("IMG_2.jpg")],
+                only_b: vec![b.path().join("2024").join("three.jpg")],
+            }
+        );
+
+        let args = DiffArguments {
+            a: a.path().to_path_buf(),
+            b: b.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert_eq!(diff(&args).unwrap(), Status::PartialFailure);
+    }
+
+    #[test]
+    fn merge_libraries() {
+        // Ensure folders are kept, copies already there skipped and clashes renamed
+        let theirs = TempDir::new().expect("Failed to create temporary folder");
+        let ours = TempDir::new().expect("Failed to create temporary folder");
+        let (their_march, our_march) = (
+            theirs.path().join("2024/March"),
+            ours.path().join("2024/March"),
+        );
+        std::fs::create_dir_all(&their_march).unwrap();
+        std::fs::create_dir_all(&our_march).unwrap();
+
+        std::fs::write(their_march.join("a.jpg"), "same").unwrap();
+        std::fs::write(their_march.join("b.jpg"), "theirs").unwrap();
+        std::fs::write(their_march.join("c.jpg"), "kept elsewhere").unwrap();
+        std::fs::write(theirs.path().join("2024/d.jpg"), "new").unwrap();
+        std::fs::write(our_march.join("a.jpg"), "same").unwrap();
+        std::fs::write(our_march.join("b.jpg"), "ours").unwrap();
+        std::fs::write(ours.path().join("2024/c.jpg"), "kept elsewhere").unwrap();
+
+        let args = MergeArguments {
+            source: theirs.path().to_path_buf(),
+            dest: ours.path().to_path_buf(),
+            dedupe: true,
+            ..Default::default()
+        };
+        assert_eq!(merge(&args, &progress::ignore).unwrap(), Status::Success);
+
+        assert_eq!(std::fs::read(our_march.join("b.jpg")).unwrap(), b"ours");
+        assert_eq!(std::fs::read(our_march.join("b_1.jpg")).unwrap(), b"theirs");
+        assert!(!our_march.join("a_1.jpg").exists());
+        assert!(!our_march.join("c.jpg").exists());
+        assert!(ours.path().join("2024/d.jpg").exists());
+    }
+
+    #[test]
+    fn watch_waits_for_files_to_settle() {
+        // Ensure a file is only passed on once it's stopped changing for the settle time
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let path = dir.path().join("a.jpg");
+        let settle = Duration::from_secs(2);
+        let start = Instant::now();
+
+        std::fs::write(&path, "part").unwrap();
+        let mut pending = watch::Pending::new(settle);
+        pending.note(path.clone(), start);
+        assert!(pending.settled(start + settle / 2).is_empty());
+
+        // Still growing when the settle time is up, so it gets longer
+        std::fs::write(&path, "partial").unwrap();
+        assert!(pending.settled(start + settle).is_empty());
+        assert!(pending.settled(start + settle * 3 / 2).is_empty());
+        assert_eq!(pending.settled(start + settle * 2), [path]);
+        assert!(pending.settled(start + settle * 3).is_empty());
+    }
+
+    #[test]
+    fn watch_sorts_like_sort() {
+        // Ensure media arriving while watching goes where a sort would put it, dated
+        // from its name when it has no EXIF
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let sorted = TempDir::new().expect("Failed to create temporary folder");
+        let watched = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["IMG_20240315_120000.jpg"], None);
+        let photo = dir.path().join("IMG_20240315_120000.jpg");
+
+        let args = |dest: &TempDir| Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+        run(&args(&sorted)).expect("Failed to sort media");
+        let journal = Journal::create(&Journal::default_path(watched.path())).unwrap();
+        let report = sort_arrived(&args(&watched), vec![photo], &journal)
+            .expect("Failed to sort arrived media");
+        assert_eq!(report.map(|report| report.saved), Some(1));
+
+        let saved = |dest: &TempDir| {
+            let month = dest.path().join("2024").join("March");
+            month.join("IMG_20240315_120000.jpg").exists()
+        };
+        assert!(saved(&sorted), "Expected the sort to date it from its name");
+        assert!(
+            saved(&watched),
+            "Expected the watch to save it beside the sort's"
+        );
+    }
+
+    #[test]
+    fn watch_takes_incoming_positionally() {
+        // Ensure watch takes its directories without --path
+        let cli = Cli::try_parse_from(["img-sort", "watch", "in", "-o", "lib", "-y"])
+            .expect("Failed to parse watch arguments");
+        let Command::Watch(watch) = cli.command else {
+            panic!("Expected the watch subcommand");
+        };
+        assert_eq!(watch.args.path, [PathBuf::from("in")]);
+        assert_eq!(watch.settle, 2.0);
+    }
+
+    #[test]
+    fn daemon_sorts_only_new_media() {
+        // Ensure each scan saves only media the state journal hasn't seen
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let state = dest.path().join("state.jsonl");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            ..Default::default()
+        };
+        assert_eq!(sort_new(&args, &state).unwrap(), 1);
+        assert_eq!(sort_new(&args, &state).unwrap(), 0);
+
+        touch(&dir, ["b.jpg"], Some("2024:03:01 00:00:00"));
+        assert_eq!(sort_new(&args, &state).unwrap(), 1);
+        assert!(!dest.path().join("2024").join("a_1.jpg").exists());
+    }
+
+    #[test]
+    fn daemon_intervals() {
+        // Ensure intervals parse with and without units, and --every needs --daemon
+        let every = |interval: &str| {
+            let args = ["sort", "-p", ".", "-o", "out", "-y", "--daemon", "--every"];
+            Cli::try_parse_from(["img-sort"].into_iter().chain(args).chain([interval])).is_ok()
+        };
+
+        assert!(every("90"));
+        assert!(every("15m"));
+        assert!(every("1h"));
+        assert!(!every("0s"));
+        assert!(!every("1w"));
+        assert!(!every(&format!("{}d", u64::MAX)));
+        assert!(
+            Cli::try_parse_from(["img-sort", "sort", "-p", ".", "-o", "out", "--every", "1h"])
+                .is_err(),
+            "Expected --every to need --daemon"
+        );
+    }
+
+    #[test]
+    fn destination_locked() {
+        // Ensure a second run on a locked destination stops, and can run once it's free
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            years: true,
+            ..Default::default()
+        };
+
+        let held = Lock::acquire(dest.path(), false).expect("Failed to lock");
+        let error = Lock::acquire(dest.path(), false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(error.to_string().contains(&std::process::id().to_string()));
+        assert_eq!(run(&args).unwrap(), Status::Busy);
+        assert_eq!(Status::Busy.code(), 75);
+        assert!(!dest.path().join("2024").exists());
+
+        drop(held);
+        assert_eq!(run(&args).unwrap(), Status::Success);
+    }
+
+    #[test]
+    fn fix_dates_writes_inferred_dates() {
+        // Ensure dates from names and folders are written into the EXIF of real JPEGs
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let july = dir.path().join("2019").join("07");
+        std::fs::create_dir_all(&july).unwrap();
+
+        RgbImage::new(8, 8)
+            .save(dir.path().join("IMG_20190704_101112.jpg"))
+            .unwrap();
+        RgbImage::new(8, 8).save(july.join("a.jpg")).unwrap();
+
+        let args = FixDatesArguments {
+            paths: vec![dir.path().to_path_buf()],
+            backup: true,
+            ..Default::default()
+        };
+        assert_eq!(fix_dates(&args).unwrap(), Status::Success);
+
+        let date = |y, m, d, h, min, s| NaiveDate::from_ymd_opt(y, m, d)?.and_hms_opt(h, min, s);
+        assert_eq!(
+            read_metadata(&dir.path().join("IMG_20190704_101112.jpg")).datetime,
+            date(2019, 7, 4, 10, 11, 12)
+        );
+        assert_eq!(
+            read_metadata(&july.join("a.jpg")).datetime,
+            date(2019, 7, 1, 0, 0, 0)
+        );
+        assert!(::image::open(july.join("a.jpg")).is_ok());
+        assert!(july.join("a.jpg_original").exists());
+
+        // Dated media is left alone
+        assert_eq!(fix_dates(&args).unwrap(), Status::NothingToDo);
+    }
+
+    #[test]
+    fn shift_dates_by_offset() {
+        // Ensure EXIF dates move by the offset, and only for media inside the filters
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let date = |y, m, d, h| NaiveDate::from_ymd_opt(y, m, d)?.and_hms_opt(h, 0, 0);
+
+        for (name, taken) in [
+            ("a.jpg", date(2019, 7, 4, 10)),
+            ("b.jpg", date(2020, 1, 1, 0)),
+        ] {
+            let path = dir.path().join(name);
+            RgbImage::new(8, 8).save(&path).unwrap();
+            write_datetime_original(&path, taken.unwrap()).unwrap();
+        }
+
+        assert_eq!(parse_offset("-2d3h"), Ok(TimeDelta::hours(-51)));
+        assert_eq!(parse_offset("+1h30m"), Ok(TimeDelta::minutes(90)));
+        assert!(parse_offset("3 fortnights").is_err());
+        assert!(parse_offset("0h").is_err());
+
+        let args = ShiftDatesArguments {
+            paths: vec![dir.path().to_path_buf()],
+            offset: parse_offset("-2d3h").unwrap(),
+            before: NaiveDate::from_ymd_opt(2019, 12, 31),
+            ..Default::default()
+        };
+        assert_eq!(shift_dates(&args).unwrap(), Status::Success);
+
+        assert_eq!(
+            read_metadata(&dir.path().join("a.jpg")).datetime,
+            date(2019, 7, 2, 7)
+        );
+        assert_eq!(
+            read_metadata(&dir.path().join("b.jpg")).datetime,
+            date(2020, 1, 1, 0)
+        );
+    }
+
+    #[test]
+    fn rewritten_exif_keeps_thumbnail() {
+        // Ensure changing a date in place keeps the thumbnail viewers show
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let path = dir.path().join("a.jpg");
+        let encode = |size| {
+            let mut jpeg = Vec::new();
+            RgbImage::new(size, size)
+                .write_to(&mut Cursor::new(&mut jpeg), ::image::ImageFormat::Jpeg)
+                .unwrap();
+            jpeg
+        };
+        let thumbnail = encode(4);
+
+        let fields = [
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"2024:01:01 00:00:00".to_vec()]),
+            },
+            Field {
+                tag: Tag::Compression,
+                ifd_num: In::THUMBNAIL,
+                value: Value::Short(vec![6]),
+            },
+        ];
+        let mut writer = experimental::Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        writer.set_jpeg(&thumbnail, In::THUMBNAIL);
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+        let jpeg = jpeg::replace_exif(&encode(8), Some(exif.get_ref())).unwrap();
+        std::fs::write(&path, jpeg).unwrap();
+
+        let taken = NaiveDate::from_ymd_opt(2020, 5, 1).and_then(|d| d.and_hms_opt(9, 0, 0));
+        write_datetime_original(&path, taken.unwrap()).unwrap();
+
+        assert_eq!(read_metadata(&path).datetime, taken);
+        assert_eq!(metadata::read_thumbnail(&path), Some(thumbnail));
+    }
+
+    #[test]
+    fn save_strips_metadata_from_copies() {
+        // Ensure copies lose their GPS, or all their metadata, while the original keeps it
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let src = dir.path().join("a.jpg");
+        RgbImage::new(8, 8).save(&src).unwrap();
+
+        let mut writer = experimental::Writer::new();
+        let fields = [
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"2024:01:01 00:00:00".to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSLatitudeRef,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"N".to_vec()]),
+            },
+        ];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut exif = std::io::Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+        let jpeg = jpeg::replace_exif(&std::fs::read(&src).unwrap(), Some(exif.get_ref()));
+        std::fs::write(&src, jpeg.unwrap()).unwrap();
+
+        let gps = |path: &Path| {
+            let file = std::fs::read(path).unwrap();
+            exif::Reader::new()
+                .read_from_container(&mut std::io::Cursor::new(file))
+                .ok()
+                .map(|exif| exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_some())
+        };
+
+        for (strip, expected) in [(Strip::Gps, Some(false)), (Strip::All, None)] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let mut tree = build_tree(&year_month());
+            tree.insert(Image::new(src.clone(), "a.jpg".to_string()));
+
+            let options = SaveOptions {
+                strip: Some(strip),
+                ..Default::default()
+            };
+            tree.save(dest.path(), &options)
+                .expect("Failed to save media");
+
+            let copy = dest.path().join("Unknown").join("a.jpg");
+            assert_eq!(gps(&copy), expected);
+            assert!(::image::open(&copy).is_ok());
+        }
+        assert_eq!(gps(&src), Some(true));
+        assert!(read_metadata(&src).datetime.is_some());
+    }
+
+    #[test]
+    fn strip_gps_from_xmp_and_fail_others() {
+        // Ensure the location goes from a copy's XMP too, and media that can't be
+        // stripped fails instead of being saved with its location
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let mut jpeg = Vec::new();
+        RgbImage::new(8, 8)
+            .write_to(&mut Cursor::new(&mut jpeg), ::image::ImageFormat::Jpeg)
+            .unwrap();
+
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+            <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                <rdf:Description
+                    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+                    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+                    exif:GPSLatitude="45,30.0N" xmp:Rating="3">
+                    <exif:GPSLongitude>73,34.0W</exif:GPSLongitude>
+                </rdf:Description>
+            </rdf:RDF>
+        </x:xmpmeta>"#;
+        let xmp = format!("http://ns.adobe.com/xap/1.0/\0{packet}");
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((xmp.len() + 2) as u16).to_be_bytes());
+        segment.extend(xmp.as_bytes());
+        jpeg.splice(2..2, segment);
+        let photo = dir.path().join("a.jpg");
+        std::fs::write(&photo, &jpeg).unwrap();
+        let png = dir.path().join("b.png");
+        RgbImage::new(8, 8).save(&png).unwrap();
+
+        let mut tree = build_tree(&year_month());
+        tree.insert(Image::new(photo.clone(), "a.jpg".to_string()));
+        tree.insert(Image::new(png.clone(), "b.png".to_string()));
+        let options = SaveOptions {
+            strip: Some(Strip::Gps),
+            ..Default::default()
+        };
+        let report = tree.save(dest.path(), &options).unwrap();
+
+        let text =
+            |path: &Path| String::from_utf8_lossy(&std::fs::read(path).unwrap()).into_owned();
+        let copy = text(&dest.path().join("Unknown").join("a.jpg"));
+        assert!(!copy.contains("GPS"), "Expected no location in {copy}");
+        assert!(copy.contains(r#"xmp:Rating="3""#));
+        assert!(text(&photo).contains("GPSLongitude"));
+
+        assert_eq!(report.saved, 1);
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.failures[0].0, png);
+        let unstripped = dest.path().join("Unknown").join("b.png");
+        assert!(!unstripped.exists());
+        assert!(!transfer::partial_path(&unstripped).exists());
+    }
+
+    #[test]
+    fn geotag_from_gpx_track() {
+        // Ensure media gets the location between the track points either side of it
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let date = |h, m, s| NaiveDate::from_ymd_opt(2024, 1, 1)?.and_hms_opt(h, m, s);
+        let utc = |h, m, s| {
+            let local = Local.from_local_datetime(&date(h, m, s).unwrap()).unwrap();
+            local.to_utc().to_rfc3339()
+        };
+
+        for (name, taken) in [("a.jpg", date(12, 0, 30)), ("b.jpg", date(18, 0, 0))] {
+            let path = dir.path().join(name);
+            RgbImage::new(8, 8).save(&path).unwrap();
+            write_datetime_original(&path, taken.unwrap()).unwrap();
+        }
+
+        let track = dir.path().join("track.gpx");
+        let gpx = format!(
+            r#"<gpx><trk><trkseg>
+                <trkpt lat="10.0" lon="20.0"><ele>100</ele><time>{}</time></trkpt>
+                <trkpt lat="10.1" lon="20.2"><ele>200</ele><time>{}</time></trkpt>
+            </trkseg></trk></gpx>"#,
+            utc(12, 0, 0),
+            utc(12, 1, 0)
+        );
+        std::fs::write(&track, gpx).unwrap();
+
+        let args = GeotagArguments {
+            common: Common::default(),
+            paths: vec![dir.path().to_path_buf()],
+            track,
+            offset: None,
+            max_gap: Duration::from_secs(300),
+            overwrite: false,
+            backup: false,
+            dry_run: false,
+        };
+        assert_eq!(geotag(&args).unwrap(), Status::Success);
+
+        let Coordinates {
+            latitude,
+            longitude,
+        } = read_metadata(&dir.path().join("a.jpg"))
+            .coordinates
+            .unwrap();
+        assert!((latitude - 10.05).abs() < 1e-5);
+        assert!((longitude - 20.1).abs() < 1e-5);
+
+        // Media taken hours from the track isn't placed
+        assert_eq!(read_metadata(&dir.path().join("b.jpg")).coordinates, None);
+    }
+
+    #[test]
+    fn report_lists_media_by_month() {
+        // Ensure the report shows dated media under its month, lists undated media and
+        // finds media with the same contents
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let report = dir.path().join("report.html");
+
+        touch(&dir, ["a.jpg", "d.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&dir, ["b & c.jpg"], None);
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            report: Some(report.clone()),
+            ..year_month()
+        };
+        run(&args).expect("Failed to sort media");
+
+        let html = std::fs::read_to_string(&report).expect("Expected a report");
+        assert!(html.contains("<h2>March 2024 (2)</h2>"));
+        assert!(html.contains("<figcaption>a.jpg</figcaption>"));
+        assert!(html.contains("<h2>Without a date (1)</h2>"));
+        assert!(html.contains("b &amp; c.jpg"));
+        assert!(html.contains("<h2>Duplicates found (1)</h2>"));
+        let month = dest.path().join("2024").join("March");
+        let same = format!(
+            "{}, {} are the same",
+            month.join("a.jpg").display(),
+            month.join("d.jpg").display()
+        );
+        assert!(html.contains(&same), "Expected {same} in {html}");
+        assert!(!html.contains("Errors"));
+    }
+
+    #[test]
+    fn run_reports_conflicts_and_summary() {
+        // Ensure front ends hear about each source, each conflict and the summary
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+        run(&args).expect("Failed to sort media");
+
+        let events = std::sync::Mutex::new(Vec::new());
+        run_with(&args, &|event| {
+            let name = match event {
+                Event::Scanning(_) => "scanning".to_string(),
+                Event::Conflict { outcome, .. } => format!("conflict {outcome:?}"),
+                Event::Summary(summary) => format!("summary {}", summary.report.renamed),
+                _ => return,
+            };
+            events.lock().unwrap().push(name);
+        })
+        .expect("Failed to sort media");
+
+        assert_eq!(
+            events.into_inner().unwrap(),
+            ["scanning", "conflict Rename", "summary 1"]
+        );
+    }
+
+    #[test]
+    fn doctor_classifies_problem_files() {
+        // Ensure damaged and misnamed media is told apart from media without a date
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let path = |name: &str| dir.path().join(name);
+
+        RgbImage::new(8, 8).save(path("fine.jpg")).unwrap();
+        RgbImage::new(8, 8).save(path("png.png")).unwrap();
+        std::fs::rename(path("png.png"), path("really-png.jpg")).unwrap();
+        let jpeg = std::fs::read(path("fine.jpg")).unwrap();
+        std::fs::write(path("cut.jpg"), &jpeg[..jpeg.len() - 10]).unwrap();
+        std::fs::write(path("notes.png"), "not an image").unwrap();
+
+        assert_eq!(doctor::diagnose(&path("fine.jpg")), Diagnosis::NoDate);
+        assert_eq!(
+            doctor::diagnose(&path("really-png.jpg")),
+            Diagnosis::WrongExtension(Format::Png)
+        );
+        assert_eq!(doctor::diagnose(&path("cut.jpg")), Diagnosis::Truncated);
+        assert!(matches!(
+            doctor::diagnose(&path("notes.png")),
+            Diagnosis::Unreadable(_)
+        ));
+
+        // Files that vanish are read as having no metadata rather than panicking
+        assert_eq!(read_metadata(&path("missing.jpg")), Default::default());
+
+        let args = DoctorArguments {
+            paths: vec![dir.path().to_path_buf()],
+            ..Default::default()
+        };
+        assert_eq!(doctor(&args).unwrap(), Status::PartialFailure);
+    }
+
+    #[test]
+    fn takeout_albums_link_sorted_photos() {
+        // Ensure album copies are sorted once and the album is rebuilt from links
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        std::fs::create_dir_all(dir.path().join("Photos from 2024")).unwrap();
+        std::fs::create_dir_all(dir.path().join("Trip")).unwrap();
+        std::fs::write(
+            dir.path().join("Trip").join("metadata.json"),
+            r#"{"title": "Trip to Spain"}"#,
+        )
+        .unwrap();
+
+        touch(
+            &dir,
+            ["Photos from 2024/a.jpg", "Trip/a.jpg"],
+            Some("2024:03:01 00:00:00"),
+        );
+        touch(&dir, ["Trip/b.jpg"], Some("2024:04:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            takeout_albums: true,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        let march = dest.path().join("2024").join("March");
+        assert!(march.join("a.jpg").exists());
+        assert!(!march.join("a_1.jpg").exists());
+
+        let album = dest.path().join(ALBUMS_DIR).join("Trip to Spain");
+        for name in ["a.jpg", "b.jpg"] {
+            let link = album.join(name);
+            assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+            assert!(link.exists(), "{link:?} should point at the sorted photo");
+        }
+        assert_eq!(std::fs::read_dir(&album).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn embed_takeout_sidecar_into_copies() {
+        // Ensure the sidecar's date sorts the photo and is written with its location
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let src = dir.path().join("a.jpg");
+        RgbImage::new(8, 8).save(&src).unwrap();
+
+        let taken = NaiveDate::from_ymd_opt(2019, 7, 4)
+            .and_then(|date| date.and_hms_opt(10, 11, 12))
+            .unwrap();
+        let timestamp = Local.from_local_datetime(&taken).unwrap().timestamp();
+        let sidecar = format!(
+            r#"{{"photoTakenTime": {{"timestamp": "{timestamp}"}},
+                "geoData": {{"latitude": 40.4, "longitude": -3.7, "altitude": 650.0}}}}"#
+        );
+        std::fs::write(dir.path().join("a.jpg.json"), sidecar).unwrap();
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            embed_takeout: true,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        let copy = read_metadata(&dest.path().join("2019").join("July").join("a.jpg"));
+        assert_eq!(copy.datetime, Some(taken));
+        let coordinates = copy.coordinates.expect("Expected the sidecar's location");
+        assert!((coordinates.latitude - 40.4).abs() < 1e-5);
+        assert!((coordinates.longitude + 3.7).abs() < 1e-5);
+
+        assert_eq!(
+            read_metadata(&src),
+            Metadata {
+                format: Some(Format::Jpeg),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn embed_takeout_replaces_zeroed_date() {
+        // Ensure a zeroed DateTimeOriginal is replaced by the sidecar's date, not kept beside it
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let mut jpeg = Vec::new();
+        RgbImage::new(8, 8)
+            .write_to(&mut Cursor::new(&mut jpeg), ::image::ImageFormat::Jpeg)
+            .unwrap();
+        let mut writer = experimental::Writer::new();
+        let zeroed = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"0000:00:00 00:00:00".to_vec()]),
+        };
+        writer.push_field(&zeroed);
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+        let jpeg = crate::jpeg::replace_exif(&jpeg, Some(exif.get_ref())).unwrap();
+        std::fs::write(dir.path().join("a.jpg"), jpeg).unwrap();
+
+        let taken = NaiveDate::from_ymd_opt(2019, 7, 4)
+            .and_then(|date| date.and_hms_opt(10, 11, 12))
+            .unwrap();
+        let timestamp = Local.from_local_datetime(&taken).unwrap().timestamp();
+        let sidecar = format!(r#"{{"photoTakenTime": {{"timestamp": "{timestamp}"}}}}"#);
+        std::fs::write(dir.path().join("a.jpg.json"), sidecar).unwrap();
+
+        // The sidecar sorts the photo whether or not its date is written into the copy
+        for embed_takeout in [false, true] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                embed_takeout,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+
+            let copy = dest.path().join("2019/July/a.jpg");
+            let expected = embed_takeout.then_some(taken);
+            assert_eq!(read_metadata(&copy).datetime, expected);
+            let exif = exif::Reader::new()
+                .read_from_container(&mut std::io::BufReader::new(File::open(&copy).unwrap()))
+                .unwrap();
+            let dates = exif
+                .fields()
+                .filter(|field| field.tag == Tag::DateTimeOriginal)
+                .count();
+            assert_eq!(dates, 1);
+        }
+    }
+
+    #[test]
+    fn quicktime_recording_dates() {
+        // Ensure videos are dated from Apple's creation date, falling back to mvhd
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let atom = |kind: &[u8], body: &[u8]| {
+            let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+            atom.extend_from_slice(kind);
+            atom.extend_from_slice(body);
+            atom
+        };
+
+        // 2019-07-04 08:11:12 UTC, counted from 1904
+        let created: u32 = 1_562_227_872 + 2_082_844_800;
+        let mut mvhd = vec![0; 4];
+        mvhd.extend_from_slice(&created.to_be_bytes());
+        mvhd.extend_from_slice(&[0; 92]);
+
+        let keys = [
+            "com.apple.quicktime.creationdate",
+            "com.apple.quicktime.make",
+            "com.apple.quicktime.model",
+        ];
+        let mut key_entries = vec![0, 0, 0, 0, 0, 0, 0, keys.len() as u8];
+        let mut items = Vec::new();
+        for (index, (key, value)) in keys
+            .iter()
+            .zip(["2019-07-04T10:11:12+0200", "Apple", "iPhone 12"])
+            .enumerate()
+        {
+            key_entries.extend(atom(b"mdta", key.as_bytes()));
+            let data = [&[0, 0, 0, 1, 0, 0, 0, 0], value.as_bytes()].concat();
+            items.extend(atom(
+                &(index as u32 + 1).to_be_bytes(),
+                &atom(b"data", &data),
+            ));
+        }
+        let meta = [atom(b"keys", &key_entries), atom(b"ilst", &items)].concat();
+
+        let movie = |moov: Vec<u8>| {
+            [
+                atom(b"ftyp", b"qt  \0\0\0\0qt  "),
+                atom(b"moov", &moov),
+                atom(b"mdat", &[0; 16]),
+            ]
+            .concat()
+        };
+        std::fs::write(
+            dir.path().join("apple.mov"),
+            movie([atom(b"mvhd", &mvhd), atom(b"meta", &meta)].concat()),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("plain.mp4"), movie(atom(b"mvhd", &mvhd))).unwrap();
+
+        let apple = read_metadata(&dir.path().join("apple.mov"));
+        assert_eq!(
+            apple.datetime,
+            NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))
+        );
+        assert_eq!(apple.camera.as_deref(), Some("Apple iPhone 12"));
+
+        let utc = DateTime::from_timestamp(1_562_227_872, 0).unwrap();
+        assert_eq!(
+            read_metadata(&dir.path().join("plain.mp4")).datetime,
+            Some(utc.with_timezone(&Local).naive_local())
+        );
+    }
+
+    #[test]
+    fn avi_and_matroska_dates() {
+        // Ensure AVIs are dated from IDIT and Matroska from DateUTC, and both are scanned
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let chunk =
+            |id: &[u8], body: &[u8]| [id, &(body.len() as u32).to_le_bytes(), body].concat();
+
+        let hdrl = [
+            b"hdrl".as_slice(),
+            &chunk(b"IDIT", b"THU JUL 04 10:11:12 2019\n\0"),
+        ]
+        .concat();
+        let riff = [
+            b"AVI ".as_slice(),
+            &chunk(b"LIST", &hdrl),
+            &chunk(b"LIST", b"movi"),
+        ]
+        .concat();
+        std::fs::write(dir.path().join("a.avi"), chunk(b"RIFF", &riff)).unwrap();
+
+        // 2019-07-04 08:11:12 UTC, in nanoseconds since 2001
+        let nanos: i64 = (1_562_227_872 - 978_307_200) * 1_000_000_000;
+        let info = [&[0x44, 0x61, 0x88][..], &nanos.to_be_bytes()].concat();
+        let segment = [
+            &[0x15, 0x49, 0xA9, 0x66, 0x80 | info.len() as u8][..],
+            &info,
+        ]
+        .concat();
+        let mkv = [
+            &[0x1A, 0x45, 0xDF, 0xA3, 0x80][..],
+            &[0x18, 0x53, 0x80, 0x67, 0x80 | segment.len() as u8],
+            &segment,
+        ]
+        .concat();
+        std::fs::write(dir.path().join("b.mkv"), mkv).unwrap();
+
+        assert_eq!(
+            read_metadata(&dir.path().join("a.avi")).datetime,
+            NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))
+        );
+        let utc = DateTime::from_timestamp(1_562_227_872, 0).unwrap();
+        assert_eq!(
+            read_metadata(&dir.path().join("b.mkv")).datetime,
+            Some(utc.with_timezone(&Local).naive_local())
+        );
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert!(tree.images().all(|image| image.media == MediaClass::Video));
+        assert_eq!(tree.size(), 2);
+    }
+
+    #[test]
+    fn raw_capture_dates() {
+        // Ensure ORF is read as TIFF, CR3 from its Canon boxes, and both are scanned as RAW
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let tiff = |fields: &[Field]| {
+            let mut writer = experimental::Writer::new();
+            for field in fields {
+                writer.push_field(field);
+            }
+            let mut tiff = Cursor::new(Vec::new());
+            writer.write(&mut tiff, true).unwrap();
+            tiff.into_inner()
+        };
+        let ascii = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+
+        let mut orf = tiff(&[ascii(Tag::DateTimeOriginal, "2019:07:04 10:11:12")]);
+        orf[2..4].copy_from_slice(b"RO");
+        std::fs::write(dir.path().join("a.orf"), orf).unwrap();
+
+        let atom = |kind: &[u8], body: &[u8]| {
+            [&((body.len() + 8) as u32).to_be_bytes(), kind, body].concat()
+        };
+        let canon = [
+            &[
+                0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b,
+                0x6a, 0x48,
+            ][..],
+            &atom(
+                b"CMT1",
+                &tiff(&[ascii(Tag::Make, "Canon"), ascii(Tag::Model, "Canon EOS R6")]),
+            ),
+            &atom(
+                b"CMT2",
+                &tiff(&[ascii(Tag(Context::Tiff, 0x9003), "2020:01:02 03:04:05")]),
+            ),
+        ]
+        .concat();
+        let cr3 = [
+            atom(b"ftyp", b"crx \0\0\0\x01crx isom"),
+            atom(b"moov", &atom(b"uuid", &canon)),
+            atom(b"mdat", &[0; 16]),
+        ]
+        .concat();
+        std::fs::write(dir.path().join("b.cr3"), cr3).unwrap();
+
+        assert_eq!(
+            read_metadata(&dir.path().join("a.orf")).datetime,
+            NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))
+        );
+        let cr3 = read_metadata(&dir.path().join("b.cr3"));
+        assert_eq!(
+            cr3.datetime,
+            NaiveDate::from_ymd_opt(2020, 1, 2).and_then(|date| date.and_hms_opt(3, 4, 5))
+        );
+        assert_eq!(cr3.camera.as_deref(), Some("Canon EOS R6"));
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert!(tree.images().all(|image| image.media == MediaClass::Raw));
+        assert_eq!(tree.size(), 2);
+    }
+
+    #[test]
+    fn tiff_dates() {
+        // Ensure TIFFs are scanned and fall back to when they were made, or stay undated
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let tiff = |path: PathBuf, fields: &[Field]| {
+            let mut writer = experimental::Writer::new();
+            for field in fields {
+                writer.push_field(field);
+            }
+            writer
+                .write(&mut BufWriter::new(File::create(path).unwrap()), false)
+                .unwrap();
+        };
+        let datetime = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+
+        tiff(
+            dir.path().join("scan.tif"),
+            &[datetime(Tag::DateTime, "1998:12:24 18:00:00")],
+        );
+        tiff(
+            dir.path().join("photo.TIFF"),
+            &[
+                datetime(Tag::DateTimeOriginal, "2019:07:04 10:11:12"),
+                datetime(Tag::DateTime, "2020:01:01 00:00:00"),
+            ],
+        );
+        tiff(
+            dir.path().join("blank.tiff"),
+            &[datetime(Tag::Make, "Epson")],
+        );
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        let dates: BTreeMap<_, _> = tree
+            .images()
+            .map(|image| (image.name.as_str(), image.datetime.map(|date| date.year())))
+            .collect();
+        assert_eq!(
+            dates,
+            BTreeMap::from([
+                ("blank.tiff", None),
+                ("photo.TIFF", Some(2019)),
+                ("scan.tif", Some(1998)),
+            ])
+        );
+    }
+
+    #[test]
+    fn webp_exif_dates() {
+        // Ensure WebPs are scanned and dated from their EXIF chunk, with or without a prefix
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let mut writer = experimental::Writer::new();
+        let field = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2019:07:04 10:11:12".to_vec()]),
+        };
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        let tiff = tiff.into_inner();
+
+        let chunk = |id: &[u8], body: &[u8]| {
+            let padding = vec![0; body.len() % 2];
+            [id, &(body.len() as u32).to_le_bytes(), body, &padding].concat()
+        };
+        let webp = |exif: &[u8]| {
+            let body = [
+                b"WEBP".as_slice(),
+                &chunk(b"VP8X", &[0x08, 0, 0, 0, 7, 0, 0, 7, 0, 0]),
+                &chunk(b"VP8L", &[0x2F, 7, 0xC0, 0x01, 0]),
+                &chunk(b"EXIF", exif),
+            ]
+            .concat();
+            chunk(b"RIFF", &body)
+        };
+        std::fs::write(dir.path().join("a.webp"), webp(&tiff)).unwrap();
+        std::fs::write(
+            dir.path().join("b.webp"),
+            webp(&[b"Exif\0\0".as_slice(), &tiff].concat()),
+        )
+        .unwrap();
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert_eq!(tree.size(), 2);
+        assert!(tree.images().all(|image| image.datetime
+            == NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))));
+    }
+
+    #[test]
+    fn heif_exif_items() {
+        // Ensure HEIF and AVIF are dated from their Exif item, in the file or in idat
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let mut writer = experimental::Writer::new();
+        let field = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2019:07:04 10:11:12".to_vec()]),
+        };
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        // The item starts with the offset of the TIFF header after it
+        let item = [&[0, 0, 0, 6][..], b"Exif\0\0", &tiff.into_inner()].concat();
+
+        let atom = |kind: &[u8], body: &[u8]| {
+            [&((body.len() + 8) as u32).to_be_bytes(), kind, body].concat()
+        };
+        let iinf = atom(
+            b"iinf",
+            &[
+                &[0, 0, 0, 0, 0, 2][..],
+                &atom(b"infe", b"\x02\0\0\0\0\x01\0\0av01"),
+                &atom(b"infe", b"\x02\0\0\0\0\x02\0\0Exif"),
+            ]
+            .concat(),
+        );
+        let ftyp = |brand: &[u8]| atom(b"ftyp", &[brand, b"\0\0\0\0", brand].concat());
+
+        // AVIF with the item after meta, and without mif1 among its brands
+        let meta = |offset: u32| {
+            let iloc = [
+                &[0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 2, 0, 0, 0, 1][..],
+                &offset.to_be_bytes(),
+                &(item.len() as u32).to_be_bytes(),
+            ]
+            .concat();
+            let body = [&[0, 0, 0, 0][..], &iinf, &atom(b"iloc", &iloc)].concat();
+            atom(b"meta", &body)
+        };
+        let offset = ftyp(b"avif").len() + meta(0).len() + 8;
+        let avif = [ftyp(b"avif"), meta(offset as u32), atom(b"mdat", &item)].concat();
+        std::fs::write(dir.path().join("a.avif"), avif).unwrap();
+
+        // HEIC with the item in idat
+        let iloc = [
+            &[
+                1, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 2, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0,
+            ][..],
+            &(item.len() as u32).to_be_bytes(),
+        ]
+        .concat();
+        let meta = [
+            &[0, 0, 0, 0][..],
+            &iinf,
+            &atom(b"iloc", &iloc),
+            &atom(b"idat", &item),
+        ]
+        .concat();
+        let heic = [ftyp(b"heic"), atom(b"meta", &meta)].concat();
+        std::fs::write(dir.path().join("b.HEIF"), heic).unwrap();
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert_eq!(tree.size(), 2);
+        assert!(tree.images().all(|image| image.datetime
+            == NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))));
+    }
+
+    #[test]
+    fn heif_unreadable_exif_item() {
+        // Ensure a HEIC whose Exif item is split up is left undated with the parser's
+        // own error, as libheif can't open a file without an image either
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let atom = |kind: &[u8], body: &[u8]| {
+            [&((body.len() + 8) as u32).to_be_bytes(), kind, body].concat()
+        };
+        let iinf = atom(
+            b"iinf",
+            &[
+                &[0, 0, 0, 0, 0, 1][..],
+                &atom(b"infe", b"\x02\0\0\0\0\x01\0\0Exif"),
+            ]
+            .concat(),
+        );
+        // Two extents of the item, each 8 bytes from the start of the file
+        let iloc = atom(
+            b"iloc",
+            &[
+                0, 0, 0, 0, 0x44, 0x00, 0, 1, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 8,
+                0, 0, 0, 8,
+            ],
+        );
+        let meta = atom(b"meta", &[&[0, 0, 0, 0][..], &iinf, &iloc].concat());
+        let heic = [atom(b"ftyp", b"heic\0\0\0\0heicmif1"), meta].concat();
+        let path = dir.path().join("a.heic");
+        std::fs::write(&path, heic).unwrap();
+
+        assert!(matches!(
+            heif::read_exif(&path),
+            Err(exif::Error::NotFound("HEIF"))
+        ));
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert_eq!(tree.size(), 1);
+        assert!(tree.images().all(|image| image.datetime.is_none()));
+    }
+
+    #[test]
+    fn jxl_exif_boxes() {
+        // Ensure JPEG XL is dated from its Exif box, whether or not it's compressed
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let mut writer = experimental::Writer::new();
+        let field = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2019:07:04 10:11:12".to_vec()]),
+        };
+        writer.push_field(&field);
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, true).unwrap();
+        let exif = [&[0, 0, 0, 0][..], &tiff.into_inner()].concat();
+
+        let atom = |kind: &[u8], body: &[u8]| {
+            [&((body.len() + 8) as u32).to_be_bytes(), kind, body].concat()
+        };
+        let jxl = |exif: Vec<u8>| {
+            [
+                atom(b"JXL ", b"\r\n\x87\n"),
+                atom(b"ftyp", b"jxl \0\0\0\0jxl "),
+                exif,
+                atom(b"jxlc", &[0xFF, 0x0A]),
+            ]
+            .concat()
+        };
+        std::fs::write(dir.path().join("a.jxl"), jxl(atom(b"Exif", &exif))).unwrap();
+
+        // Brotli can store a block as it is, ahead of an empty last one
+        let header = ((exif.len() as u32 - 1) << 4 | 1 << 20).to_le_bytes();
+        let brotli = [&header[..3], &exif, &[0x03]].concat();
+        let brob = atom(b"brob", &[b"Exif".as_slice(), &brotli].concat());
+        std::fs::write(dir.path().join("b.jxl"), jxl(brob)).unwrap();
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        assert_eq!(tree.size(), 2);
+        assert!(tree.images().all(|image| image.datetime
+            == NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))));
+    }
+
+    #[test]
+    fn gif_fallback_dates() {
+        // Ensure GIFs are dated from their names, then from when they were modified
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        for name in ["animation_20210304.gif", "plain.gif"] {
+            std::fs::write(dir.path().join(name), b"GIF89a\x01\0\x01\0\0\0\0;").unwrap();
+        }
+        let modified = NaiveDate::from_ymd_opt(2015, 6, 7)
+            .and_then(|date| date.and_hms_opt(8, 9, 10))
+            .unwrap();
+        File::options()
+            .write(true)
+            .open(dir.path().join("plain.gif"))
+            .unwrap()
+            .set_modified(modified.and_local_timezone(Local).unwrap().into())
+            .unwrap();
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        let dates: BTreeMap<_, _> = tree
+            .images()
+            .map(|image| (image.name.as_str(), image.datetime))
+            .collect();
+        assert_eq!(
+            dates,
+            BTreeMap::from([
+                (
+                    "animation_20210304.gif",
+                    NaiveDate::from_ymd_opt(2021, 3, 4).and_then(|date| date.and_hms_opt(0, 0, 0))
+                ),
+                ("plain.gif", Some(modified)),
+            ])
+        );
+    }
+
+    #[test]
+    fn png_text_dates() {
+        // Ensure PNGs are dated from their Creation Time text, then their tIME chunk
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let chunk = |kind: &[u8], data: &[u8]| {
+            [&(data.len() as u32).to_be_bytes(), kind, data, &[0; 4]].concat()
+        };
+        let png = |name: &str, chunks: Vec<u8>| {
+            let path = dir.path().join(name);
+            RgbImage::new(8, 8).save(&path).unwrap();
+            let mut data = std::fs::read(&path).unwrap();
+            // Text chunks may come after the image data, just before IEND
+            let end = data.len() - 12;
+            data.splice(end..end, chunks);
+            std::fs::write(&path, data).unwrap();
+        };
+
+        png(
+            "created.png",
+            [
+                chunk(b"tEXt", b"Software\0img-sort"),
+                chunk(b"tEXt", b"Creation Time\0Thu, 04 Jul 2019 10:11:12 +0200"),
+                chunk(b"tIME", &[0x07, 0xE4, 1, 1, 0, 0, 0]),
+            ]
+            .concat(),
+        );
+        png(
+            "modified.png",
+            chunk(b"tIME", &[0x07, 0xE3, 7, 4, 8, 11, 12]),
+        );
+        png("plain.png", Vec::new());
+
+        assert_eq!(
+            read_metadata(&dir.path().join("created.png")).datetime,
+            NaiveDate::from_ymd_opt(2019, 7, 4).and_then(|date| date.and_hms_opt(10, 11, 12))
+        );
+        let utc = DateTime::from_timestamp(1_562_227_872, 0).unwrap();
+        assert_eq!(
+            read_metadata(&dir.path().join("modified.png")).datetime,
+            Some(utc.with_timezone(&Local).naive_local())
+        );
+        assert_eq!(read_metadata(&dir.path().join("plain.png")).datetime, None);
+    }
+
+    #[test]
+    fn xmp_sidecar_dates() {
+        // Ensure XMP sidecars date undated media, and override EXIF only when preferred
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg"], Some("2019:07:04 10:11:12"));
+        touch(&dir, ["b.jpg"], None);
+        std::fs::write(
+            dir.path().join("a.xmp"),
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                <rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+                    <xmp:CreateDate>2010-02-03T04:05:06+01:00</xmp:CreateDate>
+                </rdf:Description>
+            </rdf:RDF></x:xmpmeta>"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.jpg.xmp"),
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                <rdf:Description xmlns:exif="http://ns.adobe.com/exif/1.0/"
+                    exif:DateTimeOriginal="2015-08-09T10:11:12.50"/>
+            </rdf:RDF></x:xmpmeta>"#,
+        )
+        .unwrap();
+
+        for (xmp, a, b) in [
+            (XmpPriority::Fallback, "2019/July", "2015/August"),
+            (XmpPriority::Prefer, "2010/February", "2015/August"),
+            (XmpPriority::Ignore, "2019/July", "Unknown"),
+        ] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                xmp,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            assert!(dest.path().join(a).join("a.jpg").exists(), "{xmp:?}");
+            assert!(dest.path().join(b).join("b.jpg").exists(), "{xmp:?}");
+        }
+    }
+
+    #[test]
+    fn sidecars_follow_their_media() {
+        // Ensure sidecars move with their media, are renamed with it, and go with the RAW
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["IMG_1.JPG"], Some("2019:07:04 10:11:12"));
+        std::fs::copy(dir.path().join("IMG_1.JPG"), dir.path().join("IMG_1.CR2")).unwrap();
+        for sidecar in ["IMG_1.xmp", "IMG_1.JPG.json", "notes.txt"] {
+            std::fs::write(dir.path().join(sidecar), "{}").unwrap();
+        }
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            move_media: true,
+            rename: Some("{year}-{month:02}-{day:02}".parse().unwrap()),
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        let names = |dir: &Path| {
+            let mut names: Vec<String> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(&dest.path().join("2019").join("July")),
+            [
+                "2019-07-04.CR2",
+                "2019-07-04.JPG",
+                "2019-07-04.JPG.json",
+                "2019-07-04.xmp",
+            ]
+        );
+        assert_eq!(names(dir.path()), ["notes.txt"]);
+    }
+
+    #[test]
+    fn raw_jpeg_pairs() {
+        // Ensure a shot's RAW stays with its JPEG, while RAWs taken at other times don't
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["IMG_1.JPG"], Some("2019:07:04 10:11:12"));
+        touch(&dir, ["IMG_2.JPG"], Some("2020:01:02 10:11:12"));
+        std::fs::copy(dir.path().join("IMG_1.JPG"), dir.path().join("img_1.CR2")).unwrap();
+        std::fs::copy(dir.path().join("IMG_1.JPG"), dir.path().join("IMG_2.CR2")).unwrap();
+
+        let sort = |raw_pairs| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                split_media: true,
+                raw_pairs,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(RawPairs::Nest);
+        let july = dest.path().join("Photos").join("2019").join("July");
+        assert!(july.join("IMG_1.JPG").is_file());
+        assert!(july.join("RAW").join("img_1.CR2").is_file());
+        assert!(dest.path().join("RAW/2019/July/IMG_2.CR2").is_file());
+
+        let dest = sort(RawPairs::PreferJpeg);
+        let july = dest.path().join("Photos").join("2019").join("July");
+        assert!(july.join("IMG_1.JPG").is_file());
+        assert!(!july.join("img_1.CR2").exists() && !july.join("RAW").exists());
+        assert!(dest.path().join("RAW/2019/July/IMG_2.CR2").is_file());
+        assert!(dest.path().join("Photos/2020/January/IMG_2.JPG").is_file());
+    }
+
+    #[test]
+    fn live_photos_stay_together() {
+        // Ensure a Live Photo's video is saved and numbered with its photo, paired by
+        // name or by content identifier
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let atom = |kind: &[u8], body: &[u8]| {
+            [&((body.len() + 8) as u32).to_be_bytes(), kind, body].concat()
+        };
+        let movie = |identifier: &str| {
+            let keys = atom(
+                b"keys",
+                &[
+                    &[0, 0, 0, 0, 0, 0, 0, 1][..],
+                    &atom(b"mdta", b"com.apple.quicktime.content.identifier"),
+                ]
+                .concat(),
+            );
+            let data = [&[0, 0, 0, 1, 0, 0, 0, 0], identifier.as_bytes()].concat();
+            let ilst = atom(b"ilst", &atom(&1u32.to_be_bytes(), &atom(b"data", &data)));
+            [
+                atom(b"ftyp", b"qt  \0\0\0\0qt  "),
+                atom(b"moov", &atom(b"meta", &[keys, ilst].concat())),
+            ]
+            .concat()
+        };
+
+        touch(&dir, ["IMG_1.JPG"], Some("2019:07:04 10:11:12"));
+        std::fs::write(dir.path().join("IMG_1.MOV"), movie("A")).unwrap();
+
+        // An edited photo keeps the identifier in its maker note, but not the name
+        let mut note = b"Apple iOS\0\0\x01MM\0\x01".to_vec();
+        note.extend([0, 0x11, 0, 2, 0, 0, 0, 2, b'B', 0, 0, 0]);
+        let mut writer = experimental::Writer::new();
+        let fields = [
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"2020:01:02 10:11:12".to_vec()]),
+            },
+            Field {
+                tag: Tag::MakerNote,
+                ifd_num: In::PRIMARY,
+                value: Value::Undefined(note, 0),
+            },
+        ];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        std::fs::write(dir.path().join("IMG_E2.JPG"), tiff.into_inner()).unwrap();
+        std::fs::write(dir.path().join("IMG_2.MOV"), movie("B")).unwrap();
+
+        let july = dest.path().join("2019").join("July");
+        std::fs::create_dir_all(&july).unwrap();
+        std::fs::write(july.join("IMG_1.MOV"), "taken").unwrap();
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            on_conflict: Conflict::Rename,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        assert!(july.join("IMG_1_1.JPG").is_file());
+        assert!(july.join("IMG_1_1.MOV").is_file());
+        assert!(!july.join("IMG_1.JPG").exists());
+        let january = dest.path().join("2020").join("January");
+        assert!(january.join("IMG_E2.JPG").is_file());
+        assert!(january.join("IMG_E2.MOV").is_file());
+    }
+
+    #[test]
+    fn date_tag_fallbacks() {
+        // Ensure photos are dated from the first date tag they have, in the order given,
+        // and the tag is recorded in the plan
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let tiff = |name: &str, fields: &[Field]| {
+            let mut writer = experimental::Writer::new();
+            for field in fields {
+                writer.push_field(field);
+            }
+            let path = dir.path().join(name);
+            writer
+                .write(&mut BufWriter::new(File::create(&path).unwrap()), false)
+                .unwrap();
+            path
+        };
+        let ascii = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+
+        let digitized = tiff(
+            "digitized.jpg",
+            &[
+                ascii(Tag::DateTimeDigitized, "2019:07:04 10:11:12"),
+                ascii(Tag::DateTime, "2020:01:01 00:00:00"),
+            ],
+        );
+        let gps = tiff(
+            "gps.jpg",
+            &[
+                ascii(Tag::GPSDateStamp, "2019:07:04"),
+                Field {
+                    tag: Tag::GPSTimeStamp,
+                    ifd_num: In::PRIMARY,
+                    value: Value::Rational(vec![(8, 1).into(), (11, 1).into(), (12, 1).into()]),
+                },
+            ],
+        );
+
+        let digitized_date = NaiveDate::from_ymd_opt(2019, 7, 4)
+            .and_then(|date| date.and_hms_opt(10, 11, 12))
+            .unwrap();
+        let metadata = read_metadata(&digitized);
+        assert_eq!(metadata.datetime, Some(digitized_date));
+        assert_eq!(metadata.date_source, Some(DateSource::DateTimeDigitized));
+        let metadata = read_metadata_with(&digitized, &[DateTag::DateTime]);
+        assert_eq!(metadata.datetime.map(|date| date.year()), Some(2020));
+        assert_eq!(metadata.date_source, Some(DateSource::DateTime));
+
+        let utc = NaiveDate::from_ymd_opt(2019, 7, 4)
+            .and_then(|date| date.and_hms_opt(8, 11, 12))
+            .unwrap()
+            .and_utc();
+        let metadata = read_metadata(&gps);
+        assert_eq!(
+            metadata.datetime,
+            Some(utc.with_timezone(&Local).naive_local())
+        );
+        assert_eq!(metadata.date_source, Some(DateSource::GpsDate));
+        assert_eq!(
+            read_metadata_with(&gps, &[DateTag::DateTimeOriginal]),
+            Metadata {
+                format: Some(Format::Tiff),
+                ..Default::default()
+            }
+        );
+
+        let image = Image::new(digitized.clone(), "digitized.jpg".to_string())
+            .with_datetime(Some(digitized_date))
+            .with_date_source(Some(DateSource::DateTimeDigitized));
+        let mut tree = build_tree(&year_month());
+        tree.insert(image);
+        let steps = tree.plan(dir.path(), &SaveOptions::default()).unwrap();
+        let manifest = serde_json::to_string(&steps).unwrap();
+        assert!(manifest.contains(r#""date_source":"date-time-digitized""#));
+    }
+
+    #[test]
+    fn file_time_fallback() {
+        // Ensure media without a date is dated from its file time only when asked to
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["screenshot.png"], None);
+        touch(&dir, ["photo.jpg"], Some("2019:07:04 10:11:12"));
+        let modified = NaiveDate::from_ymd_opt(2015, 6, 7)
+            .and_then(|date| date.and_hms_opt(8, 9, 10))
+            .unwrap();
+        for name in ["screenshot.png", "photo.jpg"] {
+            File::options()
+                .write(true)
+                .open(dir.path().join(name))
+                .unwrap()
+                .set_modified(modified.and_local_timezone(Local).unwrap().into())
+                .unwrap();
+        }
+
+        let sort = |fallback| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                fallback,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(Fallback::None);
+        assert!(dest.path().join("Unknown").join("screenshot.png").is_file());
+
+        let dest = sort(Fallback::Mtime);
+        assert!(dest.path().join("2015/June/screenshot.png").is_file());
+        assert!(dest.path().join("2019/July/photo.jpg").is_file());
+    }
+
+    #[test]
+    fn filename_dates() {
+        // Ensure the names phones and apps give media date it when its EXIF doesn't
+        let date = |y, m, d, h, min, s| {
+            NaiveDate::from_ymd_opt(y, m, d).and_then(|date| date.and_hms_opt(h, min, s))
+        };
+        for (name, expected) in [
+            ("IMG_20230415_093000", date(2023, 4, 15, 9, 30, 0)),
+            ("PXL_20230415_093000123.MP", date(2023, 4, 15, 9, 30, 0)),
+            (
+                "WhatsApp Image 2022-01-03 at 10.11.12 PM",
+                date(2022, 1, 3, 22, 11, 12),
+            ),
+            (
+                "WhatsApp Video 2022-01-03 at 00.11.12",
+                date(2022, 1, 3, 0, 11, 12),
+            ),
+            (
+                "Screenshot 2024-05-01 at 12.30.00\u{202f}AM",
+                date(2024, 5, 1, 0, 30, 0),
+            ),
+            (
+                "Screenshot_20240501-093000_Chrome",
+                date(2024, 5, 1, 9, 30, 0),
+            ),
+            ("signal-2021-03-04-09-30-00-123", date(2021, 3, 4, 9, 30, 0)),
+            ("2019-07-04 holiday", date(2019, 7, 4, 0, 0, 0)),
+            ("IMG_0042", None),
+        ] {
+            assert_eq!(crate::filename::parse_name(name), expected, "{name}");
+        }
+
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["signal-2021-03-04-093000.jpg", "IMG_0042.jpg"], None);
+        touch(
+            &dir,
+            ["IMG_20230415_093000.jpg"],
+            Some("2019:07:04 10:11:12"),
+        );
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        assert!(dest
+            .path()
+            .join("2021/March/signal-2021-03-04-093000.jpg")
+            .is_file());
+        assert!(dest
+            .path()
+            .join("2019/July/IMG_20230415_093000.jpg")
+            .is_file());
+        assert!(dest.path().join("Unknown/IMG_0042.jpg").is_file());
+    }
+
+    #[test]
+    fn custom_filename_patterns() {
+        // Ensure patterns from the config date media named in ways the library doesn't know
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let config = dir.path().join("img-sort.toml");
+        std::fs::write(
+            &config,
+            r#"filename-pattern = ['^scan_(?P<d>\d{2})(?P<m>\d{2})(?P<y>\d{4})']"#,
+        )
+        .unwrap();
+        touch(&dir, ["scan_04071999_front.jpg"], None);
+
+        let cli = parse_args(
+            [
+                "img-sort",
+                "sort",
+                "--config",
+                config.to_str().unwrap(),
+                "--path",
+                dir.path().to_str().unwrap(),
+                "--dest",
+                dest.path().to_str().unwrap(),
+                "-y",
+                "-m",
+            ]
+            .map(OsString::from)
+            .to_vec(),
+        )
+        .expect("Failed to read the config");
+        let Command::Sort(args) = &cli.command else {
+            panic!("Expected the sort subcommand");
+        };
+        assert_eq!(run(args).unwrap(), Status::Success);
+        assert!(dest
+            .path()
+            .join("1999/July/scan_04071999_front.jpg")
+            .is_file());
+
+        let missing_day = crate::filename::parse_pattern(r"(?P<y>\d{4})-(?P<m>\d{2})");
+        assert!(
+            missing_day.is_err(),
+            "Expected an error for a pattern without a day"
+        );
+    }
+
+    #[test]
+    fn time_zone_conversion() {
+        // Ensure dates are moved into the zone asked for, from their own offset or the
+        // assumed zone, before they're bucketed
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let ascii = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+        let tiff = |name: &str, fields: &[Field]| {
+            let mut writer = experimental::Writer::new();
+            for field in fields {
+                writer.push_field(field);
+            }
+            let mut tiff = Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            std::fs::write(dir.path().join(name), tiff.into_inner()).unwrap();
+        };
+        tiff(
+            "tokyo.jpg",
+            &[
+                ascii(Tag::DateTimeOriginal, "2019:07:01 05:00:00"),
+                ascii(Tag::OffsetTimeOriginal, "+09:00"),
+            ],
+        );
+        tiff(
+            "paris.jpg",
+            &[ascii(Tag::DateTimeOriginal, "2019:07:01 01:00:00")],
+        );
+        tiff(
+            "noon.jpg",
+            &[
+                ascii(Tag::DateTimeOriginal, "2019:07:01 12:00:00"),
+                ascii(Tag::OffsetTimeOriginal, "+02:00"),
+            ],
+        );
+
+        let sort = |assume_tz, convert_to_tz| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                assume_tz,
+                convert_to_tz,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(None, None);
+        for name in ["tokyo.jpg", "paris.jpg", "noon.jpg"] {
+            assert!(dest.path().join("2019/July").join(name).is_file(), "{name}");
+        }
+
+        let utc = crate::zone::parse_zone("UTC").ok();
+        let dest = sort(crate::zone::parse_zone("Europe/Paris").ok(), utc);
+        assert!(dest.path().join("2019/June/tokyo.jpg").is_file());
+        assert!(dest.path().join("2019/June/paris.jpg").is_file());
+        assert!(dest.path().join("2019/July/noon.jpg").is_file());
+
+        assert_eq!(
+            crate::zone::parse_zone("+0530"),
+            Ok(Zone::Offset(FixedOffset::east_opt(19800).unwrap()))
+        );
+        assert!(crate::zone::parse_zone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn burst_subsecond_order() {
+        // Ensure shots taken the same second are renamed in the order they were taken
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let ascii = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+        for (name, subsec) in [("a.jpg", "900"), ("b.jpg", "100"), ("c.jpg", "5")] {
+            let mut writer = experimental::Writer::new();
+            let fields = [
+                ascii(Tag::DateTimeOriginal, "2019:07:04 10:11:12"),
+                ascii(Tag::SubSecTimeOriginal, subsec),
+            ];
+            for field in &fields {
+                writer.push_field(field);
+            }
+            let mut tiff = Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            std::fs::write(dir.path().join(name), tiff.into_inner()).unwrap();
+        }
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            rename: Some("{hhmmss}".parse().unwrap()),
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        let july = dest.path().join("2019").join("July");
+        let millis = |name: &str| {
+            read_metadata(&july.join(name))
+                .datetime
+                .map(|datetime| datetime.and_utc().timestamp_subsec_millis())
+        };
+        assert_eq!(millis("101112.jpg"), Some(100));
+        assert_eq!(millis("101112_1.jpg"), Some(500));
+        assert_eq!(millis("101112_2.jpg"), Some(900));
+    }
+
+    #[test]
+    fn misnamed_media_formats() {
+        // Ensure media is classed by its contents, and saved under its real extension
+        // when asked
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["scan.PNG"], Some("2019:07:04 10:11:12"));
+        std::fs::write(
+            dir.path().join("clip.jpg"),
+            [&20u32.to_be_bytes()[..], b"ftypqt  \0\0\0\0qt  "].concat(),
+        )
+        .unwrap();
+
+        let mut tree = build_tree(&year_month());
+        let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+        find(walker, &mut tree).unwrap();
+        let classes: BTreeMap<_, _> = tree
+            .images()
+            .map(|image| (image.name.as_str(), (image.format, image.media)))
+            .collect();
+        assert_eq!(
+            classes,
+            BTreeMap::from([
+                ("clip.jpg", (Some(Format::Mov), MediaClass::Video)),
+                ("scan.PNG", (Some(Format::Tiff), MediaClass::Photo)),
+            ])
+        );
+
+        let sort = |fix_extensions| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                fix_extensions,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+        let dest = sort(false);
+        assert!(dest.path().join("2019/July/scan.PNG").is_file());
+        let dest = sort(true);
+        assert!(dest.path().join("2019/July/scan.TIF").is_file());
+        assert!(!dest.path().join("2019/July/scan.PNG").exists());
+    }
+
+    #[test]
+    fn exiftool_output() {
+        // Ensure exiftool's JSON is read in the order of the date tags, with offsets
+        let json = br#"[{
+            "SourceFile": "/media/clip.mkv",
+            "CreateDate": "2019:07:04 10:11:12.5+02:00",
+            "ModifyDate": "2020:01:02 03:04:05",
+            "Make": "Canon",
+            "Model": "Canon EOS R5",
+            "GPSLatitude": 40.4,
+            "GPSLongitude": -3.7
+        }, {
+            "SourceFile": "/media/scan.xyz",
+            "ModifyDate": "0000:00:00 00:00:00",
+            "OffsetTime": "-05:00",
+            "Model": 5
+        }]"#;
+        let read = exiftool::parse_output(json, &DEFAULT_DATE_TAGS).unwrap();
+
+        let clip = &read[Path::new("/media/clip.mkv")];
+        let taken = NaiveDate::from_ymd_opt(2019, 7, 4)
+            .and_then(|date| date.and_hms_milli_opt(10, 11, 12, 500))
+            .unwrap();
+        assert_eq!(clip.datetime, Some(taken));
+        assert_eq!(clip.date_source, Some(DateSource::DateTimeDigitized));
+        assert_eq!(clip.offset, FixedOffset::east_opt(2 * 3600));
+        assert_eq!(clip.camera.as_deref(), Some("Canon EOS R5"));
+        assert!(clip.coordinates.is_some());
+
+        let scan = &read[Path::new("/media/scan.xyz")];
+        assert_eq!(scan.datetime, None);
+        assert_eq!(scan.camera.as_deref(), Some("5"));
+
+        let read = exiftool::parse_output(json, &[DateTag::DateTime]).unwrap();
+        let clip = &read[Path::new("/media/clip.mkv")];
+        assert_eq!(clip.datetime.map(|date| date.year()), Some(2020));
+        assert_eq!(clip.offset, None);
+    }
+
+    #[test]
+    fn ffprobe_creation_times() {
+        // Ensure the container's creation time is preferred, skipping the epoch
+        let json = br#"{
+            "streams": [{"tags": {"creation_time": "2020-01-02T03:04:05.000000Z"}}],
+            "format": {"tags": {"creation_time": "1970-01-01T00:00:00.000000Z"}}
+        }"#;
+        let utc = NaiveDate::from_ymd_opt(2020, 1, 2)
+            .and_then(|date| date.and_hms_opt(3, 4, 5))
+            .unwrap()
+            .and_utc();
+        let (datetime, _) = ffprobe::parse_output(json).unwrap().unwrap();
+        assert_eq!(datetime, utc.with_timezone(&Local).naive_local());
+
+        let json = br#"{"format": {"tags": {}}, "streams": [{}]}"#;
+        assert_eq!(ffprobe::parse_output(json).unwrap(), None);
+        assert!(ffprobe::parse_output(b"not json").is_err());
+    }
+
+    #[test]
+    fn tolerant_exif_dates() {
+        // Ensure dates written other than as the standard says are read, and zeros aren't
+        let taken = NaiveDate::from_ymd_opt(2018, 6, 9)
+            .and_then(|date| date.and_hms_opt(14, 2, 11))
+            .unwrap();
+        for text in [
+            "2018:06:09 14:02:11",
+            "2018-06-09T14:02:11",
+            "2018-06-09 14:02:11",
+            "2018/06/09 14:02:11",
+            "2018:06:09 14:02:11.25",
+            "2018-06-09T14:02:11+02:00",
+            "2018-06-09T14:02:11Z",
+            " 2018:06:09 14:02:11\0",
+        ] {
+            assert_eq!(parse_exif_datetime(text), Some(taken), "{:?}", text);
+        }
+        assert_eq!(
+            parse_exif_datetime("2018:06:09 14:02"),
+            taken.date().and_hms_opt(14, 2, 0)
+        );
+        assert_eq!(
+            parse_exif_datetime("2018:06:09"),
+            taken.date().and_hms_opt(0, 0, 0)
+        );
+        for text in [
+            "0000:00:00 00:00:00",
+            "    :  :     :  :  ",
+            "",
+            "2018:13:09 14:02:11",
+            "2018:06:09 14:02:11 and more",
+        ] {
+            assert_eq!(parse_exif_datetime(text), None, "{:?}", text);
+        }
+
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["iso.jpg"], Some("2018-06-09T14:02:11"));
+        touch(&dir, ["zero.jpg"], Some("0000:00:00 00:00:00"));
+        assert_eq!(
+            read_metadata(&dir.path().join("iso.jpg")).datetime,
+            Some(taken)
+        );
+        assert_eq!(read_metadata(&dir.path().join("zero.jpg")).datetime, None);
+    }
+
+    #[test]
+    fn icloud_export_details_and_albums() {
+        // Ensure iCloud's CSVs date media and rebuild its albums and favorites from links
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let export = dir.path().join("iCloud Photos Part 1 of 1");
+        std::fs::create_dir_all(export.join("Photos")).unwrap();
+        std::fs::create_dir_all(export.join("Albums")).unwrap();
+        touch(&dir, ["iCloud Photos Part 1 of 1/Photos/a.jpg"], None);
+        touch(
+            &dir,
+            ["iCloud Photos Part 1 of 1/Photos/b.jpg"],
+            Some("2024:03:01 00:00:00"),
+        );
+        std::fs::write(
+            export.join("Photos").join("Photo Details.csv"),
+            "imgName,fileChecksum,favorite,hidden,deleted,originalCreationDate\r\n\
+             a.jpg,abc=,yes,no,no,\"Monday March 4,2024 12:00 PM GMT\"\r\n\
+             b.jpg,def=,no,no,no,\"Friday March 1,2024 12:00 AM GMT\"\r\n",
+        )
+        .unwrap();
+        std::fs::write(export.join("Albums").join("Trip.csv"), "Images\nb.jpg\n").unwrap();
+
+        assert_eq!(
+            icloud::parse_date("Thursday July 4,2019 8:11 AM GMT"),
+            NaiveDate::from_ymd_opt(2019, 7, 4)
+                .and_then(|date| date.and_hms_opt(8, 11, 0))
+                .map(|utc| utc.and_utc().with_timezone(&Local).naive_local())
+        );
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            icloud_albums: true,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+
+        let march = dest.path().join("2024").join("March");
+        assert!(march.join("a.jpg").is_file() && march.join("b.jpg").is_file());
+        let albums = dest.path().join(ALBUMS_DIR);
+        assert!(albums.join(icloud::FAVORITES).join("a.jpg").is_symlink());
+        assert!(albums.join("Trip").join("b.jpg").is_symlink());
+        assert_eq!(std::fs::read_dir(albums).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn keyword_layout_and_filter() {
+        // Ensure keywords are read from sidecars, XMP and IPTC, to sort and filter by
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2023:03:01 10:11:12"));
+        std::fs::write(
+            dir.path().join("a.xmp"),
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+                <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                    <rdf:Description xmlns:dc="http://purl.org/dc/elements/1.1/">
+                        <dc:subject><rdf:Bag><rdf:li>Birthday</rdf:li></rdf:Bag></dc:subject>
+                    </rdf:Description>
+                </rdf:RDF>
+            </x:xmpmeta>"#,
+        )
+        .unwrap();
+
+        // A JPEG with its keywords only in IPTC
+        let mut jpeg = Vec::new();
+        RgbImage::new(8, 8)
+            .write_to(&mut Cursor::new(&mut jpeg), ::image::ImageFormat::Jpeg)
+            .unwrap();
+        let iptc = b"\x1C\x02\x19\x00\x04Hike\x1C\x02\x19\x00\x08birthday";
+        let mut app13 = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+        app13.extend((iptc.len() as u32).to_be_bytes());
+        app13.extend(iptc);
+        let mut segment = vec![0xFF, 0xED];
+        segment.extend(((app13.len() + 2) as u16).to_be_bytes());
+        segment.extend(app13);
+        jpeg.splice(2..2, segment);
+        std::fs::write(dir.path().join("c.jpg"), jpeg).unwrap();
+        assert_eq!(
+            keywords::read_keywords(&dir.path().join("c.jpg")),
+            ["Hike", "birthday"]
+        );
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            layout: Some("year/keyword".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        assert!(dest.path().join("2023/Birthday/a.jpg").is_file());
+        assert!(dest.path().join("2023/Unknown/b.jpg").is_file());
+        assert!(dest.path().join("Unknown/c.jpg").is_file());
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            filter_keyword: vec![String::from("BIRTHDAY")],
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        assert!(dest.path().join("2023/March/a.jpg").is_file());
+        assert!(!dest.path().join("2023/March/b.jpg").exists());
+        assert!(dest.path().join("Unknown/c.jpg").is_file());
+    }
+
+    #[test]
+    fn min_rating_filter() {
+        // Ensure only starred media is sorted, with XMP ratings outranking EXIF ones
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        for (name, rating) in [("three.jpg", 3), ("one.jpg", 1), ("rerated.jpg", 1)] {
+            let mut writer = experimental::Writer::new();
+            let fields = [
+                Field {
+                    tag: Tag::DateTimeOriginal,
+                    ifd_num: In::PRIMARY,
+                    value: Value::Ascii(vec![b"2020:01:02 10:11:12".to_vec()]),
+                },
+                Field {
+                    tag: Tag(Context::Tiff, 0x4746),
+                    ifd_num: In::PRIMARY,
+                    value: Value::Short(vec![rating]),
+                },
+            ];
+            for field in &fields {
+                writer.push_field(field);
+            }
+            let mut tiff = Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            std::fs::write(dir.path().join(name), tiff.into_inner()).unwrap();
+        }
+        touch(&dir, ["unrated.jpg"], Some("2020:01:02 10:11:12"));
+        std::fs::write(
+            dir.path().join("rerated.xmp"),
+            r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+                <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                    <rdf:Description xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="4"/>
+                </rdf:RDF>
+            </x:xmpmeta>"#,
+        )
+        .unwrap();
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            min_rating: Some(3),
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        let january = dest.path().join("2020").join("January");
+        assert!(january.join("three.jpg").is_file());
+        assert!(january.join("rerated.jpg").is_file());
+        assert!(!january.join("one.jpg").exists());
+        assert!(!january.join("unrated.jpg").exists());
+
+        let sort = |rating| {
+            Cli::try_parse_from([
+                "img-sort",
+                "sort",
+                "-p",
+                ".",
+                "-o",
+                "out",
+                "--min-rating",
+                rating,
+            ])
+        };
+        assert!(sort("5").is_ok());
+        assert!(sort("6").is_err());
+    }
+
+    #[test]
+    fn rejected_exif_rating() {
+        // Ensure a rejected photo's -1 is read whether it's written signed or unsigned
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let ratings = [
+            ("signed.jpg", Value::SShort(vec![-1]), Some(-1)),
+            ("unsigned.jpg", Value::Short(vec![0xFFFF]), Some(-1)),
+            ("starred.jpg", Value::Short(vec![2]), Some(2)),
+            ("broken.jpg", Value::Short(vec![9]), None),
+        ];
+        for (name, value, expected) in ratings {
+            let field = Field {
+                tag: Tag(Context::Tiff, 0x4746),
+                ifd_num: In::PRIMARY,
+                value,
+            };
+            let mut writer = experimental::Writer::new();
+            writer.push_field(&field);
+            let mut tiff = Cursor::new(Vec::new());
+            writer.write(&mut tiff, false).unwrap();
+            let path = dir.path().join(name);
+            std::fs::write(&path, tiff.into_inner()).unwrap();
+
+            assert_eq!(read_metadata(&path).rating, expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn screenshots_routed_or_skipped() {
+        // Ensure screenshots are found by name, EXIF comment and screen size
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg"], Some("2020:01:02 10:11:12"));
+        touch(&dir, ["Screenshot 2024-05-01 at 9.30.00 AM.png"], None);
+        RgbImage::new(1170, 2532)
+            .save(dir.path().join("IMG_0001.png"))
+            .unwrap();
+        let mut writer = experimental::Writer::new();
+        let fields = [
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![b"2020:01:02 10:11:12".to_vec()]),
+            },
+            Field {
+                tag: Tag::UserComment,
+                ifd_num: In::PRIMARY,
+                value: Value::Undefined(b"ASCII\0\0\0Screenshot".to_vec(), 0),
+            },
+        ];
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut tiff = Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        std::fs::write(dir.path().join("IMG_0002.jpg"), tiff.into_inner()).unwrap();
+
+        let sort = |screenshots| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                screenshots,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(Screenshots::Route);
+        let shots = dest.path().join(SCREENSHOTS_DIR);
+        assert!(dest.path().join("2020/January/a.jpg").is_file());
+        assert!(shots.join("2020/January/IMG_0002.jpg").is_file());
+        assert!(shots
+            .join("2024/May/Screenshot 2024-05-01 at 9.30.00 AM.png")
+            .is_file());
+        assert!(shots.join("Unknown/IMG_0001.png").is_file());
+
+        let dest = sort(Screenshots::Skip);
+        assert!(dest.path().join("2020/January/a.jpg").is_file());
+        assert!(!dest.path().join("2020/January/IMG_0002.jpg").exists());
+        assert!(!dest.path().join("Unknown").exists());
+        assert!(!dest.path().join("2024").exists());
+
+        let dest = sort(Screenshots::Normal);
+        assert!(dest.path().join("2020/January/IMG_0002.jpg").is_file());
+        assert!(dest.path().join("Unknown/IMG_0001.png").is_file());
+    }
+
+    #[test]
+    fn bursts_grouped_in_folders() {
+        // Ensure shots taken within the gap of each other are saved in a burst folder
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(
+            &dir,
+            ["b1.jpg", "b2.jpg", "b3.jpg"],
+            Some("2020:01:02 10:11:12"),
+        );
+        touch(&dir, ["b4.jpg"], Some("2020:01:02 10:11:14"));
+        touch(
+            &dir,
+            ["pair1.jpg", "pair2.jpg"],
+            Some("2020:01:02 11:00:00"),
+        );
+        touch(&dir, ["alone.jpg"], Some("2020:01:02 12:00:00"));
+
+        let sort = |burst_gap| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                group_bursts: true,
+                burst_gap,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(Some(Duration::from_secs(2)));
+        let january = dest.path().join("2020").join("January");
+        let burst = january.join("Burst_20200102_101112");
+        for name in ["b1.jpg", "b2.jpg", "b3.jpg", "b4.jpg"] {
+            assert!(burst.join(name).is_file(), "{name}");
+        }
+        assert!(january.join("pair1.jpg").is_file() && january.join("alone.jpg").is_file());
+
+        // Only the first three are taken within a second of each other
+        let dest = sort(None);
+        let january = dest.path().join("2020").join("January");
+        assert!(january
+            .join("Burst_20200102_101112")
+            .join("b3.jpg")
+            .is_file());
+        assert!(january.join("b4.jpg").is_file());
+    }
+
+    #[test]
+    fn place_layout_from_gps() {
+        // Ensure media is sorted by the country and city nearest its GPS, if any is near
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let taken = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .and_then(|date| date.and_hms_opt(10, 11, 12))
+            .unwrap();
+        let places = [
+            ("a.jpg", Some((35.66, 139.70))),
+            ("b.jpg", Some((0.0, -150.0))),
+            ("c.jpg", None),
+        ];
+        for (name, place) in places {
+            let path = dir.path().join(name);
+            RgbImage::new(8, 8).save(&path).unwrap();
+            write_datetime_original(&path, taken).unwrap();
+            if let Some((latitude, longitude)) = place {
+                let coordinates = Coordinates {
+                    latitude,
+                    longitude,
+                };
+                write_gps(&path, coordinates, None).unwrap();
+            }
+        }
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            layout: Some("year/month/place".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        let month = dest.path().join("2024/March");
+        assert!(month.join("Japan/Tokyo/a.jpg").is_file());
+        assert!(month.join("Unknown/Unknown/b.jpg").is_file());
+        assert!(month.join("Unknown/Unknown/c.jpg").is_file());
+    }
+
+    #[test]
+    fn implausible_years_forgotten() {
+        // Ensure dates outside the years allowed are treated as missing
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.jpg"], Some("0012:07:04 10:11:12"));
+        touch(
+            &dir,
+            ["b.jpg", "IMG_20190704_101112.jpg"],
+            Some("2071:07:04 10:11:12"),
+        );
+
+        let sort = |max_year| {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dest.path().to_path_buf(),
+                max_year,
+                ..year_month()
+            };
+            assert_eq!(run(&args).unwrap(), Status::Success);
+            dest
+        };
+
+        let dest = sort(None);
+        assert!(dest.path().join("Unknown/a.jpg").is_file());
+        assert!(dest.path().join("Unknown/b.jpg").is_file());
+        // The name is still there to go by
+        assert!(dest
+            .path()
+            .join("2019/July/IMG_20190704_101112.jpg")
+            .is_file());
+
+        let dest = sort(Some(2080));
+        assert!(dest.path().join("Unknown/a.jpg").is_file());
+        assert!(dest.path().join("2071/July/b.jpg").is_file());
+
+        let cli = Cli::try_parse_from([
+            "img-sort",
+            "sort",
+            "-p",
+            ".",
+            "-o",
+            "out",
+            "--min-year",
+            "2000",
+            "--max-year",
+            "1990",
+        ]);
+        let Command::Sort(args) = cli.unwrap().command else {
+            panic!("Expected the sort subcommand");
+        };
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn dates_file_overrides() {
+        // Ensure globs in dates files date what they match, nearest file and longest glob first
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        std::fs::create_dir_all(dir.path().join("ScansBox3/box")).unwrap();
+        touch(
+            &dir,
+            ["ScansBox3/a.jpg", "ScansBox3/box/c.jpg", "d.jpg"],
+            None,
+        );
+        touch(&dir, ["ScansBox3/b.jpg"], Some("2019:07:04 10:11:12"));
+        std::fs::write(
+            dir.path().join(".img-sort-dates.toml"),
+            "\"ScansBox3/\" = \"1987-06\"\n\"ScansBox3/b.jpg\" = 1990-01-02\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("ScansBox3/box/.img-sort-dates.toml"),
+            "\"*.jpg\" = \"1975\"\n",
+        )
+        .unwrap();
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        assert!(dest.path().join("1987/June/a.jpg").is_file());
+        assert!(dest.path().join("1990/January/b.jpg").is_file());
+        assert!(dest.path().join("1975/January/c.jpg").is_file());
+        assert!(dest.path().join("Unknown/d.jpg").is_file());
+
+        assert_eq!(
+            overrides::parse_date("1987-06-12 10:11"),
+            NaiveDate::from_ymd_opt(1987, 6, 12).and_then(|date| date.and_hms_opt(10, 11, 0))
+        );
+        assert!(overrides::parse_overrides("\"*.jpg\" = \"June\"").is_err());
+        assert!(overrides::parse_overrides("\"*.jpg\" = true").is_err());
+    }
+
+    #[test]
+    fn motion_photo_videos_split() {
+        // Ensure the videos inside Pixel and Samsung Motion Photos are saved beside them
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let mut mp4 = Vec::new();
+        mp4.extend(b"\0\0\0\x10ftypisom\0\0\0\0");
+        mp4.extend(b"\0\0\0\x0cmdat\x01\x02\x03\x04");
+        let mut jpeg = Vec::new();
+        RgbImage::new(8, 8)
+            .write_to(&mut Cursor::new(&mut jpeg), ::image::ImageFormat::Jpeg)
+            .unwrap();
+
+        // Pixels say in their XMP how long the video at the end is
+        let xmp = format!(
+            r#"http://ns.adobe.com/xap/1.0/\0<x:xmpmeta xmlns:x="adobe:ns:meta/">
+                <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                    <rdf:Description
+                        xmlns:GCamera="http://ns.google.com/photos/1.0/camera/"
+                        xmlns:Container="http://ns.google.com/photos/1.0/container/"
+                        xmlns:Item="http://ns.google.com/photos/1.0/container/item/"
+                        GCamera:MotionPhoto="1">
+                        <Container:Directory><rdf:Seq>
+                            <rdf:li rdf:parseType="Resource">
+                                <Container:Item Item:Semantic="Primary" Item:Mime="image/jpeg"/>
+                            </rdf:li>
+                            <rdf:li rdf:parseType="Resource">
+                                <Container:Item Item:Semantic="MotionPhoto" Item:Length="{}"/>
+                            </rdf:li>
+                        </rdf:Seq></Container:Directory>
+                    </rdf:Description>
+                </rdf:RDF>
+            </x:xmpmeta>"#,
+            mp4.len()
+        );
+        let mut pixel = jpeg.clone();
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((xmp.len() + 2) as u16).to_be_bytes());
+        segment.extend(xmp.as_bytes());
+        pixel.splice(2..2, segment);
+        pixel.extend(&mp4);
+        std::fs::write(dir.path().join("PXL_1.jpg"), &pixel).unwrap();
+
+        // Samsungs mark where the video starts, and write more after it
+        let mut samsung = jpeg.clone();
+        samsung.extend(b"MotionPhoto_Data");
+        samsung.extend(&mp4);
+        samsung.extend(b"SEFH\0\0\0\x6b\x01\0\0\0SEFT");
+        std::fs::write(dir.path().join("samsung.jpg"), &samsung).unwrap();
+        std::fs::write(dir.path().join("still.jpg"), &jpeg).unwrap();
+        assert_eq!(motion::find_video(&jpeg), None);
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            split_motion_photos: true,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        let saved = dest.path().join("Unknown");
+        assert_eq!(std::fs::read(saved.join("PXL_1.jpg")).unwrap(), pixel);
+        assert_eq!(std::fs::read(saved.join("PXL_1.mp4")).unwrap(), mp4);
+        assert_eq!(std::fs::read(saved.join("samsung.jpg")).unwrap(), samsung);
+        assert_eq!(std::fs::read(saved.join("samsung.mp4")).unwrap(), mp4);
+        assert!(saved.join("still.jpg").is_file());
+        assert!(!saved.join("still.mp4").exists());
+    }
+
+    #[test]
+    fn every_raw_extension_scanned() {
+        // Ensure Pentax and Samsung RAWs are found and sorted as RAW like the others
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        touch(&dir, ["a.pef", "b.srw"], None);
+
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            split_media: true,
+            ..year_month()
+        };
+        assert_eq!(run(&args).unwrap(), Status::Success);
+        assert!(dest.path().join("RAW/Unknown/a.pef").is_file());
+        assert!(dest.path().join("RAW/Unknown/b.srw").is_file());
+    }
+
+    #[test]
+    fn conflicting_transfer_flags() {
+        // Ensure args has error when several transfer modes are requested
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dir.path().join("dest"),
+            months: true,
+            move_media: true,
+            link: true,
+            ..Default::default()
+        };
+
+        assert!(
+            args.validate().is_err(),
+            "Expected an error for conflicting transfer modes"
+        );
+    }
+
+    #[test]
+    fn invalid_days_flags() {
+        // Ensure days can't be used without years and months
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dir.path().join("dest"),
+            months: true,
+            days: true,
+            ..Default::default()
+        };
+
+        assert!(
+            args.validate().is_err(),
+            "Expected an error for days without years"
+        );
+    }
+
+    #[test]
+    fn trash_needs_replaced_files() {
+        // Ensure the trash is only asked for when files can be replaced
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let trash = |on_conflict, interactive| Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dir.path().join("dest"),
+            trash: true,
+            on_conflict,
+            interactive,
+            ..year_month()
+        };
+
+        assert!(trash(Conflict::Skip, false).validate().is_err());
+        assert!(trash(Conflict::Rename, false).validate().is_err());
+        assert!(trash(Conflict::Overwrite, false).validate().is_ok());
+        assert!(trash(Conflict::Skip, true).validate().is_ok());
+    }
+
+    #[test]
+    fn run_multiple_sources() {
+        // Ensure media from several sources is merged into one destination
+        let first = TempDir::new().expect("Failed to create temporary folder");
+        let second = TempDir::new().expect("Failed to create temporary folder");
+        let empty = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&first, ["a.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&second, ["a.jpg"], Some("2024:03:02 00:00:00"));
+
+        let args = Arguments {
+            path: vec![
+                first.path().to_path_buf(),
+                second.path().to_path_buf(),
+                empty.path().to_path_buf(),
+            ],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+
+        run(&args).expect("Failed to sort media");
+
+        let month = dest.path().join("2024").join("March");
+        assert!(month.join("a.jpg").exists());
+        assert!(month.join("a_1.jpg").exists());
+    }
+
+    #[test]
+    fn run_strict() {
+        // Ensure strict mode refuses to sort when any media has no date
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&dir, ["b.jpg"], None);
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            strict: true,
+            ..year_month()
+        };
+
+        assert!(run(&args).is_err());
+        assert!(!dest.path().join("2024").exists());
+    }
+
+    #[test]
+    fn run_nothing_to_do() {
+        // Ensure a run that finds nothing says so instead of failing
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+
+        let status = run(&args).expect("Failed to sort media");
+        assert_eq!(status, Status::NothingToDo);
+        assert_eq!(status.code(), 3);
+    }
+
+    #[test]
+    fn run_skip_unknown() {
+        // Ensure media without a date is left out of the destination
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+        touch(&dir, ["b.jpg"], None);
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            skip_unknown: true,
+            ..year_month()
+        };
+
+        run(&args).expect("Failed to sort media");
+
+        assert!(dest
+            .path()
+            .join("2024")
+            .join("March")
+            .join("a.jpg")
+            .exists());
+        assert!(!dest.path().join("Unknown").exists());
+        assert!(dir.path().join("b.jpg").exists());
+    }
+
+    #[test]
+    fn run_keep_structure() {
+        // Ensure source subdirectories are recreated inside the bucket
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        std::fs::create_dir_all(dir.path().join("Trips").join("Japan")).unwrap();
+        touch(
+            &dir,
+            ["a.jpg", "Trips/Japan/b.jpg"],
+            Some("2024:03:01 00:00:00"),
+        );
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            keep_structure: true,
+            ..year_month()
+        };
+
+        run(&args).expect("Failed to sort media");
+
+        let month = dest.path().join("2024").join("March");
+        assert!(month.join("a.jpg").exists());
+        assert!(month.join("Trips").join("Japan").join("b.jpg").exists());
+    }
+
+    #[test]
+    fn verbosity_log_levels() {
+        // Ensure -q and each -v map onto a log level
+        let level = |quiet, verbose| {
+            Common {
+                quiet,
+                verbose,
+                ..Default::default()
+            }
+            .log_level()
+        };
+
+        assert_eq!(level(true, 0), log::LevelFilter::Warn);
+        assert_eq!(level(false, 0), log::LevelFilter::Info);
+        assert_eq!(level(false, 1), log::LevelFilter::Debug);
+        assert_eq!(level(false, 2), log::LevelFilter::Trace);
+    }
+
+    #[test]
+    fn colored_preview() {
+        // Ensure years are bold when colors are on, and the preview is plain when they're
+        // off or it isn't going to a terminal
+        let color = |when: &str| {
+            let cli = Cli::try_parse_from(["img-sort", "scan", "-p", ".", "--color", when])
+                .expect("Failed to parse the color");
+            cli.command.common().color_choice()
+        };
+        assert_eq!(color("always"), anstream::ColorChoice::Always);
+        assert_eq!(color("never"), anstream::ColorChoice::Never);
+        assert_eq!(color("auto"), anstream::ColorChoice::Auto);
+
+        let mut tree = build_tree(&year_month());
+        let taken = NaiveDate::from_ymd_opt(2024, 3, 1).and_then(|d| d.and_hms_opt(0, 0, 0));
+        tree.insert(Image::new(PathBuf::from("a.jpg"), String::from("a.jpg")).with_datetime(taken));
+        let preview = |choice| {
+            let mut out = anstream::AutoStream::new(Vec::new(), choice);
+            tree.print_to(&mut out).unwrap();
+            String::from_utf8(out.into_inner()).unwrap()
+        };
+
+        let colored = preview(anstream::ColorChoice::Always);
+        assert!(colored.starts_with("\x1b[1mYear: 2024\x1b[0m, Month: 3\n"));
+        let plain = "Year: 2024, Month: 3\n  Image: \"a.jpg\"\n";
+        assert_eq!(preview(anstream::ColorChoice::Never), plain);
+        assert_eq!(preview(anstream::ColorChoice::Auto), plain);
+    }
+
+    #[test]
+    fn run_reports_progress() {
+        // Ensure scanning and saving report an event per piece of media
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let args = Arguments {
+            path: vec![dir.path().to_path_buf()],
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+
+        let found = AtomicUsize::new(0);
+        let saved = AtomicU64::new(0);
+        let total = AtomicU64::new(0);
+        run_with(&args, &|event| match event {
+            Event::Found(_) => {
+                found.fetch_add(1, Ordering::Relaxed);
+            }
+            Event::Saving { bytes, .. } => total.store(bytes, Ordering::Relaxed),
+            Event::Saved { bytes, .. } => {
+                saved.fetch_add(bytes, Ordering::Relaxed);
+            }
+            _ => {}
+        })
+        .expect("Failed to sort media");
+
+        assert_eq!(found.into_inner(), 2);
+        assert!(total.load(Ordering::Relaxed) > 0);
+        assert_eq!(saved.into_inner(), total.into_inner());
+    }
+
+    #[test]
+    fn events_as_json() {
+        // Ensure each event keeps the name and fields scripts reading NDJSON rely on
+        let (path, dest) = (Path::new("in/a.jpg"), Path::new("out/a.jpg"));
+        assert_eq!(
+            Event::Scanning(Path::new("in")).to_json(),
+            serde_json::json!({ "event": "scan-start", "path": "in" })
+        );
+        assert_eq!(
+            Event::Saved {
+                path,
+                dest,
+                outcome: Outcome::Save,
+                bytes: 10,
+            }
+            .to_json(),
+            serde_json::json!({
+                "event": "file-copied",
+                "path": "in/a.jpg",
+                "dest": "out/a.jpg",
+                "bytes": 10,
+            })
+        );
+        assert_eq!(
+            Event::Conflict {
+                path,
+                dest,
+                outcome: Outcome::Rename,
+            }
+            .to_json(),
+            serde_json::json!({
+                "event": "conflict",
+                "path": "in/a.jpg",
+                "dest": "out/a.jpg",
+                "outcome": "rename",
+            })
+        );
+        assert_eq!(
+            Event::Failed {
+                path,
+                bytes: 10,
+                error: "Permission denied",
+            }
+            .to_json(),
+            serde_json::json!({
+                "event": "error",
+                "path": "in/a.jpg",
+                "message": "Permission denied",
+            })
+        );
+
+        let summary = Summary {
+            found: 3,
+            filtered: 1,
+            undated: 1,
+            report: SaveReport {
+                saved: 2,
+                bytes: 20,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            Event::Summary(&summary).to_json(),
+            serde_json::json!({
+                "event": "summary",
+                "found": 3,
+                "filtered": 1,
+                "transfer": "copy",
+                "saved": 2,
+                "renamed": 0,
+                "overwritten": 0,
+                "skipped": 0,
+                "failed": 0,
+                "bytes": 20,
+                "undated": 1,
+                "left_in_place": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn summary_counts() {
+        // Ensure the summary counts media per year and without a date
+        let datetime = |year| {
+            NaiveDate::from_ymd_opt(year, 3, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        };
+
+        let mut tree = build_tree(&year_month());
+        for (name, datetime) in [
+            ("a.jpg", datetime(2023)),
+            ("b.jpg", datetime(2024)),
+            ("c.jpg", datetime(2024)),
+            ("d.jpg", None),
+        ] {
+            tree.insert(Image::new(PathBuf::from(name), name.to_string()).with_datetime(datetime));
+        }
+
+        let summary = Summary::new(&tree, 6, 1, 1);
+
+        assert_eq!(summary.undated, 1);
+        assert_eq!(
+            summary.years,
+            BTreeMap::from([(None, 1), (Some(2023), 1), (Some(2024), 2)])
+        );
+        assert!(summary.to_string().contains("2024: 2"));
+    }
+
+    #[test]
+    fn cli_subcommands() {
+        // Ensure the command line is consistent and scanning needs no destination
+        config::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["img-sort", "scan", "-p", "photos", "-y"])
+            .expect("Failed to parse a scan");
+        assert!(matches!(cli.command, Command::Scan(_)));
+        assert!(Cli::try_parse_from(["img-sort", "sort", "-p", "photos", "-y"]).is_err());
+    }
+
+    #[test]
+    fn config_profiles() {
+        // Ensure config options and profiles fill in what the command line leaves out
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let config = dir.path().join("img-sort.toml");
+        std::fs::write(
+            &config,
+            r#"
+            path = ["/photos"]
+            dest = "/sorted"
+            years = true
+            on-conflict = "skip"
+
+            [profile.nas]
+            dest = "/mnt/nas"
+            max_depth = 8
+            "#,
+        )
+        .unwrap();
+
+        let cli = parse_args(
+            [
+                "img-sort",
+                "sort",
+                "--config",
+                config.to_str().unwrap(),
+                "--profile",
+                "nas",
+                "--on-conflict",
+                "error",
+            ]
+            .map(OsString::from)
+            .to_vec(),
+        )
+        .expect("Failed to read the config");
+        let Command::Sort(args) = &cli.command else {
+            panic!("Expected the sort subcommand");
+        };
+
+        assert_eq!(args.path, vec![PathBuf::from("/photos")]);
+        assert_eq!(args.dest, PathBuf::from("/mnt/nas"));
+        assert!(args.years);
+        assert_eq!(args.max_depth, 8);
+        assert_eq!(args.on_conflict, Conflict::Error);
+
+        let missing = read_config(&config, Some("laptop"));
+        assert!(missing.is_err(), "Expected an error for a missing profile");
+    }
+
+    #[test]
+    fn environment_options() {
+        // Ensure options are read from environment variables, behind the command line
+        // A prefix of its own keeps other tests from seeing these variables
+        let prefix = "IMG_SORT_ENVIRONMENT_TEST_";
+        let vars = [
+            ("PATH", "/photos"),
+            ("UNKNOWN_DIR", "Unsorted"),
+            ("YEARS", "yes"),
+            ("ON_CONFLICT", "skip"),
+        ]
+        .map(|(name, value)| (format!("{prefix}{name}"), value));
+
+        // Removed again even if an assertion fails, so nothing outlives the test
+        struct Restore<'a>(&'a [(String, &'a str)]);
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                for (name, _) in self.0 {
+                    std::env::remove_var(name);
+                }
+            }
+        }
+        let _restore = Restore(&vars);
+        for (name, value) in &vars {
+            std::env::set_var(name, value);
+        }
+
+        let matches = crate::config::command_with_prefix(prefix)
+            .try_get_matches_from(["img-sort", "sort", "-o", "out", "--on-conflict", "error"])
+            .expect("Failed to parse the environment");
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        let Command::Sort(args) = &cli.command else {
+            panic!("Expected the sort subcommand");
+        };
+
+        assert_eq!(args.path, vec![PathBuf::from("/photos")]);
+        assert_eq!(args.unknown_dir.as_deref(), Some("Unsorted"));
+        assert!(args.years);
+        assert_eq!(args.on_conflict, Conflict::Error);
+    }
+
+    #[test]
+    fn file_list_separators() {
+        // Ensure both newline and NUL separated lists are understood
+        let newlines = read_file_list("a.jpg\r\nb c.jpg\n\n".as_bytes()).unwrap();
+        let nuls = read_file_list("a.jpg\0b c.jpg\0".as_bytes()).unwrap();
+
+        let expected = vec![PathBuf::from("a.jpg"), PathBuf::from("b c.jpg")];
+        assert_eq!(newlines, expected);
+        assert_eq!(nuls, expected);
+    }
+
+    #[test]
+    fn run_files_from_list() {
+        // Ensure listed files are sorted without walking a source directory
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
+
+        let list = dir.path().join("list.txt");
+        std::fs::write(&list, format!("{}\n", dir.path().join("a.jpg").display())).unwrap();
+
+        let args = Arguments {
+            files_from: Some(list),
+            dest: dest.path().to_path_buf(),
+            ..year_month()
+        };
+
+        run(&args).expect("Failed to sort media");
+
+        let month = dest.path().join("2024").join("March");
+        assert!(month.join("a.jpg").exists());
+        assert!(!month.join("b.jpg").exists());
+    }
+
+    #[test]
+    fn globwalker_invalid_patterns() {
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = PathBuf::from(dir.path());
+        let invalid_patterns = ["\\", ""];
+
+        let walker = build_glob_walker(&dir_path, &invalid_patterns, &ScanOptions::default());
+
+        assert!(
+            walker.is_err(),
+            "Expected an error for invalid search patterns"
+        );
+    }
+    #[test]
+    fn globwalker_valid_patterns() {
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = PathBuf::from(dir.path());
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default());
+
+        assert!(walker.is_ok(), "Expected OK for valid search patterns");
+    }
+    #[test]
+    fn build_year_month_tree() {
+        let tree = build_tree(&year_month());
+
+        assert_eq!(
+            tree.layout().keys,
+            vec![Key::Year, Key::Month],
+            "Expected a YearMonth layout"
+        );
+    }
+    #[test]
+    fn build_year_month_day_tree() {
+        let tree = build_tree(&Arguments {
+            days: true,
+            ..year_month()
+        });
+
+        assert_eq!(
+            tree.layout().keys,
+            vec![Key::Year, Key::Month, Key::Day],
+            "Expected a YearMonthDay layout"
+        );
+    }
+    #[test]
+    fn build_year_tree() {
+        let tree = build_tree(&Arguments {
+            years: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            tree.layout().keys,
+            vec![Key::Year],
+            "Expected a Year layout"
+        );
+    }
+    #[test]
+    fn build_month_tree() {
+        let tree = build_tree(&Arguments {
+            months: true,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            tree.layout().keys,
+            vec![Key::Month],
+            "Expected a Month layout"
+        );
+    }
+
+    #[test]
+    fn save_link_media() {
+        // Ensure linked media shares the source's data
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        tree.save(
+            dest.path(),
+            &SaveOptions {
+                transfer: Transfer::Link,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to link media");
+
+        let linked = dest.path().join("2024").join("March").join("a.jpg");
+        std::fs::write(&linked, b"changed").unwrap();
+
+        assert_eq!(
+            std::fs::read(dir_path.join("a.jpg")).unwrap(),
+            b"changed",
+            "Expected the destination to be a hardlink"
+        );
+    }
+
+    #[test]
+    fn save_symlink_media() {
+        // Ensure symlinked media points back at the original
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        tree.save(
+            dest.path(),
+            &SaveOptions {
+                transfer: Transfer::Symlink,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to symlink media");
+
+        let linked = dest.path().join("2024").join("March").join("a.jpg");
+
+        assert_eq!(
+            std::fs::read_link(&linked).unwrap(),
+            std::fs::canonicalize(dir_path.join("a.jpg")).unwrap(),
+            "Expected the destination to link to the original"
+        );
+    }
+
+    #[test]
+    fn save_reflink_or_copy_media() {
+        // Ensure auto reflinking produces a copy on any filesystem
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        tree.save(
+            dest.path(),
+            &SaveOptions {
+                transfer: Transfer::ReflinkOrCopy,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to clone media");
+
+        let cloned = dest.path().join("2024").join("March").join("a.jpg");
+
+        assert_eq!(
+            std::fs::read(cloned).unwrap(),
+            std::fs::read(dir_path.join("a.jpg")).unwrap(),
+            "Expected the clone to match the original"
+        );
+    }
+
+    fn save_conflict(on_conflict: Conflict) -> (TempDir, io::Result<SaveReport>) {
+        // Save a.jpg into a destination that already holds a file with its name
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let existing = dest.path().join("2024").join("March");
+        std::fs::create_dir_all(&existing).unwrap();
+        std::fs::write(existing.join("a.jpg"), b"existing").unwrap();
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        let options = SaveOptions {
+            on_conflict,
+            ..Default::default()
+        };
+        let report = tree.save(dest.path(), &options);
+
+        (dest, report)
+    }
+
+    #[test]
+    fn save_conflict_strategies() {
+        // Ensure each conflict strategy treats the existing file as expected
+        let (dest, report) = save_conflict(Conflict::Skip);
+        let month = dest.path().join("2024").join("March");
+        assert_eq!(
+            report.unwrap().skipped,
+            1,
+            "Expected the file to be skipped"
+        );
+        assert_eq!(std::fs::read(month.join("a.jpg")).unwrap(), b"existing");
+
+        let (dest, report) = save_conflict(Conflict::Overwrite);
+        let month = dest.path().join("2024").join("March");
+        assert_eq!(report.unwrap().overwritten, 1, "Expected an overwrite");
+        assert_ne!(std::fs::read(month.join("a.jpg")).unwrap(), b"existing");
+
+        let (dest, report) = save_conflict(Conflict::Rename);
+        let month = dest.path().join("2024").join("March");
+        assert_eq!(report.unwrap().renamed, 1, "Expected a rename");
+        assert_eq!(std::fs::read(month.join("a.jpg")).unwrap(), b"existing");
+        assert!(
+            month.join("a_1.jpg").exists(),
+            "Expected a_1.jpg to be saved"
+        );
+
+        let (_dest, report) = save_conflict(Conflict::Error);
+        assert!(report.is_err(), "Expected an error for a conflict");
+    }
+
+    #[test]
+    fn overwrite_keeps_existing_until_replaced() {
+        // Ensure a file being overwritten survives a save that fails, and is replaced
+        // whole by one that doesn't, links included
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let src = dir.path().join("a.jpg");
+        std::fs::write(&src, b"\xFF\xD8 not really a JPEG").unwrap();
+        let existing = dest.path().join("Unknown").join("a.jpg");
+        std::fs::create_dir_all(existing.parent().unwrap()).unwrap();
+
+        let mut tree = build_tree(&year_month());
+        tree.insert(Image::new(src.clone(), "a.jpg".to_string()));
+
+        for (transfer, strip, saved) in [
+            (Transfer::Copy, Some(Strip::Gps), false),
+            (Transfer::Link, None, true),
+        ] {
+            std::fs::write(&existing, b"existing").unwrap();
+            let options = SaveOptions {
+                on_conflict: Conflict::Overwrite,
+                transfer,
+                strip,
+                ..Default::default()
+            };
+            let report = tree.save(dest.path(), &options).unwrap();
+
+            assert_eq!(report.failed, usize::from(!saved));
+            let expected = if saved {
+                std::fs::read(&src).unwrap()
+            } else {
+                b"existing".to_vec()
+            };
+            assert_eq!(std::fs::read(&existing).unwrap(), expected);
+            assert!(!transfer::partial_path(&existing).exists());
+        }
+    }
+
+    #[test]
+    fn save_threads() {
+        // Ensure several workers save every file, numbering same-named media once each
+        let first = TempDir::new().expect("Failed to create temporary folder");
+        let second = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        let names: Vec<String> = (0..20).map(|n| format!("{n}.jpg")).collect();
+        touch(&first, &names, Some("2024:03:01 00:00:00"));
+        touch(&second, &names, Some("2024:03:01 00:00:00"));
+
+        let mut tree = build_tree(&year_month());
+        for dir in [&first, &second] {
+            let walker = build_glob_walker(dir.path(), &PATTERNS, &ScanOptions::default()).unwrap();
+            find(walker, &mut tree).unwrap();
+        }
+
+        let options = SaveOptions {
+            on_conflict: Conflict::Skip,
+            threads: 4,
+            ..Default::default()
+        };
+        let report = tree
+            .save(dest.path(), &options)
+            .expect("Failed to save media");
+
+        assert_eq!(report.saved, 40);
+        assert_eq!(report.renamed, 20);
+        let month = dest.path().join("2024").join("March");
+        assert!(month.join("19.jpg").exists());
+        assert!(month.join("19_1.jpg").exists());
+    }
+
+    #[test]
+    fn free_space_check() {
+        // Ensure copies are checked against the destination's free space, but links aren't
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let steps = [Step {
+            source: PathBuf::from("a.jpg"),
+            dest: dest.path().join("a.jpg"),
+            outcome: Outcome::Save,
+            bytes: u64::MAX,
+            hash: None,
+            sidecars: Vec::new(),
+            motion_video: None,
+            date_source: None,
+        }];
+
+        let error = check_free_space(&steps, &SaveOptions::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+
+        let options = SaveOptions {
+            transfer: Transfer::Symlink,
+            ..Default::default()
+        };
+        assert!(check_free_space(&steps, &options).is_ok());
+    }
+
+    #[test]
+    fn prompt_conflicts() {
+        // Ensure answers are re-asked until valid and "apply to all" answers are kept
+        let mut output = Vec::new();
+        let mut prompt = Prompt::new("x\no\nc\nR\n".as_bytes(), &mut output);
+        let (src, dest) = (Path::new("a.jpg"), Path::new("dest/a.jpg"));
+
+        // Overwriting isn't offered for media from the same run
+        let first = prompt.ask(src, dest, Path::new("b/a.jpg"), false).unwrap();
+        let second = prompt.ask(src, dest, dest, true).unwrap();
+        drop(prompt);
+
+        assert_eq!(first, Conflict::Rename);
+        assert_eq!(second, Conflict::Rename);
+        assert!(String::from_utf8(output).unwrap().contains("New:"));
+    }
+
+    #[test]
+    fn template_render() {
+        // Ensure templates render padded fields and keep the extension
+        let template: Template = "{year}{month:02}{day:02}_{hhmmss}_{original}"
+            .parse()
+            .expect("Failed to parse template");
+        let datetime = NaiveDate::from_ymd_opt(2024, 3, 7)
+            .unwrap()
+            .and_hms_opt(9, 5, 1);
+        let image =
+            Image::new(PathBuf::from("IMG_1.JPG"), "IMG_1.JPG".to_string()).with_datetime(datetime);
+
+        assert_eq!(
+            template.render(&image),
+            Some(String::from("20240307_090501_IMG_1.JPG"))
+        );
+        assert!(
+            "{bleh}".parse::<Template>().is_err(),
+            "Expected an error for an unknown field"
+        );
+    }
+
+    #[test]
+    fn save_rename_collisions() {
+        // Ensure identical renders are numbered instead of overwriting each other
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-const PATTERNS: [&str; 5] = ["*.png", "*.jpg", "*.jpeg", "*.heic", ".mov"];
+        touch(&dir, ["a.jpg", "b.jpg"], Some("2024:03:01 00:00:00"));
 
-fn build_glob_walker(path: &PathBuf, patterns: &[&str]) -> Result<GlobWalker, GlobError> {
-    globwalk::GlobWalkerBuilder::from_patterns(path, patterns)
-        .max_depth(4)
-        .follow_links(true)
-        .case_insensitive(true)
-        .build()
-}
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
 
-fn get_datetime_original(path: &PathBuf) -> Option<(i32, u32)> {
-    let file = std::fs::File::open(path).unwrap();
-    let mut bufreader = std::io::BufReader::new(&file);
+        let options = SaveOptions {
+            rename: Some("{year}-{month:02}-{day:02}".parse().unwrap()),
+            on_conflict: Conflict::Overwrite,
+            ..Default::default()
+        };
+        tree.save(dest.path(), &options)
+            .expect("Failed to save media");
 
-    let exifreader = exif::Reader::new();
-    let exif = match exifreader.read_from_container(&mut bufreader) {
-        Ok(exif) => exif,
-        Err(_) => return None,
-    };
+        let month = dest.path().join("2024").join("March");
+        assert!(month.join("2024-03-01.jpg").exists());
+        assert!(month.join("2024-03-01_1.jpg").exists());
+    }
 
-    match exif.get_field(Tag::DateTimeOriginal, In::PRIMARY) {
-        None => None,
-        Some(field) => {
-            let datetime_str = field.display_value().with_unit(&exif).to_string();
-            NaiveDateTime::parse_from_str(&datetime_str, "%Y-%m-%d %H:%M:%S")
-                .ok()
-                .map(|dt| (dt.year(), dt.month()))
+    #[test]
+    fn save_year_week_tree() {
+        // Ensure weeks are bucketed by ISO year, which can differ from the calendar year
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:12:30 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&Arguments {
+            weeks: true,
+            ..Default::default()
+        });
+        find(walker, &mut tree).unwrap();
+
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
+
+        assert!(dest.path().join("2025").join("W01").join("a.jpg").exists());
+    }
+
+    #[test]
+    fn save_year_quarter_tree() {
+        // Ensure quarters compose with the years flag
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2023:06:30 00:00:00"));
+        touch(&dir, ["b.jpg"], Some("2023:07:01 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&Arguments {
+            years: true,
+            quarters: true,
+            ..Default::default()
+        });
+        find(walker, &mut tree).unwrap();
+
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
+
+        assert!(dest.path().join("2023").join("Q2").join("a.jpg").exists());
+        assert!(dest.path().join("2023").join("Q3").join("b.jpg").exists());
+    }
+
+    #[test]
+    fn save_decade_tree() {
+        // Ensure media is grouped into decades and undated media is kept apart
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("1974:06:30 00:00:00"));
+        touch(&dir, ["b.jpg"], None);
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&Arguments {
+            decades: true,
+            ..Default::default()
+        });
+        find(walker, &mut tree).unwrap();
+
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
+
+        assert!(dest.path().join("1970s").join("a.jpg").exists());
+        assert!(dest.path().join("Unknown").join("b.jpg").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_preserve_xattrs() {
+        // Ensure extended attributes are copied when asked, where the filesystem has them
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+
+        touch(&dir, ["a.jpg"], None);
+        let src = dir.path().join("a.jpg");
+        if xattr::set(&src, "user.img-sort", b"tagged").is_err() {
+            return;
         }
+
+        let mut tree = build_tree(&year_month());
+        tree.insert(Image::new(src, "a.jpg".to_string()));
+
+        let options = SaveOptions {
+            preserve_xattrs: true,
+            ..Default::default()
+        };
+        tree.save(dest.path(), &options)
+            .expect("Failed to save media");
+
+        let value = xattr::get(dest.path().join("Unknown").join("a.jpg"), "user.img-sort").unwrap();
+        assert_eq!(value, Some(b"tagged".to_vec()));
     }
-}
 
-fn find(walker: GlobWalker, tree: &mut Tree) -> Result<(), Box<dyn Error>> {
-    // Convert to peekable iterator to check if empty
-    let mut images = walker.into_iter().filter_map(Result::ok).peekable();
+    #[test]
+    fn save_unknown_dir() {
+        // Ensure undated media lands in one named folder below any undated keys
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-    if images.peek().is_none() {
-        return Err(Box::new(io::Error::new(
-            io::ErrorKind::NotFound,
-            "Did not find any media with metadata.",
-        )));
+        touch(&dir, ["a.jpg"], Some("2024:01:01 00:00:00"));
+        touch(&dir, ["b.jpg"], None);
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&Arguments {
+            layout: Some("type/year/month".parse().unwrap()),
+            ..Default::default()
+        });
+        find(walker, &mut tree).unwrap();
+
+        let options = SaveOptions {
+            unknown_dir: Some(String::from("Unsorted")),
+            ..Default::default()
+        };
+        tree.save(dest.path(), &options)
+            .expect("Failed to save media");
+
+        let photos = dest.path().join("Photos");
+        assert!(photos.join("2024").join("January").join("a.jpg").exists());
+        assert!(photos.join("Unsorted").join("b.jpg").exists());
     }
 
-    for image in images {
-        let path = image.path().to_path_buf();
+    #[test]
+    fn save_year_season_tree() {
+        // Ensure seasons follow the hemisphere and December joins the next winter
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-        if let Some(datetime) = get_datetime_original(&path) {
-            tree.insert(
-                datetime,
-                Image::new(path, image.file_name().to_str().unwrap().to_owned()),
-            );
-        } else {
-            // Insert pics without metadata under (0, 0)
-            tree.insert(
-                (0, 0),
-                Image::new(path, image.file_name().to_str().unwrap().to_owned()),
-            );
+        touch(&dir, ["a.jpg"], Some("2023:12:24 00:00:00"));
+        touch(&dir, ["b.jpg"], Some("2024:07:01 00:00:00"));
+
+        for (hemisphere, a, b) in [
+            (Hemisphere::North, "Winter", "Summer"),
+            (Hemisphere::South, "Summer", "Winter"),
+        ] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+            let mut tree = build_tree(&Arguments {
+                seasons: true,
+                ..Default::default()
+            });
+            find(walker, &mut tree).unwrap();
+
+            let options = SaveOptions {
+                hemisphere,
+                ..Default::default()
+            };
+            tree.save(dest.path(), &options)
+                .expect("Failed to save media");
+
+            assert!(dest.path().join("2024").join(a).join("a.jpg").exists());
+            assert!(dest.path().join("2024").join(b).join("b.jpg").exists());
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn save_format_tree() {
+        // Ensure a format string controls the directory layout
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-// Means that function will return a type that implements the Error trait
-pub fn run(args: &Arguments) -> Result<(), Box<dyn Error>> {
-    let walker = build_glob_walker(&args.path, &PATTERNS)?;
-    let mut tree = build_tree(&args.years, &args.months);
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
 
-    println!("Searching for media...");
-    let find_start = Instant::now();
-    find(walker, &mut tree)?;
-    let find_duration = find_start.elapsed();
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&Arguments {
+            format: Some(String::from("%Y/%Y-%m")),
+            ..Default::default()
+        });
+        find(walker, &mut tree).unwrap();
 
-    println!(
-        "Found {} pieces of media in {:?}",
-        tree.size(),
-        find_duration
-    );
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
 
-    println!("Saving sorted media...");
-    let save_start = Instant::now();
-    tree.save(&args.dest)?;
-    let save_duration = save_start.elapsed();
+        assert!(dest
+            .path()
+            .join("2024")
+            .join("2024-03")
+            .join("a.jpg")
+            .exists());
+    }
 
-    println!(
-        "Media successfully saved to: {:?} in {:?}",
-        &args.dest, save_duration
-    );
+    #[test]
+    fn invalid_format() {
+        // Ensure bad formats and formats escaping the destination are rejected
+        let dir = TempDir::new().expect("Failed to create temporary folder");
 
-    Ok(())
-}
+        for format in ["%Y/%Q", "../%Y", "/%Y"] {
+            let args = Arguments {
+                path: vec![dir.path().to_path_buf()],
+                dest: dir.path().join("dest"),
+                format: Some(String::from(format)),
+                ..Default::default()
+            };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use ::image::RgbImage;
-    use exif::experimental;
-    use exif::{Field, In, Tag, Value};
-    use std::collections::HashSet;
-    use std::fs::File;
-    use std::io::BufWriter;
-    use tempfile::TempDir;
+            assert!(
+                args.validate().is_err(),
+                "Expected an error for the format {format:?}"
+            );
+        }
+    }
 
-    fn create_image_with_metadata(path: &PathBuf, datetime: &str) -> Result<(), Box<dyn Error>> {
-        // Create and save image
-        let img = RgbImage::new(32, 32);
-        img.save(path)?;
+    #[test]
+    fn save_month_styles() {
+        // Ensure month directories follow the requested style
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-        // Open image to add EXIF data
-        let file = File::create(path)?;
-        let mut writer = BufWriter::new(file);
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
 
-        // Add the DateTimeOriginal tag
-        let mut exif_writer = experimental::Writer::new();
-        let datetime_original = Field {
-            tag: Tag::DateTimeOriginal,
-            ifd_num: In::PRIMARY,
-            value: Value::Ascii(vec![datetime.as_bytes().to_vec()]),
-        };
+        for (month_style, month) in [
+            (MonthStyle::Numeric, "03"),
+            (MonthStyle::Name, "March"),
+            (MonthStyle::Both, "03-March"),
+        ] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+            let mut tree = build_tree(&year_month());
+            find(walker, &mut tree).unwrap();
 
-        exif_writer.push_field(&datetime_original);
-        exif_writer.write(&mut writer, false)?;
+            let options = SaveOptions {
+                month_style,
+                ..Default::default()
+            };
+            tree.save(dest.path(), &options)
+                .expect("Failed to save media");
 
-        Ok(())
+            assert!(dest.path().join("2024").join(month).join("a.jpg").exists());
+        }
     }
 
-    fn touch(
-        dir: &TempDir,
-        names: impl IntoIterator<Item = impl AsRef<str>>,
-        datetime: Option<&str>,
-    ) {
-        for name in names {
-            let file_path = dir.path().join(name.as_ref());
+    #[test]
+    fn save_localized_months() {
+        // Ensure month directories are named in the requested locale
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
 
-            if let Some(datetime) = datetime {
-                create_image_with_metadata(&file_path, datetime)
-                    .expect("Failed to create a test file with metadata");
-            } else {
-                File::create(&file_path).expect("Failed to create a test file");
-            }
+        touch(&dir, ["a.jpg"], Some("2024:01:01 00:00:00"));
+
+        for (locale, month) in [
+            (Locale::es_ES, "Enero"),
+            (Locale::fr_FR, "Janvier"),
+            (Locale::de_DE, "Januar"),
+        ] {
+            let dest = TempDir::new().expect("Failed to create temporary folder");
+            let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+            let mut tree = build_tree(&year_month());
+            find(walker, &mut tree).unwrap();
+
+            let options = SaveOptions {
+                locale: Some(locale),
+                ..Default::default()
+            };
+            tree.save(dest.path(), &options)
+                .expect("Failed to save media");
+
+            assert!(dest.path().join("2024").join(month).join("a.jpg").exists());
         }
     }
 
     #[test]
-    fn not_dir() {
-        // Ensure args has error on invalid directory
+    fn save_split_media() {
+        // Ensure media classes are saved under separate roots
         let dir = TempDir::new().expect("Failed to create temporary folder");
-        let dir_path = dir.path();
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let datetime = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
 
-        touch(&dir, ["f.txt"], None);
+        touch(&dir, ["a.jpg", "b.mov", "c.dng"], None);
 
-        let path = dir_path.join("f.txt");
+        let mut tree = build_tree(&Arguments {
+            split_media: true,
+            ..year_month()
+        });
+        for name in ["a.jpg", "b.mov", "c.dng"] {
+            let path = dir.path().join(name);
+            tree.insert(Image::new(path, name.to_string()).with_datetime(datetime));
+        }
 
-        let args = Arguments {
-            path,
-            dest: PathBuf::from("dest"),
-            months: true,
-            years: true,
-        };
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
 
-        let args = Arguments::validate(&args);
+        for (root, name) in [("Photos", "a.jpg"), ("Videos", "b.mov"), ("RAW", "c.dng")] {
+            assert!(dest
+                .path()
+                .join(root)
+                .join("2024")
+                .join("March")
+                .join(name)
+                .exists());
+        }
+    }
+
+    #[test]
+    fn parse_layout() {
+        // Ensure layouts parse in order and years follow weeks
+        let layout: Layout = "year/month/camera".parse().expect("Failed to parse layout");
+        assert_eq!(layout.keys, vec![Key::Year, Key::Month, Key::Camera]);
+
+        let layout: Layout = "year/week".parse().expect("Failed to parse layout");
+        assert_eq!(layout.keys, vec![Key::IsoYear, Key::Week]);
 
         assert!(
-            args.is_err(),
-            "Expected an error for a file path used as a directory"
+            "year/bleh".parse::<Layout>().is_err(),
+            "Expected an error for an unknown keyword"
         );
     }
 
     #[test]
-    fn invalid_path() {
-        // Ensure args has error on invalid path
-        let args = Arguments {
-            path: PathBuf::from("bleh"),
-            dest: PathBuf::from("dest"),
-            months: true,
-            years: true,
-        };
+    fn save_layout_tree() {
+        // Ensure every key in a layout becomes a directory level
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let datetime = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
 
-        let args = Arguments::validate(&args);
+        touch(&dir, ["a.jpg", "b.jpg"], None);
 
-        assert!(
-            args.is_err(),
-            "Expected an error for a non-existent file path used as a directory"
+        let mut tree = build_tree(&Arguments {
+            layout: Some("year/camera/month".parse().unwrap()),
+            ..Default::default()
+        });
+        tree.insert(
+            Image::new(dir.path().join("a.jpg"), "a.jpg".to_string())
+                .with_datetime(datetime)
+                .with_camera(Some(String::from("Canon EOS 5D"))),
+        );
+        tree.insert(
+            Image::new(dir.path().join("b.jpg"), "b.jpg".to_string()).with_datetime(datetime),
         );
+
+        tree.save(dest.path(), &SaveOptions::default())
+            .expect("Failed to save media");
+
+        let year = dest.path().join("2024");
+        assert!(year
+            .join("Canon EOS 5D")
+            .join("March")
+            .join("a.jpg")
+            .exists());
+        assert!(year.join("Unknown").join("March").join("b.jpg").exists());
     }
+
     #[test]
-    fn invalid_sort_flags() {
-        // Ensure args has error on invalid path
-        let args = Arguments {
-            path: PathBuf::from("bleh"),
-            dest: PathBuf::from("dest"),
-            months: false,
-            years: false,
-        };
+    fn globwalker_max_depth() {
+        // Ensure the depth limit applies and can be lifted
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let nested = ["a", "b", "c", "d", "e"].iter().collect::<PathBuf>();
+        std::fs::create_dir_all(dir.path().join(&nested)).unwrap();
 
-        let args = Arguments::validate(&args);
+        touch(&dir, [nested.join("deep.jpg").to_str().unwrap()], None);
 
-        assert!(
-            args.is_err(),
-            "Expected an error for neither sort option selected"
+        let count = |max_depth| {
+            let options = ScanOptions {
+                max_depth,
+                ..Default::default()
+            };
+            build_glob_walker(dir.path(), &PATTERNS, &options)
+                .unwrap()
+                .filter_map(Result::ok)
+                .count()
+        };
+
+        assert_eq!(
+            count(Some(4)),
+            0,
+            "Expected the default depth to stop early"
+        );
+        assert_eq!(
+            count(None),
+            1,
+            "Expected an unlimited depth to find the file"
         );
     }
 
+    #[cfg(unix)]
     #[test]
-    fn globwalker_invalid_patterns() {
+    fn find_symlink_loop() {
+        // Ensure a symlinked directory cycle neither hangs nor duplicates media
         let dir = TempDir::new().expect("Failed to create temporary folder");
-        let dir_path = PathBuf::from(dir.path());
-        let invalid_patterns = ["\\", ""];
+        let dir_path = dir.path().to_path_buf();
+        std::fs::create_dir(dir_path.join("a")).unwrap();
+        std::os::unix::fs::symlink(&dir_path, dir_path.join("a").join("loop")).unwrap();
 
-        let walker = build_glob_walker(&dir_path, &invalid_patterns);
+        touch(&dir, ["a/a.jpg"], Some("2024:03:01 00:00:00"));
 
-        assert!(
-            walker.is_err(),
-            "Expected an error for invalid search patterns"
-        );
+        for follow_links in [true, false] {
+            let options = ScanOptions {
+                max_depth: None,
+                follow_links,
+                ..Default::default()
+            };
+            let walker = build_glob_walker(&dir_path, &PATTERNS, &options).unwrap();
+            let mut tree = build_tree(&year_month());
+            find(walker, &mut tree).unwrap();
+
+            assert_eq!(tree.size(), 1, "Expected the file to be found once");
+        }
     }
+
     #[test]
-    fn globwalker_valid_patterns() {
+    fn globwalker_excludes() {
+        // Ensure excluded folders are skipped
         let dir = TempDir::new().expect("Failed to create temporary folder");
-        let dir_path = PathBuf::from(dir.path());
+        std::fs::create_dir_all(dir.path().join("Trip").join("Thumbnails")).unwrap();
 
-        let walker = build_glob_walker(&dir_path, &PATTERNS);
+        touch(&dir, ["Trip/a.jpg", "Trip/Thumbnails/a.jpg"], None);
 
-        assert!(walker.is_ok(), "Expected OK for valid search patterns");
+        let options = ScanOptions {
+            excludes: vec![String::from("**/Thumbnails/**")],
+            ..Default::default()
+        };
+        let found: Vec<PathBuf> = build_glob_walker(dir.path(), &PATTERNS, &options)
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path().to_path_buf())
+            .collect();
+
+        assert_eq!(found, vec![dir.path().join("Trip").join("a.jpg")]);
     }
+
     #[test]
-    fn build_year_month_tree() {
-        let years = true;
-        let months = true;
+    fn extension_patterns() {
+        // Ensure extensions extend or replace the default patterns
+        let args = Arguments {
+            ext: vec![String::from("dng"), String::from(".CR2")],
+            ..Default::default()
+        };
+        assert_eq!(args.patterns().len(), PATTERNS.len() + 2);
+        assert!(args.patterns().contains(&String::from("*.mov")));
 
-        let tree = build_tree(&years, &months);
+        let args = Arguments {
+            no_default_ext: true,
+            ..args
+        };
+        assert_eq!(args.patterns(), vec!["*.dng", "*.CR2"]);
+    }
 
-        match tree {
-            Tree::YearMonth(_) => println!("Tree is an instance of YearMonth"),
-            _ => panic!("Expected Tree to be YearMonth variant"),
-        }
+    #[test]
+    fn filter_date_range() {
+        // Ensure only media inside the range is kept
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2018:12:31 23:59:59"));
+        touch(&dir, ["b.jpg"], Some("2019:01:01 00:00:00"));
+        touch(&dir, ["c.jpg"], Some("2021:12:31 23:59:59"));
+        touch(&dir, ["d.jpg"], Some("2022:01:01 00:00:00"));
+        touch(&dir, ["e.jpg"], None);
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        let filter = Filter {
+            after: NaiveDate::from_ymd_opt(2019, 1, 1),
+            before: NaiveDate::from_ymd_opt(2021, 12, 31),
+            ..Default::default()
+        };
+        let filtered = tree.retain(|image| filter.matches(image));
+
+        assert_eq!(filtered, 3, "Expected a, d and e to be filtered out");
+        assert_eq!(tree.size(), 2, "Expected b and c to be kept");
     }
+
     #[test]
-    fn build_year_tree() {
-        let years = true;
-        let months = false;
+    fn filter_size() {
+        // Ensure sizes parse with units and bound the media kept
+        assert_eq!(parse_size("500"), Ok(500));
+        assert_eq!(parse_size("10K"), Ok(10 * 1024));
+        assert_eq!(parse_size("1.5 MB"), Ok(1536 * 1024));
+        assert!(parse_size("10 parsecs").is_err());
+
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        std::fs::write(dir.path().join("small.jpg"), [0; 10]).unwrap();
+        std::fs::write(dir.path().join("large.jpg"), [0; 2000]).unwrap();
+
+        let filter = Filter {
+            min_size: Some(100),
+            max_size: Some(1024),
+            ..Default::default()
+        };
+        let keep: Vec<bool> = ["small.jpg", "large.jpg"]
+            .iter()
+            .map(|name| filter.matches(&Image::new(dir.path().join(name), name.to_string())))
+            .collect();
 
-        let tree = build_tree(&years, &months);
+        assert_eq!(keep, vec![false, false]);
 
-        match tree {
-            Tree::Year(_) => println!("Tree is an instance of Year"),
-            _ => panic!("Expected Tree to be Year variant"),
-        }
+        let filter = Filter {
+            max_size: None,
+            ..filter
+        };
+        assert!(filter.matches(&Image::new(
+            dir.path().join("large.jpg"),
+            "large.jpg".to_string()
+        )));
     }
+
     #[test]
-    fn build_month_tree() {
-        let years = false;
-        let months = true;
+    fn filter_near() {
+        // Ensure places and radii parse, and only media with GPS within the radius is kept
+        let eiffel_tower = parse_coordinates("48.8584, 2.2945").unwrap();
+        assert_eq!(eiffel_tower.latitude, 48.8584);
+        assert!(parse_coordinates("-33.8568,151.2153").is_ok());
+        assert!(parse_coordinates("48.8584").is_err());
+        assert!(parse_coordinates("91,0").is_err());
+        assert_eq!(parse_distance("50km"), Ok(50.0));
+        assert_eq!(parse_distance("500m"), Ok(0.5));
+        assert_eq!(parse_distance("2"), Ok(2.0));
+        assert!(parse_distance("0km").is_err());
+        assert!(parse_distance("3 leagues").is_err());
 
-        let tree = build_tree(&years, &months);
+        let cli = Cli::try_parse_from([
+            "img-sort",
+            "sort",
+            "-p",
+            ".",
+            "-o",
+            "out",
+            "--near",
+            "48.8584,2.2945",
+        ]);
+        assert!(cli.is_ok());
+        let cli = Cli::try_parse_from([
+            "img-sort", "sort", "-p", ".", "-o", "out", "--radius", "5km",
+        ]);
+        assert!(cli.is_err(), "Expected --radius to need --near");
 
-        match tree {
-            Tree::Month(_) => println!("Tree is an instance of Month"),
-            _ => panic!("Expected Tree to be Month variant"),
-        }
+        let taken_at = |latitude, longitude| {
+            Image::new(PathBuf::from("a.jpg"), String::from("a.jpg")).with_coordinates(Some(
+                Coordinates {
+                    latitude,
+                    longitude,
+                },
+            ))
+        };
+        let filter = Filter {
+            near: Some(eiffel_tower),
+            radius_km: 50.0,
+            ..Default::default()
+        };
+        // The Louvre, Versailles, Lyon and no GPS at all
+        assert!(filter.matches(&taken_at(48.8606, 2.3376)));
+        assert!(filter.matches(&taken_at(48.8049, 2.1204)));
+        assert!(!filter.matches(&taken_at(45.7640, 4.8357)));
+        assert!(!filter.matches(&Image::new(PathBuf::from("b.jpg"), String::from("b.jpg"))));
     }
 
     #[test]
@@ -282,9 +6050,9 @@ mod tests {
         let dir = TempDir::new().expect("Failed to create temporary folder");
         let dir_path = dir.path().to_path_buf();
 
-        let walker = build_glob_walker(&dir_path, &PATTERNS).unwrap();
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
 
-        let mut tree = build_tree(&true, &true);
+        let mut tree = build_tree(&year_month());
 
         let results = find(walker, &mut tree);
 
@@ -304,29 +6072,65 @@ mod tests {
         // Need metadata or else find will error
         touch(&dir, files, Some("2024:01:01 00:00:00"));
 
-        let walker = build_glob_walker(&dir_path, &PATTERNS).unwrap();
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
 
-        let mut tree = build_tree(&true, &true);
+        let mut tree = build_tree(&year_month());
 
         let _ = find(walker, &mut tree);
 
-        let mut expected = build_tree(&true, &true);
-        expected.insert(
-            (2024, 1),
-            Image::new(dir_path.join("c.jpeg"), "c.jpeg".to_string()),
-        );
-        expected.insert(
-            (2024, 1),
-            Image::new(dir_path.join("b.jpg"), "b.jpg".to_string()),
-        );
-        expected.insert(
-            (2024, 1),
-            Image::new(dir_path.join("a.png"), "a.png".to_string()),
-        );
+        let datetime = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
+
+        let mut expected = build_tree(&year_month());
+        for name in files {
+            expected.insert(
+                Image::new(dir_path.join(name), name.to_string())
+                    .with_datetime(datetime)
+                    .with_date_source(Some(DateSource::DateTimeOriginal))
+                    .with_format(Some(Format::Tiff)),
+            );
+        }
 
         assert_eq!(tree, expected, "Expected tree equality")
     }
 
+    #[test]
+    fn save_move_media() {
+        // Ensure moved media leaves the source and lands in the destination
+        let dir = TempDir::new().expect("Failed to create temporary folder");
+        let dest = TempDir::new().expect("Failed to create temporary folder");
+        let dir_path = dir.path().to_path_buf();
+
+        touch(&dir, ["a.jpg"], Some("2024:03:01 00:00:00"));
+
+        let walker = build_glob_walker(&dir_path, &PATTERNS, &ScanOptions::default()).unwrap();
+        let mut tree = build_tree(&year_month());
+        find(walker, &mut tree).unwrap();
+
+        tree.save(
+            dest.path(),
+            &SaveOptions {
+                transfer: Transfer::Move,
+                ..Default::default()
+            },
+        )
+        .expect("Failed to move media");
+
+        assert!(
+            !dir_path.join("a.jpg").exists(),
+            "Expected source to be moved"
+        );
+        assert!(
+            dest.path()
+                .join("2024")
+                .join("March")
+                .join("a.jpg")
+                .exists(),
+            "Expected media in the destination"
+        );
+    }
+
     #[test]
     fn find_existing_datetime() {
         // Ensure that datetimes found are as expected
@@ -341,7 +6145,7 @@ mod tests {
         let datetimes: HashSet<Option<(i32, u32)>> = files
             .iter()
             .map(|name| dir_path.join(name))
-            .map(|f| get_datetime_original(&f))
+            .map(|f| read_metadata(&f).datetime.map(|dt| (dt.year(), dt.month())))
             .collect();
 
         let expected_datetimes: HashSet<Option<(i32, u32)>> = HashSet::from([Some((2024, 1))]);
@@ -362,7 +6166,7 @@ mod tests {
         let datetimes: HashSet<Option<(i32, u32)>> = files
             .iter()
             .map(|name| dir_path.join(name))
-            .map(|f| get_datetime_original(&f))
+            .map(|f| read_metadata(&f).datetime.map(|dt| (dt.year(), dt.month())))
             .collect();
 
         let expected_datetimes: HashSet<Option<(i32, u32)>> = HashSet::from([None]);
@@ -0,0 +1,174 @@
+use crate::heif;
+use crate::image::MediaClass;
+use crate::infer::DateSource;
+use crate::quicktime::read_content_identifier;
+use crate::sniff::{sniff_file, Format};
+use crate::tree::Tree;
+use chrono::{FixedOffset, NaiveDateTime};
+use exif::{Exif, In, Reader, Tag, Value};
+use log::{debug, trace};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/// Apple's maker notes start with this, a version, and the byte order of the IFD after
+const APPLE_MAKER_NOTE: &[u8] = b"Apple iOS\0";
+
+/// The maker note tag holding the identifier a Live Photo's photo shares with its video
+const CONTENT_IDENTIFIER: u16 = 0x0011;
+
+/// A date, where it came from and its UTC offset
+type Dated = (
+    Option<NaiveDateTime>,
+    Option<DateSource>,
+    Option<FixedOffset>,
+);
+
+/// Finds the photo and video of each Live Photo, which share a folder and either a name
+/// or Apple's content identifier, so the video is saved beside its photo and named after
+/// it, returning how many were found
+///
+/// Both take the photo's date, or the video's if the photo has none.
+pub fn pair_live_photos(tree: &mut Tree) -> usize {
+    let is_photo =
+        |path: &Path| Format::Heic.matches_extension(path) || Format::Jpeg.matches_extension(path);
+    let is_video = |path: &Path| {
+        path.extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mov"))
+    };
+
+    // Media is paired within its folder, photos and videos apart
+    let mut dirs: BTreeMap<&Path, (Vec<&Path>, Vec<&Path>)> = BTreeMap::new();
+    let mut dates = HashMap::new();
+    for image in tree.images() {
+        let dir = image.path.parent().unwrap_or(Path::new("."));
+        if is_photo(&image.path) {
+            dirs.entry(dir).or_default().0.push(&image.path);
+        } else if is_video(&image.path) {
+            dirs.entry(dir).or_default().1.push(&image.path);
+        } else {
+            continue;
+        }
+        dates.insert(
+            image.path.as_path(),
+            (image.datetime, image.date_source, image.offset),
+        );
+    }
+
+    let mut pairs: HashMap<PathBuf, (PathBuf, Dated)> = HashMap::new();
+    for (photos, videos) in dirs.into_values() {
+        for (photo, video) in pair_in_dir(photos, videos) {
+            let taken = match dates[photo] {
+                (Some(_), ..) => dates[photo],
+                (None, ..) => dates[video],
+            };
+            pairs.insert(photo.to_path_buf(), (video.to_path_buf(), taken));
+            pairs.insert(video.to_path_buf(), (photo.to_path_buf(), taken));
+        }
+    }
+    if pairs.is_empty() {
+        return 0;
+    }
+
+    tree.update(|image| {
+        let Some((other, taken)) = pairs.get(&image.path) else {
+            return;
+        };
+        (image.datetime, image.date_source, image.offset) = *taken;
+        if is_video(&image.path) {
+            debug!("Saving {:?} beside its photo {:?}", image.path, other);
+            image.media = MediaClass::from_path(other);
+            image.live_photo = Some(other.clone());
+        }
+    });
+    pairs.len() / 2
+}
+
+/// Pairs the photos and videos of one folder by name, then by content identifier, which
+/// is only read when some are left over on both sides
+fn pair_in_dir<'a>(
+    mut photos: Vec<&'a Path>,
+    mut videos: Vec<&'a Path>,
+) -> Vec<(&'a Path, &'a Path)> {
+    let stem = |path: &Path| path.file_stem().map(|stem| stem.to_ascii_lowercase());
+    let mut pairs = Vec::new();
+
+    photos.retain(
+        |photo| match videos.iter().position(|video| stem(video) == stem(photo)) {
+            Some(index) => {
+                pairs.push((*photo, videos.remove(index)));
+                false
+            }
+            None => true,
+        },
+    );
+    if photos.is_empty() || videos.is_empty() {
+        return pairs;
+    }
+
+    let mut identified: HashMap<String, &Path> = HashMap::new();
+    for video in videos {
+        match read_content_identifier(video) {
+            Ok(Some(identifier)) => {
+                identified.insert(identifier, video);
+            }
+            Ok(None) => {}
+            Err(e) => trace!("Couldn't read the identifier of {:?}: {}", video, e),
+        }
+    }
+    for photo in photos {
+        if let Some(video) = read_photo_identifier(photo).and_then(|id| identified.remove(&id)) {
+            pairs.push((photo, video));
+        }
+    }
+    pairs
+}
+
+/// Reads the content identifier from the Apple maker note of a HEIC or JPEG
+pub fn read_photo_identifier(path: &Path) -> Option<String> {
+    let exif: Exif = if sniff_file(path).ok()?? == Format::Heic {
+        heif::read_exif(path).ok()?
+    } else {
+        let file = File::open(path).ok()?;
+        Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .ok()?
+    };
+    match &exif.get_field(Tag::MakerNote, In::PRIMARY)?.value {
+        Value::Undefined(note, _) => read_maker_note_identifier(note),
+        _ => None,
+    }
+}
+
+/// Finds the content identifier among the entries of an Apple maker note, whose offsets
+/// are from the start of the note
+fn read_maker_note_identifier(note: &[u8]) -> Option<String> {
+    let ifd = note.strip_prefix(APPLE_MAKER_NOTE)?;
+    // Only big-endian notes have been seen, after a version of 1
+    if ifd.get(..4)? != b"\0\x01MM" {
+        return None;
+    }
+    let u16_at = |at: usize| Some(u16::from_be_bytes(note.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_be_bytes(note.get(at..at + 4)?.try_into().ok()?));
+
+    let start = APPLE_MAKER_NOTE.len() + 4;
+    let count = u16_at(start)? as usize;
+    (0..count).find_map(|index| {
+        let entry = start + 2 + index * 12;
+        // Only ASCII text is expected
+        if u16_at(entry)? != CONTENT_IDENTIFIER || u16_at(entry + 2)? != 2 {
+            return None;
+        }
+        let len = u32_at(entry + 4)? as usize;
+        let text = match len {
+            0..=4 => note.get(entry + 8..entry + 8 + len)?,
+            _ => {
+                let offset = u32_at(entry + 8)? as usize;
+                note.get(offset..offset.checked_add(len)?)?
+            }
+        };
+        let text = String::from_utf8_lossy(text);
+        Some(text.trim_end_matches('\0').to_string())
+    })
+}
@@ -0,0 +1,72 @@
+use crate::journal::JOURNAL_DIR;
+use log::info;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::process;
+
+/// Lock file kept with the journals in the destination
+const LOCK_FILE: &str = "lock";
+
+/// A claim on a destination that stops other runs saving to it, let go when dropped
+///
+/// The lock is taken by the operating system, so it's released however a run ends. A lock
+/// file left behind by a run that was killed isn't locked, and is simply taken over.
+#[derive(Debug)]
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Locks a destination, waiting for another run to finish with it if `wait`, or failing
+    /// with `WouldBlock` and a message naming the other run otherwise
+    pub fn acquire(dest: &Path, wait: bool) -> io::Result<Lock> {
+        let dir = dest.join(JOURNAL_DIR);
+        fs::create_dir_all(&dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LOCK_FILE))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) if wait => {
+                info!(
+                    "Waiting for {} to finish with {:?}...",
+                    holder(&mut file),
+                    dest
+                );
+                file.lock()?;
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "{} is already saving to {:?}, try again once it's done or pass --wait",
+                        holder(&mut file),
+                        dest
+                    ),
+                ))
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+
+        // Leave the process id for runs that find the destination locked
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", process::id())?;
+
+        Ok(Lock { _file: file })
+    }
+}
+
+/// Names the run holding a lock from the process id it wrote
+fn holder(file: &mut File) -> String {
+    let mut pid = String::new();
+    match file.read_to_string(&mut pid) {
+        Ok(_) if !pid.trim().is_empty() => format!("img-sort (process {})", pid.trim()),
+        _ => String::from("Another img-sort"),
+    }
+}
@@ -1,20 +1,198 @@
-use clap::Parser;
-use img_sort::arguments::Arguments;
+use anstyle::{AnsiColor, Style};
+use img_sort::arguments::{Arguments, Common, Output};
+use img_sort::cli::Command;
+use img_sort::progress::{Event, Hook};
+use img_sort::{config, interrupt, Status};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::env;
+use std::io::{self, Write};
 use std::process;
 
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
 
-fn main() {
-    // Parse the arguments
-    let args = Arguments::parse();
+/// Prints the library's messages, warnings and errors to stderr and the rest to stdout
+struct Logger {
+    // Lines are printed around the progress bar so they don't tear it
+    progress: ProgressBar,
+    /// Keeps stdout for NDJSON events
+    stderr_only: bool,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies log too, but only img-sort's messages are meant for users
+        metadata.target().starts_with("img_sort") && metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        self.progress.suspend(|| match record.level() {
+            Level::Error => anstream::eprintln!("{ERROR}Error:{ERROR:#} {}", record.args()),
+            Level::Warn => anstream::eprintln!("{WARNING}Warning:{WARNING:#} {}", record.args()),
+            _ if self.stderr_only => anstream::eprintln!("{}", record.args()),
+            _ => anstream::println!("{}", record.args()),
+        })
+    }
+
+    fn flush(&self) {}
+}
+
+fn init_logger(level: LevelFilter, progress: ProgressBar, stderr_only: bool) {
+    let logger = Box::leak(Box::new(Logger {
+        progress,
+        stderr_only,
+    }));
+    log::set_logger(logger).expect("The logger is only set once");
+    log::set_max_level(level);
+}
+
+/// Counts files while scanning, then shows bytes saved with throughput and an ETA
+fn show_progress(progress: &ProgressBar) -> impl Fn(Event) + Sync + '_ {
+    progress.set_style(
+        ProgressStyle::with_template("{spinner} Scanned {pos} files in {elapsed}").unwrap(),
+    );
+
+    move |event| match event {
+        Event::Found(_) => progress.inc(1),
+        Event::Saving { bytes, .. } => {
+            progress.set_style(
+                ProgressStyle::with_template(
+                    "[{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+                )
+                .unwrap()
+                .progress_chars("=> "),
+            );
+            progress.set_length(bytes);
+            progress.reset();
+        }
+        Event::Saved { bytes, .. } | Event::Failed { bytes, .. } => progress.inc(bytes),
+        Event::Finished => progress.finish_and_clear(),
+        _ => {}
+    }
+}
 
-    // Validate args to make config
-    let config = Arguments::validate(&args).unwrap_or_else(|err| {
-        eprintln!("Problem validating arguments: {err}");
-        process::exit(1)
+/// Prints each event as a line of JSON on stdout
+fn emit_ndjson(event: Event) {
+    // A script that stopped reading isn't worth panicking over
+    let _ = writeln!(io::stdout().lock(), "{}", event.to_json());
+}
+
+/// Checks the arguments make sense together, exiting if they don't
+fn validate(args: &Arguments) -> &Arguments {
+    args.validate().unwrap_or_else(|err| usage_error(err))
+}
+
+fn validate_common(common: &Common) -> &Common {
+    common.validate().unwrap_or_else(|err| usage_error(err))
+}
+
+fn usage_error(err: String) -> ! {
+    anstream::eprintln!("{ERROR}Problem validating arguments:{ERROR:#} {err}");
+    process::exit(Status::Usage.code())
+}
+
+fn main() {
+    // Parse the arguments, with defaults from the config file
+    let cli = config::parse_args(env::args_os().collect()).unwrap_or_else(|err| {
+        anstream::eprintln!("{ERROR}Problem reading the config:{ERROR:#} {err}");
+        process::exit(Status::Usage.code())
     });
+    let common = cli.command.common();
+
+    anstream::ColorChoice::write_global(common.color_choice());
+
+    // The bar hides itself when stderr isn't a terminal
+    let ndjson = common.output == Output::Ndjson;
+    let progress = if common.quiet || ndjson {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    init_logger(common.log_level(), progress.clone(), ndjson);
+    let text_progress = show_progress(&progress);
+    let on_event: Hook = if ndjson { &emit_ndjson } else { &text_progress };
 
-    if let Err(e) = img_sort::run(config) {
-        eprintln!("Application error: {e}");
-        process::exit(1);
+    // Without the handler, a signal still stops the run, just without finishing up
+    if let Err(e) = interrupt::install() {
+        anstream::eprintln!("{WARNING}Warning:{WARNING:#} Could not handle Ctrl-C: {e}");
     }
+
+    let result = match &cli.command {
+        Command::Sort(args) if args.daemon => img_sort::daemon(validate(args)),
+        Command::Sort(args) => img_sort::run_with(validate(args), on_event),
+        Command::Scan(args) => img_sort::scan(validate(args)),
+        Command::Plan(plan) => img_sort::plan(validate(&plan.args), &plan.file),
+        Command::Apply(apply) => {
+            validate_common(&apply.common);
+            img_sort::apply(apply, on_event)
+        }
+        Command::Undo(undo) => {
+            validate_common(&undo.common);
+            img_sort::undo(undo)
+        }
+        Command::Verify(verify) => {
+            validate_common(&verify.common);
+            img_sort::verify(verify)
+        }
+        Command::Audit(audit) => {
+            validate_common(&audit.common);
+            img_sort::audit(audit)
+        }
+        Command::Stats(stats) => {
+            validate_common(&stats.common);
+            img_sort::stats(stats)
+        }
+        Command::Dedupe(dedupe) => {
+            validate_common(&dedupe.common);
+            img_sort::dedupe(dedupe)
+        }
+        Command::Diff(diff) => {
+            validate_common(&diff.common);
+            img_sort::diff(diff)
+        }
+        Command::Merge(merge) => {
+            validate_common(&merge.common);
+            img_sort::merge(merge, on_event)
+        }
+        Command::Watch(watch) => {
+            validate(&watch.args);
+            img_sort::watch(watch)
+        }
+        Command::FixDates(fix) => {
+            validate_common(&fix.common);
+            img_sort::fix_dates(fix)
+        }
+        Command::ShiftDates(shift) => {
+            validate_common(&shift.common);
+            img_sort::shift_dates(shift)
+        }
+        Command::Geotag(geotag) => {
+            validate_common(&geotag.common);
+            img_sort::geotag(geotag)
+        }
+        Command::Doctor(doctor) => {
+            validate_common(&doctor.common);
+            img_sort::doctor(doctor)
+        }
+    };
+    progress.finish_and_clear();
+
+    let status = result.unwrap_or_else(|e| {
+        if ndjson {
+            let _ = writeln!(
+                io::stdout().lock(),
+                "{}",
+                json!({ "event": "error", "message": e.to_string() })
+            );
+        }
+        anstream::eprintln!("{ERROR}Application error:{ERROR:#} {e}");
+        Status::Fatal
+    });
+    process::exit(status.code());
 }
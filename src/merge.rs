@@ -0,0 +1,81 @@
+use crate::hash::hash_file;
+use crate::image::Image;
+use crate::save::{plan_image, Outcome, SaveOptions, Step};
+use crate::scan::list_media;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Plans merging a sorted library into another, keeping each file's place in the tree
+///
+/// Media already at the same place with the same contents is skipped rather than treated
+/// as a conflict, and with `dedupe` so is media whose contents are anywhere in `dest`.
+pub fn plan_merge(
+    source: &Path,
+    dest: &Path,
+    options: &SaveOptions,
+    dedupe: bool,
+) -> Result<Vec<Step>, Box<dyn Error>> {
+    let files = list_media(source)?;
+    let existing = match dedupe && dest.is_dir() {
+        true => hashes_in(dest, &files)?,
+        false => HashSet::new(),
+    };
+
+    let mut planned = HashMap::new();
+    let mut steps = Vec::with_capacity(files.len());
+    for file in files {
+        let relative = file.strip_prefix(source)?;
+        let target = dest.join(relative);
+        let hash = hash_file(&file)?;
+
+        let duplicate = existing.contains(&hash)
+            || (target.is_file() && hash_file(&target).is_ok_and(|other| other == hash));
+        let step = if duplicate {
+            debug!("{:?} is already in {:?}", file, dest);
+            Step {
+                bytes: fs::metadata(&file)?.len(),
+                source: file,
+                dest: target,
+                outcome: Outcome::Skip,
+                hash: Some(hash),
+                sidecars: Vec::new(),
+                motion_video: None,
+                date_source: None,
+            }
+        } else {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let image = Image::new(file.clone(), name.into_owned());
+            let step = plan_image(&image, target, options, &planned, None)?;
+            Step {
+                hash: Some(hash),
+                ..step
+            }
+        };
+
+        planned.insert(step.dest.clone(), step.source.clone());
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+/// Hashes the media in `dir` that's the same size as one of `files`, which is all of it
+/// that could have the same contents
+fn hashes_in(dir: &Path, files: &[impl AsRef<Path>]) -> Result<HashSet<String>, Box<dyn Error>> {
+    let sizes = files
+        .iter()
+        .map(|file| Ok(fs::metadata(file)?.len()))
+        .collect::<Result<HashSet<u64>, std::io::Error>>()?;
+
+    let mut hashes = HashSet::new();
+    for file in list_media(dir)? {
+        if sizes.contains(&fs::metadata(&file)?.len()) {
+            hashes.insert(hash_file(&file)?);
+        }
+    }
+
+    Ok(hashes)
+}
@@ -0,0 +1,653 @@
+use crate::infer::DateSource;
+use crate::jpeg::{edit_xmp, is_jpeg, replace_exif, strip_metadata};
+use crate::sniff::{sniff_file, Format};
+use crate::transfer::write_atomic;
+use crate::zone::parse_utc_offset;
+use crate::{heif, jxl, png, quicktime, raw, video, webp, xmp};
+use chrono::{
+    FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeDelta, TimeZone, Timelike,
+};
+use clap::ValueEnum;
+use exif::{Context, Exif, Field, In, Tag, Value};
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+/// What could be read from a file's embedded metadata
+#[derive(Debug, Default, PartialEq)]
+pub struct Metadata {
+    pub datetime: Option<NaiveDateTime>,
+    /// Where the date was read from, when there is one
+    pub date_source: Option<DateSource>,
+    /// The UTC offset the date was written in, when it's known
+    pub offset: Option<FixedOffset>,
+    /// What the file's first bytes say it is, whatever its extension
+    pub format: Option<Format>,
+    pub camera: Option<String>,
+    pub coordinates: Option<Coordinates>,
+    /// Stars from 0 to 5, or -1 for a rejected photo
+    pub rating: Option<i32>,
+    /// Width and height, when the EXIF says
+    pub dimensions: Option<(u32, u32)>,
+    /// Whether the EXIF says it's a screenshot, as iPhones and some apps write
+    pub screenshot: bool,
+}
+
+/// A place on Earth, in decimal degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    /// Positive north of the equator
+    pub latitude: f64,
+    /// Positive east of Greenwich
+    pub longitude: f64,
+}
+
+impl Coordinates {
+    /// How far apart two places are along the Earth's surface, taking it to be a sphere
+    pub fn distance_km(self, other: Coordinates) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6371.0;
+        let (a, b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let half_latitude = (b - a) / 2.0;
+        let half_longitude = (other.longitude - self.longitude).to_radians() / 2.0;
+        let h = half_latitude.sin().powi(2) + a.cos() * b.cos() * half_longitude.sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * h.sqrt().min(1.0).asin()
+    }
+}
+
+/// EXIF tags that can say when a photo was taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DateTag {
+    /// When the photo was taken
+    DateTimeOriginal,
+    /// When it was digitized, which is when it was taken for digital cameras
+    DateTimeDigitized,
+    /// When the file was last changed, which editors and scanners write
+    DateTime,
+    /// The GPS date and time, which is in UTC
+    Gps,
+}
+
+/// The date tags tried by default, most trusted first
+pub const DEFAULT_DATE_TAGS: [DateTag; 4] = [
+    DateTag::DateTimeOriginal,
+    DateTag::DateTimeDigitized,
+    DateTag::DateTime,
+    DateTag::Gps,
+];
+
+pub fn read_metadata(path: &Path) -> Metadata {
+    read_metadata_with(path, &DEFAULT_DATE_TAGS)
+}
+
+/// Reads a file's metadata like `read_metadata`, dating photos from the first of
+/// `date_tags` their EXIF has
+pub fn read_metadata_with(path: &Path, date_tags: &[DateTag]) -> Metadata {
+    let format = sniff_file(path).ok().flatten();
+
+    // Videos keep their dates in their own containers rather than in EXIF
+    let video = match format {
+        Some(Format::Mov | Format::Mp4) => Some(quicktime::read_metadata(path)),
+        Some(Format::Avi) => Some(video::read_avi(path)),
+        Some(Format::Matroska) => Some(video::read_matroska(path)),
+        Some(Format::Cr3) => Some(read_cr3(path)),
+        _ => None,
+    };
+    if let Some(metadata) = video {
+        let mut metadata = metadata.unwrap_or_else(|e| {
+            debug!("Couldn't read the metadata of {:?}: {}", path, e);
+            Metadata::default()
+        });
+        if metadata.date_source.is_none() {
+            metadata.date_source = metadata.datetime.map(|_| DateSource::Metadata);
+        }
+        metadata.format = format;
+        return metadata;
+    }
+
+    // Many formats keep their EXIF where the reader doesn't look for it, or not as it expects
+    let exif = match format {
+        Some(Format::Orf | Format::Rw2) => raw::read_tiff_variant(path),
+        Some(Format::Raf) => raw::read_raf(path),
+        Some(Format::WebP) => webp::read_exif(path),
+        Some(Format::Heic | Format::Avif) => heif::read_exif(path),
+        Some(Format::Jxl) => jxl::read_exif(path),
+        _ => {
+            // A file that vanished or can't be opened is just media without metadata
+            let file = match fs::File::open(path) {
+                Ok(file) => file,
+                Err(e) => {
+                    debug!("Couldn't open {:?} to read its EXIF: {}", path, e);
+                    return Metadata::default();
+                }
+            };
+            exif::Reader::new().read_from_container(&mut io::BufReader::new(&file))
+        }
+    };
+    let exif = match exif {
+        Ok(exif) => Some(exif),
+        Err(e) => {
+            trace!("No EXIF read from {:?}: {}", path, e);
+            None
+        }
+    };
+
+    let default = || Metadata {
+        format,
+        ..Default::default()
+    };
+    let mut metadata = exif.map_or_else(default, |exif| {
+        let dated = date_tags
+            .iter()
+            .find_map(|&tag| Some((get_tag_datetime(&exif, tag)?, tag)));
+        Metadata {
+            datetime: dated.map(|(datetime, _)| datetime),
+            date_source: dated.map(|(_, tag)| DateSource::from(tag)),
+            offset: dated.and_then(|(datetime, tag)| get_offset(&exif, tag, datetime)),
+            camera: get_camera(&exif),
+            coordinates: get_coordinates(&exif),
+            format,
+            rating: get_rating(&exif),
+            dimensions: get_dimensions(&exif),
+            screenshot: get_screenshot(&exif),
+        }
+    });
+
+    // PNGs mostly keep their dates in text chunks rather than EXIF
+    if metadata.datetime.is_none() && format == Some(Format::Png) {
+        metadata.datetime = png::read_datetime(path).unwrap_or_else(|e| {
+            debug!("Couldn't read the chunks of {:?}: {}", path, e);
+            None
+        });
+        metadata.date_source = metadata.datetime.map(|_| DateSource::Metadata);
+    }
+    metadata
+}
+
+/// Reads a CR3's camera from its `CMT1` box and when it was taken from `CMT2`
+fn read_cr3(path: &Path) -> io::Result<Metadata> {
+    let cr3 = raw::read_cr3(path)?;
+
+    // `CMT2` holds the EXIF IFD's tags as the first IFD of a TIFF of its own
+    let datetime_original = Tag(Context::Tiff, Tag::DateTimeOriginal.number());
+    let datetime = cr3
+        .exif
+        .as_ref()
+        .and_then(|exif| get_datetime(exif, datetime_original));
+    Ok(Metadata {
+        datetime,
+        date_source: datetime.map(|_| DateSource::DateTimeOriginal),
+        camera: cr3.tiff.as_ref().and_then(get_camera),
+        ..Default::default()
+    })
+}
+
+pub fn read_thumbnail(path: &Path) -> Option<Vec<u8>> {
+    let file = fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+
+    thumbnail(&exif)
+        .filter(|thumbnail| is_jpeg(thumbnail))
+        .map(<[u8]>::to_vec)
+}
+
+/// The JPEG thumbnail an EXIF points its thumbnail IFD at
+fn thumbnail(exif: &Exif) -> Option<&[u8]> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+    let length = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?
+        .value
+        .get_uint(0)? as usize;
+
+    exif.buf().get(offset..offset.checked_add(length)?)
+}
+
+/// Reads a date tag, with the fraction of a second its sub-second tag adds, so shots of a
+/// burst keep their order
+fn get_tag_datetime(exif: &Exif, tag: DateTag) -> Option<NaiveDateTime> {
+    let (tag, subsec_tag) = match tag {
+        DateTag::DateTimeOriginal => (Tag::DateTimeOriginal, Tag::SubSecTimeOriginal),
+        DateTag::DateTimeDigitized => (Tag::DateTimeDigitized, Tag::SubSecTimeDigitized),
+        DateTag::DateTime => (Tag::DateTime, Tag::SubSecTime),
+        DateTag::Gps => return get_gps_datetime(exif),
+    };
+    let datetime = get_datetime(exif, tag)?;
+    match get_subsec(exif, subsec_tag) {
+        Some(nanos) => datetime.with_nanosecond(nanos),
+        None => Some(datetime),
+    }
+}
+
+/// Reads the digits of a fraction of a second, e.g. `25` for a quarter, as nanoseconds
+fn get_subsec(exif: &Exif, tag: Tag) -> Option<u32> {
+    let digits = get_string(exif, tag)?;
+    let digits = digits.trim();
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    format!("{:0<9}", &digits[..digits.len().min(9)])
+        .parse()
+        .ok()
+}
+
+/// Reads the UTC offset of the time a date tag gives, from the offset tag that goes with it
+fn get_offset(exif: &Exif, tag: DateTag, datetime: NaiveDateTime) -> Option<FixedOffset> {
+    let offset_tag = match tag {
+        DateTag::DateTimeOriginal => Tag::OffsetTimeOriginal,
+        DateTag::DateTimeDigitized => Tag::OffsetTimeDigitized,
+        DateTag::DateTime => Tag::OffsetTime,
+        // The GPS time has already been moved into this computer's zone
+        DateTag::Gps => {
+            let local = Local.from_local_datetime(&datetime).earliest()?;
+            return Some(local.offset().fix());
+        }
+    };
+    parse_utc_offset(&get_string(exif, offset_tag)?)
+}
+
+/// Reads a date from its text, since tags the reader doesn't know aren't displayed as dates
+fn get_datetime(exif: &Exif, tag: Tag) -> Option<NaiveDateTime> {
+    let datetime_str = get_string(exif, tag)?;
+    trace!("Read {} {:?}", tag, datetime_str);
+    parse_exif_datetime(&datetime_str)
+}
+
+/// Parses a date as cameras and editors write it into EXIF, which isn't always as the
+/// standard says, ignoring a fraction of a second or UTC offset after it
+///
+/// Zeros or blanks where the digits go, which cameras write before their clock is set,
+/// are no date at all.
+pub fn parse_exif_datetime(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    if !text.bytes().any(|byte| matches!(byte, b'1'..=b'9')) {
+        return None;
+    }
+    let ends = |rest: &str| {
+        let rest = rest.trim_start();
+        rest.is_empty() || rest.starts_with(['.', '+', '-', 'Z'])
+    };
+    EXIF_DATETIME_FORMATS
+        .iter()
+        .find_map(
+            |format| match NaiveDateTime::parse_and_remainder(text, format) {
+                Ok((datetime, rest)) if ends(rest) => Some(datetime),
+                _ => None,
+            },
+        )
+        .or_else(|| {
+            ["%Y:%m:%d", "%Y-%m-%d"].iter().find_map(|format| {
+                let date = NaiveDate::parse_from_str(text, format).ok()?;
+                Some(date.and_time(NaiveTime::MIN))
+            })
+        })
+}
+
+/// Reads the GPS date and time, which are kept apart and in UTC, as local time
+fn get_gps_datetime(exif: &Exif) -> Option<NaiveDateTime> {
+    let date = NaiveDate::parse_from_str(&get_string(exif, Tag::GPSDateStamp)?, "%Y:%m:%d").ok()?;
+    let exif::Value::Rational(parts) = &exif.get_field(Tag::GPSTimeStamp, In::PRIMARY)?.value
+    else {
+        return None;
+    };
+    let [hour, minute, second] = parts.as_slice() else {
+        return None;
+    };
+    let time = NaiveTime::from_hms_opt(
+        hour.to_f64() as u32,
+        minute.to_f64() as u32,
+        second.to_f64() as u32,
+    )?;
+    Some(
+        date.and_time(time)
+            .and_utc()
+            .with_timezone(&Local)
+            .naive_local(),
+    )
+}
+
+fn get_string(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let value = match &field.value {
+        exif::Value::Ascii(values) => values
+            .iter()
+            .map(|value| String::from_utf8_lossy(value).to_string())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => return None,
+    };
+
+    let value = value.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// Reads degrees, minutes and seconds, and which hemisphere they're in
+fn get_degrees(exif: &Exif, tag: Tag, reference: Tag, negative: u8) -> Option<f64> {
+    let exif::Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part.to_f64() / divisor)
+        .sum::<f64>();
+
+    match get_string(exif, reference)?.as_bytes().first() {
+        Some(hemisphere) if *hemisphere == negative => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+fn get_coordinates(exif: &Exif) -> Option<Coordinates> {
+    Some(Coordinates {
+        latitude: get_degrees(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?,
+        longitude: get_degrees(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?,
+    })
+}
+
+/// Reads the stars Windows and some cameras write into the `Rating` tag, or -1 for a
+/// rejected photo
+fn get_rating(exif: &Exif) -> Option<i32> {
+    // Rejections are written as a signed -1, or as its bits in the usual unsigned type
+    let rating = match &exif.get_field(RATING, In::PRIMARY)?.value {
+        Value::SShort(ratings) => i32::from(*ratings.first()?),
+        Value::Short(ratings) if ratings.first() == Some(&0xFFFF) => -1,
+        value => i32::try_from(value.get_uint(0)?).ok()?,
+    };
+    (-1..=5).contains(&rating).then_some(rating)
+}
+
+fn get_dimensions(exif: &Exif) -> Option<(u32, u32)> {
+    let get = |tag| exif.get_field(tag, In::PRIMARY)?.value.get_uint(0);
+    Some((get(Tag::PixelXDimension)?, get(Tag::PixelYDimension)?))
+}
+
+/// Whether the user comment iPhones give screenshots, or the software that made the
+/// media, says it's a screenshot
+fn get_screenshot(exif: &Exif) -> bool {
+    let comment = match exif
+        .get_field(Tag::UserComment, In::PRIMARY)
+        .map(|f| &f.value)
+    {
+        // The comment starts with 8 bytes naming its character set
+        Some(Value::Undefined(comment, _)) => {
+            String::from_utf8_lossy(comment.get(8..).unwrap_or_default()).into_owned()
+        }
+        _ => String::new(),
+    };
+    let software = get_string(exif, Tag::Software).unwrap_or_default();
+    [comment, software]
+        .iter()
+        .any(|text| text.to_lowercase().contains("screenshot"))
+}
+
+fn get_camera(exif: &Exif) -> Option<String> {
+    name_camera(get_string(exif, Tag::Make), get_string(exif, Tag::Model))
+}
+
+/// Names the camera from its make and model, e.g. "Canon EOS 5D"
+pub fn name_camera(make: Option<String>, model: Option<String>) -> Option<String> {
+    let camera = match (make, model) {
+        // Most models already start with the make
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            model
+        }
+        (Some(make), Some(model)) => format!("{make} {model}"),
+        (None, Some(model)) => model,
+        (Some(make), None) => make,
+        (None, None) => return None,
+    };
+
+    // The camera becomes a directory name
+    Some(camera.replace(['/', '\\'], "-"))
+}
+
+/// The tag Microsoft added for a photo's stars, which the reader doesn't name
+const RATING: Tag = Tag(Context::Tiff, 0x4746);
+
+/// What's removed from the metadata of copies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strip {
+    /// EXIF, XMP, IPTC and comments
+    All,
+    /// Only where the media was taken
+    Gps,
+}
+
+/// Removes metadata from a JPEG, for media that's going to be shared
+///
+/// Where the media was taken is removed from its XMP as well as its EXIF.
+pub fn strip(path: &Path, strip: Strip) -> io::Result<()> {
+    let data = read_jpeg(path)?;
+    let jpeg = match strip {
+        Strip::Gps => {
+            let jpeg = rebuild_exif(&data, |fields| {
+                fields.retain(|field| field.tag.context() != Context::Gps)
+            })?;
+            edit_xmp(&jpeg, |xmp| {
+                xmp::remove_gps(xmp).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "The XMP is malformed")
+                })
+            })?
+        }
+        Strip::All => strip_metadata(&data)?,
+    };
+    write_atomic(path, |partial| fs::write(partial, jpeg))
+}
+
+/// The tags that say when a photo was taken or changed
+const DATE_TAGS: [Tag; 3] = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime];
+
+/// How EXIF, and exiftool after it, writes dates
+pub const EXIF_DATETIME: &str = "%Y:%m:%d %H:%M:%S";
+
+/// How dates are written into EXIF, the standard's way first, then the ways of editors
+/// and cameras that get it wrong
+const EXIF_DATETIME_FORMATS: [&str; 6] = [
+    EXIF_DATETIME,
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y:%m:%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+fn datetime_field(tag: Tag, datetime: NaiveDateTime) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![datetime
+            .format(EXIF_DATETIME)
+            .to_string()
+            .into_bytes()]),
+    }
+}
+
+/// Sets a JPEG's `DateTimeOriginal`, keeping the rest of its EXIF
+pub fn write_datetime_original(path: &Path, datetime: NaiveDateTime) -> io::Result<()> {
+    rewrite_exif(path, |fields| {
+        fields.retain(|field| field.tag != Tag::DateTimeOriginal);
+        fields.push(datetime_field(Tag::DateTimeOriginal, datetime));
+    })
+}
+
+/// Moves every date in a JPEG's EXIF by `offset`, returning when it says it was taken
+/// before and after, or `None` if it had no date to shift
+pub fn shift_datetimes(
+    path: &Path,
+    offset: TimeDelta,
+) -> io::Result<Option<(NaiveDateTime, NaiveDateTime)>> {
+    let mut taken = None;
+    rewrite_exif(path, |fields| {
+        for field in fields
+            .iter_mut()
+            .filter(|field| DATE_TAGS.contains(&field.tag))
+        {
+            let Value::Ascii(values) = &field.value else {
+                continue;
+            };
+            let Some(datetime) = values
+                .first()
+                .and_then(|value| std::str::from_utf8(value).ok())
+                .and_then(parse_exif_datetime)
+            else {
+                continue;
+            };
+
+            let shifted = datetime + offset;
+            if field.tag == Tag::DateTimeOriginal {
+                taken = Some((datetime, shifted));
+            }
+            *field = datetime_field(field.tag, shifted);
+        }
+    })?;
+    Ok(taken)
+}
+
+/// Sets where a JPEG was taken, replacing any location it had
+pub fn write_gps(path: &Path, coordinates: Coordinates, altitude: Option<f64>) -> io::Result<()> {
+    let gps = gps_fields(coordinates, altitude);
+    rewrite_exif(path, |fields| {
+        fields.retain(|field| field.tag.context() != Context::Gps);
+        fields.extend(gps);
+    })
+}
+
+/// Adds a date and location to a JPEG's EXIF, keeping any it already has
+pub fn fill_in(
+    path: &Path,
+    datetime: Option<NaiveDateTime>,
+    coordinates: Option<Coordinates>,
+    altitude: Option<f64>,
+) -> io::Result<()> {
+    let existing = read_metadata(path);
+    let datetime = datetime.filter(|_| existing.datetime.is_none());
+    let coordinates = coordinates.filter(|_| existing.coordinates.is_none());
+    if datetime.is_none() && coordinates.is_none() {
+        return Ok(());
+    }
+
+    rewrite_exif(path, |fields| {
+        if let Some(datetime) = datetime {
+            fields.retain(|field| field.tag != Tag::DateTimeOriginal);
+            fields.push(datetime_field(Tag::DateTimeOriginal, datetime));
+        }
+        if let Some(coordinates) = coordinates {
+            fields.retain(|field| field.tag.context() != Context::Gps);
+            fields.extend(gps_fields(coordinates, altitude));
+        }
+    })
+}
+
+fn gps_fields(coordinates: Coordinates, altitude: Option<f64>) -> Vec<Field> {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let ascii = |text: &str| Value::Ascii(vec![text.as_bytes().to_vec()]);
+    let rational = |value: f64, denom: u32| exif::Rational {
+        num: (value * denom as f64).round() as u32,
+        denom,
+    };
+    let degrees = |value: f64| {
+        let value = value.abs();
+        let minutes = value.fract() * 60.0;
+        Value::Rational(vec![
+            rational(value.trunc(), 1),
+            rational(minutes.trunc(), 1),
+            rational(minutes.fract() * 60.0, 10_000),
+        ])
+    };
+
+    let mut gps = vec![
+        field(Tag::GPSVersionID, Value::Byte(vec![2, 3, 0, 0])),
+        field(
+            Tag::GPSLatitudeRef,
+            ascii(if coordinates.latitude < 0.0 { "S" } else { "N" }),
+        ),
+        field(Tag::GPSLatitude, degrees(coordinates.latitude)),
+        field(
+            Tag::GPSLongitudeRef,
+            ascii(if coordinates.longitude < 0.0 {
+                "W"
+            } else {
+                "E"
+            }),
+        ),
+        field(Tag::GPSLongitude, degrees(coordinates.longitude)),
+    ];
+    if let Some(altitude) = altitude {
+        gps.push(field(
+            Tag::GPSAltitudeRef,
+            Value::Byte(vec![u8::from(altitude < 0.0)]),
+        ));
+        gps.push(field(
+            Tag::GPSAltitude,
+            Value::Rational(vec![rational(altitude.abs(), 100)]),
+        ));
+    }
+    gps
+}
+
+/// Rebuilds a JPEG's EXIF from its fields after `edit` has changed them
+fn rewrite_exif(path: &Path, edit: impl FnOnce(&mut Vec<Field>)) -> io::Result<()> {
+    let jpeg = rebuild_exif(&read_jpeg(path)?, edit)?;
+    write_atomic(path, |partial| fs::write(partial, jpeg))
+}
+
+/// Reads a file that's about to have its metadata written, which only JPEGs can
+fn read_jpeg(path: &Path) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if !is_jpeg(&data) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Only JPEG metadata can be written",
+        ));
+    }
+    Ok(data)
+}
+
+/// The JPEG in `data` with its EXIF rebuilt from its fields after `edit` has changed
+/// them, keeping the thumbnail in it
+fn rebuild_exif(data: &[u8], edit: impl FnOnce(&mut Vec<Field>)) -> io::Result<Vec<u8>> {
+    let existing = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok();
+    let little_endian = existing.as_ref().is_some_and(Exif::little_endian);
+
+    let mut fields: Vec<Field> = existing
+        .iter()
+        .flat_map(|exif| exif.fields())
+        .filter(|field| matches!(field.ifd_num, In::PRIMARY | In::THUMBNAIL))
+        .cloned()
+        .collect();
+    edit(&mut fields);
+
+    // The writer needs a field, and media without any needs no EXIF
+    if fields.is_empty() {
+        return replace_exif(data, None);
+    }
+
+    let mut writer = exif::experimental::Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    // The writer places the thumbnail and points its IFD at it afresh
+    if let Some(thumbnail) = existing.as_ref().and_then(thumbnail) {
+        writer.set_jpeg(thumbnail, In::THUMBNAIL);
+    }
+
+    let mut exif = Cursor::new(Vec::new());
+    writer
+        .write(&mut exif, little_endian)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    replace_exif(data, Some(exif.get_ref()))
+}
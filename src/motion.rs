@@ -0,0 +1,89 @@
+use crate::quicktime::boxes;
+use crate::sniff::Format;
+use crate::tree::Tree;
+use crate::xmp::read_motion_photo;
+use log::{debug, trace};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What Samsung writes just before the video of a Motion Photo
+const SAMSUNG_MARKER: &[u8] = b"MotionPhoto_Data";
+
+/// What starts and ends an XMP packet, which JPEGs and HEICs keep as plain text
+const XMP_START: &[u8] = b"<x:xmpmeta";
+const XMP_END: &[u8] = b"</x:xmpmeta>";
+
+/// Finds the Motion Photos among the JPEGs and HEICs, whose videos are saved beside
+/// them, returning how many there are
+///
+/// A Motion Photo is a photo with an MP4 after it, which is read whole to find it.
+pub fn find_motion_photos(tree: &mut Tree) -> usize {
+    let mut found = 0;
+    tree.update(|image| {
+        if !matches!(image.format, Some(Format::Jpeg | Format::Heic)) {
+            return;
+        }
+        match fs::read(&image.path) {
+            Ok(data) => image.motion_photo = find_video(&data).is_some(),
+            Err(e) => trace!("Couldn't read {:?}: {}", image.path, e),
+        }
+        if image.motion_photo {
+            debug!("{:?} is a Motion Photo", image.path);
+            found += 1;
+        }
+    });
+    found
+}
+
+/// Writes the video inside the Motion Photo at `photo` to `video`, leaving the photo whole
+pub fn extract_video(photo: &Path, video: &Path) -> io::Result<()> {
+    let data = fs::read(photo)?;
+    let found = find_video(&data).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{:?} has no video inside it", photo),
+        )
+    })?;
+    fs::write(video, found)
+}
+
+/// The MP4 inside a Motion Photo, where its XMP says it is, or else after Samsung's marker
+pub fn find_video(data: &[u8]) -> Option<&[u8]> {
+    let marked = read_xmp(data).and_then(read_motion_photo);
+    let start = match marked.and_then(|motion| motion.video_len) {
+        Some(len) => data.len().checked_sub(len)?,
+        None => find(data, SAMSUNG_MARKER)? + SAMSUNG_MARKER.len(),
+    };
+
+    let video = &data[start..];
+    if video.get(4..8) != Some(b"ftyp") {
+        return None;
+    }
+    Some(&video[..mp4_len(video)])
+}
+
+/// The first XMP packet in a file
+fn read_xmp(data: &[u8]) -> Option<&str> {
+    let start = find(data, XMP_START)?;
+    let end = start + find(&data[start..], XMP_END)? + XMP_END.len();
+    std::str::from_utf8(&data[start..end]).ok()
+}
+
+/// How long the MP4 at the start of `data` is, going by its top-level boxes, since
+/// Samsung writes more after it
+fn mp4_len(data: &[u8]) -> usize {
+    let start = data.as_ptr() as usize;
+    boxes(data)
+        .take_while(|(kind, _)| {
+            kind.iter()
+                .all(|byte| byte.is_ascii_alphanumeric() || *byte == b' ')
+        })
+        .last()
+        .map_or(0, |(_, body)| body.as_ptr_range().end as usize - start)
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,142 @@
+use crate::infer::DateSource;
+use crate::tree::Tree;
+use chrono::{NaiveDate, NaiveDateTime};
+use globset::{GlobBuilder, GlobMatcher};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use toml::{Table, Value};
+
+/// A file in a source folder dating the media in it, mapping globs relative to the folder
+/// to dates, e.g. `"ScansBox3/" = "1987-06"` or `"*.tif" = 1990-01-02`
+pub const OVERRIDES_FILE: &str = ".img-sort-dates.toml";
+
+/// The globs of one file and the dates they give
+struct Overrides {
+    dir: PathBuf,
+    dates: Vec<(String, GlobMatcher, NaiveDateTime)>,
+}
+
+impl Overrides {
+    /// The date of the most specific glob matching `path`, i.e. the longest
+    fn date(&self, path: &Path) -> Option<NaiveDateTime> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        self.dates
+            .iter()
+            .filter(|(_, matcher, _)| matcher.is_match(relative))
+            .max_by_key(|(glob, ..)| glob.len())
+            .map(|(.., datetime)| *datetime)
+    }
+}
+
+/// Dates media from the `.img-sort-dates.toml` files of its folder and those above it,
+/// up to its source, where the nearest file wins, returning how many were dated
+///
+/// The dates are written by the user, so they outrank whatever else the media says.
+pub fn date_from_overrides(tree: &mut Tree) -> usize {
+    let mut files: HashMap<PathBuf, Option<Rc<Overrides>>> = HashMap::new();
+    let mut dated = 0;
+
+    tree.update(|image| {
+        // Files are only looked for inside the source the media was found in
+        let levels = image
+            .subdir
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .count();
+        let found = image
+            .path
+            .ancestors()
+            .skip(1)
+            .take(levels + 1)
+            .find_map(|dir| {
+                files
+                    .entry(dir.to_path_buf())
+                    .or_insert_with_key(|dir| read_overrides(dir).map(Rc::new))
+                    .clone()
+                    .and_then(|overrides| overrides.date(&image.path))
+            });
+        if let Some(datetime) = found {
+            debug!(
+                "Dated {:?} to {} from {}",
+                image.path, datetime, OVERRIDES_FILE
+            );
+            image.datetime = Some(datetime);
+            image.date_source = Some(DateSource::Override);
+            image.offset = None;
+            dated += 1;
+        }
+    });
+    dated
+}
+
+fn read_overrides(dir: &Path) -> Option<Overrides> {
+    let path = dir.join(OVERRIDES_FILE);
+    let text = fs::read_to_string(&path).ok()?;
+    match parse_overrides(&text) {
+        Ok(dates) => Some(Overrides {
+            dir: dir.to_path_buf(),
+            dates,
+        }),
+        Err(e) => {
+            warn!("Ignoring {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Reads the globs and dates of an overrides file, where a glob ending in `/` matches
+/// everything inside that folder
+pub fn parse_overrides(text: &str) -> Result<Vec<(String, GlobMatcher, NaiveDateTime)>, String> {
+    let table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| e.message().to_string())?;
+    let mut dates = Vec::new();
+    for (glob, value) in table {
+        let date = match &value {
+            Value::String(date) => parse_date(date),
+            Value::Datetime(date) => parse_date(&date.to_string()),
+            _ => None,
+        }
+        .ok_or_else(|| format!("{} isn't a date for {:?}, e.g. \"1987-06\"", value, glob))?;
+
+        let pattern = match glob.strip_suffix('/') {
+            Some(folder) => format!("{folder}/**"),
+            None => glob.clone(),
+        };
+        // A `*` stays within a folder, like in a shell, while `**` crosses them
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("{:?} isn't a valid glob: {}", glob, e))?
+            .compile_matcher();
+        dates.push((glob, matcher, date));
+    }
+    Ok(dates)
+}
+
+/// Parses a year, a month like `1987-06`, a day like `1987-06-12`, or a day and time,
+/// where whatever is left out is the start of it
+pub fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    let date = date.trim();
+    for format in [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(date, format) {
+            return Some(datetime);
+        }
+    }
+    let day = match date.len() {
+        4 => format!("{date}-01-01"),
+        7 => format!("{date}-01"),
+        _ => date.to_string(),
+    };
+    NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)
+}
@@ -0,0 +1,123 @@
+use crate::image::{Image, MediaClass};
+use crate::infer::DateSource;
+use crate::sniff::Format;
+use crate::tree::Tree;
+use chrono::{FixedOffset, NaiveDateTime, TimeDelta};
+use clap::ValueEnum;
+use log::debug;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What to do with the RAW and JPEG a camera writes for the same shot
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum RawPairs {
+    /// Save both side by side
+    #[default]
+    Keep,
+    /// Save only the RAW
+    PreferRaw,
+    /// Save only the JPEG
+    PreferJpeg,
+    /// Save the RAW in a `RAW` folder beside the JPEG
+    Nest,
+}
+
+/// How far apart the dates of a pair may be, since a camera can take a moment to write
+/// both, while a counter that wraps around reuses names for shots taken long apart
+const MAX_APART: TimeDelta = TimeDelta::seconds(2);
+
+/// A shot's RAW and JPEG, and when it was taken
+struct Pair {
+    raw: PathBuf,
+    jpeg: PathBuf,
+    taken: Option<NaiveDateTime>,
+    date_source: Option<DateSource>,
+    offset: Option<FixedOffset>,
+}
+
+/// Finds the RAW and JPEG shots, which share a folder and a name, and keeps each pair
+/// together as `mode` says, returning how many files were left out
+///
+/// Both files take the JPEG's date, or the RAW's if the JPEG has none, and the RAW is
+/// classed like the JPEG so splitting media by type doesn't pull them apart.
+pub fn pair_raw_jpeg(tree: &mut Tree, mode: RawPairs) -> usize {
+    let pairs = find_pairs(tree);
+    if pairs.is_empty() {
+        return 0;
+    }
+    debug!("Found {} RAW and JPEG pairs", pairs.len());
+
+    let by_path: HashMap<&Path, &Pair> = pairs
+        .iter()
+        .flat_map(|pair| [(pair.raw.as_path(), pair), (pair.jpeg.as_path(), pair)])
+        .collect();
+    tree.update(|image| {
+        let Some(pair) = by_path.get(image.path.as_path()) else {
+            return;
+        };
+        image.datetime = pair.taken;
+        image.date_source = pair.date_source;
+        image.offset = pair.offset;
+        if image.path == pair.raw {
+            image.media = MediaClass::from_path(&pair.jpeg);
+            if mode == RawPairs::Nest {
+                image.nest = Some(PathBuf::from(MediaClass::Raw.dir_name()));
+            }
+        }
+    });
+
+    let left_out = |image: &Image| {
+        let pair = by_path.get(image.path.as_path());
+        match mode {
+            RawPairs::PreferRaw => pair.is_some_and(|pair| image.path == pair.jpeg),
+            RawPairs::PreferJpeg => pair.is_some_and(|pair| image.path == pair.raw),
+            RawPairs::Keep | RawPairs::Nest => false,
+        }
+    };
+    tree.retain(|image| {
+        let leave_out = left_out(image);
+        if leave_out {
+            debug!(
+                "Left out {:?}, which was shot with another format",
+                image.path
+            );
+        }
+        !leave_out
+    })
+}
+
+fn find_pairs(tree: &Tree) -> Vec<Pair> {
+    let key = |path: &Path| {
+        let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+        Some((path.parent()?.to_path_buf(), stem))
+    };
+
+    let mut jpegs = HashMap::new();
+    for image in tree.images() {
+        if Format::Jpeg.matches_extension(&image.path) {
+            if let Some(key) = key(&image.path) {
+                jpegs.insert(key, image);
+            }
+        }
+    }
+
+    tree.images()
+        .filter(|image| MediaClass::from_path(&image.path) == MediaClass::Raw)
+        .filter_map(|raw| {
+            let jpeg = jpegs.get(&key(&raw.path)?)?;
+            if let (Some(a), Some(b)) = (raw.datetime, jpeg.datetime) {
+                if (a - b).abs() > MAX_APART {
+                    return None;
+                }
+            }
+            let dated = if jpeg.datetime.is_some() { jpeg } else { raw };
+            Some(Pair {
+                raw: raw.path.clone(),
+                jpeg: jpeg.path.clone(),
+                taken: dated.datetime,
+                date_source: dated.date_source,
+                offset: dated.offset,
+            })
+        })
+        .collect()
+}
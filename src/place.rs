@@ -0,0 +1,45 @@
+use crate::metadata::Coordinates;
+use std::sync::LazyLock;
+
+/// Larger cities and the places people travel to, as `city,country,latitude,longitude`
+/// lines, so places are found without a network
+const CITIES: &str = include_str!("cities.csv");
+
+/// How far from a city media may have been taken and still be placed in it
+const MAX_DISTANCE_KM: f64 = 150.0;
+
+/// A city and the country it's in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub city: &'static str,
+    pub country: &'static str,
+    pub coordinates: Coordinates,
+}
+
+static PLACES: LazyLock<Vec<Place>> =
+    LazyLock::new(|| CITIES.lines().filter_map(parse_line).collect());
+
+fn parse_line(line: &'static str) -> Option<Place> {
+    let mut fields = line.split(',');
+    let (city, country) = (fields.next()?, fields.next()?);
+    let latitude = fields.next()?.trim().parse().ok()?;
+    let longitude = fields.next()?.trim().parse().ok()?;
+    Some(Place {
+        city,
+        country,
+        coordinates: Coordinates {
+            latitude,
+            longitude,
+        },
+    })
+}
+
+/// The city nearest to where media was taken, unless it was taken far from any
+pub fn locate(coordinates: Coordinates) -> Option<&'static Place> {
+    PLACES
+        .iter()
+        .map(|place| (place, place.coordinates.distance_km(coordinates)))
+        .filter(|(_, distance)| *distance <= MAX_DISTANCE_KM)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(place, _)| place)
+}
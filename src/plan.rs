@@ -0,0 +1,97 @@
+use crate::hash::hash_file;
+use crate::save::{Outcome, Step};
+use crate::transfer::Transfer;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Version of the plan format, bumped when old plans can no longer be read
+pub const PLAN_VERSION: u32 = 1;
+
+/// Every step of a sort, written out to be reviewed, edited and applied later
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub dest: PathBuf,
+    pub transfer: Transfer,
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    pub fn new(dest: &Path, transfer: Transfer, steps: Vec<Step>) -> Self {
+        Plan {
+            version: PLAN_VERSION,
+            dest: dest.to_path_buf(),
+            transfer,
+            steps,
+        }
+    }
+
+    /// Writes the plan as JSON to a file, or to stdout for `-`
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut writer: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Describes each way the sources and destination have changed since planning
+    pub fn drift(&self) -> Vec<String> {
+        self.steps.iter().filter_map(drift).collect()
+    }
+
+    pub fn read(path: &Path) -> io::Result<Plan> {
+        let plan: Plan = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+
+        if plan.version != PLAN_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{:?} is a version {} plan, but only version {} is supported",
+                    path, plan.version, PLAN_VERSION
+                ),
+            ));
+        }
+
+        Ok(plan)
+    }
+}
+
+fn drift(step: &Step) -> Option<String> {
+    let Step { source, dest, .. } = step;
+
+    let metadata = match source.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return Some(format!("{:?} no longer exists", source)),
+    };
+    if metadata.len() != step.bytes {
+        return Some(format!(
+            "{:?} was {} bytes but is now {}",
+            source,
+            step.bytes,
+            metadata.len()
+        ));
+    }
+    if let Some(hash) = &step.hash {
+        match hash_file(source) {
+            Ok(current) if current == *hash => {}
+            Ok(_) => return Some(format!("{:?} has different contents", source)),
+            Err(e) => return Some(format!("{:?} could not be read: {}", source, e)),
+        }
+    }
+
+    // Saves and renames were planned for free destinations
+    let taken = dest.symlink_metadata().is_ok();
+    match step.outcome {
+        Outcome::Save | Outcome::Rename if taken => {
+            Some(format!("{:?} has been created since planning", dest))
+        }
+        _ => None,
+    }
+}
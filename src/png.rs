@@ -0,0 +1,114 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Text chunks worth reading are short, unlike the image data beside them
+const MAX_TEXT_LEN: u32 = 1 << 16;
+
+/// Reads a PNG's width and height from its `IHDR`, which is always its first chunk
+pub fn read_dimensions(path: &Path) -> io::Result<Option<(u32, u32)>> {
+    let mut head = [0; 24];
+    File::open(path)?.read_exact(&mut head)?;
+    if &head[12..16] != b"IHDR" {
+        return Ok(None);
+    }
+    let width = u32::from_be_bytes(head[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(head[20..24].try_into().unwrap());
+    Ok(Some((width, height)))
+}
+
+/// Reads when a PNG was made from its `Creation Time` text, or failing that from `tIME`,
+/// when its image was last changed
+///
+/// Dates in an `eXIf` chunk are read with the rest of the EXIF, so this is only for PNGs
+/// without one.
+pub fn read_datetime(path: &Path) -> io::Result<Option<NaiveDateTime>> {
+    let mut file = BufReader::new(File::open(path)?);
+    file.seek(SeekFrom::Start(8))?;
+
+    let mut created = None;
+    let mut modified = None;
+    loop {
+        let mut header = [0; 8];
+        match file.read_exact(&mut header) {
+            Ok(()) => {}
+            // Chunks run to the end, so a short read is just the end
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let kind = &header[4..8];
+        if kind == b"IEND" {
+            break;
+        }
+
+        if len <= MAX_TEXT_LEN && matches!(kind, b"tEXt" | b"iTXt" | b"tIME") {
+            let mut data = vec![0; len as usize];
+            file.read_exact(&mut data)?;
+            match kind {
+                b"tIME" => modified = modified.or_else(|| parse_time(&data)),
+                _ => created = created.or_else(|| creation_time(kind, &data)),
+            }
+            file.seek_relative(4)?;
+        } else {
+            // Skip the data and its checksum
+            file.seek_relative(len as i64 + 4)?;
+        }
+    }
+
+    Ok(created.or(modified))
+}
+
+/// Reads the `Creation Time` keyword of a `tEXt` or uncompressed `iTXt` chunk
+fn creation_time(kind: &[u8], data: &[u8]) -> Option<NaiveDateTime> {
+    let (keyword, text) = data.split_at(data.iter().position(|&byte| byte == 0)?);
+    if keyword != b"Creation Time" {
+        return None;
+    }
+    let text = match kind {
+        b"iTXt" => {
+            // A compression flag and method, then a language and translated keyword
+            let rest = text.get(1..)?;
+            if rest.first() != Some(&0) {
+                return None;
+            }
+            let mut fields = rest.get(2..)?.splitn(3, |&byte| byte == 0);
+            fields.nth(2)?
+        }
+        _ => &text[1..],
+    };
+    parse_creation_time(String::from_utf8_lossy(text).trim())
+}
+
+/// Parses the creation times PNG writers use, which should be RFC 1123 but often
+/// aren't
+fn parse_creation_time(text: &str) -> Option<NaiveDateTime> {
+    if let Ok(datetime) = DateTime::parse_from_rfc2822(text) {
+        return Some(datetime.naive_local());
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.naive_local());
+    }
+    [
+        "%Y:%m:%d %H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+}
+
+/// Reads a `tIME` chunk, which is in UTC
+fn parse_time(data: &[u8]) -> Option<NaiveDateTime> {
+    let [year_high, year_low, month, day, hour, minute, second] = *data else {
+        return None;
+    };
+    let utc = NaiveDate::from_ymd_opt(
+        u16::from_be_bytes([year_high, year_low]) as i32,
+        month as u32,
+        day as u32,
+    )?
+    .and_hms_opt(hour as u32, minute as u32, second as u32)?;
+    Some(utc.and_utc().with_timezone(&Local).naive_local())
+}
@@ -0,0 +1,103 @@
+use crate::save::Outcome;
+use crate::summary::Summary;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Something that happened during a run, reported so front ends can show progress
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'a> {
+    /// A source is about to be searched for media
+    Scanning(&'a Path),
+    /// A piece of media was found and its metadata read
+    Found(&'a Path),
+    /// Saving is about to start on this many files, totalling this many bytes
+    Saving { files: usize, bytes: u64 },
+    /// A piece of media's name was already taken in the destination
+    Conflict {
+        path: &'a Path,
+        dest: &'a Path,
+        outcome: Outcome,
+    },
+    /// A piece of media was saved, or skipped, and is done with
+    Saved {
+        path: &'a Path,
+        dest: &'a Path,
+        outcome: Outcome,
+        bytes: u64,
+    },
+    /// A piece of media couldn't be saved and was left in the source
+    Failed {
+        path: &'a Path,
+        bytes: u64,
+        error: &'a str,
+    },
+    /// Every piece of media has been saved
+    Finished,
+    /// What the run did, once it's over
+    Summary(&'a Summary),
+}
+
+impl Event<'_> {
+    /// The event as a JSON object named by its `event` field, one of which is written per
+    /// line for `--output ndjson`
+    pub fn to_json(&self) -> Value {
+        let path = |path: &Path| path.to_string_lossy().into_owned();
+        match *self {
+            Event::Scanning(source) => json!({ "event": "scan-start", "path": path(source) }),
+            Event::Found(file) => json!({ "event": "file-scanned", "path": path(file) }),
+            Event::Saving { files, bytes } => {
+                json!({ "event": "save-start", "files": files, "bytes": bytes })
+            }
+            Event::Conflict {
+                path: source,
+                dest,
+                outcome,
+            } => json!({
+                "event": "conflict",
+                "path": path(source),
+                "dest": path(dest),
+                "outcome": outcome,
+            }),
+            Event::Saved {
+                path: source,
+                dest,
+                outcome,
+                bytes,
+            } => json!({
+                "event": if outcome == Outcome::Skip { "file-skipped" } else { "file-copied" },
+                "path": path(source),
+                "dest": path(dest),
+                "bytes": bytes,
+            }),
+            Event::Failed {
+                path: source,
+                error,
+                ..
+            } => json!({ "event": "error", "path": path(source), "message": error }),
+            Event::Finished => json!({ "event": "save-finished" }),
+            Event::Summary(summary) => {
+                let report = &summary.report;
+                json!({
+                    "event": "summary",
+                    "found": summary.found,
+                    "filtered": summary.filtered,
+                    "transfer": summary.transfer,
+                    "saved": report.saved,
+                    "renamed": report.renamed,
+                    "overwritten": report.overwritten,
+                    "skipped": report.skipped,
+                    "failed": report.failed,
+                    "bytes": report.bytes,
+                    "undated": summary.undated,
+                    "left_in_place": summary.left_in_place,
+                })
+            }
+        }
+    }
+}
+
+/// Called with each event as it happens
+pub type Hook<'a> = &'a (dyn Fn(Event) + Sync);
+
+/// A hook for callers that don't report progress
+pub fn ignore(_: Event) {}
@@ -0,0 +1,122 @@
+use crate::save::Conflict;
+use chrono::{DateTime, Local};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// Asks how to resolve each conflict, remembering "apply to all" answers
+pub struct Prompt<'a> {
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    remembered: Option<Conflict>,
+}
+
+impl<'a> Prompt<'a> {
+    pub fn new(input: impl BufRead + 'a, output: impl Write + 'a) -> Self {
+        Prompt {
+            input: Box::new(input),
+            output: Box::new(output),
+            remembered: None,
+        }
+    }
+
+    /// Prompts on the terminal, keeping stdout free for the run's output
+    pub fn stdio() -> Prompt<'static> {
+        Prompt::new(io::stdin().lock(), io::stderr())
+    }
+
+    /// Asks what to do with `src`, whose destination `dest` is already claimed by `existing`
+    ///
+    /// `existing` is `dest` itself for files already in the destination, or the source of
+    /// other media from this run, which can't be overwritten.
+    pub fn ask(
+        &mut self,
+        src: &Path,
+        dest: &Path,
+        existing: &Path,
+        can_overwrite: bool,
+    ) -> io::Result<Conflict> {
+        if let Some(conflict) = self.remembered {
+            if can_overwrite || conflict != Conflict::Overwrite {
+                return Ok(conflict);
+            }
+        }
+
+        let choices = if can_overwrite {
+            "[s]kip, [o]verwrite, [r]ename, [c]ompare or [a]bort"
+        } else {
+            "[s]kip, [r]ename, [c]ompare or [a]bort"
+        };
+
+        loop {
+            if existing == dest {
+                writeln!(self.output, "{:?} already exists.", dest)?;
+            } else {
+                writeln!(
+                    self.output,
+                    "{:?} is also being saved as {:?}.",
+                    existing, dest
+                )?;
+            }
+            write!(
+                self.output,
+                "Save {:?}? {} (capitalize to apply to all): ",
+                src, choices
+            )?;
+            self.output.flush()?;
+
+            let mut answer = String::new();
+            if self.input.read_line(&mut answer)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "No answer given for a conflict",
+                ));
+            }
+
+            let answer = answer.trim();
+            let conflict = match answer.to_lowercase().as_str() {
+                "s" => Conflict::Skip,
+                "o" if can_overwrite => Conflict::Overwrite,
+                "r" => Conflict::Rename,
+                "a" => Conflict::Error,
+                "c" => {
+                    writeln!(self.output, "  New:      {}", describe(src))?;
+                    writeln!(self.output, "  Existing: {}", describe(existing))?;
+                    continue;
+                }
+                _ => {
+                    writeln!(self.output, "Please answer with one of {}.", choices)?;
+                    continue;
+                }
+            };
+
+            if answer.chars().all(char::is_uppercase) {
+                self.remembered = Some(conflict);
+            }
+            return Ok(conflict);
+        }
+    }
+}
+
+/// Describes a file's size and modification time, so two files can be compared
+fn describe(path: &Path) -> String {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            let modified = metadata.modified().map_or_else(
+                |_| String::from("unknown"),
+                |time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                },
+            );
+            format!(
+                "{:?}, {} bytes, modified {}",
+                path,
+                metadata.len(),
+                modified
+            )
+        }
+        Err(e) => format!("{:?}, {}", path, e),
+    }
+}
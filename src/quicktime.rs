@@ -0,0 +1,248 @@
+use crate::metadata::Metadata;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use log::trace;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Boxes like `moov` and `meta` hold metadata, and are small next to the media beside them
+const MAX_BOX_LEN: u64 = 64 << 20;
+
+/// Reads when a QuickTime or MP4 video was recorded, and on what, from its `moov` box
+///
+/// Apple's `com.apple.quicktime.creationdate` is preferred, since it keeps the local
+/// time, then the `©day` atom, then the `mvhd` creation time, which is in UTC.
+pub fn read_metadata(path: &Path) -> io::Result<Metadata> {
+    let Some(moov) = read_top_level_box(path, b"moov")? else {
+        return Ok(Metadata::default());
+    };
+
+    let keys = read_keys(&moov);
+    let key = |name: &str| {
+        keys.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let udta = find_box(&moov, b"udta");
+    let day = udta
+        .and_then(|udta| find_box(udta, b"\xa9day"))
+        .and_then(user_data_text);
+
+    let datetime = key("com.apple.quicktime.creationdate")
+        .and_then(parse_date)
+        .or_else(|| day.as_deref().and_then(parse_date))
+        .or_else(|| find_box(&moov, b"mvhd").and_then(mvhd_creation_time));
+    trace!("Read a recording date of {:?} from {:?}", datetime, path);
+
+    let camera = match (
+        key("com.apple.quicktime.make"),
+        key("com.apple.quicktime.model"),
+    ) {
+        (Some(make), Some(model)) if model.starts_with(make) => Some(model.to_string()),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model).map(str::to_string),
+    };
+
+    Ok(Metadata {
+        datetime,
+        camera: camera.map(|camera| camera.replace(['/', '\\'], "-")),
+        ..Default::default()
+    })
+}
+
+/// Reads the identifier Apple gives the video of a Live Photo, shared with its photo
+pub fn read_content_identifier(path: &Path) -> io::Result<Option<String>> {
+    let Some(moov) = read_top_level_box(path, b"moov")? else {
+        return Ok(None);
+    };
+    Ok(read_keys(&moov)
+        .into_iter()
+        .find(|(key, _)| key == "com.apple.quicktime.content.identifier")
+        .map(|(_, value)| value))
+}
+
+/// A box at the top level of a file, as its type and where its body is
+pub struct TopLevelBox {
+    pub kind: [u8; 4],
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Lists the top-level boxes of a file from their headers, without reading the media in
+/// them
+pub fn top_level_boxes(file: &mut File) -> io::Result<Vec<TopLevelBox>> {
+    let size = file.metadata()?.len();
+    let mut found = Vec::new();
+    let mut position = 0;
+
+    while position + 8 <= size {
+        let mut header = [0; 16];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header[..8])?;
+
+        let (len, header_len) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            0 => (size - position, 8),
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                (u64::from_be_bytes(header[8..].try_into().unwrap()), 16)
+            }
+            len => (len as u64, 8),
+        };
+        if len < header_len {
+            break;
+        }
+
+        found.push(TopLevelBox {
+            kind: header[4..8].try_into().unwrap(),
+            offset: position + header_len,
+            len: (len - header_len).min(size - position - header_len),
+        });
+        position = position.saturating_add(len);
+    }
+
+    Ok(found)
+}
+
+/// Reads the body of a top-level box, unless it's too big to be metadata
+pub fn read_body(file: &mut File, found: &TopLevelBox) -> io::Result<Option<Vec<u8>>> {
+    if found.len > MAX_BOX_LEN {
+        return Ok(None);
+    }
+    let mut body = vec![0; found.len as usize];
+    file.seek(SeekFrom::Start(found.offset))?;
+    file.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Finds a top-level box, such as `moov`, by its header without reading the media around
+/// it, and reads its body
+pub fn read_top_level_box(path: &Path, kind: &[u8; 4]) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    match top_level_boxes(&mut file)?
+        .iter()
+        .find(|found| &found.kind == kind)
+    {
+        Some(found) => read_body(&mut file, found),
+        None => Ok(None),
+    }
+}
+
+/// Iterates over the boxes in `data`, as their types and bodies
+pub fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let (len, header_len) = match len {
+            0 => (rest.len(), 8),
+            1 => (
+                u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?) as usize,
+                16,
+            ),
+            len => (len, 8),
+        };
+        if len < header_len || len > rest.len() {
+            return None;
+        }
+
+        let (current, next) = rest.split_at(len);
+        rest = next;
+        Some((&current[4..8], &current[header_len..]))
+    })
+}
+
+pub fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(found, _)| found == kind)
+        .map(|(_, body)| body)
+}
+
+/// Seconds from 1904, QuickTime's epoch, until the Unix epoch
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+fn mvhd_creation_time(mvhd: &[u8]) -> Option<NaiveDateTime> {
+    let seconds = match mvhd.first()? {
+        0 => u32::from_be_bytes(mvhd.get(4..8)?.try_into().ok()?) as i64,
+        1 => i64::try_from(u64::from_be_bytes(mvhd.get(4..12)?.try_into().ok()?)).ok()?,
+        _ => return None,
+    };
+
+    // Many cameras leave it at zero rather than setting a clock
+    if seconds == 0 {
+        return None;
+    }
+    let utc = DateTime::from_timestamp(seconds - QUICKTIME_EPOCH_OFFSET, 0)?;
+    Some(utc.with_timezone(&Local).naive_local())
+}
+
+/// Reads the text of a QuickTime user data atom, which is a length and language
+/// followed by the text
+fn user_data_text(atom: &[u8]) -> Option<String> {
+    let len = u16::from_be_bytes(atom.get(..2)?.try_into().ok()?) as usize;
+    let text = atom.get(4..4 + len)?;
+    Some(String::from_utf8_lossy(text).into_owned())
+}
+
+/// Reads Apple's metadata keys and their text values from `moov/meta`
+fn read_keys(moov: &[u8]) -> Vec<(String, String)> {
+    let Some(meta) = find_box(moov, b"meta") else {
+        return Vec::new();
+    };
+    // MP4 gives the box a version and flags, QuickTime doesn't
+    let meta = match meta.get(4..8) {
+        Some(b"hdlr" | b"keys" | b"ilst") => meta,
+        _ => meta.get(4..).unwrap_or_default(),
+    };
+
+    let names: Vec<String> = find_box(meta, b"keys")
+        .and_then(|keys| keys.get(8..))
+        .map(|entries| {
+            boxes(entries)
+                .map(|(_, name)| String::from_utf8_lossy(name).into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Each item's type is the 1-based index of its key
+    let Some(ilst) = find_box(meta, b"ilst") else {
+        return Vec::new();
+    };
+    boxes(ilst)
+        .filter_map(|(index, item)| {
+            let index = u32::from_be_bytes(index.try_into().ok()?) as usize;
+            let name = names.get(index.checked_sub(1)?)?;
+            let data = find_box(item, b"data")?;
+            // UTF-8 text is type 1, after a type and a locale
+            if data.get(..4)? != [0, 0, 0, 1] {
+                return None;
+            }
+            let value = String::from_utf8_lossy(data.get(8..)?).into_owned();
+            Some((name.clone(), value))
+        })
+        .collect()
+}
+
+/// Parses the dates found in video metadata, such as `2019-07-04T10:11:12+0200`,
+/// keeping the local time they were recorded at
+fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%z",
+        "%Y-%m-%dT%H:%M:%S%.f%z",
+        "%Y-%m-%d %H:%M:%S%z",
+    ] {
+        if let Ok(datetime) = DateTime::<FixedOffset>::parse_from_str(text, format) {
+            return Some(datetime.naive_local());
+        }
+    }
+
+    // Without an offset, a trailing Z means UTC
+    if let Some(utc) = text.strip_suffix('Z') {
+        let datetime = NaiveDateTime::parse_from_str(utc, "%Y-%m-%dT%H:%M:%S").ok()?;
+        return Some(datetime.and_utc().with_timezone(&Local).naive_local());
+    }
+    NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .ok()
+}
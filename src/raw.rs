@@ -0,0 +1,72 @@
+use crate::quicktime::{boxes, find_box, read_top_level_box};
+use exif::{Exif, Reader};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Canon's `uuid` box in a CR3's `moov`, which holds its metadata boxes
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// The EXIF of a CR3, split between boxes that are each a TIFF of their own
+#[derive(Default)]
+pub struct Cr3Metadata {
+    /// `CMT1`, with the tags of a TIFF's first IFD, such as the make and model
+    pub tiff: Option<Exif>,
+    /// `CMT2`, with the tags of the EXIF IFD, such as when the photo was taken
+    pub exif: Option<Exif>,
+}
+
+/// Reads the EXIF of an Olympus or Panasonic RAW, which is TIFF with another magic number
+pub fn read_tiff_variant(path: &Path) -> Result<Exif, exif::Error> {
+    let mut data = fs::read(path)?;
+    if data.len() < 8 {
+        return Err(exif::Error::InvalidFormat("Truncated TIFF header"));
+    }
+
+    // The magic number is written in the byte order the rest of the file uses
+    let magic = match &data[..2] {
+        b"II" => [42, 0],
+        _ => [0, 42],
+    };
+    data[2..4].copy_from_slice(&magic);
+    Reader::new().read_raw(data)
+}
+
+/// Reads the EXIF of a Fujifilm RAW from the JPEG preview its header points to
+pub fn read_raf(path: &Path) -> Result<Exif, exif::Error> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 92];
+    file.read_exact(&mut header)?;
+
+    let offset = u32::from_be_bytes(header[84..88].try_into().unwrap());
+    let len = u32::from_be_bytes(header[88..92].try_into().unwrap());
+    file.seek(SeekFrom::Start(offset as u64))?;
+    let mut jpeg = Vec::new();
+    file.take(len as u64).read_to_end(&mut jpeg)?;
+
+    Reader::new().read_from_container(&mut Cursor::new(jpeg))
+}
+
+/// Reads the metadata boxes from the Canon `uuid` box of a CR3's `moov`
+pub fn read_cr3(path: &Path) -> io::Result<Cr3Metadata> {
+    let Some(moov) = read_top_level_box(path, b"moov")? else {
+        return Ok(Cr3Metadata::default());
+    };
+    let Some(canon) = boxes(&moov)
+        .filter(|(kind, _)| kind == b"uuid")
+        .find_map(|(_, body)| body.strip_prefix(&CANON_UUID[..]))
+    else {
+        return Ok(Cr3Metadata::default());
+    };
+
+    let read = |kind| {
+        let tiff = find_box(canon, kind)?;
+        Reader::new().read_raw(tiff.to_vec()).ok()
+    };
+    Ok(Cr3Metadata {
+        tiff: read(b"CMT1"),
+        exif: read(b"CMT2"),
+    })
+}
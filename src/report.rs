@@ -0,0 +1,230 @@
+use crate::dedupe::{find_duplicates, Group};
+use crate::metadata::read_thumbnail;
+use crate::save::{Outcome, Step};
+use crate::summary::Summary;
+use crate::tree::Tree;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use chrono::{Datelike, Local, Month, NaiveDateTime};
+use indicatif::HumanBytes;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h2 { border-bottom: 1px solid #ccc; padding-bottom: 0.2em; }
+.grid { display: flex; flex-wrap: wrap; gap: 0.5em; }
+figure { margin: 0; width: 160px; text-align: center; }
+figure img { width: 160px; height: 120px; object-fit: cover; background: #eee; }
+figcaption { font-size: 0.75em; overflow-wrap: anywhere; }
+.error { color: #b00; }
+";
+
+/// Writes a static HTML page of what a run did, for anyone to look over in a browser
+///
+/// Media is shown by the preview embedded in its EXIF where it has one, so the page stays
+/// quick to write, and otherwise by linking to the saved file.
+pub fn write_report(path: &Path, summary: &Summary, tree: &Tree, steps: &[Step]) -> io::Result<()> {
+    fs::write(path, render(summary, tree, steps)?)
+}
+
+fn render(summary: &Summary, tree: &Tree, steps: &[Step]) -> io::Result<String> {
+    let report = &summary.report;
+    let failed: HashMap<&PathBuf, &String> = report
+        .failures
+        .iter()
+        .map(|(path, error)| (path, error))
+        .collect();
+    let steps: HashMap<&PathBuf, &Step> = steps.iter().map(|step| (&step.source, step)).collect();
+
+    let mut months: BTreeMap<(i32, u32), Vec<&Step>> = BTreeMap::new();
+    let mut undated = Vec::new();
+    let mut skipped = Vec::new();
+    for image in tree.images() {
+        let Some(step) = steps.get(&image.path) else {
+            continue;
+        };
+        if failed.contains_key(&image.path) {
+            continue;
+        }
+        if step.outcome == Outcome::Skip {
+            skipped.push(*step);
+            continue;
+        }
+        match image.datetime.as_ref().map(NaiveDateTime::date) {
+            Some(date) => months
+                .entry((date.year(), date.month()))
+                .or_default()
+                .push(step),
+            None => undated.push(*step),
+        }
+    }
+    let duplicates = duplicates(&months, &undated, &skipped)?;
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>img-sort report</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>img-sort report</h1>\n<p>Made {}</p>\n<ul>\n\
+         <li>{} pieces of media found, {} filtered out</li>\n\
+         <li>{}: {}, renamed: {}, overwritten: {}, skipped: {}, failed: {}</li>\n\
+         <li>Size: {}</li>\n</ul>\n",
+        Local::now().format("%Y-%m-%d %H:%M"),
+        summary.found,
+        summary.filtered,
+        summary.transfer.past_tense(),
+        report.saved,
+        report.renamed,
+        report.overwritten,
+        report.skipped,
+        report.failed,
+        HumanBytes(report.bytes),
+    );
+
+    for ((year, month), steps) in &months {
+        let name = Month::try_from(*month as u8).map_or("Unknown", |month| month.name());
+        let _ = writeln!(html, "<h2>{name} {year} ({})</h2>", steps.len());
+        html.push_str("<div class=\"grid\">\n");
+        for step in steps {
+            figure(&mut html, step);
+        }
+        html.push_str("</div>\n");
+    }
+
+    list(&mut html, "Without a date", &undated, |step| {
+        format!(
+            "{} saved to {}",
+            escape(&step.source.to_string_lossy()),
+            escape(&step.dest.to_string_lossy())
+        )
+    });
+    list(&mut html, "Duplicates found", &duplicates, |group| {
+        let files: Vec<String> = group
+            .files
+            .iter()
+            .map(|file| escape(&file.to_string_lossy()))
+            .collect();
+        format!("{} are the same", files.join(", "))
+    });
+    list(
+        &mut html,
+        "Skipped, their names were taken",
+        &skipped,
+        |step| {
+            format!(
+                "{} was left out, {} already exists",
+                escape(&step.source.to_string_lossy()),
+                escape(&step.dest.to_string_lossy())
+            )
+        },
+    );
+    list(&mut html, "Errors", &report.failures, |(path, error)| {
+        format!(
+            "<span class=\"error\">{}: {}</span>",
+            escape(&path.to_string_lossy()),
+            escape(error)
+        )
+    });
+
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Finds the media of a run with the same contents, as what was saved and, where a name
+/// was taken, both the media left out and the file that took its name
+fn duplicates(
+    months: &BTreeMap<(i32, u32), Vec<&Step>>,
+    undated: &[&Step],
+    skipped: &[&Step],
+) -> io::Result<Vec<Group>> {
+    // Moved media is only in the destination, and copies there match their source anyway
+    let saved = months.values().flatten().chain(undated).map(|step| {
+        if step.dest.exists() {
+            step.dest.clone()
+        } else {
+            step.source.clone()
+        }
+    });
+    let skipped = skipped
+        .iter()
+        .flat_map(|step| [step.source.clone(), step.dest.clone()]);
+
+    find_duplicates(saved.chain(skipped).filter(|file| file.exists()))
+}
+
+/// Adds a thumbnail of saved media, linking to the file
+fn figure(html: &mut String, step: &Step) {
+    // Moved media is only in the destination, and failed copies only in the source
+    let file = if step.dest.exists() {
+        &step.dest
+    } else {
+        &step.source
+    };
+    let link = file_url(file);
+    let src = match read_thumbnail(file) {
+        Some(thumbnail) => format!(
+            "data:image/jpeg;base64,{}",
+            BASE64_STANDARD.encode(thumbnail)
+        ),
+        None => link.clone(),
+    };
+    let name = step.dest.file_name().unwrap_or_default();
+
+    let _ = writeln!(
+        html,
+        "<figure><a href=\"{link}\"><img src=\"{src}\" loading=\"lazy\" alt=\"\"></a>\
+         <figcaption>{}</figcaption></figure>",
+        escape(&name.to_string_lossy()),
+    );
+}
+
+fn list<T>(html: &mut String, title: &str, items: &[T], describe: impl Fn(&T) -> String) {
+    if items.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(html, "<h2>{title} ({})</h2>\n<ul>", items.len());
+    for item in items {
+        let _ = writeln!(html, "<li>{}</li>", describe(item));
+    }
+    html.push_str("</ul>\n");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Links to a local file, percent-encoding what isn't allowed in a URL
+fn file_url(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut url = String::from("file://");
+    if !path.starts_with("/") {
+        url.push('/');
+    }
+
+    for byte in path.to_string_lossy().replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                url.push(byte as char)
+            }
+            byte => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    url
+}
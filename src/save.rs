@@ -0,0 +1,629 @@
+use crate::hash::hash_file;
+use crate::image::Image;
+use crate::infer::{read_sidecar, DateSource};
+use crate::interrupt::interrupted;
+use crate::journal::{Entry, Journal};
+use crate::layout::{Hemisphere, MonthStyle};
+use crate::metadata::{self, Strip};
+use crate::motion::extract_video;
+use crate::progress::{Event, Hook};
+use crate::prompt::Prompt;
+use crate::sidecar::sidecar_dest;
+use crate::template::Template;
+use crate::transfer::{copy_xattrs, write_atomic, Transfer};
+use chrono::Locale;
+use clap::ValueEnum;
+use indicatif::HumanBytes;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// What to do when a file with the same name already exists in the destination
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Conflict {
+    /// Leave the existing file and skip the new one
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Save the new file with a numbered suffix (`_1`, `_2`, ...)
+    #[default]
+    Rename,
+    /// Abort the run
+    Error,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    pub transfer: Transfer,
+    pub on_conflict: Conflict,
+    pub rename: Option<Template>,
+    pub month_style: MonthStyle,
+    pub locale: Option<Locale>,
+    pub hemisphere: Hemisphere,
+    /// Keep the directories media was found in, relative to its source, inside each bucket
+    pub keep_structure: bool,
+    /// Copy extended attributes along with copied media
+    pub preserve_xattrs: bool,
+    /// Send overwritten files to the platform trash instead of deleting them
+    pub trash: bool,
+    /// Ask about each conflict instead of following `on_conflict`
+    pub interactive: bool,
+    /// Save even if a destination filesystem looks too full
+    pub force: bool,
+    /// How many files are saved at once, or 0 for one per CPU
+    pub threads: usize,
+    /// Directory for media without a date, `Unknown` if unset
+    pub unknown_dir: Option<String>,
+    /// Metadata to remove from copies, leaving the originals as they were
+    pub strip: Option<Strip>,
+    /// Write the date and location from Takeout sidecars into copies without them
+    pub embed_takeout: bool,
+    /// Give media whose extension doesn't match its contents the right one
+    pub fix_extensions: bool,
+}
+
+impl SaveOptions {
+    /// Whether copies are changed after they're made, so no longer match their source
+    pub fn rewrites_copies(&self) -> bool {
+        self.transfer.copies() && (self.strip.is_some() || self.embed_takeout)
+    }
+
+    pub fn unknown_dir(&self) -> &str {
+        self.unknown_dir.as_deref().unwrap_or("Unknown")
+    }
+}
+
+/// Counts of what happened to each piece of media during a save
+#[derive(Debug, Default, PartialEq)]
+pub struct SaveReport {
+    pub saved: usize,
+    pub skipped: usize,
+    pub renamed: usize,
+    pub overwritten: usize,
+    /// Media that couldn't be saved and was left in the source
+    pub failed: usize,
+    /// Size of the media saved
+    pub bytes: u64,
+    /// Each piece of media that failed, with why
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl SaveReport {
+    /// Adds the counts from another report, e.g. one kept by another worker
+    pub fn merge(&mut self, other: SaveReport) {
+        self.saved += other.saved;
+        self.skipped += other.skipped;
+        self.renamed += other.renamed;
+        self.overwritten += other.overwritten;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.failures.extend(other.failures);
+    }
+}
+
+/// What saving a piece of media will do to the destination
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Save,
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+/// A piece of media, where it's going and how, decided before anything is saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    pub outcome: Outcome,
+    /// Read up front, since moving media takes it out of the source
+    pub bytes: u64,
+    /// BLAKE3 hash of the source, when the step is kept to be checked later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Sidecars saved along with the media, as where they are and where they're going
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<(PathBuf, PathBuf)>,
+    /// Where the video inside a Motion Photo is saved, beside it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion_video: Option<PathBuf>,
+    /// Where the media's date came from, to tell how far it can be trusted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_source: Option<DateSource>,
+}
+
+/// Decides how an image is saved to `dest`, given the sources already `planned` for each
+/// destination, asking through `prompt` about conflicts when there is one
+///
+/// The conflict policy applies to files already in the destination. Media from the same
+/// run never replace each other, so a destination that's already planned is numbered.
+pub fn plan_image(
+    image: &Image,
+    dest: PathBuf,
+    options: &SaveOptions,
+    planned: &HashMap<PathBuf, PathBuf>,
+    prompt: Option<&mut Prompt>,
+) -> io::Result<Step> {
+    let mut steps = plan_images(&[image], dest, options, planned, prompt)?;
+    Ok(steps.remove(0))
+}
+
+/// Decides how media saved as a unit, like the photo and video of a Live Photo, is saved,
+/// the first to `dest` and the rest beside it under the same name
+///
+/// A conflict over any of them is a conflict over all of them, so they're skipped,
+/// overwritten or numbered together.
+pub fn plan_images(
+    images: &[&Image],
+    dest: PathBuf,
+    options: &SaveOptions,
+    planned: &HashMap<PathBuf, PathBuf>,
+    prompt: Option<&mut Prompt>,
+) -> io::Result<Vec<Step>> {
+    // symlink_metadata also catches dangling links left by a previous run
+    let exists = |path: &Path| path.symlink_metadata().is_ok();
+    let taken = |path: &Path| planned.contains_key(path) || exists(path);
+    let dests = |dest: &Path| -> Vec<PathBuf> {
+        let mut dests = vec![dest.to_path_buf()];
+        for image in images.iter().skip(1) {
+            dests.push(match image.path.extension() {
+                Some(ext) => dest.with_extension(ext),
+                None => dest.with_extension(""),
+            });
+        }
+        dests
+    };
+
+    let wanted = dests(&dest);
+    let clash = images.iter().zip(&wanted).find_map(|(image, dest)| {
+        let planned = planned.get(dest);
+        (planned.is_some() || exists(dest)).then_some((image, dest, planned))
+    });
+    let conflict = match (clash, prompt) {
+        (None, _) => None,
+        (Some((image, dest, Some(other))), Some(prompt)) => {
+            Some(prompt.ask(&image.path, dest, other, false)?)
+        }
+        (Some((_, _, Some(_))), None) => Some(Conflict::Rename),
+        (Some((image, dest, None)), Some(prompt)) => {
+            Some(prompt.ask(&image.path, dest, dest, true)?)
+        }
+        (Some((_, _, None)), None) => Some(options.on_conflict),
+    };
+
+    let (dests, outcome) = match conflict {
+        None => (wanted, Outcome::Save),
+        Some(Conflict::Skip) => (wanted, Outcome::Skip),
+        Some(Conflict::Overwrite) => (wanted, Outcome::Overwrite),
+        Some(Conflict::Rename) => {
+            let dest = numbered(&dest, |path| dests(path).iter().any(|path| taken(path)));
+            (dests(&dest), Outcome::Rename)
+        }
+        Some(Conflict::Error) => {
+            let (_, dest, _) = clash.unwrap();
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} already exists in the destination", dest),
+            ));
+        }
+    };
+
+    let steps = images.iter().zip(dests).map(|(image, dest)| {
+        let sidecars = image
+            .sidecars
+            .iter()
+            .map(|sidecar| (sidecar.clone(), sidecar_dest(sidecar, &image.path, &dest)))
+            .collect();
+        let motion_video = image.motion_photo.then(|| dest.with_extension("mp4"));
+        // Only what's there can be overwritten, the rest of the unit is simply saved
+        let outcome = match outcome {
+            Outcome::Overwrite if !taken(&dest) => Outcome::Save,
+            outcome => outcome,
+        };
+        Step {
+            source: image.path.clone(),
+            dest,
+            outcome,
+            bytes: size(&image.path),
+            hash: None,
+            sidecars,
+            motion_video,
+            date_source: image.date_source,
+        }
+    });
+    Ok(steps.collect())
+}
+
+fn size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+pub fn save_step(step: &Step, options: &SaveOptions, report: &mut SaveReport) -> io::Result<()> {
+    let Step { source, dest, .. } = step;
+
+    match step.outcome {
+        Outcome::Save => {}
+        Outcome::Skip => {
+            debug!("Skipping {:?}, {:?} already exists", source, dest);
+            report.skipped += 1;
+            return Ok(());
+        }
+        Outcome::Overwrite => report.overwritten += 1,
+        Outcome::Rename => report.renamed += 1,
+    }
+
+    debug!("Saving {:?} to {:?}", source, dest);
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Copies are rewritten before they're renamed into place, so a failure leaves
+    // neither the original nor a file being replaced touched
+    options.transfer.apply_with(source, dest, |new| {
+        if options.embed_takeout && options.transfer.copies() {
+            if let Some(sidecar) = read_sidecar(source) {
+                let embedded =
+                    metadata::fill_in(new, sidecar.taken, sidecar.coordinates, sidecar.altitude);
+                match embedded {
+                    Ok(()) => debug!("Embedded the sidecar of {:?} into {:?}", source, dest),
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                        warn!("Could not embed the sidecar into {:?}: {}", dest, e)
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        // A copy that still has what was meant to be stripped mustn't be saved, though
+        // one that can't be stripped only fails itself
+        if let Some(strip) = options.strip.filter(|_| options.transfer.copies()) {
+            metadata::strip(new, strip)
+                .map_err(|e| io::Error::other(format!("Could not strip the metadata: {}", e)))?;
+        }
+
+        if step.outcome == Outcome::Overwrite {
+            trash_replaced(dest, options.trash)?;
+        }
+        Ok(())
+    })?;
+    report.saved += 1;
+    report.bytes += step.bytes;
+
+    // Losing attributes isn't worth failing the save over
+    if options.preserve_xattrs && options.transfer.copies() {
+        if let Err(e) = copy_xattrs(source, dest) {
+            warn!("Could not copy extended attributes to {:?}: {}", dest, e);
+        }
+    }
+
+    // The photo is saved whole, with its video copied out of it
+    if let Some(video) = &step.motion_video {
+        match save_motion_video(dest, video, step.outcome, options) {
+            Ok(()) => debug!("Saved the video inside {:?} to {:?}", source, video),
+            Err(e) => warn!("Could not save the video inside {:?}: {}", source, e),
+        }
+    }
+
+    // Sidecars go last, since embedding Takeout's reads the one beside the source
+    for (sidecar, sidecar_dest) in &step.sidecars {
+        match save_sidecar(sidecar, sidecar_dest, step.outcome, options) {
+            Ok(()) => debug!("Saved the sidecar {:?} to {:?}", sidecar, sidecar_dest),
+            Err(e) => warn!("Could not save the sidecar {:?}: {}", sidecar, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Saves a sidecar the way its media was, replacing one already there only if the media
+/// replaced its own
+fn save_sidecar(
+    source: &Path,
+    dest: &Path,
+    outcome: Outcome,
+    options: &SaveOptions,
+) -> io::Result<()> {
+    if outcome != Outcome::Overwrite && dest.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} already exists", dest),
+        ));
+    }
+    options
+        .transfer
+        .apply_with(source, dest, |_| trash_replaced(dest, options.trash))
+}
+
+/// Saves the video inside a Motion Photo already saved to `photo`, replacing one already
+/// there only if the photo replaced its own
+fn save_motion_video(
+    photo: &Path,
+    video: &Path,
+    outcome: Outcome,
+    options: &SaveOptions,
+) -> io::Result<()> {
+    if outcome != Outcome::Overwrite && video.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} already exists", video),
+        ));
+    }
+    write_atomic(video, |partial| {
+        extract_video(photo, partial)?;
+        trash_replaced(video, options.trash)
+    })
+}
+
+/// Checks each destination filesystem has room for the data the steps will write to it
+pub fn check_free_space(steps: &[Step], options: &SaveOptions) -> io::Result<()> {
+    let mut needed: HashMap<u64, (&Path, u64)> = HashMap::new();
+
+    for step in steps.iter().filter(|step| step.outcome != Outcome::Skip) {
+        let dir = step.dest.parent().unwrap_or(&step.dest);
+        let writes = match options.transfer {
+            Transfer::Copy | Transfer::ReflinkOrCopy => true,
+            // Moves only write data when they fall back to copying across filesystems
+            Transfer::Move => device(&step.source)? != device(dir)?,
+            Transfer::Link | Transfer::Symlink | Transfer::Reflink => false,
+        };
+
+        if writes {
+            needed.entry(device(dir)?).or_insert((dir, 0)).1 += step.bytes;
+        }
+    }
+
+    for (dir, bytes) in needed.into_values() {
+        let available = fs4::available_space(existing_ancestor(dir))?;
+        if bytes > available {
+            return Err(io::Error::new(
+                io::ErrorKind::StorageFull,
+                format!(
+                    "Saving needs {} on the filesystem holding {:?}, but only {} is free",
+                    HumanBytes(bytes),
+                    dir,
+                    HumanBytes(available)
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Identifies the filesystem a path is on, or will be on once its directories are created
+#[cfg(unix)]
+fn device(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(existing_ancestor(path))?.dev())
+}
+
+#[cfg(not(unix))]
+fn device(path: &Path) -> io::Result<u64> {
+    // Without device ids, treat everything under one drive letter or share as one filesystem
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    fs::canonicalize(path)?
+        .components()
+        .next()
+        .hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Saves planned media, after checking there's room for it
+///
+/// Every destination is decided before any media is saved, so the saving itself can be
+/// spread over several threads.
+pub fn execute(
+    steps: &[Step],
+    options: &SaveOptions,
+    journal: Option<&Journal>,
+    on_event: Hook,
+) -> io::Result<SaveReport> {
+    if let Err(e) = check_free_space(steps, options) {
+        if !options.force {
+            return Err(e);
+        }
+        warn!("{}", e);
+    }
+
+    on_event(Event::Saving {
+        files: steps.len(),
+        bytes: steps.iter().map(|step| step.bytes).sum(),
+    });
+    let report = save_steps(steps, options, journal, on_event)?;
+    on_event(Event::Finished);
+
+    if let Some(journal) = journal {
+        journal.flush()?;
+    }
+    if interrupted() {
+        let done = report.saved + report.skipped + report.failed;
+        warn!("Interrupted after {} of {} files", done, steps.len());
+    }
+
+    Ok(report)
+}
+
+/// Finds the closest of a path and its parents that exists
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        // Relative paths run out at "", which is the working directory
+        .map(|ancestor| match ancestor.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => ancestor,
+        })
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path)
+}
+
+/// Carries out the steps on `options.threads` workers, reporting each to `on_event` and
+/// recording what was done in `journal`
+///
+/// A file that fails to save is counted and skipped, unless every file would fail the
+/// same way, e.g. reflinks on a filesystem without them, which stops the save.
+pub fn save_steps(
+    steps: &[Step],
+    options: &SaveOptions,
+    journal: Option<&Journal>,
+    on_event: Hook,
+) -> io::Result<SaveReport> {
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        threads => threads,
+    };
+    let next = AtomicUsize::new(0);
+    let fatal = Mutex::new(None);
+
+    let reports: Vec<SaveReport> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(steps.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut report = SaveReport::default();
+
+                    // Interrupted runs finish the file they're on, so none is left half written
+                    while let Some(step) = steps
+                        .get(next.fetch_add(1, Ordering::Relaxed))
+                        .filter(|_| !interrupted())
+                    {
+                        match save_step(step, options, &mut report) {
+                            Ok(()) => {
+                                if let Some(journal) = journal {
+                                    record(journal, step, options);
+                                }
+                                if step.outcome != Outcome::Save {
+                                    on_event(Event::Conflict {
+                                        path: &step.source,
+                                        dest: &step.dest,
+                                        outcome: step.outcome,
+                                    });
+                                }
+                                on_event(Event::Saved {
+                                    path: &step.source,
+                                    dest: &step.dest,
+                                    outcome: step.outcome,
+                                    bytes: step.bytes,
+                                });
+                            }
+                            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                                fatal.lock().unwrap().get_or_insert(e);
+                                // Leave nothing for the other workers to pick up
+                                next.store(steps.len(), Ordering::Relaxed);
+                                break;
+                            }
+                            Err(e) => {
+                                warn!("Failed to save {:?}: {}", step.source, e);
+                                let error = e.to_string();
+                                on_event(Event::Failed {
+                                    path: &step.source,
+                                    bytes: step.bytes,
+                                    error: &error,
+                                });
+                                report.failed += 1;
+                                report.failures.push((step.source.clone(), error));
+                            }
+                        }
+                    }
+
+                    report
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("A save worker panicked"))
+            .collect()
+    });
+
+    if let Some(e) = fatal.into_inner().unwrap() {
+        return Err(e);
+    }
+
+    let mut report = SaveReport::default();
+    for other in reports {
+        report.merge(other);
+    }
+    Ok(report)
+}
+
+/// Adds a saved step to the journal, which is worth warning about but not failing over
+fn record(journal: &Journal, step: &Step, options: &SaveOptions) {
+    if step.outcome == Outcome::Skip {
+        return;
+    }
+
+    // Planned hashes were checked against the source just before saving, but rewritten
+    // copies no longer match it
+    let hash = match &step.hash {
+        Some(hash) if !options.rewrites_copies() => Ok(hash.clone()),
+        _ => hash_file(&step.dest),
+    };
+    let result = hash.and_then(|hash| {
+        let mut entry = Entry::new(options.transfer, &step.source, &step.dest, step.bytes, hash);
+        entry.replaced = step.outcome == Outcome::Overwrite;
+        journal.record(&entry)
+    });
+
+    if let Err(e) = result {
+        warn!(
+            "Could not record {:?} in {:?}: {}",
+            step.dest,
+            journal.path(),
+            e
+        );
+    }
+
+    // Sidecars that couldn't be saved were warned about already
+    for (sidecar, dest) in &step.sidecars {
+        let result = hash_file(dest).and_then(|hash| {
+            let bytes = size(dest);
+            journal.record(&Entry::new(options.transfer, sidecar, dest, bytes, hash))
+        });
+        if let Err(e) = result {
+            debug!("Did not record the sidecar {:?}: {}", dest, e);
+        }
+    }
+}
+
+/// Moves a file that's being replaced to the trash, once what replaces it is written, so
+/// it can still be recovered
+///
+/// Without the trash there's nothing to do, as the rename that follows replaces it.
+fn trash_replaced(path: &Path, trash: bool) -> io::Result<()> {
+    if !trash || path.symlink_metadata().is_err() {
+        return Ok(());
+    }
+    trash::delete(path)
+        .map_err(|e| io::Error::other(format!("Failed to move {:?} to the trash: {}", path, e)))
+}
+
+/// Finds the first of `path`, `name_1.ext`, `name_2.ext`, ... that isn't taken
+pub fn numbered(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !taken(path) {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+
+    (1..)
+        .map(|n| {
+            let name = match &extension {
+                Some(ext) => format!("{stem}_{n}.{ext}"),
+                None => format!("{stem}_{n}"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
@@ -0,0 +1,231 @@
+use crate::image::Image;
+use crate::infer::fallback_datetime;
+use crate::interrupt::interrupted;
+use crate::journal::JOURNAL_DIR;
+use crate::metadata::{read_metadata_with, DateTag, DEFAULT_DATE_TAGS};
+use crate::progress::{ignore, Event, Hook};
+use crate::screenshot::is_screenshot;
+use crate::sniff::Format;
+use crate::tree::Tree;
+use globwalk::{GlobError, GlobWalker};
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// How source directories are walked
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    /// How deep to descend, or `None` for no limit
+    pub max_depth: Option<usize>,
+    pub follow_links: bool,
+    /// Globs for paths to leave out, e.g. `**/@eaDir/**`
+    pub excludes: Vec<String>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            max_depth: Some(4),
+            follow_links: true,
+            excludes: Vec::new(),
+        }
+    }
+}
+
+// Left unformatted, since rustfmt moves the comments onto the ends of the lines above
+#[rustfmt::skip]
+pub const PATTERNS: [&str; 31] = [
+    // Photos
+    "*.png", "*.jpg", "*.jpeg", "*.heic", "*.heif", "*.hif", "*.avif", "*.tif", "*.tiff",
+    "*.webp", "*.jxl", "*.gif",
+    // Videos, dated from their containers rather than EXIF
+    "*.mov", "*.mp4", "*.m4v", "*.avi", "*.mkv", "*.3gp", "*.mts", "*.m2ts", "*.wmv",
+    // RAW, most of it TIFF underneath
+    "*.cr2", "*.cr3", "*.nef", "*.arw", "*.orf", "*.rw2", "*.dng", "*.raf", "*.pef",
+    "*.srw",
+];
+
+/// Whether a path has one of the extensions of `patterns`, e.g. `*.jpg`, ignoring case
+pub fn matches_patterns(path: &Path, patterns: &[impl AsRef<str>]) -> bool {
+    let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+
+    patterns.iter().any(|pattern| {
+        pattern
+            .as_ref()
+            .strip_prefix("*.")
+            .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+    })
+}
+
+pub fn build_glob_walker(
+    path: &Path,
+    patterns: &[impl AsRef<str>],
+    options: &ScanOptions,
+) -> Result<GlobWalker, GlobError> {
+    // Excludes are negated patterns, which the walker applies after the others
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|pattern| pattern.as_ref().to_string())
+        .chain(options.excludes.iter().map(|exclude| format!("!{exclude}")))
+        .collect();
+
+    globwalk::GlobWalkerBuilder::from_patterns(path, &patterns)
+        .max_depth(options.max_depth.unwrap_or(usize::MAX))
+        .follow_links(options.follow_links)
+        .case_insensitive(true)
+        // Walk in a stable order so runs are reproducible
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .build()
+}
+
+/// Lists every media file under a directory, at any depth and without following links,
+/// leaving out the journals
+pub fn list_media(path: &Path) -> Result<Vec<PathBuf>, GlobError> {
+    let options = ScanOptions {
+        max_depth: None,
+        follow_links: false,
+        excludes: vec![format!("**/{JOURNAL_DIR}/**")],
+    };
+
+    Ok(build_glob_walker(path, &PATTERNS, &options)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect())
+}
+
+pub fn find(walker: GlobWalker, tree: &mut Tree) -> Result<(), Box<dyn Error>> {
+    find_with(walker, &DEFAULT_DATE_TAGS, tree, &ignore)
+}
+
+/// Finds media like `find`, dating it from the first of `date_tags` it has and
+/// reporting each piece to `on_event`
+pub fn find_with(
+    walker: GlobWalker,
+    date_tags: &[DateTag],
+    tree: &mut Tree,
+    on_event: Hook,
+) -> Result<(), Box<dyn Error>> {
+    let mut seen = HashSet::new();
+
+    // Convert to peekable iterator to check if empty
+    let mut images = walker
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                    warn!("Skipping symlink loop from {:?} to {:?}", path, ancestor);
+                }
+                None
+            }
+        })
+        // Links can reach the same file more than once, so keep only the first
+        .filter(|entry| fs::canonicalize(entry.path()).map_or(true, |path| seen.insert(path)))
+        .peekable();
+
+    if images.peek().is_none() {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::NotFound,
+            "Did not find any media with metadata.",
+        )));
+    }
+
+    for image in images {
+        if interrupted() {
+            break;
+        }
+
+        // Entries directly inside the source are at depth 1, so the directories between
+        // the source and the file are the last `depth - 1` components before its name
+        let components: Vec<_> = image.path().components().collect();
+        let parents = &components[..components.len() - 1];
+        let subdir = parents[parents.len() - (image.depth() - 1)..]
+            .iter()
+            .collect();
+
+        insert_image(image.path().to_path_buf(), subdir, date_tags, tree);
+        on_event(Event::Found(image.path()));
+    }
+
+    Ok(())
+}
+
+pub fn insert_image(path: PathBuf, subdir: PathBuf, date_tags: &[DateTag], tree: &mut Tree) {
+    let mut metadata = read_metadata_with(&path, date_tags);
+
+    // GIFs have no EXIF, so are dated from their names or file times instead
+    if metadata.datetime.is_none() && metadata.format == Some(Format::Gif) {
+        if let Some((datetime, source)) = fallback_datetime(&path) {
+            metadata.datetime = Some(datetime);
+            metadata.date_source = Some(source);
+        }
+    }
+    match (metadata.datetime, metadata.date_source) {
+        (Some(datetime), Some(source)) => {
+            debug!("Found {:?}, taken {} going by {}", path, datetime, source)
+        }
+        (Some(datetime), None) => debug!("Found {:?}, taken {}", path, datetime),
+        (None, _) => debug!("Found {:?}, which has no date", path),
+    }
+
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let screenshot = is_screenshot(&path, &metadata);
+
+    tree.insert(
+        Image::new(path, name)
+            .with_datetime(metadata.datetime)
+            .with_date_source(metadata.date_source)
+            .with_offset(metadata.offset)
+            .with_format(metadata.format)
+            .with_camera(metadata.camera)
+            .with_coordinates(metadata.coordinates)
+            .with_rating(metadata.rating)
+            .with_screenshot(screenshot)
+            .with_subdir(subdir),
+    );
+}
+
+/// Reads newline or NUL separated paths, e.g. from `find -print0`
+pub fn read_file_list(reader: impl Read) -> io::Result<Vec<PathBuf>> {
+    let mut list = String::new();
+    BufReader::new(reader).read_to_string(&mut list)?;
+
+    let separator = if list.contains('\0') { '\0' } else { '\n' };
+
+    Ok(list
+        .split(separator)
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+pub fn find_listed(
+    list: &Path,
+    date_tags: &[DateTag],
+    tree: &mut Tree,
+    on_event: Hook,
+) -> io::Result<()> {
+    let paths = if list == Path::new("-") {
+        read_file_list(io::stdin().lock())?
+    } else {
+        read_file_list(File::open(list)?)?
+    };
+
+    for path in paths {
+        if path.is_file() {
+            insert_image(path.clone(), PathBuf::new(), date_tags, tree);
+            on_event(Event::Found(&path));
+        } else {
+            warn!("Skipping {:?}, which is not a file", path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use crate::metadata::Metadata;
+use crate::png;
+use crate::sniff::Format;
+use clap::ValueEnum;
+use std::path::Path;
+
+/// What to do with screenshots
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Screenshots {
+    /// Sort them like any other media
+    #[default]
+    Normal,
+    /// Sort them apart, under a `Screenshots` folder
+    Route,
+    /// Leave them out
+    Skip,
+}
+
+/// Where screenshots are sorted apart from photos
+pub const SCREENSHOTS_DIR: &str = "Screenshots";
+
+/// How phones and computers start the names of screenshots, in lowercase
+const NAMES: [&str; 4] = ["screenshot", "screen shot", "screen_shot", "screen-shot"];
+
+/// Sizes of the screens of common phones, tablets and computers, shortest side first
+const SCREENS: [(u32, u32); 24] = [
+    // iPhones
+    (640, 1136),
+    (750, 1334),
+    (828, 1792),
+    (1080, 1920),
+    (1125, 2436),
+    (1170, 2532),
+    (1179, 2556),
+    (1242, 2208),
+    (1242, 2688),
+    (1284, 2778),
+    (1290, 2796),
+    // Android phones
+    (720, 1600),
+    (1080, 2340),
+    (1080, 2400),
+    (1440, 3040),
+    (1440, 3120),
+    (1440, 3200),
+    // iPads and computers
+    (1536, 2048),
+    (1620, 2160),
+    (768, 1366),
+    (900, 1440),
+    (1440, 2560),
+    (1600, 2560),
+    (1800, 2880),
+];
+
+/// Whether media looks like a screenshot, going by what its metadata says, its name, or,
+/// for media no camera took, a size that's exactly a screen's
+pub fn is_screenshot(path: &Path, metadata: &Metadata) -> bool {
+    if metadata.screenshot {
+        return true;
+    }
+    let name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_lowercase();
+    if NAMES.iter().any(|start| name.starts_with(start)) {
+        return true;
+    }
+    if metadata.camera.is_some() {
+        return false;
+    }
+
+    // PNGs keep their size in their header rather than EXIF
+    let dimensions = metadata.dimensions.or_else(|| match metadata.format {
+        Some(Format::Png) => png::read_dimensions(path).ok().flatten(),
+        _ => None,
+    });
+    dimensions
+        .is_some_and(|(width, height)| SCREENS.contains(&(width.min(height), width.max(height))))
+}
@@ -0,0 +1,105 @@
+use crate::image::MediaClass;
+use crate::infer::takeout_sidecars;
+use crate::sniff::Format;
+use crate::tree::Tree;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files editors and phones keep beside media, e.g. `IMG_0001.xmp`
+/// beside `IMG_0001.CR2`, or the `.aae` edits and `.thm` thumbnails of cameras
+const EXTENSIONS: [&str; 3] = ["xmp", "aae", "thm"];
+
+/// Whether `file` is a sidecar of `media`, named after either its stem or its whole name,
+/// or one of the JSON files Takeout writes, ignoring case
+pub fn is_sidecar(file: &Path, media: &Path) -> bool {
+    let lowercase = |name: Option<&OsStr>| name.map(|name| name.to_string_lossy().to_lowercase());
+    let (Some(file_name), Some(name), Some(stem)) = (
+        lowercase(file.file_name()),
+        lowercase(media.file_name()),
+        lowercase(media.file_stem()),
+    ) else {
+        return false;
+    };
+
+    EXTENSIONS
+        .iter()
+        .any(|ext| file_name == format!("{stem}.{ext}") || file_name == format!("{name}.{ext}"))
+        || takeout_sidecars(media)
+            .iter()
+            .any(|sidecar| lowercase(sidecar.file_name()).as_ref() == Some(&file_name))
+}
+
+/// Gives each piece of media the sidecars beside it, each of which goes with one piece
+///
+/// RAW and HEIC files claim theirs first, since editors write sidecars for the original
+/// rather than the JPEG shot with it.
+pub fn associate_sidecars(tree: &mut Tree) {
+    let mut listings: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut claimed = HashSet::new();
+    let original = |path: &Path| {
+        MediaClass::from_path(path) == MediaClass::Raw || Format::Heic.matches_extension(path)
+    };
+
+    for first in [true, false] {
+        tree.update(|image| {
+            if original(&image.path) != first {
+                return;
+            }
+
+            // Each folder is listed once, rather than looking for every possible name
+            let dir = image.path.parent().unwrap_or(Path::new(".")).to_path_buf();
+            let files = listings
+                .entry(dir)
+                .or_insert_with_key(|dir| list_files(dir));
+            for sidecar in files.iter().filter(|file| is_sidecar(file, &image.path)) {
+                if claimed.insert(sidecar.clone()) {
+                    debug!("Saving {:?} along with {:?}", sidecar, image.path);
+                    image.sidecars.push(sidecar.clone());
+                }
+            }
+        });
+    }
+}
+
+fn list_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Where a sidecar is saved, beside its media at `dest` and named to match it, so
+/// `IMG_0001.xmp` follows `IMG_0001.CR2` to `Trip_1.xmp`
+pub fn sidecar_dest(sidecar: &Path, source: &Path, dest: &Path) -> PathBuf {
+    let name = |path: &Path| {
+        path.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let stem = |path: &Path| {
+        path.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+    let sidecar_name = name(sidecar);
+
+    let renamed = match sidecar_name.strip_prefix(&name(source)) {
+        Some(rest) => format!("{}{rest}", name(dest)),
+        None => match sidecar_name.strip_prefix(&stem(source)) {
+            Some(rest) => format!("{}{rest}", stem(dest)),
+            None => sidecar_name.clone(),
+        },
+    };
+    dest.with_file_name(renamed)
+}
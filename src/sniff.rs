@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How many bytes from the start of a file are enough to tell its format
+pub const HEADER_LEN: usize = 32;
+
+/// A media format, told apart by the first bytes of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    Tiff,
+    /// Olympus RAW, TIFF with its own magic number
+    Orf,
+    /// Panasonic RAW, TIFF with its own magic number
+    Rw2,
+    /// Fujifilm RAW, which wraps a JPEG preview holding the EXIF
+    Raf,
+    /// Canon's newer RAW, an ISO media file like HEIC
+    Cr3,
+    WebP,
+    Heic,
+    Avif,
+    Jxl,
+    Mov,
+    Mp4,
+    Avi,
+    Matroska,
+}
+
+impl Format {
+    /// The extensions files of this format go by, the usual one first
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Jpeg => &["jpg", "jpeg", "jpe"],
+            Format::Png => &["png"],
+            Format::Gif => &["gif"],
+            // Most RAW formats are TIFF underneath
+            Format::Tiff => &[
+                "tif", "tiff", "cr2", "nef", "nrw", "arw", "dng", "pef", "srw",
+            ],
+            Format::Orf => &["orf"],
+            Format::Rw2 => &["rw2"],
+            Format::Raf => &["raf"],
+            Format::Cr3 => &["cr3"],
+            Format::WebP => &["webp"],
+            Format::Heic => &["heic", "heif", "hif"],
+            Format::Avif => &["avif"],
+            Format::Jxl => &["jxl"],
+            Format::Mov => &["mov", "qt"],
+            Format::Mp4 => &["mp4", "m4v", "3gp", "3g2"],
+            Format::Avi => &["avi"],
+            Format::Matroska => &["mkv", "webm"],
+        }
+    }
+
+    /// Whether a path's extension is one this format goes by, ignoring case
+    pub fn matches_extension(self, path: &Path) -> bool {
+        let extension = path.extension().unwrap_or_default().to_string_lossy();
+        self.extensions()
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(&extension))
+    }
+}
+
+/// Tells the format of a file from its first bytes, if it's one img-sort knows
+pub fn sniff(header: &[u8]) -> Option<Format> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    if at(0, &[0xFF, 0xD8, 0xFF]) {
+        Some(Format::Jpeg)
+    } else if at(0, b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        Some(Format::Gif)
+    } else if at(0, b"II*\0") || at(0, b"MM\0*") {
+        Some(Format::Tiff)
+    } else if at(0, b"IIRO") || at(0, b"IIRS") || at(0, b"MMOR") {
+        Some(Format::Orf)
+    } else if at(0, b"IIU\0") {
+        Some(Format::Rw2)
+    } else if at(0, b"FUJIFILMCCD-RAW") {
+        Some(Format::Raf)
+    } else if at(0, b"\0\0\0\x0CJXL \r\n\x87\n") || at(0, &[0xFF, 0x0A]) {
+        Some(Format::Jxl)
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        Some(Format::WebP)
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        Some(Format::Avi)
+    } else if at(0, &[0x1A, 0x45, 0xDF, 0xA3]) {
+        Some(Format::Matroska)
+    } else if at(4, b"ftyp") {
+        let brand = header.get(8..12)?;
+        match brand {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => {
+                Some(Format::Heic)
+            }
+            b"avif" | b"avis" => Some(Format::Avif),
+            b"crx " => Some(Format::Cr3),
+            b"qt  " => Some(Format::Mov),
+            _ => Some(Format::Mp4),
+        }
+    } else if [b"moov", b"mdat", b"wide", b"free", b"skip"]
+        .iter()
+        .any(|atom| at(4, *atom))
+    {
+        // Older QuickTime files start straight into their atoms
+        Some(Format::Mov)
+    } else {
+        None
+    }
+}
+
+/// Reads the first bytes of a file and tells its format from them
+pub fn sniff_file(path: &Path) -> io::Result<Option<Format>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path)?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    Ok(sniff(&header))
+}
+
+/// Whether a file of a known format stops before its end, as an interrupted copy or
+/// download leaves it
+pub fn is_truncated(path: &Path, format: Format) -> io::Result<bool> {
+    match format {
+        // Image data can't hold the end marker, so any after the start of scan is the end
+        Format::Jpeg => {
+            let data = std::fs::read(path)?;
+            let scan = data.windows(2).position(|marker| marker == [0xFF, 0xDA]);
+            Ok(scan
+                .is_none_or(|scan| !data[scan..].windows(2).any(|marker| marker == [0xFF, 0xD9])))
+        }
+        Format::Png => Ok(!tail(path, 4096)?.windows(4).any(|chunk| chunk == b"IEND")),
+        Format::Gif => Ok(tail(path, 1)? != [0x3B]),
+        Format::Heic | Format::Avif | Format::Cr3 | Format::Mov | Format::Mp4 => {
+            boxes_truncated(path)
+        }
+        Format::Tiff
+        | Format::Orf
+        | Format::Rw2
+        | Format::Raf
+        | Format::WebP
+        | Format::Jxl
+        | Format::Avi
+        | Format::Matroska => Ok(false),
+    }
+}
+
+fn tail(path: &Path, len: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(len)))?;
+
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// Walks the top-level boxes of an ISO media file, which is truncated when the last one
+/// claims more bytes than are left
+fn boxes_truncated(path: &Path) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut position = 0;
+
+    while position < size {
+        let mut header = [0; 16];
+        file.seek(SeekFrom::Start(position))?;
+        if size - position < 8 {
+            return Ok(true);
+        }
+        file.read_exact(&mut header[..8])?;
+
+        let len = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // The box runs to the end of the file
+            0 => return Ok(false),
+            // The length follows the type as 64 bits
+            1 => {
+                if size - position < 16 {
+                    return Ok(true);
+                }
+                file.read_exact(&mut header[8..])?;
+                u64::from_be_bytes(header[8..].try_into().unwrap())
+            }
+            len => len as u64,
+        };
+        if len < 8 {
+            return Ok(true);
+        }
+        position = position.saturating_add(len);
+    }
+
+    Ok(position > size)
+}
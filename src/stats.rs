@@ -0,0 +1,119 @@
+use crate::image::Image;
+use anstyle::Style;
+use chrono::Datelike;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+const BOLD: Style = Style::new().bold();
+
+/// How many files there are and how much space they take
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Count {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Counts of a collection's media, broken down a few ways, with `Unknown` for media
+/// without a date or camera
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub total: Count,
+    pub years: BTreeMap<String, Count>,
+    /// Keyed like `2024-03`
+    pub months: BTreeMap<String, Count>,
+    pub cameras: BTreeMap<String, Count>,
+    /// Keyed by lowercase file extension
+    pub types: BTreeMap<String, Count>,
+}
+
+impl Stats {
+    pub fn new<'a>(images: impl IntoIterator<Item = &'a Image>) -> Self {
+        let mut stats = Stats::default();
+        for image in images {
+            stats.add(image);
+        }
+        stats
+    }
+
+    /// The counts as JSON, for scripts
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn add(&mut self, image: &Image) {
+        let bytes = fs::metadata(&image.path).map_or(0, |metadata| metadata.len());
+        let unknown = || String::from("Unknown");
+
+        let (year, month) = match image.datetime {
+            Some(dt) => (
+                dt.year().to_string(),
+                format!("{}-{:02}", dt.year(), dt.month()),
+            ),
+            None => (unknown(), unknown()),
+        };
+        let camera = image.camera.clone().unwrap_or_else(unknown);
+        let extension = image
+            .path
+            .extension()
+            .map_or_else(unknown, |ext| ext.to_string_lossy().to_lowercase());
+
+        self.total.add(bytes);
+        self.years.entry(year).or_default().add(bytes);
+        self.months.entry(month).or_default().add(bytes);
+        self.cameras.entry(camera).or_default().add(bytes);
+        self.types.entry(extension).or_default().add(bytes);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tables = [
+            ("Year", &self.years),
+            ("Month", &self.months),
+            ("Camera", &self.cameras),
+            ("Type", &self.types),
+        ];
+        let width = tables
+            .iter()
+            .flat_map(|(_, counts)| counts.keys())
+            .map(|key| key.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Camera".len());
+
+        write!(
+            f,
+            "{BOLD}{}{BOLD:#} pieces of media, {}",
+            self.total.files,
+            HumanBytes(self.total.bytes)
+        )?;
+
+        for (title, counts) in tables {
+            write!(
+                f,
+                "\n\n{BOLD}{title:<width$}  {:>8}  {:>10}{BOLD:#}",
+                "Files", "Size"
+            )?;
+            for (key, count) in counts {
+                write!(
+                    f,
+                    "\n{key:<width$}  {:>8}  {:>10}",
+                    count.files,
+                    HumanBytes(count.bytes).to_string()
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
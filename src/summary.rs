@@ -0,0 +1,83 @@
+use crate::save::SaveReport;
+use crate::transfer::Transfer;
+use crate::tree::Tree;
+use anstyle::Style;
+use chrono::Datelike;
+use indicatif::HumanBytes;
+use std::collections::BTreeMap;
+use std::fmt;
+
+const BOLD: Style = Style::new().bold();
+
+/// What a run found and what happened to it, printed once media is saved
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    /// Media found in the sources, before any was filtered out
+    pub found: usize,
+    pub filtered: usize,
+    /// Undated media left in the source by `--skip-unknown`
+    pub left_in_place: usize,
+    /// Undated media that was saved anyway
+    pub undated: usize,
+    /// Media saved from each year, with `None` for undated media
+    pub years: BTreeMap<Option<i32>, usize>,
+    pub transfer: Transfer,
+    pub report: SaveReport,
+}
+
+impl Summary {
+    /// Counts the media in a tree about to be saved
+    pub fn new(tree: &Tree, found: usize, filtered: usize, left_in_place: usize) -> Self {
+        let mut years = BTreeMap::new();
+        for image in tree.images() {
+            *years.entry(image.datetime.map(|dt| dt.year())).or_default() += 1;
+        }
+
+        Summary {
+            found,
+            filtered,
+            left_in_place,
+            undated: years.get(&None).copied().unwrap_or(0),
+            years,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let report = &self.report;
+
+        writeln!(
+            f,
+            "Processed {BOLD}{}{BOLD:#} pieces of media, {} filtered out",
+            self.found, self.filtered
+        )?;
+        writeln!(
+            f,
+            "{}: {BOLD}{}{BOLD:#}, Renamed: {}, Overwritten: {}, Skipped: {}, Failed: {}",
+            self.transfer.past_tense(),
+            report.saved,
+            report.renamed,
+            report.overwritten,
+            report.skipped,
+            report.failed
+        )?;
+        writeln!(f, "Size: {}", HumanBytes(report.bytes))?;
+        writeln!(
+            f,
+            "Without a date: {} saved, {} left in place",
+            self.undated, self.left_in_place
+        )?;
+
+        write!(f, "By year:")?;
+        for (year, count) in &self.years {
+            match year {
+                Some(year) => write!(f, "\n  {year}: {count}")?,
+                None => write!(f, "\n  Unknown: {count}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
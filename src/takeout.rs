@@ -0,0 +1,139 @@
+use crate::hash::hash_file;
+use crate::journal::{Entry, Journal};
+use crate::save::numbered;
+use crate::transfer::Transfer;
+use crate::tree::Tree;
+use log::{debug, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where albums are recreated in the destination
+pub const ALBUMS_DIR: &str = "Albums";
+
+/// A Takeout album, as the media its photos are sorted from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Album {
+    pub title: String,
+    pub media: Vec<PathBuf>,
+}
+
+/// Names the album a folder in a Takeout export holds, if it's one
+///
+/// Takeout gives albums a `metadata.json`, but the `Photos from 2019` folders that hold
+/// every photo by year may have one too, and those aren't albums.
+fn album_title(dir: &Path) -> Option<String> {
+    let name = dir.file_name()?.to_string_lossy();
+    let year = name.strip_prefix("Photos from ");
+    if year.is_some_and(|year| year.len() == 4 && year.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+
+    let json: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.join("metadata.json")).ok()?).ok()?;
+    let title = json["title"]
+        .as_str()
+        .filter(|title| !title.trim().is_empty())
+        .unwrap_or(&name);
+
+    // The title becomes a directory name
+    Some(title.replace(['/', '\\'], "-"))
+}
+
+/// Takes the copies in album folders out of a tree when the same photo is sorted from
+/// elsewhere, so each photo is saved once, returning what each album held
+pub fn collapse_albums(tree: &mut Tree) -> io::Result<Vec<Album>> {
+    let mut titles: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut in_albums = Vec::new();
+    let mut elsewhere = Vec::new();
+    for image in tree.images() {
+        let dir = image.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let title = titles.entry(dir).or_insert_with_key(|dir| album_title(dir));
+        match title {
+            Some(title) => in_albums.push((title.clone(), image.path.clone())),
+            None => elsewhere.push(image.path.clone()),
+        }
+    }
+    in_albums.sort();
+
+    // Only media the same size as something in an album can be a copy of it
+    let sizes: HashSet<u64> = in_albums
+        .iter()
+        .filter_map(|(_, path)| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .collect();
+    let mut kept: HashMap<String, PathBuf> = HashMap::new();
+    for path in elsewhere {
+        if fs::metadata(&path).is_ok_and(|metadata| sizes.contains(&metadata.len())) {
+            kept.entry(hash_file(&path)?).or_insert(path);
+        }
+    }
+
+    let mut albums: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    let mut copies = HashSet::new();
+    for (title, path) in in_albums {
+        let media = match kept.get(&hash_file(&path)?) {
+            Some(original) => {
+                debug!("{:?} is a copy of {:?}", path, original);
+                copies.insert(path);
+                original.clone()
+            }
+            // Photos only in albums, or in several, are still sorted once
+            None => {
+                kept.insert(hash_file(&path)?, path.clone());
+                path
+            }
+        };
+        albums.entry(title).or_default().push(media);
+    }
+
+    tree.retain(|image| !copies.contains(&image.path));
+    Ok(albums
+        .into_iter()
+        .map(|(title, media)| Album { title, media })
+        .collect())
+}
+
+/// Links each album's photos, wherever they were saved, into a folder per album under
+/// the destination, recording each link so it's undone with the run
+///
+/// Returns how many links were made.
+pub fn link_albums(
+    albums: &[Album],
+    saved: &HashMap<&Path, &Path>,
+    dest: &Path,
+    journal: Option<&Journal>,
+) -> io::Result<usize> {
+    let mut linked = 0;
+
+    for album in albums {
+        let dir = dest.join(ALBUMS_DIR).join(&album.title);
+        fs::create_dir_all(&dir)?;
+
+        for media in &album.media {
+            let Some(target) = saved.get(media.as_path()) else {
+                debug!(
+                    "Not linking {:?} into {:?}, it wasn't saved",
+                    media, album.title
+                );
+                continue;
+            };
+            let name = target.file_name().unwrap_or_default();
+            let link = numbered(&dir.join(name), |path| path.symlink_metadata().is_ok());
+            Transfer::Symlink.apply(target, &link)?;
+            linked += 1;
+
+            if let Some(journal) = journal {
+                let result = hash_file(&link).and_then(|hash| {
+                    journal.record(&Entry::new(Transfer::Symlink, target, &link, 0, hash))
+                });
+                if let Err(e) = result {
+                    warn!("Could not record {:?} in {:?}: {}", link, journal.path(), e);
+                }
+            }
+        }
+    }
+
+    Ok(linked)
+}
@@ -0,0 +1,151 @@
+use crate::image::Image;
+use chrono::{Datelike, Timelike};
+use std::path::Path;
+use std::str::FromStr;
+
+/// A filename template such as `{year}{month:02}{day:02}_{hhmmss}_{original}`
+///
+/// Fields may carry a width (`{month:02}`) to zero-pad numbers. `{{` and `}}`
+/// produce literal braces. The original extension is appended unless the
+/// template places it itself with `{ext}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field { field: Field, width: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Hhmmss,
+    Original,
+    Ext,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "year" => Ok(Field::Year),
+            "month" => Ok(Field::Month),
+            "day" => Ok(Field::Day),
+            "hour" => Ok(Field::Hour),
+            "minute" => Ok(Field::Minute),
+            "second" => Ok(Field::Second),
+            "hhmmss" => Ok(Field::Hhmmss),
+            "original" => Ok(Field::Original),
+            "ext" => Ok(Field::Ext),
+            _ => Err(format!("Unknown template field {{{name}}}")),
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(String::from("Unterminated '{' in template")),
+                        }
+                    }
+                    let (name, width) = spec.split_once(':').unwrap_or((&spec, "0"));
+                    let width = width
+                        .parse()
+                        .map_err(|_| format!("Invalid width in template field {{{spec}}}"))?;
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field {
+                        field: name.parse()?,
+                        width,
+                    });
+                }
+                '}' => return Err(String::from("Unmatched '}' in template")),
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        if parts.is_empty() {
+            return Err(String::from("The template is empty"));
+        }
+
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// Renders the file name for an image, or `None` if it has no timestamp
+    pub fn render(&self, image: &Image) -> Option<String> {
+        let datetime = image.datetime?;
+        let path = Path::new(&image.name);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = path.extension().map(|ext| ext.to_string_lossy());
+
+        let mut name = String::new();
+        let mut has_ext = false;
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Field { field, width } => {
+                    let value = match field {
+                        Field::Year => datetime.year().to_string(),
+                        Field::Month => datetime.month().to_string(),
+                        Field::Day => datetime.day().to_string(),
+                        Field::Hour => datetime.hour().to_string(),
+                        Field::Minute => datetime.minute().to_string(),
+                        Field::Second => datetime.second().to_string(),
+                        Field::Hhmmss => datetime.format("%H%M%S").to_string(),
+                        Field::Original => stem.to_string(),
+                        Field::Ext => {
+                            has_ext = true;
+                            ext.clone().unwrap_or_default().to_string()
+                        }
+                    };
+                    name.push_str(&format!("{value:0>width$}"));
+                }
+            }
+        }
+
+        if let (false, Some(ext)) = (has_ext, ext) {
+            name.push('.');
+            name.push_str(&ext);
+        }
+
+        Some(name)
+    }
+}
@@ -0,0 +1,190 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How media is placed into the destination tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transfer {
+    #[default]
+    Copy,
+    Move,
+    Link,
+    Symlink,
+    Reflink,
+    ReflinkOrCopy,
+}
+
+/// When to clone files with copy-on-write instead of copying their data
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum Reflink {
+    /// Clone when the filesystem supports it, otherwise copy
+    Auto,
+    /// Always clone, failing on filesystems without support
+    Always,
+    /// Always copy
+    #[default]
+    Never,
+}
+
+impl Transfer {
+    pub fn apply(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        self.apply_with(src, dest, |_| Ok(()))
+    }
+
+    /// Places `src` at `dest`, calling `finish` on the new file before it replaces
+    /// anything already at `dest`, so a failure leaves that file as it was
+    ///
+    /// Moves make no new file, so `finish` is called on the source before it's moved.
+    pub fn apply_with(
+        &self,
+        src: &Path,
+        dest: &Path,
+        finish: impl FnOnce(&Path) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match self {
+            Transfer::Copy => write_atomic(dest, |partial| {
+                fs::copy(src, partial)?;
+                finish(partial)
+            }),
+            Transfer::Move => {
+                finish(src)?;
+                move_file(src, dest)
+            }
+            Transfer::Link => write_atomic(dest, |partial| {
+                link_file(src, partial, dest)?;
+                finish(partial)
+            }),
+            Transfer::Symlink => write_atomic(dest, |partial| {
+                symlink_file(src, partial)?;
+                finish(partial)
+            }),
+            Transfer::Reflink => write_atomic(dest, |partial| {
+                reflink_copy::reflink(src, partial)?;
+                finish(partial)
+            }),
+            Transfer::ReflinkOrCopy => write_atomic(dest, |partial| {
+                reflink_copy::reflink_or_copy(src, partial)?;
+                finish(partial)
+            }),
+        }
+    }
+
+    /// Describes what happened to saved media, e.g. "Copied"
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Transfer::Copy | Transfer::ReflinkOrCopy => "Copied",
+            Transfer::Move => "Moved",
+            Transfer::Link => "Linked",
+            Transfer::Symlink => "Symlinked",
+            Transfer::Reflink => "Cloned",
+        }
+    }
+
+    /// Whether the destination is a new file that doesn't keep the source's attributes
+    pub fn copies(&self) -> bool {
+        matches!(
+            self,
+            Transfer::Copy | Transfer::Reflink | Transfer::ReflinkOrCopy
+        )
+    }
+}
+
+/// Copies extended attributes, such as `user.*` attributes or Finder tags, from one file to another
+///
+/// Filesystems without extended attributes are skipped silently, as are attributes the
+/// destination refuses, e.g. `security.*` ones without the privileges to set them.
+#[cfg(unix)]
+pub fn copy_xattrs(src: &Path, dest: &Path) -> io::Result<()> {
+    let names = match xattr::list(src) {
+        Ok(names) => names,
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for name in names {
+        let Some(value) = xattr::get(src, &name)? else {
+            continue;
+        };
+
+        match xattr::set(dest, &name, &value) {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn copy_xattrs(_src: &Path, _dest: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Where a file is written before it's complete, hidden next to where it's going
+pub fn partial_path(dest: &Path) -> PathBuf {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    dest.with_file_name(format!(".{name}.partial"))
+}
+
+/// Writes a file under its partial path and renames it into place once it's complete,
+/// so an interrupted write never leaves a truncated file at `dest`
+pub fn write_atomic(dest: &Path, write: impl FnOnce(&Path) -> io::Result<()>) -> io::Result<()> {
+    let partial = partial_path(dest);
+
+    // An interrupted run may have left one behind, which clones refuse to replace
+    let _ = fs::remove_file(&partial);
+    let result = write(&partial).and_then(|()| fs::rename(&partial, dest));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+
+    result
+}
+
+fn move_file(src: &Path, dest: &Path) -> io::Result<()> {
+    match fs::rename(src, dest) {
+        Ok(()) => Ok(()),
+        // A rename can't cross filesystems, so copy then remove the original
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            write_atomic(dest, |partial| fs::copy(src, partial).map(|_| ()))?;
+            fs::remove_file(src)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Hardlinks `src` at `link`, on its way to `dest`
+fn link_file(src: &Path, link: &Path, dest: &Path) -> io::Result<()> {
+    fs::hard_link(src, link).map_err(|e| {
+        if e.kind() == io::ErrorKind::CrossesDevices {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "Cannot hardlink {:?} to {:?}: source and destination are on different filesystems",
+                    src, dest
+                ),
+            )
+        } else {
+            e
+        }
+    })
+}
+
+fn symlink_file(src: &Path, link: &Path) -> io::Result<()> {
+    // Links must point at absolute paths to resolve from inside the destination
+    let src = fs::canonicalize(src)?;
+
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(src, link);
+
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_file(src, link);
+}
@@ -1,135 +1,244 @@
+use crate::arguments::Arguments;
 use crate::image::Image;
-use std::collections::BTreeMap;
-use std::fs;
-use std::io;
-use std::path::PathBuf;
-
-#[derive(PartialEq,Debug)]
-pub enum Tree {
-    YearMonth(BTreeMap<(i32, u32), Vec<Image>>),
-    Year(BTreeMap<i32, Vec<Image>>),
-    Month(BTreeMap<u32, Vec<Image>>),
+use crate::journal::Journal;
+use crate::layout::{Key, Layout, Value};
+use crate::progress::{ignore, Hook};
+use crate::prompt::Prompt;
+use crate::save::{execute, plan_images, SaveOptions, SaveReport, Step};
+use anstyle::Style;
+use log::debug;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const BOLD: Style = Style::new().bold();
+
+/// Images grouped into buckets by the values of each key in a layout
+#[derive(PartialEq, Debug)]
+pub struct Tree {
+    layout: Layout,
+    buckets: BTreeMap<Vec<Value>, Vec<Image>>,
 }
 
 impl Tree {
-    pub fn insert(&mut self, datetime: (i32, u32), image: Image) {
-        match self {
-            Tree::YearMonth(tree) => {
-                tree.entry(datetime).or_insert_with(Vec::new).push(image);
-            }
-            Tree::Year(tree) => {
-                let (year, _) = datetime;
-                tree.entry(year).or_insert_with(Vec::new).push(image);
-            }
-            Tree::Month(tree) => {
-                let (_, month) = datetime;
-                tree.entry(month).or_insert_with(Vec::new).push(image);
-            }
+    pub fn new(layout: Layout) -> Self {
+        Tree {
+            layout,
+            buckets: BTreeMap::new(),
         }
     }
 
-    pub fn size(&self) -> usize {
-        match self {
-            Tree::YearMonth(tree) => tree.values().map(Vec::len).sum(),
-            Tree::Year(tree) => tree.values().map(Vec::len).sum(),
-            Tree::Month(tree) => tree.values().map(Vec::len).sum(),
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn insert(&mut self, image: Image) {
+        let values = self
+            .layout
+            .keys
+            .iter()
+            .map(|key| key.value(&image))
+            .collect();
+
+        self.buckets.entry(values).or_default().push(image);
+    }
+
+    /// Drops the images that don't satisfy `keep`, returning how many were dropped
+    pub fn retain(&mut self, mut keep: impl FnMut(&Image) -> bool) -> usize {
+        let before = self.size();
+
+        for images in self.buckets.values_mut() {
+            images.retain(&mut keep);
         }
+        self.buckets.retain(|_, images| !images.is_empty());
+
+        before - self.size()
     }
 
-    pub fn print(&self) {
-        match self {
-            Tree::YearMonth(tree) => {
-                for ((year, month), images) in tree {
-                    println!("Year: {}, Month: {}", year, month);
-                    for image in images {
-                        println!("  Image: {:?}", image.path);
-                    }
-                }
-            }
-            Tree::Year(tree) => {
-                for (year, images) in tree {
-                    println!("Year: {}", year);
-                    for image in images {
-                        println!("  Image: {:?}", image.path);
-                    }
-                }
-            }
-            Tree::Month(tree) => {
-                for (month, images) in tree {
-                    println!("Month: {}", month);
-                    for image in images {
-                        println!("  Image: {:?}", image.path);
+    /// Changes images in place, moving each to the bucket it then belongs in
+    pub fn update(&mut self, mut change: impl FnMut(&mut Image)) {
+        let buckets = std::mem::take(&mut self.buckets);
+        for mut image in buckets.into_values().flatten() {
+            change(&mut image);
+            self.insert(image);
+        }
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &Image> {
+        self.buckets.values().flatten()
+    }
+
+    pub fn size(&self) -> usize {
+        self.buckets.values().map(Vec::len).sum()
+    }
+
+    pub fn print(&self) -> io::Result<()> {
+        self.print_to(&mut anstream::stdout())
+    }
+
+    /// Writes each bucket's labels and images, styled for `out` to strip or keep
+    pub fn print_to(&self, out: &mut impl Write) -> io::Result<()> {
+        for (values, images) in &self.buckets {
+            let labels: Vec<String> = self
+                .layout
+                .keys
+                .iter()
+                .zip(values)
+                .map(|(key, value)| {
+                    let label = match value {
+                        Value::Number(number) => format!("{}: {}", key.label(), number),
+                        Value::Text(text) => format!("{}: {}", key.label(), text),
+                        Value::Unknown => format!("{}: Unknown", key.label()),
+                    };
+
+                    // Years are the top of most trees, so make them stand out
+                    match key {
+                        Key::Year | Key::IsoYear | Key::SeasonYear => {
+                            format!("{BOLD}{label}{BOLD:#}")
+                        }
+                        _ => label,
                     }
-                }
+                })
+                .collect();
+
+            writeln!(out, "{}", labels.join(", "))?;
+            for image in images {
+                writeln!(out, "  Image: {:?}", image.path)?;
             }
         }
+        Ok(())
     }
 
-    pub fn save(&self, dest: &PathBuf) -> io::Result<()> {
-        match self {
-            Tree::YearMonth(tree) => {
-                for ((year, month), images) in tree {
-                    let dir = dest.join(year.to_string()).join(get_month(month));
-                    fs::create_dir_all(&dir)?;
+    /// Lists each bucket's directory, relative to the destination, with its images
+    ///
+    /// Undated media stops at the first date key, e.g. `Photos/Unknown` rather than
+    /// `Photos/Unknown/Unknown`, so it all lands in one clearly named folder.
+    fn buckets(&self, options: &SaveOptions) -> Vec<(PathBuf, &Vec<Image>)> {
+        self.buckets
+            .iter()
+            .map(|(values, images)| {
+                let known = values
+                    .iter()
+                    .position(|value| *value == Value::Unknown)
+                    .map_or(values.len(), |unknown| unknown + 1);
 
-                    for image in images {
-                        let dest = dir.join(&image.name);
-                        fs::copy(&image.path, &dest)?;
-                    }
+                let dir = self
+                    .layout
+                    .keys
+                    .iter()
+                    .zip(&values[..known])
+                    .map(|(key, value)| key.render(value, options))
+                    .collect();
+                (dir, images)
+            })
+            .collect()
+    }
+
+    pub fn save(&self, dest: &Path, options: &SaveOptions) -> io::Result<SaveReport> {
+        self.save_with(dest, options, None, &ignore)
+    }
+
+    /// Saves like `save`, recording what's done in `journal` and reporting progress to
+    /// `on_event`
+    pub fn save_with(
+        &self,
+        dest: &Path,
+        options: &SaveOptions,
+        journal: Option<&Journal>,
+        on_event: Hook,
+    ) -> io::Result<SaveReport> {
+        let steps = self.plan(dest, options)?;
+        execute(&steps, options, journal, on_event)
+    }
+
+    /// Decides where each image is saved and how, without touching the destination
+    pub fn plan(&self, dest: &Path, options: &SaveOptions) -> io::Result<Vec<Step>> {
+        let mut planned = HashMap::new();
+        let mut prompt = options.interactive.then(Prompt::stdio);
+        let mut steps = Vec::with_capacity(self.size());
+
+        // The video of a Live Photo is saved beside its photo, wherever that goes
+        let paths: HashSet<&Path> = self.images().map(|image| image.path.as_path()).collect();
+        let videos: HashMap<&Path, &Image> = self
+            .images()
+            .filter_map(|image| Some((image.live_photo.as_deref()?, image)))
+            .filter(|(photo, _)| paths.contains(photo))
+            .collect();
+
+        for (bucket, images) in self.buckets(options) {
+            let dir = dest.join(&bucket);
+
+            // Shots taken the same second are numbered in the order they were taken
+            let mut images: Vec<&Image> = images.iter().collect();
+            images.sort_by_key(|image| image.datetime);
+
+            for image in images {
+                if image
+                    .live_photo
+                    .as_deref()
+                    .is_some_and(|photo| videos.contains_key(photo))
+                {
+                    continue;
                 }
-            }
-            Tree::Year(tree) => {
-                for (year, images) in tree {
-                    let dir = dest.join(year.to_string());
-                    fs::create_dir_all(&dir)?;
-
-                    for image in images {
-                        let dest = dir.join(&image.name);
-                        fs::copy(&image.path, &dest)?;
-                    }
+
+                let dir = match &image.branch {
+                    Some(branch) => dest.join(branch).join(&bucket),
+                    None => dir.clone(),
+                };
+                let dir = match options.keep_structure {
+                    true => dir.join(&image.subdir),
+                    false => dir,
+                };
+                let dir = match &image.nest {
+                    Some(nest) => dir.join(nest),
+                    None => dir,
+                };
+
+                let name = options.rename.as_ref().and_then(|t| t.render(image));
+                let mut dest = dir.join(name.as_deref().unwrap_or(&image.name));
+                if options.fix_extensions {
+                    dest = fix_extension(dest, image);
                 }
-            }
-            Tree::Month(tree) => {
-                for (month, images) in tree {
-                    let dir = dest.join(get_month(month));
-                    println!("dir: {:?}", &dir);
-                    fs::create_dir_all(&dir)?;
-
-                    for image in images {
-                        let dest = dir.join(&image.name);
-                        fs::copy(&image.path, &dest)?;
-                    }
+
+                let mut unit = vec![image];
+                unit.extend(videos.get(image.path.as_path()));
+                for step in plan_images(&unit, dest, options, &planned, prompt.as_mut())? {
+                    planned.insert(step.dest.clone(), step.source.clone());
+                    steps.push(step);
                 }
             }
         }
 
-        Ok(())
+        Ok(steps)
     }
 }
 
-fn get_month(month: &u32) -> String {
-    match month {
-        1 => String::from("January"),
-        2 => String::from("February"),
-        3 => String::from("March"),
-        4 => String::from("April"),
-        5 => String::from("May"),
-        6 => String::from("June"),
-        7 => String::from("July"),
-        8 => String::from("August"),
-        9 => String::from("September"),
-        10 => String::from("October"),
-        11 => String::from("November"),
-        12 => String::from("December"),
-        _ => String::from("Unknown"),
+/// Gives a destination the usual extension of the media's format, if its own is wrong,
+/// in upper case if the original's was
+fn fix_extension(dest: PathBuf, image: &Image) -> PathBuf {
+    let Some(format) = image
+        .format
+        .filter(|format| !format.matches_extension(&image.path))
+    else {
+        return dest;
+    };
+    let upper = image.path.extension().is_some_and(|ext| {
+        ext.to_string_lossy()
+            .chars()
+            .all(|c| c.is_ascii_uppercase())
+    });
+    let extension = format.extensions()[0];
+    debug!(
+        "Saving {:?} as .{}, which it really is",
+        image.path, extension
+    );
+    if upper {
+        dest.with_extension(extension.to_ascii_uppercase())
+    } else {
+        dest.with_extension(extension)
     }
 }
 
-pub fn build_tree(years: &bool, months: &bool) -> Tree {
-    match (years, months) {
-        (true, true) => Tree::YearMonth(BTreeMap::new()),
-        (true, false) => Tree::Year(BTreeMap::new()),
-        (false, true) => Tree::Month(BTreeMap::new()),
-        _ => unreachable!("Invalid combination of years and months"),
-    }
+pub fn build_tree(args: &Arguments) -> Tree {
+    Tree::new(args.layout())
 }
@@ -0,0 +1,132 @@
+use crate::hash::hash_file;
+use crate::journal::{Entry, JOURNAL_DIR};
+use crate::transfer::Transfer;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What checking a destination against its journals found
+#[derive(Debug, Default, PartialEq)]
+pub struct Verification {
+    /// Files that match the hash recorded when they were saved
+    pub intact: usize,
+    /// Files whose contents no longer match their recorded hash
+    pub corrupted: Vec<PathBuf>,
+    /// Files the journals recorded that are no longer there
+    pub missing: Vec<PathBuf>,
+    /// Files in the destination that no journal recorded
+    pub extra: Vec<PathBuf>,
+    /// Files the journals recorded that couldn't be read to check them
+    pub unreadable: Vec<PathBuf>,
+}
+
+impl Verification {
+    /// Whether every recorded file is there and intact, which extra files don't change
+    pub fn passed(&self) -> bool {
+        self.corrupted.is_empty() && self.missing.is_empty() && self.unreadable.is_empty()
+    }
+}
+
+/// Re-hashes every file the journal `entries` saved into `dest`, and looks for files
+/// in `dest` they don't account for
+///
+/// The entries are replayed in the order they were recorded, so media moved within the
+/// destination, e.g. by `audit --fix`, or undone since is only expected where it is now.
+pub fn verify(dest: &Path, entries: &[Entry]) -> io::Result<Verification> {
+    let root = normalize(dest);
+    let mut recorded: HashMap<PathBuf, &Entry> = HashMap::new();
+    for entry in entries {
+        let saved = normalize(&entry.dest);
+        let source = normalize(&entry.source);
+        if entry.undone {
+            recorded.remove(&saved);
+            // Undoing a move within the destination puts the media back where it was
+            if entry.op == Transfer::Move && source.starts_with(&root) {
+                recorded.insert(source, entry);
+            }
+        } else {
+            if entry.op == Transfer::Move {
+                recorded.remove(&source);
+            }
+            // A later entry for the same file replaced the earlier one
+            recorded.insert(saved, entry);
+        }
+    }
+    let mut verification = Verification::default();
+
+    // A file that can't be read fails on its own, and the rest are still checked
+    for (path, entry) in &recorded {
+        match hash_file(path) {
+            Ok(hash) if hash == entry.hash => {
+                debug!("{:?} is intact", path);
+                verification.intact += 1;
+            }
+            Ok(_) => verification.corrupted.push(path.clone()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                verification.missing.push(path.clone())
+            }
+            Err(e) => {
+                debug!("Could not read {:?}: {}", path, e);
+                verification.unreadable.push(path.clone())
+            }
+        }
+    }
+
+    let recorded: HashSet<_> = recorded.into_keys().collect();
+    let mut files = Vec::new();
+    list_files(&root, &mut files)?;
+    verification.extra = files
+        .into_iter()
+        .filter(|file| !recorded.contains(file))
+        .collect();
+
+    verification.corrupted.sort();
+    verification.missing.sort();
+    verification.extra.sort();
+    verification.unreadable.sort();
+    Ok(verification)
+}
+
+/// Lists the files under a directory, leaving out the journals and without following
+/// symlinks
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if entry.file_type()?.is_dir() {
+            if entry.file_name() != JOURNAL_DIR {
+                list_files(&path, files)?;
+            }
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves the directory a path is in, so the same file is recognised however the
+/// destination was written, while a symlink still names the link and not its target
+///
+/// Directories that are gone, e.g. emptied by an undo, are resolved from the nearest
+/// one that's still there.
+fn normalize(path: &Path) -> PathBuf {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return path.to_path_buf();
+    };
+
+    dir.ancestors()
+        .find_map(|ancestor| {
+            let existing = if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            };
+            let rest = dir.strip_prefix(ancestor).ok()?;
+            Some(fs::canonicalize(existing).ok()?.join(rest).join(name))
+        })
+        .unwrap_or_else(|| path.to_path_buf())
+}
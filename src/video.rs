@@ -0,0 +1,157 @@
+use crate::metadata::Metadata;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Where to look for a video's headers, which come before its frames
+const HEADER_LEN: u64 = 1 << 20;
+
+fn read_head(path: &Path) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    File::open(path)?.take(HEADER_LEN).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Reads when an AVI was recorded from its `IDIT` chunk, or the `ICRD` of its `INFO` list
+pub fn read_avi(path: &Path) -> io::Result<Metadata> {
+    let head = read_head(path)?;
+    let mut idit = None;
+    let mut icrd = None;
+    walk_riff(
+        head.get(12..).unwrap_or_default(),
+        &mut |id, data| match id {
+            b"IDIT" => idit = Some(riff_text(data)),
+            b"ICRD" => icrd = Some(riff_text(data)),
+            _ => {}
+        },
+    );
+
+    Ok(Metadata {
+        datetime: idit
+            .as_deref()
+            .and_then(parse_avi_date)
+            .or_else(|| icrd.as_deref().and_then(parse_avi_date)),
+        ..Default::default()
+    })
+}
+
+/// Calls `on_chunk` with each chunk in a RIFF list, going into the lists inside it but
+/// not the frames of `movi`
+pub fn walk_riff(mut data: &[u8], on_chunk: &mut impl FnMut(&[u8], &[u8])) {
+    while data.len() >= 8 {
+        let id = &data[..4];
+        let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let body = &data[8..data.len().min(8 + len)];
+
+        if id == b"LIST" && body.get(..4) != Some(b"movi") {
+            walk_riff(body.get(4..).unwrap_or_default(), on_chunk);
+        } else {
+            on_chunk(id, body);
+        }
+
+        // Chunks are padded to an even length
+        let next = 8 + len + len % 2;
+        data = data.get(next..).unwrap_or_default();
+    }
+}
+
+fn riff_text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data)
+        .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+        .to_string()
+}
+
+/// Parses the dates cameras write into AVIs, such as `THU JUL 04 10:11:12 2019` or
+/// `2019:07:04 10:11:12`
+fn parse_avi_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    [
+        "%a %b %d %H:%M:%S %Y",
+        "%Y:%m:%d %H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y/%m/%d %H:%M:%S",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(&text, format).ok())
+    .or_else(|| {
+        let date = NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()?;
+        Some(date.and_time(NaiveTime::MIN))
+    })
+}
+
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const DATE_UTC: u32 = 0x4461;
+const CLUSTER: u32 = 0x1F43B675;
+
+/// Reads when a Matroska or WebM video was made from the `DateUTC` of its segment info
+pub fn read_matroska(path: &Path) -> io::Result<Metadata> {
+    let head = read_head(path)?;
+    Ok(Metadata {
+        datetime: matroska_date(&head),
+        ..Default::default()
+    })
+}
+
+fn matroska_date(mut data: &[u8]) -> Option<NaiveDateTime> {
+    while let Some((id, body, rest)) = ebml_element(data) {
+        match id {
+            // The segment holds everything else, so look inside it
+            SEGMENT => data = body,
+            INFO => {
+                let mut info = body;
+                while let Some((id, body, rest)) = ebml_element(info) {
+                    if id == DATE_UTC && body.len() == 8 {
+                        // Nanoseconds since the start of 2001, in UTC
+                        let nanos = i64::from_be_bytes(body.try_into().ok()?);
+                        let epoch = DateTime::from_timestamp(978_307_200, 0)?;
+                        let utc = epoch + TimeDelta::nanoseconds(nanos);
+                        return Some(utc.with_timezone(&Local).naive_local());
+                    }
+                    info = rest;
+                }
+                return None;
+            }
+            CLUSTER => return None,
+            // The EBML header and anything else before the segment
+            _ => data = rest,
+        }
+    }
+    None
+}
+
+/// Splits the first EBML element off `data`, as its ID, its body and what follows it
+///
+/// Elements of unknown size, as streamed segments have, run to the end of `data`.
+fn ebml_element(data: &[u8]) -> Option<(u32, &[u8], &[u8])> {
+    let (id, id_len) = vint(data, true)?;
+    let (size, size_len) = vint(data.get(id_len..)?, false)?;
+    let start = id_len + size_len;
+    let unknown = size == (1 << (7 * size_len)) - 1;
+    let end = match unknown {
+        true => data.len(),
+        false => start
+            .checked_add(usize::try_from(size).ok()?)?
+            .min(data.len()),
+    };
+    Some((id as u32, data.get(start..end)?, data.get(end..)?))
+}
+
+/// Reads a variable-length EBML integer, keeping its length marker for IDs
+fn vint(data: &[u8], keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return None;
+    }
+
+    let mut value = match keep_marker {
+        true => first as u64,
+        false => (first & (0xFF >> len)) as u64,
+    };
+    for byte in data.get(1..len)? {
+        value = (value << 8) | *byte as u64;
+    }
+    Some((value, len))
+}
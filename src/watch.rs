@@ -0,0 +1,124 @@
+use crate::interrupt::interrupted;
+use notify::event::{EventKind, ModifyKind};
+use notify::{Event, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Files that have appeared but may still be being written
+///
+/// A file has settled once it's gone `settle` without an event and its size stopped
+/// changing, which catches copies that write in bursts.
+#[derive(Debug)]
+pub struct Pending {
+    settle: Duration,
+    files: HashMap<PathBuf, (Instant, Option<u64>)>,
+}
+
+impl Pending {
+    pub fn new(settle: Duration) -> Self {
+        Pending {
+            settle,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Notes that a file was written to at `now`
+    pub fn note(&mut self, path: PathBuf, now: Instant) {
+        let size = size(&path);
+        self.files.insert(path, (now, size));
+    }
+
+    /// Takes the files that have settled by `now`, in path order
+    pub fn settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+
+        self.files.retain(|path, (seen, recorded)| {
+            if now.duration_since(*seen) < self.settle {
+                return true;
+            }
+
+            match size(path) {
+                // Gone again, e.g. a temporary file renamed into place
+                None => false,
+                current if current == *recorded => {
+                    settled.push(path.clone());
+                    false
+                }
+                current => {
+                    (*seen, *recorded) = (now, current);
+                    true
+                }
+            }
+        });
+
+        settled.sort();
+        settled
+    }
+}
+
+fn size(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.len())
+}
+
+/// Watches directories and everything in them, calling `on_settled` with batches of files
+/// that were created or written to once they've settled
+///
+/// Only files `wanted` says are media are passed on. Runs until the watch fails.
+pub fn watch(
+    dirs: &[PathBuf],
+    settle: Duration,
+    wanted: impl Fn(&Path) -> bool,
+    mut on_settled: impl FnMut(Vec<PathBuf>),
+) -> notify::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for dir in dirs {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+    }
+
+    // Stopping between batches means media is never left half saved
+    let mut pending = Pending::new(settle);
+    while !interrupted() {
+        receive(&receiver, &mut pending, &wanted, settle / 4)?;
+
+        let settled = pending.settled(Instant::now());
+        if !settled.is_empty() {
+            on_settled(settled);
+        }
+    }
+    Ok(())
+}
+
+/// Waits up to `timeout` for events, noting each written file
+fn receive(
+    receiver: &Receiver<notify::Result<Event>>,
+    pending: &mut Pending,
+    wanted: impl Fn(&Path) -> bool,
+    timeout: Duration,
+) -> notify::Result<()> {
+    let event = match receiver.recv_timeout(timeout) {
+        Ok(event) => event?,
+        Err(RecvTimeoutError::Timeout) => return Ok(()),
+        Err(RecvTimeoutError::Disconnected) => {
+            return Err(notify::Error::generic("The watcher stopped"))
+        }
+    };
+
+    let written = matches!(
+        event.kind,
+        EventKind::Create(_)
+            | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+    );
+    if written {
+        for path in event.paths {
+            if path.is_file() && wanted(&path) {
+                pending.note(path, Instant::now());
+            }
+        }
+    }
+
+    Ok(())
+}
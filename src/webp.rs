@@ -0,0 +1,25 @@
+use crate::video::walk_riff;
+use exif::{Exif, Reader};
+use std::fs;
+use std::path::Path;
+
+/// Reads the EXIF of a WebP from its `EXIF` chunk, which extended WebPs keep after the
+/// image data
+///
+/// Some tools start the chunk with the `Exif\0\0` a JPEG's APP1 segment has, so that's
+/// skipped before reading the TIFF inside.
+pub fn read_exif(path: &Path) -> Result<Exif, exif::Error> {
+    let data = fs::read(path)?;
+
+    let mut exif = None;
+    walk_riff(data.get(12..).unwrap_or_default(), &mut |id, body| {
+        if id == b"EXIF" && exif.is_none() {
+            exif = Some(body.strip_prefix(b"Exif\0\0").unwrap_or(body).to_vec());
+        }
+    });
+
+    match exif {
+        Some(tiff) => Reader::new().read_raw(tiff),
+        None => Err(exif::Error::NotFound("WebP")),
+    }
+}
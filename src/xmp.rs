@@ -0,0 +1,217 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use roxmltree::{Document, Node};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Where an XMP sidecar's date stands next to the one in the media's own EXIF
+#[derive(Debug, Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum XmpPriority {
+    /// Only date media whose EXIF has no date
+    #[default]
+    Fallback,
+    /// Prefer the sidecar's date, since it may have been corrected
+    Prefer,
+    /// Don't read sidecars
+    Ignore,
+}
+
+/// Properties holding when a photo was taken, most trusted first, as namespace and name
+const DATE_PROPERTIES: [(&str, &str); 3] = [
+    ("http://ns.adobe.com/exif/1.0/", "DateTimeOriginal"),
+    ("http://ns.adobe.com/photoshop/1.0/", "DateCreated"),
+    ("http://ns.adobe.com/xap/1.0/", "CreateDate"),
+];
+
+/// The namespace of `exif:GPSLatitude` and the other properties saying where a photo was taken
+const EXIF: &str = "http://ns.adobe.com/exif/1.0/";
+
+/// The namespace of `xmp:Rating`
+const XMP: &str = "http://ns.adobe.com/xap/1.0/";
+
+/// The namespace of `dc:subject`, which holds keywords
+const DUBLIN_CORE: &str = "http://purl.org/dc/elements/1.1/";
+
+/// The namespace of `GCamera:MotionPhoto`, which marks a Motion Photo
+const GCAMERA: &str = "http://ns.google.com/photos/1.0/camera/";
+
+/// The namespace of the items of `Container:Directory`, which lists what's in a file
+const CONTAINER_ITEM: &str = "http://ns.google.com/photos/1.0/container/item/";
+
+/// What the XMP of a Motion Photo says about the video at the end of the file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionPhoto {
+    /// How many bytes the video takes at the end of the file, when it says
+    pub video_len: Option<usize>,
+}
+
+/// The sidecar beside `path`, named either `IMG_0001.xmp` or `IMG_0001.CR2.xmp`
+pub fn find_sidecar(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    [
+        path.with_extension("xmp"),
+        path.with_extension("XMP"),
+        path.with_file_name(format!("{name}.xmp")),
+        path.with_file_name(format!("{name}.XMP")),
+    ]
+    .into_iter()
+    .find(|sidecar| sidecar.is_file())
+}
+
+/// Reads when the media beside an XMP sidecar was taken, if it has a sidecar
+pub fn read_sidecar_date(path: &Path) -> Option<NaiveDateTime> {
+    let sidecar = find_sidecar(path)?;
+    read_date(&fs::read_to_string(sidecar).ok()?)
+}
+
+/// Reads the most trusted date in an XMP packet, written either as an attribute of a
+/// description or as an element inside it
+pub fn read_date(xmp: &str) -> Option<NaiveDateTime> {
+    let document = Document::parse(xmp).ok()?;
+    let descriptions: Vec<Node> = document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Description")
+        .collect();
+
+    DATE_PROPERTIES.iter().find_map(|&(namespace, name)| {
+        descriptions.iter().find_map(|description| {
+            let text = description.attribute((namespace, name)).or_else(|| {
+                description
+                    .children()
+                    .find(|child| child.has_tag_name((namespace, name)))
+                    .and_then(|child| child.text())
+            })?;
+            parse_date(text)
+        })
+    })
+}
+
+/// Reads the stars of an XMP packet, from 0 to 5, or -1 for a rejected photo
+pub fn read_rating(xmp: &str) -> Option<i32> {
+    let document = Document::parse(xmp).ok()?;
+    let name = (XMP, "Rating");
+    let text = document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Description")
+        .find_map(|description| {
+            description.attribute(name).or_else(|| {
+                description
+                    .children()
+                    .find(|child| child.has_tag_name(name))
+                    .and_then(|child| child.text())
+            })
+        })?;
+    // Some editors write ratings like `3.0`
+    let rating = text.trim().parse::<f64>().ok()?.round() as i32;
+    (-1..=5).contains(&rating).then_some(rating)
+}
+
+/// Removes the `exif:GPS` properties of an XMP packet, written either as attributes of a
+/// description or as elements inside it, leaving the rest of it as it was
+pub fn remove_gps(xmp: &str) -> Option<String> {
+    let document = Document::parse(xmp).ok()?;
+    let is_gps =
+        |namespace: Option<&str>, name: &str| namespace == Some(EXIF) && name.starts_with("GPS");
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for description in document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Description")
+    {
+        let attributes = description
+            .attributes()
+            .filter(|attribute| is_gps(attribute.namespace(), attribute.name()))
+            .map(|attribute| attribute.range());
+        let elements = description
+            .children()
+            .filter(|child| is_gps(child.tag_name().namespace(), child.tag_name().name()))
+            .map(|child| child.range());
+        ranges.extend(attributes.chain(elements));
+    }
+    ranges.sort_by_key(|range| range.start);
+
+    let mut stripped = String::with_capacity(xmp.len());
+    let mut position = 0;
+    for range in ranges {
+        // A description nested in a property that's already gone
+        if range.start < position {
+            continue;
+        }
+        stripped.push_str(&xmp[position..range.start]);
+        position = range.end;
+    }
+    stripped.push_str(&xmp[position..]);
+    Some(stripped)
+}
+
+/// Reads the keywords of an XMP packet, which are the items of its `dc:subject`
+pub fn read_keywords(xmp: &str) -> Vec<String> {
+    let Ok(document) = Document::parse(xmp) else {
+        return Vec::new();
+    };
+    document
+        .descendants()
+        .filter(|node| node.has_tag_name((DUBLIN_CORE, "subject")))
+        .flat_map(|subject| subject.descendants())
+        .filter(|node| node.tag_name().name() == "li")
+        .filter_map(|item| item.text())
+        .map(|keyword| keyword.trim().to_string())
+        .filter(|keyword| !keyword.is_empty())
+        .collect()
+}
+
+/// Reads whether an XMP packet marks its photo as a Motion Photo, as Pixel and Samsung
+/// phones write `GCamera:MotionPhoto`, or `GCamera:MicroVideo` before 2021
+pub fn read_motion_photo(xmp: &str) -> Option<MotionPhoto> {
+    let document = Document::parse(xmp).ok()?;
+    let property = |name: &str| {
+        document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "Description")
+            .find_map(|description| {
+                description.attribute((GCAMERA, name)).or_else(|| {
+                    description
+                        .children()
+                        .find(|child| child.has_tag_name((GCAMERA, name)))
+                        .and_then(|child| child.text())
+                })
+            })
+            .map(str::trim)
+    };
+
+    let video_len = if property("MotionPhoto") == Some("1") {
+        document
+            .descendants()
+            .find(|node| node.attribute((CONTAINER_ITEM, "Semantic")) == Some("MotionPhoto"))
+            .and_then(|item| item.attribute((CONTAINER_ITEM, "Length")))
+    } else if property("MicroVideo") == Some("1") {
+        property("MicroVideoOffset")
+    } else {
+        return None;
+    };
+    Some(MotionPhoto {
+        video_len: video_len.and_then(|len| len.trim().parse().ok()),
+    })
+}
+
+/// Parses XMP's dates, which are ISO 8601 with as much precision as is known, keeping
+/// the local time they were taken at
+fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.naive_local());
+    }
+    // A time without seconds, with an offset after it
+    if let Ok(datetime) = DateTime::parse_from_str(text, "%Y-%m-%dT%H:%M%:z") {
+        return Some(datetime.naive_local());
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+            date.and_hms_opt(0, 0, 0)
+        })
+}
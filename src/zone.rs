@@ -0,0 +1,88 @@
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// A time zone dates are read or sorted in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// This computer's
+    Local,
+    /// A fixed offset from UTC, like `+09:00`
+    Offset(FixedOffset),
+    /// A zone of the tz database, like `Asia/Tokyo`, which keeps its daylight saving time
+    Named(Tz),
+}
+
+impl Zone {
+    /// The moment a local time in the zone was, taking the earlier one when clocks go back
+    fn to_utc(self, datetime: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let utc = match self {
+            Zone::Local => Local.from_local_datetime(&datetime).earliest()?.to_utc(),
+            Zone::Offset(offset) => offset.from_local_datetime(&datetime).earliest()?.to_utc(),
+            Zone::Named(tz) => tz.from_local_datetime(&datetime).earliest()?.to_utc(),
+        };
+        Some(utc)
+    }
+
+    /// The local time in the zone at a moment, and its offset then
+    fn local_time(self, utc: DateTime<Utc>) -> (NaiveDateTime, FixedOffset) {
+        match self {
+            Zone::Local => {
+                let local = utc.with_timezone(&Local);
+                (local.naive_local(), local.offset().fix())
+            }
+            Zone::Offset(offset) => (utc.with_timezone(&offset).naive_local(), offset),
+            Zone::Named(tz) => {
+                let local = utc.with_timezone(&tz);
+                (local.naive_local(), local.offset().fix())
+            }
+        }
+    }
+}
+
+/// Parses `local`, `UTC`, an offset like `+09:00` or `-0500`, or a name like `Asia/Tokyo`
+pub fn parse_zone(zone: &str) -> Result<Zone, String> {
+    if zone.eq_ignore_ascii_case("local") {
+        return Ok(Zone::Local);
+    }
+    if let Some(offset) = parse_utc_offset(zone) {
+        return Ok(Zone::Offset(offset));
+    }
+    zone.parse::<Tz>().map(Zone::Named).map_err(|_| {
+        format!(
+            "Unknown time zone {:?}, expected e.g. local, UTC, +09:00 or Asia/Tokyo",
+            zone
+        )
+    })
+}
+
+/// Parses offsets as EXIF writes them, like `+09:00`, or without the colon
+pub fn parse_utc_offset(offset: &str) -> Option<FixedOffset> {
+    let offset = offset.trim().trim_end_matches('\0');
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = offset[1..].replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Moves a date taken at `offset`, or in the `assumed` zone when that isn't known, into
+/// the `target` zone, giving the local time there and its offset
+pub fn convert(
+    datetime: NaiveDateTime,
+    offset: Option<FixedOffset>,
+    assumed: Zone,
+    target: Zone,
+) -> Option<(NaiveDateTime, FixedOffset)> {
+    let utc = match offset {
+        Some(offset) => Zone::Offset(offset).to_utc(datetime)?,
+        None => assumed.to_utc(datetime)?,
+    };
+    Some(target.local_time(utc))
+}